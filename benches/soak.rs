@@ -0,0 +1,23 @@
+//! Soak test: connects many headless bot clients to a server and reports server tick time, bandwidth,
+//! RTT and rollback counts.
+//!
+//! Run with `cargo bench -p lightyear-benches --bench soak -- [NUM_CLIENTS] [FRAMES] [udp]`
+use lightyear_benches::load::{LoadTest, LoadTransport};
+
+fn main() {
+    let mut args = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"));
+    let num_clients = args.next().and_then(|arg| arg.parse().ok()).unwrap_or(100);
+    let frames = args.next().and_then(|arg| arg.parse().ok()).unwrap_or(600);
+    let transport = match args.next().as_deref() {
+        Some("udp") => LoadTransport::UdpLoopback,
+        _ => LoadTransport::LocalChannel,
+    };
+
+    let report = LoadTest::new(num_clients)
+        .with_transport(transport)
+        .with_frames(frames)
+        .run();
+    println!("{report}");
+}
@@ -2,6 +2,7 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 pub mod client;
+pub mod load;
 pub mod local_stepper;
 pub mod protocol;
 pub mod server;
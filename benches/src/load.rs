@@ -0,0 +1,512 @@
+//! Load-testing harness: runs a server with many headless bot clients and reports how the server copes.
+//!
+//! Every bot is a full client [`App`] with the real [`ClientPlugin`](lightyear::prelude::client::ClientPlugin),
+//! connected to the server over local channels or UDP loopback. The bots send inputs and messages according to
+//! their [`BotBehaviour`]; the server spawns one predicted entity per bot and applies the bot's inputs to it,
+//! so that the clients also have to replicate, predict and rollback.
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use bevy::prelude::{
+    default, App, Component, EventReader, FixedUpdate, IntoSystemConfigs, PreUpdate, Query, Real,
+    ResMut, Resource, Time, Update,
+};
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::tracing::Level;
+use bevy::utils::{Duration, HashMap, Instant};
+use bevy::MinimalPlugins;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use lightyear::client::sync::client_is_synced;
+use lightyear::netcode::{generate_key, ConnectToken, RateLimitConfig};
+use lightyear::prelude::client::{
+    Authentication, ClientConfig, InputSystemSet, NetClient, PredictionSet,
+};
+use lightyear::prelude::server::{NetcodeConfig, ServerConfig};
+use lightyear::prelude::*;
+use lightyear::transport::LOCAL_SOCKET;
+
+use crate::protocol::*;
+
+/// Transport used between the bots and the server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadTransport {
+    /// In-memory channels
+    LocalChannel,
+    /// Real UDP sockets on the loopback interface
+    UdpLoopback,
+}
+
+/// What a bot does while it is connected
+#[derive(Clone, Copy)]
+pub struct BotBehaviour {
+    /// Input buffered by the bot every tick
+    pub input: fn(&mut StdRng) -> Option<MyInput>,
+    /// Called every frame to send messages to the server
+    pub messages: fn(&mut ClientMut, &mut StdRng),
+}
+
+impl BotBehaviour {
+    /// Bot that stays connected without sending any input or message
+    pub fn idle() -> Self {
+        Self {
+            input: |_| None,
+            messages: |_, _| {},
+        }
+    }
+
+    /// Bot that presses random inputs, and regularly sends messages of random sizes
+    /// (some of them big enough to be fragmented)
+    pub fn random() -> Self {
+        Self {
+            input: |rng| rng.gen_bool(0.5).then(|| MyInput(rng.gen_range(-1..=1))),
+            messages: |client, rng| {
+                if rng.gen_bool(0.1) {
+                    let length = rng.gen_range(0..2000);
+                    let _ = client.send_message::<Channel1, _>(Message1("a".repeat(length)));
+                }
+                if rng.gen_bool(0.5) {
+                    let _ = client.send_message::<Channel2, _>(Message2(rng.gen()));
+                }
+            },
+        }
+    }
+}
+
+/// Load test with many bot clients connected to one server.
+///
+/// The apps are stepped with a simulated clock (one `frame_duration` per frame), as fast as possible.
+pub struct LoadTest {
+    pub num_clients: usize,
+    pub transport: LoadTransport,
+    pub behaviour: BotBehaviour,
+    pub shared_config: SharedConfig,
+    pub frame_duration: Duration,
+    /// Number of frames simulated after the bots are connected
+    pub frames: usize,
+    pub seed: u64,
+}
+
+impl LoadTest {
+    pub fn new(num_clients: usize) -> Self {
+        Self {
+            num_clients,
+            transport: LoadTransport::LocalChannel,
+            behaviour: BotBehaviour::random(),
+            shared_config: SharedConfig {
+                tick: TickConfig::new(Duration::from_millis(10)),
+                log: LogConfig {
+                    level: Level::WARN,
+                    ..default()
+                },
+                ..default()
+            },
+            frame_duration: Duration::from_secs_f32(1.0 / 60.0),
+            frames: 600,
+            seed: 0,
+        }
+    }
+
+    pub fn with_transport(mut self, transport: LoadTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn with_behaviour(mut self, behaviour: BotBehaviour) -> Self {
+        self.behaviour = behaviour;
+        self
+    }
+
+    pub fn with_shared_config(mut self, shared_config: SharedConfig) -> Self {
+        self.shared_config = shared_config;
+        self
+    }
+
+    pub fn with_frames(mut self, frames: usize) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Connect the bots, simulate `frames` frames and return the measurements
+    pub fn run(&self) -> LoadReport {
+        let mut harness = LoadHarness::new(self);
+        harness.connect();
+        let bytes_sent = harness.server_io_stats().0;
+        let bytes_received = harness.server_io_stats().1;
+
+        let mut server_frame_times = Vec::with_capacity(self.frames);
+        for _ in 0..self.frames {
+            server_frame_times.push(harness.frame_step());
+        }
+
+        let simulated = self.frame_duration * self.frames as u32;
+        let (total_sent, total_received) = harness.server_io_stats();
+        let rtts: Vec<Duration> = harness
+            .clients
+            .iter()
+            .map(|app| app.world.resource::<ClientConnectionManager>().rtt())
+            .collect();
+        LoadReport {
+            num_clients: self.num_clients,
+            synced_clients: harness.synced_clients(),
+            frames: self.frames,
+            server_frame_time: DurationStats::new(server_frame_times),
+            server_bytes_sent_per_sec: (total_sent - bytes_sent) as f64 / simulated.as_secs_f64(),
+            server_bytes_received_per_sec: (total_received - bytes_received) as f64
+                / simulated.as_secs_f64(),
+            rtt: DurationStats::new(rtts),
+            rollbacks: harness
+                .clients
+                .iter()
+                .map(|app| app.world.resource::<Bot>().rollbacks)
+                .sum(),
+        }
+    }
+}
+
+/// Mean, 99th percentile and maximum of a set of durations
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DurationStats {
+    pub mean: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl DurationStats {
+    fn new(mut durations: Vec<Duration>) -> Self {
+        if durations.is_empty() {
+            return Self::default();
+        }
+        durations.sort();
+        let p99_index = (durations.len() * 99 / 100).min(durations.len() - 1);
+        Self {
+            mean: durations.iter().sum::<Duration>() / durations.len() as u32,
+            p99: durations[p99_index],
+            max: *durations.last().unwrap(),
+        }
+    }
+}
+
+/// Measurements of a [`LoadTest`]
+#[derive(Clone, Debug)]
+pub struct LoadReport {
+    pub num_clients: usize,
+    /// Number of bots that were synced with the server at the end of the test
+    pub synced_clients: usize,
+    pub frames: usize,
+    /// Wall-clock time spent running one frame of the server app
+    pub server_frame_time: DurationStats,
+    /// Bytes sent by the server per simulated second
+    pub server_bytes_sent_per_sec: f64,
+    /// Bytes received by the server per simulated second
+    pub server_bytes_received_per_sec: f64,
+    /// RTT estimated by the bots at the end of the test
+    pub rtt: DurationStats,
+    /// Total number of rollbacks done by the bots
+    pub rollbacks: usize,
+}
+
+impl Display for LoadReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "clients: {} ({} synced), frames: {}",
+            self.num_clients, self.synced_clients, self.frames
+        )?;
+        writeln!(
+            f,
+            "server frame time: mean {:?}, p99 {:?}, max {:?}",
+            self.server_frame_time.mean, self.server_frame_time.p99, self.server_frame_time.max
+        )?;
+        writeln!(
+            f,
+            "server bandwidth: {:.1} kB/s out, {:.1} kB/s in",
+            self.server_bytes_sent_per_sec / 1000.0,
+            self.server_bytes_received_per_sec / 1000.0
+        )?;
+        writeln!(
+            f,
+            "rtt: mean {:?}, p99 {:?}, max {:?}",
+            self.rtt.mean, self.rtt.p99, self.rtt.max
+        )?;
+        write!(f, "rollbacks: {}", self.rollbacks)
+    }
+}
+
+/// State of a bot client
+#[derive(Resource)]
+struct Bot {
+    rng: StdRng,
+    behaviour: BotBehaviour,
+    rollbacks: usize,
+}
+
+/// Marks the entity controlled by a bot on the server
+#[derive(Component)]
+struct BotEntity(ClientId);
+
+struct LoadHarness {
+    server: App,
+    clients: Vec<App>,
+    frame_duration: Duration,
+    current_time: Instant,
+}
+
+impl LoadHarness {
+    fn new(test: &LoadTest) -> Self {
+        let now = Instant::now();
+        let protocol_id = 0;
+        let private_key = generate_key();
+
+        let server_io = match test.transport {
+            LoadTransport::LocalChannel => None,
+            LoadTransport::UdpLoopback => Some(
+                IoConfig::from_transport(TransportConfig::UdpSocket(
+                    SocketAddr::from_str("127.0.0.1:0").unwrap(),
+                ))
+                .get_io(),
+            ),
+        };
+        // Local channels transport only works with server socket = LOCAL_SOCKET
+        let server_addr = server_io
+            .as_ref()
+            .map_or(LOCAL_SOCKET, |io| io.local_addr());
+
+        let mut channels = vec![];
+        let mut clients = vec![];
+        for i in 0..test.num_clients {
+            let client_id = i as ClientId;
+            let client_io = match test.transport {
+                LoadTransport::LocalChannel => {
+                    let addr = SocketAddr::from_str(&format!("127.0.0.1:{}", 1234 + i)).unwrap();
+                    let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
+                    let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
+                    channels.push((addr, to_server_recv, from_server_send));
+                    IoConfig::from_transport(TransportConfig::LocalChannel {
+                        recv: from_server_recv,
+                        send: to_server_send,
+                    })
+                    .get_io()
+                }
+                LoadTransport::UdpLoopback => IoConfig::from_transport(TransportConfig::UdpSocket(
+                    SocketAddr::from_str("127.0.0.1:0").unwrap(),
+                ))
+                .get_io(),
+            };
+            let config = ClientConfig {
+                shared: test.shared_config.clone(),
+                ..default()
+            };
+            // the apps are driven by a simulated clock, but the server checks the token expiry against
+            // the wall clock: use tokens that never expire so that slow runs with many bots can still connect
+            let token = ConnectToken::build(server_addr, protocol_id, client_id, private_key)
+                .timeout_seconds(config.netcode.client_timeout_secs)
+                .expire_seconds(-1)
+                .generate()
+                .unwrap();
+            let auth = Authentication::Token(token);
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+                .add_plugins(client::ClientPlugin::new(client::PluginConfig::new(
+                    config,
+                    client_io,
+                    protocol(),
+                    auth,
+                )))
+                .insert_resource(Bot {
+                    rng: StdRng::seed_from_u64(test.seed.wrapping_add(client_id)),
+                    behaviour: test.behaviour,
+                    rollbacks: 0,
+                })
+                .add_systems(
+                    FixedUpdate,
+                    bot_input
+                        .in_set(InputSystemSet::BufferInputs)
+                        .run_if(client_is_synced::<MyProtocol>),
+                )
+                .add_systems(Update, bot_messages.run_if(client_is_synced::<MyProtocol>))
+                .add_systems(PreUpdate, count_rollbacks.in_set(PredictionSet::Rollback));
+            app.world
+                .resource_mut::<Time<Real>>()
+                .update_with_instant(now);
+            clients.push(app);
+        }
+
+        let server_io = server_io.unwrap_or_else(|| {
+            IoConfig::from_transport(TransportConfig::Channels { channels }).get_io()
+        });
+        let config = ServerConfig {
+            shared: test.shared_config.clone(),
+            netcode: NetcodeConfig::default()
+                .with_protocol_id(protocol_id)
+                .with_key(private_key)
+                .with_max_clients(test.num_clients)
+                .with_rate_limit(bot_rate_limit(test.num_clients)),
+            ..default()
+        };
+        let mut server = App::new();
+        server
+            .add_plugins(MinimalPlugins)
+            .add_plugins(server::ServerPlugin::new(server::PluginConfig::new(
+                config,
+                server_io,
+                protocol(),
+            )))
+            .add_systems(Update, spawn_bot_entities)
+            .add_systems(FixedUpdate, apply_bot_inputs.in_set(FixedUpdateSet::Main));
+        server
+            .world
+            .resource_mut::<Time<Real>>()
+            .update_with_instant(now);
+
+        Self {
+            server,
+            clients,
+            frame_duration: test.frame_duration,
+            current_time: now,
+        }
+    }
+
+    /// Connect all the bots and step until they are synced.
+    ///
+    /// Panics if some bots are still not synced after 1000 frames, so that we don't report measurements
+    /// for a swarm that never connected.
+    fn connect(&mut self) {
+        for client in self.clients.iter_mut() {
+            client.world.resource_mut::<NetClient>().connect();
+        }
+        for _ in 0..1000 {
+            if self.synced_clients() == self.clients.len() {
+                return;
+            }
+            self.frame_step();
+        }
+        let synced_clients = self.synced_clients();
+        assert_eq!(
+            synced_clients,
+            self.clients.len(),
+            "only {synced_clients}/{} bots are synced after 1000 frames",
+            self.clients.len()
+        );
+    }
+
+    /// Number of bots that are synced with the server
+    fn synced_clients(&self) -> usize {
+        self.clients
+            .iter()
+            .filter(|app| app.world.resource::<ClientConnectionManager>().is_synced())
+            .count()
+    }
+
+    /// Step all the apps by one frame, and return the time spent running the server
+    fn frame_step(&mut self) -> Duration {
+        self.current_time += self.frame_duration;
+        self.server
+            .insert_resource(TimeUpdateStrategy::ManualInstant(self.current_time));
+        let start = Instant::now();
+        self.server.update();
+        let server_frame_time = start.elapsed();
+        for client in self.clients.iter_mut() {
+            client.insert_resource(TimeUpdateStrategy::ManualInstant(self.current_time));
+            client.update();
+        }
+        server_frame_time
+    }
+
+    /// Total number of bytes sent and received by the server
+    fn server_io_stats(&self) -> (usize, usize) {
+        let stats = self.server.world.resource::<Io>().stats();
+        (stats.bytes_sent, stats.bytes_received)
+    }
+}
+
+/// All the bots connect from the same ip address, so the per-address limits of the server
+/// have to grow with the number of bots
+fn bot_rate_limit(num_clients: usize) -> RateLimitConfig {
+    let default = RateLimitConfig::default();
+    let num_clients = num_clients.max(1);
+    RateLimitConfig::default()
+        .with_max_packets_per_second(default.max_packets_per_second * num_clients as u32)
+        .with_max_connection_requests_per_second(
+            default.max_connection_requests_per_second * num_clients as u32,
+        )
+        .with_max_pending_connections(default.max_pending_connections.max(num_clients))
+        // the bots resend their connection requests every 0.1 seconds
+        .with_load_threshold(default.load_threshold.max(10 * num_clients as u32))
+}
+
+fn bot_input(mut client: ClientMut, mut bot: ResMut<Bot>) {
+    let bot = bot.as_mut();
+    if let Some(input) = (bot.behaviour.input)(&mut bot.rng) {
+        client.add_input(input);
+    }
+}
+
+fn bot_messages(mut client: ClientMut, mut bot: ResMut<Bot>) {
+    let bot = bot.as_mut();
+    (bot.behaviour.messages)(&mut client, &mut bot.rng);
+}
+
+fn count_rollbacks(mut bot: ResMut<Bot>) {
+    bot.rollbacks += 1;
+}
+
+/// Spawn one entity per bot, predicted by that bot and interpolated by the others
+fn spawn_bot_entities(
+    mut commands: bevy::prelude::Commands,
+    mut connections: EventReader<server::ConnectEvent>,
+) {
+    for connection in connections.read() {
+        let client_id = *connection.context();
+        commands.spawn((
+            Component1(0.0),
+            BotEntity(client_id),
+            Replicate {
+                prediction_target: NetworkTarget::Only(vec![client_id]),
+                interpolation_target: NetworkTarget::AllExceptSingle(client_id),
+                ..default()
+            },
+        ));
+    }
+}
+
+fn apply_bot_inputs(
+    mut inputs: EventReader<server::InputEvent<MyInput>>,
+    mut entities: Query<(&BotEntity, &mut Component1)>,
+) {
+    let mut moves: HashMap<ClientId, f32> = HashMap::default();
+    for input in inputs.read() {
+        if let Some(MyInput(value)) = input.input() {
+            *moves.entry(*input.context()).or_default() += *value as f32;
+        }
+    }
+    for (bot, mut component) in entities.iter_mut() {
+        if let Some(value) = moves.get(&bot.0) {
+            component.0 += value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_smoke() {
+        // more bots than the default per-address limits of the server allow
+        let num_clients = 80;
+        let report = LoadTest::new(num_clients).with_frames(20).run();
+        assert_eq!(report.num_clients, num_clients);
+        assert_eq!(report.synced_clients, num_clients);
+        assert_eq!(report.frames, 20);
+        assert!(report.server_bytes_sent_per_sec > 0.0);
+        assert!(report.server_bytes_received_per_sec > 0.0);
+    }
+}
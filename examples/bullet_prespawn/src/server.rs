@@ -46,6 +46,7 @@ impl Plugin for MyServerPlugin {
             shared: shared_config().clone(),
             netcode: netcode_config,
             ping: PingConfig::default(),
+            ..Default::default()
         };
         let plugin_config = PluginConfig::new(config, io, protocol());
         app.add_plugins(server::ServerPlugin::new(plugin_config));
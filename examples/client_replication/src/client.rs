@@ -277,11 +277,11 @@ pub(crate) fn send_message(
         let message = Message1(5);
         info!("Send message: {:?}", message);
         // the message will be re-broadcasted by the server to all clients
-        client
-            .send_message_to_target::<Channel1, Message1>(Message1(5), NetworkTarget::All)
-            .unwrap_or_else(|e| {
-                error!("Failed to send message: {:?}", e);
-            });
+        if let Err(e) =
+            client.send_message_to_target::<Channel1, Message1>(Message1(5), NetworkTarget::All)
+        {
+            error!("Failed to send message: {:?}", e);
+        }
     }
 }
 
@@ -142,6 +142,9 @@ pub enum Components {
     // The default is `InstantCorrector` which just snaps to the corrected value
     // You can also use `InterpolatedCorrector` which will re-use your interpolation function to
     // interpolate smoothly from the previously predicted value to the newly corrected value
+    // For components that implement `Add<Self>` and `Mul<f32>`, `ExponentialCorrector` and `SpringCorrector`
+    // smooth the correction on top of the predicted value, which avoids rubber-banding on fast entities.
+    // You can also implement `CorrectionFn` yourself.
     #[sync(
         full,
         lerp = "PositionLinearInterpolation",
@@ -72,6 +72,7 @@ impl BevyStepper {
             shared: shared_config.clone(),
             netcode: netcode_config,
             ping: PingConfig::default(),
+            ..Default::default()
         };
         let plugin_config = server::PluginConfig::new(config, io, protocol());
         let plugin = server::ServerPlugin::new(plugin_config);
@@ -3,6 +3,7 @@ use bevy::utils::Duration;
 
 use lightyear_macros::ChannelInternal;
 
+use crate::channel::receivers::blob::BlobReceiver;
 use crate::channel::receivers::ordered_reliable::OrderedReliableReceiver;
 use crate::channel::receivers::sequenced_reliable::SequencedReliableReceiver;
 use crate::channel::receivers::sequenced_unreliable::SequencedUnreliableReceiver;
@@ -10,6 +11,7 @@ use crate::channel::receivers::tick_unreliable::TickUnreliableReceiver;
 use crate::channel::receivers::unordered_reliable::UnorderedReliableReceiver;
 use crate::channel::receivers::unordered_unreliable::UnorderedUnreliableReceiver;
 use crate::channel::receivers::ChannelReceiver;
+use crate::channel::senders::blob::BlobSender;
 use crate::channel::senders::reliable::ReliableSender;
 use crate::channel::senders::sequenced_unreliable::SequencedUnreliableSender;
 use crate::channel::senders::tick_unreliable::TickUnreliableSender;
@@ -75,16 +77,26 @@ impl ChannelContainer {
             }
             ChannelMode::SequencedReliable(reliable_settings) => {
                 receiver = SequencedReliableReceiver::new().into();
-                sender = ReliableSender::new(reliable_settings).into();
+                sender = ReliableSender::with_streams(reliable_settings).into();
             }
             ChannelMode::OrderedReliable(reliable_settings) => {
                 receiver = OrderedReliableReceiver::new().into();
+                sender = ReliableSender::with_streams(reliable_settings).into();
+            }
+            ChannelMode::Scheduled(reliable_settings) => {
+                receiver = UnorderedReliableReceiver::new().into();
                 sender = ReliableSender::new(reliable_settings).into();
             }
             ChannelMode::TickBuffered => {
                 receiver = TickUnreliableReceiver::new().into();
                 sender = TickUnreliableSender::new().into();
             }
+            ChannelMode::Blob(blob_settings) => {
+                receiver =
+                    BlobReceiver::new(blob_settings.max_blob_size, blob_settings.max_partial_blobs)
+                        .into();
+                sender = Box::new(BlobSender::new(blob_settings)).into();
+            }
         }
         Self {
             setting: settings_clone,
@@ -118,12 +130,27 @@ pub enum ChannelMode {
     /// will arrive
     UnorderedReliable(ReliableSettings),
     /// Same as unordered reliable, but the messages are sequenced (only the newest message is accepted)
+    ///
+    /// The messages are only sequenced relative to the other messages of the same [`StreamId`](crate::prelude::StreamId)
     SequencedReliable(ReliableSettings),
     /// Messages will arrive in the correct order at the destination
+    ///
+    /// The messages are only ordered relative to the other messages of the same [`StreamId`](crate::prelude::StreamId),
+    /// so that a lost message does not block the messages of the other streams
     OrderedReliable(ReliableSettings),
     /// Inputs from the client are associated with the current tick on the client.
     /// The server will buffer them and only receive them on the same tick.
     TickBuffered,
+    /// Messages sent by the server ahead of time for a given tick
+    /// (see [`send_scheduled_message`](crate::server::connection::ConnectionManager::send_scheduled_message)).
+    /// They are delivered reliably, and the client emits them as [`ScheduledEvent`](crate::client::scheduled::ScheduledEvent)s
+    /// when its timeline reaches that tick.
+    Scheduled(ReliableSettings),
+    /// Large payloads (several MB) are split into chunks that are streamed reliably,
+    /// with a limited number of chunks in flight and a bandwidth cap so that the other channels
+    /// are not starved.
+    /// The payloads are received as [`BlobReceivedEvent`](crate::shared::events::BlobReceivedEvent)s.
+    Blob(BlobSettings),
 }
 
 impl ChannelMode {
@@ -136,6 +163,20 @@ impl ChannelMode {
             ChannelMode::SequencedReliable(_) => true,
             ChannelMode::OrderedReliable(_) => true,
             ChannelMode::TickBuffered => false,
+            ChannelMode::Scheduled(_) => true,
+            ChannelMode::Blob(_) => true,
+        }
+    }
+
+    /// Duration after which an unacked message is not resent anymore
+    /// (see [`ReliableSettings::message_ttl`])
+    pub(crate) fn message_ttl(&self) -> Option<Duration> {
+        match self {
+            ChannelMode::UnorderedReliable(settings)
+            | ChannelMode::SequencedReliable(settings)
+            | ChannelMode::OrderedReliable(settings)
+            | ChannelMode::Scheduled(settings) => settings.message_ttl,
+            _ => None,
         }
     }
 
@@ -149,6 +190,8 @@ impl ChannelMode {
             ChannelMode::SequencedReliable(_) => true,
             ChannelMode::OrderedReliable(_) => true,
             ChannelMode::TickBuffered => false,
+            ChannelMode::Scheduled(_) => true,
+            ChannelMode::Blob(_) => true,
         }
     }
 }
@@ -167,6 +210,13 @@ pub struct ReliableSettings {
     pub rtt_resend_factor: f32,
     /// Minimum duration to wait before resending a packet if it has not been acked
     pub rtt_resend_min_delay: Duration,
+    /// If set, the sender stops resending a message that has not been acked after this duration.
+    ///
+    /// The receiver is notified that the message was skipped (so that ordered channels don't wait for it),
+    /// and a [`MessageExpiredEvent`](crate::shared::events::MessageExpiredEvent) is emitted on the sender.
+    /// Messages sent on a channel with a TTL cannot serialize to zero bytes.
+    /// This is ignored for [`ChannelMode::Blob`] channels.
+    pub message_ttl: Option<Duration>,
 }
 
 impl Default for ReliableSettings {
@@ -174,6 +224,7 @@ impl Default for ReliableSettings {
         Self {
             rtt_resend_factor: 1.5,
             rtt_resend_min_delay: Duration::default(),
+            message_ttl: None,
         }
     }
 }
@@ -185,6 +236,33 @@ impl ReliableSettings {
     }
 }
 
+#[derive(Clone, PartialEq, Debug)]
+pub struct BlobSettings {
+    /// Settings used to resend the chunks that have not been acked
+    pub reliable_settings: ReliableSettings,
+    /// Maximum number of chunks that can be sent but not acked yet
+    pub window_size: usize,
+    /// Maximum number of bytes per second that the channel can send (including resends)
+    pub bandwidth: usize,
+    /// Maximum size of a blob that we accept to receive, in bytes
+    pub max_blob_size: usize,
+    /// Maximum number of blobs that can be partially received at the same time.
+    /// If more blobs are started, the oldest partial blobs are dropped
+    pub max_partial_blobs: usize,
+}
+
+impl Default for BlobSettings {
+    fn default() -> Self {
+        Self {
+            reliable_settings: ReliableSettings::default(),
+            window_size: 256,
+            bandwidth: 256 * 1024,
+            max_blob_size: 64 * 1024 * 1024,
+            max_partial_blobs: 16,
+        }
+    }
+}
+
 /// Default channel to replicate entity actions.
 /// This is an Unordered Reliable channel.
 /// (SpawnEntity, DespawnEntity, InsertComponent, RemoveComponent)
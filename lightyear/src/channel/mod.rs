@@ -3,3 +3,4 @@
 pub mod builder;
 pub(crate) mod receivers;
 pub(crate) mod senders;
+pub(crate) mod stream;
@@ -0,0 +1,470 @@
+use std::collections::{btree_map, hash_map, BTreeMap, HashMap, HashSet, VecDeque};
+
+use anyhow::{anyhow, bail};
+use bytes::{Buf, Bytes, BytesMut};
+use tracing::warn;
+
+use crate::channel::receivers::ChannelReceive;
+use crate::channel::senders::blob::{
+    num_chunks, BlobId, BlobProgress, BLOB_CHUNK_SIZE, BLOB_HEADER_BYTES,
+};
+use crate::packet::message::{MessageContainer, SingleData};
+use crate::shared::tick_manager::TickManager;
+use crate::shared::time_manager::TimeManager;
+
+/// Maximum number of blobs that can be received after the oldest blob that we haven't received yet.
+/// Past this limit, the missing blobs are considered lost
+const MAX_RECEIVED_BLOB_IDS: usize = 1024;
+
+/// A blob for which we haven't received all the chunks yet
+struct PartialBlob {
+    total_bytes: usize,
+    /// Chunks received so far (we only store what we received, so that a remote peer
+    /// cannot make us allocate a huge blob by sending a single chunk)
+    chunks: BTreeMap<usize, Bytes>,
+    received_bytes: usize,
+    /// Order in which the blobs were started, used to drop the oldest ones
+    start_order: u64,
+}
+
+/// Receiver that re-assembles the blobs sent by a [`BlobSender`](crate::channel::senders::blob::BlobSender).
+///
+/// Blobs are returned as soon as all their chunks have been received
+pub struct BlobReceiver {
+    /// Maximum size of a blob that we accept to receive
+    max_blob_size: usize,
+    /// Maximum number of blobs that can be partially received at the same time
+    max_partial_blobs: usize,
+    partial_blobs: HashMap<BlobId, PartialBlob>,
+    /// Number of partial blobs that were started so far
+    num_started_blobs: u64,
+    /// Oldest blob id that we haven't received yet.
+    /// The sender uses consecutive blob ids, so all the blobs before this one have been received
+    pending_recv_blob_id: BlobId,
+    /// Blobs that were received after `pending_recv_blob_id`, so that we can ignore chunks that were resent
+    received_blob_ids: HashSet<BlobId>,
+    /// Blobs that were fully received but haven't been read yet
+    recv_blobs: VecDeque<SingleData>,
+}
+
+impl BlobReceiver {
+    pub(crate) fn new(max_blob_size: usize, max_partial_blobs: usize) -> Self {
+        Self {
+            max_blob_size,
+            max_partial_blobs,
+            partial_blobs: HashMap::new(),
+            num_started_blobs: 0,
+            pending_recv_blob_id: 0,
+            received_blob_ids: HashSet::new(),
+            recv_blobs: VecDeque::new(),
+        }
+    }
+
+    /// Progress of the blobs that are being received
+    pub(crate) fn progress(&self) -> Vec<BlobProgress> {
+        self.partial_blobs
+            .iter()
+            .map(|(blob_id, blob)| BlobProgress {
+                blob_id: *blob_id,
+                transferred_bytes: blob.received_bytes,
+                total_bytes: blob.total_bytes,
+            })
+            .collect()
+    }
+
+    /// Returns true if the blob was already received
+    fn is_received(&self, blob_id: BlobId) -> bool {
+        // the blob is older than the pending blob id (with wrapping)
+        blob_id.wrapping_sub(self.pending_recv_blob_id) > BlobId::MAX / 2
+            || self.received_blob_ids.contains(&blob_id)
+    }
+
+    /// Drop the oldest partial blobs until a new blob can be started.
+    /// The dropped blobs are considered received, so that their resent chunks are ignored
+    fn drop_oldest_partial_blobs(&mut self) {
+        while self.partial_blobs.len() >= self.max_partial_blobs.max(1) {
+            let Some(oldest) = self
+                .partial_blobs
+                .iter()
+                .min_by_key(|(_, blob)| blob.start_order)
+                .map(|(blob_id, _)| *blob_id)
+            else {
+                return;
+            };
+            warn!(
+                blob_id = oldest,
+                "Too many partial blobs, dropping the oldest one"
+            );
+            self.partial_blobs.remove(&oldest);
+            self.mark_received(oldest);
+        }
+    }
+
+    fn mark_received(&mut self, blob_id: BlobId) {
+        self.received_blob_ids.insert(blob_id);
+        self.skip_received_blob_ids();
+        // a peer that never completes the pending blob would make the set grow without bound:
+        // give up on the missing blobs once too many blobs were received after it
+        if self.received_blob_ids.len() > MAX_RECEIVED_BLOB_IDS {
+            let pending = self.pending_recv_blob_id;
+            let Some(oldest) = self
+                .received_blob_ids
+                .iter()
+                .copied()
+                .min_by_key(|id| id.wrapping_sub(pending))
+            else {
+                return;
+            };
+            warn!(
+                pending_blob_id = pending,
+                blob_id = oldest,
+                "Too many blobs received out of order, skipping the missing ones"
+            );
+            let distance = oldest.wrapping_sub(pending);
+            self.partial_blobs
+                .retain(|id, _| id.wrapping_sub(pending) > distance);
+            self.pending_recv_blob_id = oldest;
+            self.skip_received_blob_ids();
+        }
+    }
+
+    /// Skip through all the blob ids we have already received out of order
+    fn skip_received_blob_ids(&mut self) {
+        while self.received_blob_ids.remove(&self.pending_recv_blob_id) {
+            self.pending_recv_blob_id = self.pending_recv_blob_id.wrapping_add(1);
+        }
+    }
+}
+
+impl ChannelReceive for BlobReceiver {
+    fn update(&mut self, _: &TimeManager, _: &TickManager) {}
+
+    fn buffer_recv(&mut self, message: MessageContainer) -> anyhow::Result<()> {
+        let MessageContainer::Single(data) = message else {
+            bail!("blob chunks cannot be fragmented");
+        };
+        let mut bytes = data.bytes;
+        if bytes.len() < BLOB_HEADER_BYTES {
+            bail!("blob chunk is too short");
+        }
+        let blob_id = bytes.get_u32_le();
+        let chunk_index = bytes.get_u32_le() as usize;
+        let total_bytes = bytes.get_u32_le() as usize;
+
+        // the chunk was resent but we already received the blob
+        if self.is_received(blob_id) {
+            return Ok(());
+        }
+        if total_bytes > self.max_blob_size {
+            bail!(
+                "blob of {} bytes exceeds the maximum blob size of {} bytes",
+                total_bytes,
+                self.max_blob_size
+            );
+        }
+        let num_chunks = num_chunks(total_bytes);
+        if chunk_index >= num_chunks {
+            bail!("invalid blob chunk index");
+        }
+        let expected_len = if chunk_index + 1 == num_chunks {
+            total_bytes - chunk_index * BLOB_CHUNK_SIZE
+        } else {
+            BLOB_CHUNK_SIZE
+        };
+        if bytes.len() != expected_len {
+            bail!("invalid blob chunk length");
+        }
+
+        if !self.partial_blobs.contains_key(&blob_id) {
+            self.drop_oldest_partial_blobs();
+            // dropping a blob can make us skip the missing blobs, including this one
+            if self.is_received(blob_id) {
+                return Ok(());
+            }
+        }
+        let blob = match self.partial_blobs.entry(blob_id) {
+            hash_map::Entry::Occupied(blob) => blob.into_mut(),
+            hash_map::Entry::Vacant(blob) => {
+                self.num_started_blobs += 1;
+                blob.insert(PartialBlob {
+                    total_bytes,
+                    chunks: BTreeMap::new(),
+                    received_bytes: 0,
+                    start_order: self.num_started_blobs,
+                })
+            }
+        };
+        if blob.total_bytes != total_bytes {
+            return Err(anyhow!("blob chunks have different sizes"));
+        }
+        let btree_map::Entry::Vacant(chunk) = blob.chunks.entry(chunk_index) else {
+            return Ok(());
+        };
+        blob.received_bytes += bytes.len();
+        chunk.insert(bytes);
+
+        if blob.chunks.len() == num_chunks {
+            let blob = self.partial_blobs.remove(&blob_id).unwrap();
+            let mut buffer = BytesMut::with_capacity(blob.total_bytes);
+            blob.chunks
+                .into_values()
+                .for_each(|chunk| buffer.extend_from_slice(&chunk));
+            self.mark_received(blob_id);
+            self.recv_blobs
+                .push_back(SingleData::new(None, buffer.freeze()));
+        }
+        Ok(())
+    }
+
+    fn read_message(&mut self) -> Option<SingleData> {
+        self.recv_blobs.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::ManualEventReader;
+    use bevy::prelude::Events;
+    use bevy::utils::Duration;
+    use bytes::BufMut;
+
+    use crate::channel::builder::BlobSettings;
+    use crate::channel::senders::blob::BlobSender;
+    use crate::channel::senders::ChannelSend;
+    use crate::packet::message::{MessageAck, MessageId};
+    use crate::shared::ping::manager::{PingConfig, PingManager};
+    use crate::shared::tick_manager::TickConfig;
+
+    use crate::prelude::client::{InterpolationConfig, PredictionConfig, SyncConfig};
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_blob_lossy_transfer() {
+        let mut sender = BlobSender::new(BlobSettings {
+            bandwidth: usize::MAX,
+            ..Default::default()
+        });
+        let mut receiver = BlobReceiver::new(
+            BlobSettings::default().max_blob_size,
+            BlobSettings::default().max_partial_blobs,
+        );
+
+        let mut blob = BytesMut::new();
+        for i in 0..(5 * BLOB_CHUNK_SIZE as u32) {
+            blob.put_u8(i as u8);
+        }
+        let blob = blob.freeze();
+        sender.send_blob(blob.clone());
+        sender.send_blob(Bytes::new());
+
+        // every other chunk is lost
+        let mut lost = Vec::new();
+        let mut time_manager = TimeManager::new(Duration::default());
+        time_manager.update(Duration::from_secs(1));
+        sender.update(
+            &time_manager,
+            &PingManager::new(&PingConfig::default()),
+            &TickManager::from_config(TickConfig::new(Duration::from_millis(10))),
+        );
+        sender.collect_messages_to_send();
+        let (chunks, _) = sender.send_packet();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            if i % 2 == 0 {
+                lost.push(chunk);
+                continue;
+            }
+            sender.notify_message_delivered(&MessageAck {
+                message_id: chunk.id.unwrap(),
+                fragment_id: None,
+            });
+            receiver
+                .buffer_recv(MessageContainer::Single(chunk))
+                .unwrap();
+        }
+        // the empty blob only has one chunk, which was received
+        assert_eq!(receiver.read_message().unwrap().bytes, Bytes::new());
+        assert!(receiver.read_message().is_none());
+        let progress = receiver.progress();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].transferred_bytes, 2 * BLOB_CHUNK_SIZE);
+
+        // the empty blob was received out of order
+        assert_eq!(receiver.received_blob_ids, HashSet::from([1]));
+
+        // the lost chunks are received later
+        let resent = lost[0].clone();
+        for chunk in lost {
+            receiver
+                .buffer_recv(MessageContainer::Single(chunk))
+                .unwrap();
+        }
+        assert_eq!(receiver.read_message().unwrap().bytes, blob);
+        assert!(receiver.progress().is_empty());
+        // all the blobs were received: we don't need to keep track of their ids anymore
+        assert_eq!(receiver.pending_recv_blob_id, 2);
+        assert!(receiver.received_blob_ids.is_empty());
+
+        // a chunk that is resent is ignored
+        receiver
+            .buffer_recv(MessageContainer::Single(resent))
+            .unwrap();
+        assert!(receiver.read_message().is_none());
+        assert!(receiver.progress().is_empty());
+    }
+
+    #[test]
+    fn test_blob_channel_with_packet_loss() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: false,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.1,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = *stepper
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>()
+            .connections
+            .keys()
+            .next()
+            .unwrap();
+        let blob: Bytes = (0..100_000u32).map(|i| i as u8).collect();
+        let blob_id = stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .send_blob::<BlobChannel>(client_id, blob.clone())
+            .unwrap();
+
+        let mut reader = ManualEventReader::<client::BlobReceivedEvent>::default();
+        let mut received = vec![];
+        let mut progress = vec![];
+        for _ in 0..200 {
+            stepper.frame_step();
+            if let Some(p) = stepper
+                .client_app
+                .world
+                .resource::<ClientConnectionManager>()
+                .incoming_blobs::<BlobChannel>()
+                .unwrap()
+                .first()
+            {
+                progress.push(p.fraction());
+            }
+            received.extend(
+                reader
+                    .read(
+                        stepper
+                            .client_app
+                            .world
+                            .resource::<Events<client::BlobReceivedEvent>>(),
+                    )
+                    .map(|event| event.bytes().clone()),
+            );
+        }
+        assert_eq!(received, vec![blob]);
+        // the blob was streamed over multiple frames
+        assert!(progress.len() > 1);
+        assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+        // the server knows that the blob was fully received
+        assert!(stepper
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>()
+            .outgoing_blobs::<BlobChannel>(client_id)
+            .unwrap()
+            .iter()
+            .all(|p| p.blob_id != blob_id));
+    }
+
+    #[test]
+    fn test_blob_receiver_rejects_large_blobs() {
+        let mut receiver = BlobReceiver::new(10, 1);
+        let mut chunk = BytesMut::new();
+        crate::channel::senders::blob::encode_chunk_header(&mut chunk, 0, 0, 11);
+        chunk.put_slice(&[0; 11]);
+        assert!(receiver
+            .buffer_recv(MessageContainer::Single(SingleData::new(
+                Some(MessageId(0)),
+                chunk.freeze()
+            )))
+            .is_err());
+    }
+
+    #[test]
+    fn test_blob_receiver_drops_oldest_partial_blobs() {
+        let mut receiver = BlobReceiver::new(BlobSettings::default().max_blob_size, 2);
+        let first_chunk = |blob_id: BlobId| {
+            let mut chunk = BytesMut::new();
+            crate::channel::senders::blob::encode_chunk_header(
+                &mut chunk,
+                blob_id,
+                0,
+                2 * BLOB_CHUNK_SIZE as u32,
+            );
+            chunk.put_slice(&[0; BLOB_CHUNK_SIZE]);
+            MessageContainer::Single(SingleData::new(None, chunk.freeze()))
+        };
+        for blob_id in [3, 1, 2] {
+            receiver.buffer_recv(first_chunk(blob_id)).unwrap();
+        }
+        // only 2 blobs can be partially received at the same time: the oldest one was dropped
+        let mut partial_blobs: Vec<BlobId> =
+            receiver.progress().iter().map(|p| p.blob_id).collect();
+        partial_blobs.sort();
+        assert_eq!(partial_blobs, vec![1, 2]);
+
+        // the chunks of the dropped blob are ignored
+        receiver.buffer_recv(first_chunk(3)).unwrap();
+        assert_eq!(receiver.progress().len(), 2);
+        assert!(!receiver.partial_blobs.contains_key(&3));
+    }
+
+    #[test]
+    fn test_blob_receiver_bounds_out_of_order_blobs() {
+        let mut receiver = BlobReceiver::new(BlobSettings::default().max_blob_size, 2);
+        let blob = |blob_id: BlobId| {
+            let mut chunk = BytesMut::new();
+            crate::channel::senders::blob::encode_chunk_header(&mut chunk, blob_id, 0, 1);
+            chunk.put_u8(0);
+            MessageContainer::Single(SingleData::new(None, chunk.freeze()))
+        };
+        // the blob 0 is never received
+        for blob_id in 1..=(MAX_RECEIVED_BLOB_IDS as BlobId + 1) {
+            receiver.buffer_recv(blob(blob_id)).unwrap();
+            assert!(receiver.received_blob_ids.len() <= MAX_RECEIVED_BLOB_IDS);
+        }
+        // we gave up on the blob 0 and all the received blobs were skipped through
+        assert_eq!(
+            receiver.pending_recv_blob_id,
+            MAX_RECEIVED_BLOB_IDS as BlobId + 2
+        );
+        assert!(receiver.received_blob_ids.is_empty());
+        receiver.buffer_recv(blob(0)).unwrap();
+        let mut num_blobs = 0;
+        while receiver.read_message().is_some() {
+            num_blobs += 1;
+        }
+        assert_eq!(num_blobs, MAX_RECEIVED_BLOB_IDS + 1);
+    }
+}
@@ -1,22 +1,39 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use anyhow::Result;
-use bytes::Bytes;
+use anyhow::{bail, Result};
+use bytes::{Bytes, BytesMut};
 use tracing::trace;
 
+use crate::packet::compression::Compressor;
 use crate::packet::message::{FragmentData, MessageId, SingleData};
 use crate::packet::packet::FRAGMENT_SIZE;
 use crate::shared::time_manager::WrappedTime;
 
+/// Maximum number of fragmented messages that can be partially received at the same time on a channel.
+/// This bounds the memory that a remote peer can make us allocate by sending the first fragments of many messages
+pub(crate) const MAX_PARTIAL_MESSAGES: usize = 512;
+
 /// `FragmentReceiver` is used to reconstruct fragmented messages
 pub struct FragmentReceiver {
     fragment_messages: HashMap<MessageId, FragmentConstructor>,
+    /// Used to decompress the messages that were compressed by the sender before being fragmented
+    /// (only for reliable channels)
+    pub(crate) compressor: Option<Compressor>,
 }
 
 impl FragmentReceiver {
     pub fn new() -> Self {
         Self {
             fragment_messages: HashMap::new(),
+            compressor: None,
+        }
+    }
+
+    /// Receiver for messages that can be compressed before being fragmented
+    pub fn with_compression() -> Self {
+        Self {
+            fragment_messages: HashMap::new(),
+            compressor: Some(Compressor::default()),
         }
     }
 
@@ -32,23 +49,54 @@ impl FragmentReceiver {
         })
     }
 
+    /// Discard the fragments received for a message
+    /// (for example because the sender gave up on it and sent a skip notification instead)
+    pub fn discard(&mut self, message_id: MessageId) {
+        self.fragment_messages.remove(&message_id);
+    }
+
     pub fn receive_fragment(
         &mut self,
         fragment: FragmentData,
         current_time: Option<WrappedTime>,
     ) -> Result<Option<SingleData>> {
+        if !self.fragment_messages.contains_key(&fragment.message_id)
+            && self.fragment_messages.len() >= MAX_PARTIAL_MESSAGES
+        {
+            bail!(
+                "too many fragmented messages are being received (maximum {})",
+                MAX_PARTIAL_MESSAGES
+            );
+        }
         let fragment_message = self
             .fragment_messages
             .entry(fragment.message_id)
-            .or_insert_with(|| FragmentConstructor::new(fragment.num_fragments as usize));
+            .or_insert_with(|| {
+                FragmentConstructor::new(fragment.num_fragments as usize, fragment.compressed)
+            });
+        if fragment_message.num_fragments != fragment.num_fragments as usize
+            || fragment_message.compressed != fragment.compressed
+        {
+            bail!(
+                "fragments of the same message have a different number of fragments or compression"
+            );
+        }
+        let compressed = fragment_message.compressed;
 
         // completed the fragmented message!
         if let Some(payload) = fragment_message.receive_fragment(
             fragment.fragment_id as usize,
-            fragment.bytes.as_ref(),
+            fragment.bytes,
             current_time,
         )? {
             self.fragment_messages.remove(&fragment.message_id);
+            let payload = match (compressed, &mut self.compressor) {
+                (false, _) => payload,
+                (true, Some(compressor)) => compressor.decompress_message(payload.as_ref())?.into(),
+                (true, None) => bail!(
+                    "received a compressed message on a channel that does not support compression"
+                ),
+            };
             let mut data = SingleData::new(Some(fragment.message_id), payload);
             // TODO: verify that all fragments had the same tick
             data.tick = fragment.tick;
@@ -63,21 +111,21 @@ impl FragmentReceiver {
 /// Data structure to reconstruct a single fragmented message from individual fragments
 pub struct FragmentConstructor {
     num_fragments: usize,
-    num_received_fragments: usize,
-    received: Vec<bool>,
-    // bytes: Bytes,
-    bytes: Vec<u8>,
+    /// True if the message was compressed before being fragmented
+    compressed: bool,
+    /// Fragments received so far (we only store what we received, so that a remote peer
+    /// cannot make us allocate the full message by sending a single fragment)
+    fragments: BTreeMap<usize, Bytes>,
 
     last_received: Option<WrappedTime>,
 }
 
 impl FragmentConstructor {
-    pub fn new(num_fragments: usize) -> Self {
+    pub fn new(num_fragments: usize, compressed: bool) -> Self {
         Self {
             num_fragments,
-            num_received_fragments: 0,
-            received: vec![false; num_fragments],
-            bytes: vec![0; num_fragments * FRAGMENT_SIZE],
+            compressed,
+            fragments: BTreeMap::new(),
             last_received: None,
         }
     }
@@ -85,32 +133,31 @@ impl FragmentConstructor {
     pub fn receive_fragment(
         &mut self,
         fragment_index: usize,
-        bytes: &[u8],
+        bytes: Bytes,
         received_time: Option<WrappedTime>,
     ) -> Result<Option<Bytes>> {
-        self.last_received = received_time;
-
+        if fragment_index >= self.num_fragments {
+            bail!(
+                "invalid fragment index {} for a message with {} fragments",
+                fragment_index,
+                self.num_fragments
+            );
+        }
         let is_last_fragment = fragment_index == self.num_fragments - 1;
-        // TODO: check sizes?
-
-        if !self.received[fragment_index] {
-            self.received[fragment_index] = true;
-            self.num_received_fragments += 1;
-
-            if is_last_fragment {
-                let len = (self.num_fragments - 1) * FRAGMENT_SIZE + bytes.len();
-                self.bytes.resize(len, 0);
-            }
-
-            let start = fragment_index * FRAGMENT_SIZE;
-            let end = start + bytes.len();
-            self.bytes[start..end].copy_from_slice(bytes);
+        if bytes.len() > FRAGMENT_SIZE || (!is_last_fragment && bytes.len() != FRAGMENT_SIZE) {
+            bail!("invalid fragment length {}", bytes.len());
         }
+        self.last_received = received_time;
+        self.fragments.entry(fragment_index).or_insert(bytes);
 
-        if self.num_received_fragments == self.num_fragments {
+        if self.fragments.len() == self.num_fragments {
             trace!("Received all fragments!");
-            let payload = std::mem::take(&mut self.bytes);
-            return Ok(Some(payload.into()));
+            let fragments = std::mem::take(&mut self.fragments);
+            let mut payload = BytesMut::with_capacity(self.num_fragments * FRAGMENT_SIZE);
+            fragments
+                .into_values()
+                .for_each(|fragment| payload.extend_from_slice(&fragment));
+            return Ok(Some(payload.freeze()));
         }
 
         Ok(None)
@@ -142,4 +189,49 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_receiver_invalid_fragments() {
+        let mut receiver = FragmentReceiver::new();
+        let fragment =
+            |message_id: u16, fragment_id: u8, num_fragments: u8, len: usize| FragmentData {
+                message_id: MessageId(message_id),
+                tick: None,
+                fragment_id,
+                num_fragments,
+                bytes: Bytes::from(vec![0; len]),
+                compressed: false,
+            };
+        // the fragment index is out of bounds
+        assert!(receiver
+            .receive_fragment(fragment(0, 3, 2, 10), None)
+            .is_err());
+        // a fragment that is not the last one must be full
+        assert!(receiver
+            .receive_fragment(fragment(1, 0, 2, 10), None)
+            .is_err());
+        // the last fragment cannot be bigger than a fragment
+        assert!(receiver
+            .receive_fragment(fragment(2, 1, 2, FRAGMENT_SIZE + 1), None)
+            .is_err());
+        // the fragments of a message must agree on the number of fragments
+        assert!(receiver
+            .receive_fragment(fragment(3, 0, 2, FRAGMENT_SIZE), None)
+            .unwrap()
+            .is_none());
+        assert!(receiver
+            .receive_fragment(fragment(3, 5, 10, FRAGMENT_SIZE), None)
+            .is_err());
+
+        // the number of messages that are partially received is bounded
+        let mut receiver = FragmentReceiver::new();
+        for message_id in 0..MAX_PARTIAL_MESSAGES {
+            assert!(receiver
+                .receive_fragment(fragment(message_id as u16, 0, 2, FRAGMENT_SIZE), None)
+                .is_ok());
+        }
+        assert!(receiver
+            .receive_fragment(fragment(u16::MAX, 0, 2, FRAGMENT_SIZE), None)
+            .is_err());
+    }
 }
@@ -1,9 +1,13 @@
+use crate::packet::compression::Compressor;
 use crate::packet::message::MessageContainer;
 use crate::packet::message::{FragmentData, SingleData};
 use crate::shared::tick_manager::TickManager;
 use crate::shared::time_manager::TimeManager;
 use enum_dispatch::enum_dispatch;
 
+/// Receive large payloads streamed in chunks
+pub(crate) mod blob;
+
 /// Utilities to receive a Message from multiple fragment packets
 pub(crate) mod fragment_receiver;
 
@@ -46,4 +50,23 @@ pub enum ChannelReceiver {
     SequencedReliable(sequenced_reliable::SequencedReliableReceiver),
     UnorderedReliable(unordered_reliable::UnorderedReliableReceiver),
     TickUnreliable(tick_unreliable::TickUnreliableReceiver),
+    Blob(blob::BlobReceiver),
+}
+
+impl ChannelReceiver {
+    /// The compressor used to read the fragmented messages (only for reliable receivers)
+    pub(crate) fn compressor_mut(&mut self) -> Option<&mut Compressor> {
+        match self {
+            ChannelReceiver::OrderedReliable(receiver) => {
+                receiver.fragment_receiver.compressor.as_mut()
+            }
+            ChannelReceiver::SequencedReliable(receiver) => {
+                receiver.fragment_receiver.compressor.as_mut()
+            }
+            ChannelReceiver::UnorderedReliable(receiver) => {
+                receiver.fragment_receiver.compressor.as_mut()
+            }
+            _ => None,
+        }
+    }
 }
@@ -1,31 +1,41 @@
-use std::collections::{btree_map, BTreeMap};
+use std::collections::{btree_map, BTreeMap, HashMap};
 
 use anyhow::anyhow;
 
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
 use crate::channel::receivers::ChannelReceive;
+use crate::channel::stream::{read_stream_header, ReceivedMessageIds, StreamId};
 use crate::packet::message::{MessageContainer, MessageId, SingleData};
 pub use crate::shared::tick_manager::TickManager;
 pub use crate::shared::time_manager::TimeManager;
 
-/// Ordered Reliable receiver: make sure that all messages are received,
-/// and return them in order
-pub struct OrderedReliableReceiver {
-    /// Next message id that we are waiting to receive
-    /// The channel is reliable so we should see all message ids sequentially.
+/// Messages of a stream that are waiting to be read in order
+#[derive(Default)]
+struct OrderedStream {
+    /// Next message id (in the stream) that we are waiting to receive
+    /// The channel is reliable so we should see all message ids of the stream sequentially.
     pending_recv_message_id: MessageId,
     // TODO: optimize via ring buffer?
     /// Buffer of the messages that we received, but haven't processed yet
     recv_message_buffer: BTreeMap<MessageId, SingleData>,
-    fragment_receiver: FragmentReceiver,
+}
+
+/// Ordered Reliable receiver: make sure that all messages are received,
+/// and return them in order (within each [`StreamId`])
+pub struct OrderedReliableReceiver {
+    /// Messages received on each stream
+    streams: HashMap<StreamId, OrderedStream>,
+    /// Channel-wide message ids that we already received
+    received_message_ids: ReceivedMessageIds,
+    pub(crate) fragment_receiver: FragmentReceiver,
 }
 
 impl OrderedReliableReceiver {
     pub fn new() -> Self {
         Self {
-            pending_recv_message_id: MessageId(0),
-            recv_message_buffer: BTreeMap::new(),
-            fragment_receiver: FragmentReceiver::new(),
+            streams: HashMap::new(),
+            received_message_ids: ReceivedMessageIds::default(),
+            fragment_receiver: FragmentReceiver::with_compression(),
         }
     }
 }
@@ -39,46 +49,55 @@ impl ChannelReceive for OrderedReliableReceiver {
             .message_id()
             .ok_or_else(|| anyhow!("message id not found"))?;
 
-        // if the message is too old, ignore it
-        if message_id < self.pending_recv_message_id {
+        // we already received this message
+        if self.received_message_ids.contains(message_id) {
             return Ok(());
         }
 
-        // add the message to the buffer
-        if let btree_map::Entry::Vacant(entry) = self.recv_message_buffer.entry(message_id) {
-            match message {
-                MessageContainer::Single(data) => {
-                    entry.insert(data);
-                }
-                MessageContainer::Fragment(data) => {
-                    if let Some(single_data) =
-                        self.fragment_receiver.receive_fragment(data, None)?
-                    {
-                        entry.insert(single_data);
-                    }
+        let mut data = match message {
+            MessageContainer::Single(data) => {
+                // the message could have been a fragmented message that expired
+                self.fragment_receiver.discard(message_id);
+                data
+            }
+            MessageContainer::Fragment(data) => {
+                match self.fragment_receiver.receive_fragment(data, None)? {
+                    Some(single_data) => single_data,
+                    None => return Ok(()),
                 }
             }
+        };
+        self.received_message_ids.insert(message_id);
+
+        // add the message to the buffer of its stream
+        let (stream_id, stream_message_id) = read_stream_header(&mut data)?;
+        let stream = self.streams.entry(stream_id).or_default();
+        // if the message is too old, ignore it
+        if stream_message_id < stream.pending_recv_message_id {
+            return Ok(());
+        }
+        if let btree_map::Entry::Vacant(entry) = stream.recv_message_buffer.entry(stream_message_id)
+        {
+            entry.insert(data);
         }
         Ok(())
     }
 
     /// Reads a message from the internal buffer to get its content
-    /// Since we are receiving messages in order, we don't return from the buffer
-    /// until we have received the message we are waiting for (the next expected MessageId)
-    /// This assumes that the sender sends all message ids sequentially.
+    /// Since we are receiving messages in order, we don't return from the buffer of a stream
+    /// until we have received the message we are waiting for (the next expected MessageId of the stream)
+    /// This assumes that the sender sends all message ids of a stream sequentially.
     fn read_message(&mut self) -> Option<SingleData> {
-        // Check if we have received the message we are waiting for
-        let Some(message) = self
-            .recv_message_buffer
-            .remove(&self.pending_recv_message_id)
-        else {
-            return None;
-        };
-
-        // if we have finally received the message we are waiting for, return it and
-        // wait for the next one
-        self.pending_recv_message_id += 1;
-        Some(message)
+        // Check if we have received the message we are waiting for, on any stream
+        self.streams.values_mut().find_map(|stream| {
+            let message = stream
+                .recv_message_buffer
+                .remove(&stream.pending_recv_message_id)?;
+            // if we have finally received the message we are waiting for, return it and
+            // wait for the next one
+            stream.pending_recv_message_id += 1;
+            Some(message)
+        })
     }
 }
 
@@ -88,40 +107,94 @@ mod tests {
 
     use crate::channel::receivers::ordered_reliable::OrderedReliableReceiver;
     use crate::channel::receivers::ChannelReceive;
+    use crate::channel::stream::{write_stream_header, StreamId};
     use crate::packet::message::{MessageId, SingleData};
 
+    fn stream_message(
+        message_id: u16,
+        stream: StreamId,
+        stream_message_id: u16,
+        bytes: &'static str,
+    ) -> SingleData {
+        SingleData::new(
+            Some(MessageId(message_id)),
+            write_stream_header(stream, MessageId(stream_message_id), bytes.as_bytes()),
+        )
+    }
+
     #[test]
     fn test_ordered_reliable_receiver_internals() -> anyhow::Result<()> {
         let mut receiver = OrderedReliableReceiver::new();
-
-        let mut single1 = SingleData::new(None, Bytes::from("hello"));
-        let mut single2 = SingleData::new(None, Bytes::from("world"));
+        let stream = StreamId::default();
+        let single1 = SingleData::new(Some(MessageId(0)), Bytes::from("hello"));
+        let single2 = SingleData::new(Some(MessageId(1)), Bytes::from("world"));
 
         // receive an old message: it doesn't get added to the buffer because the next one we expect is 0
-        single2.id = Some(MessageId(60000));
-        receiver.buffer_recv(single2.clone().into())?;
-        assert_eq!(receiver.recv_message_buffer.len(), 0);
+        receiver.buffer_recv(stream_message(60000, stream, 60000, "world").into())?;
+        assert!(receiver.streams.is_empty());
 
         // receive message in the wrong order
-        single2.id = Some(MessageId(1));
-        receiver.buffer_recv(single2.clone().into())?;
+        receiver.buffer_recv(stream_message(1, stream, 1, "world").into())?;
 
         // the message has been buffered, but we are not processing it yet
         // until we have received message 0
-        assert_eq!(receiver.recv_message_buffer.len(), 1);
-        assert!(receiver.recv_message_buffer.get(&MessageId(1)).is_some());
+        let buffer = &receiver.streams[&stream].recv_message_buffer;
+        assert_eq!(buffer.len(), 1);
+        assert!(buffer.get(&MessageId(1)).is_some());
         assert_eq!(receiver.read_message(), None);
-        assert_eq!(receiver.pending_recv_message_id, MessageId(0));
+        assert_eq!(
+            receiver.streams[&stream].pending_recv_message_id,
+            MessageId(0)
+        );
 
         // receive message 0
-        single1.id = Some(MessageId(0));
-        receiver.buffer_recv(single1.clone().into())?;
-        assert_eq!(receiver.recv_message_buffer.len(), 2);
+        receiver.buffer_recv(stream_message(0, stream, 0, "hello").into())?;
+        assert_eq!(receiver.streams[&stream].recv_message_buffer.len(), 2);
 
         // now we can read the messages in order
-        assert_eq!(receiver.read_message(), Some(single1.clone()));
-        assert_eq!(receiver.pending_recv_message_id, MessageId(1));
-        assert_eq!(receiver.read_message(), Some(single2.clone()));
+        assert_eq!(receiver.read_message(), Some(single1));
+        assert_eq!(
+            receiver.streams[&stream].pending_recv_message_id,
+            MessageId(1)
+        );
+        assert_eq!(receiver.read_message(), Some(single2));
+
+        // a duplicate of a message that was already read is ignored
+        receiver.buffer_recv(stream_message(1, stream, 1, "world").into())?;
+        assert_eq!(receiver.read_message(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordered_reliable_receiver_streams() -> anyhow::Result<()> {
+        let mut receiver = OrderedReliableReceiver::new();
+        let chat = StreamId(0);
+        let entity = StreamId(1);
+
+        // the first chat message is missing: the other chat messages wait for it
+        receiver.buffer_recv(stream_message(1, chat, 1, "chat 1").into())?;
+        // the entity stream is not blocked by the chat stream
+        receiver.buffer_recv(stream_message(2, entity, 0, "entity 0").into())?;
+        receiver.buffer_recv(stream_message(3, entity, 1, "entity 1").into())?;
+        assert_eq!(
+            receiver.read_message(),
+            Some(SingleData::new(Some(MessageId(2)), Bytes::from("entity 0")))
+        );
+        assert_eq!(
+            receiver.read_message(),
+            Some(SingleData::new(Some(MessageId(3)), Bytes::from("entity 1")))
+        );
+        assert_eq!(receiver.read_message(), None);
+
+        receiver.buffer_recv(stream_message(0, chat, 0, "chat 0").into())?;
+        assert_eq!(
+            receiver.read_message(),
+            Some(SingleData::new(Some(MessageId(0)), Bytes::from("chat 0")))
+        );
+        assert_eq!(
+            receiver.read_message(),
+            Some(SingleData::new(Some(MessageId(1)), Bytes::from("chat 1")))
+        );
         Ok(())
     }
 }
@@ -1,31 +1,42 @@
-use std::collections::{btree_map, BTreeMap};
+use std::collections::{btree_map, BTreeMap, HashMap};
 
 use anyhow::anyhow;
 
 use crate::channel::receivers::fragment_receiver::FragmentReceiver;
 use crate::channel::receivers::ChannelReceive;
+use crate::channel::stream::{read_stream_header, ReceivedMessageIds, StreamId};
 use crate::packet::message::{MessageContainer, MessageId, SingleData};
 use crate::shared::tick_manager::TickManager;
 use crate::shared::time_manager::TimeManager;
 
-/// Sequenced Reliable receiver: make sure that all messages are received,
-/// do not return them in order, but ignore the messages that are older than the most recent one received
-pub struct SequencedReliableReceiver {
+/// Messages of a stream that haven't been processed yet
+#[derive(Default)]
+struct SequencedStream {
     // TODO: optimize via ring buffer?
     // TODO: actually do we even need a buffer? we might just need a buffer of 1
     /// Buffer of the messages that we received, but haven't processed yet
     recv_message_buffer: BTreeMap<MessageId, SingleData>,
-    /// Highest message id received so far
+    /// Highest message id (in the stream) received so far
     most_recent_message_id: MessageId,
-    fragment_receiver: FragmentReceiver,
+}
+
+/// Sequenced Reliable receiver: make sure that all messages are received,
+/// do not return them in order, but ignore the messages that are older than the most recent one received
+/// (within each [`StreamId`])
+pub struct SequencedReliableReceiver {
+    /// Messages received on each stream
+    streams: HashMap<StreamId, SequencedStream>,
+    /// Channel-wide message ids that we already received
+    received_message_ids: ReceivedMessageIds,
+    pub(crate) fragment_receiver: FragmentReceiver,
 }
 
 impl SequencedReliableReceiver {
     pub fn new() -> Self {
         Self {
-            recv_message_buffer: BTreeMap::new(),
-            most_recent_message_id: MessageId(0),
-            fragment_receiver: FragmentReceiver::new(),
+            streams: HashMap::new(),
+            received_message_ids: ReceivedMessageIds::default(),
+            fragment_receiver: FragmentReceiver::with_compression(),
         }
     }
 }
@@ -39,43 +50,55 @@ impl ChannelReceive for SequencedReliableReceiver {
             .message_id()
             .ok_or_else(|| anyhow!("message id not found"))?;
 
+        // we already received this message
+        if self.received_message_ids.contains(message_id) {
+            return Ok(());
+        }
+
+        let mut data = match message {
+            MessageContainer::Single(data) => {
+                // the message could have been a fragmented message that expired
+                self.fragment_receiver.discard(message_id);
+                data
+            }
+            MessageContainer::Fragment(data) => {
+                match self.fragment_receiver.receive_fragment(data, None)? {
+                    Some(single_data) => single_data,
+                    None => return Ok(()),
+                }
+            }
+        };
+        self.received_message_ids.insert(message_id);
+
+        let (stream_id, stream_message_id) = read_stream_header(&mut data)?;
+        let stream = self.streams.entry(stream_id).or_default();
         // if the message is too old, ignore it
-        if message_id < self.most_recent_message_id {
+        if stream_message_id < stream.most_recent_message_id {
             return Ok(());
         }
 
         // update the most recent message id
-        if message_id > self.most_recent_message_id {
-            self.most_recent_message_id = message_id;
+        if stream_message_id > stream.most_recent_message_id {
+            stream.most_recent_message_id = stream_message_id;
         }
 
         // add the message to the buffer
-        if let btree_map::Entry::Vacant(entry) = self.recv_message_buffer.entry(message_id) {
-            match message {
-                MessageContainer::Single(data) => {
-                    entry.insert(data);
-                }
-                MessageContainer::Fragment(data) => {
-                    if let Some(single_data) =
-                        self.fragment_receiver.receive_fragment(data, None)?
-                    {
-                        entry.insert(single_data);
-                    }
-                }
-            }
+        if let btree_map::Entry::Vacant(entry) = stream.recv_message_buffer.entry(stream_message_id)
+        {
+            entry.insert(data);
         }
         Ok(())
     }
     fn read_message(&mut self) -> Option<SingleData> {
-        // keep popping messages until we get one that is more recent than the last one we processed
-        loop {
-            let Some((message_id, message)) = self.recv_message_buffer.pop_first() else {
-                return None;
-            };
-            if message_id >= self.most_recent_message_id {
-                return Some(message);
+        self.streams.values_mut().find_map(|stream| {
+            // keep popping messages until we get one that is more recent than the last one we processed
+            loop {
+                let (message_id, message) = stream.recv_message_buffer.pop_first()?;
+                if message_id >= stream.most_recent_message_id {
+                    return Some(message);
+                }
             }
-        }
+        })
     }
 }
 
@@ -84,42 +107,63 @@ mod tests {
     use bytes::Bytes;
 
     use crate::channel::receivers::ChannelReceive;
+    use crate::channel::stream::write_stream_header;
     use crate::packet::message::SingleData;
 
     use super::*;
 
+    fn stream_message(
+        message_id: u16,
+        stream: StreamId,
+        stream_message_id: u16,
+        bytes: &'static str,
+    ) -> SingleData {
+        SingleData::new(
+            Some(MessageId(message_id)),
+            write_stream_header(stream, MessageId(stream_message_id), bytes.as_bytes()),
+        )
+    }
+
     // TODO: check that the fragment receiver correctly removes items from the buffer, so they dont accumulate!
 
     #[test]
     fn test_ordered_reliable_receiver_internals() -> anyhow::Result<()> {
         let mut receiver = SequencedReliableReceiver::new();
-
-        let mut single1 = SingleData::new(None, Bytes::from("hello"));
-        let mut single2 = SingleData::new(None, Bytes::from("world"));
+        let stream = StreamId::default();
 
         // receive an old message: it doesn't get added to the buffer because the next one we expect is 0
-        single2.id = Some(MessageId(60000));
-        receiver.buffer_recv(single2.clone().into())?;
-        assert_eq!(receiver.recv_message_buffer.len(), 0);
+        receiver.buffer_recv(stream_message(60000, stream, 60000, "world").into())?;
+        assert!(receiver.streams.is_empty());
 
         // receive message in the wrong order
-        single2.id = Some(MessageId(1));
-        receiver.buffer_recv(single2.clone().into())?;
+        receiver.buffer_recv(stream_message(1, stream, 1, "world").into())?;
 
         // we process the message
-
-        assert_eq!(receiver.recv_message_buffer.len(), 1);
-        assert!(receiver.recv_message_buffer.get(&MessageId(1)).is_some());
-        assert_eq!(receiver.read_message(), Some(single2.clone()));
-        assert_eq!(receiver.most_recent_message_id, MessageId(1));
+        let buffer = &receiver.streams[&stream].recv_message_buffer;
+        assert_eq!(buffer.len(), 1);
+        assert!(buffer.get(&MessageId(1)).is_some());
+        assert_eq!(
+            receiver.read_message(),
+            Some(SingleData::new(Some(MessageId(1)), Bytes::from("world")))
+        );
+        assert_eq!(
+            receiver.streams[&stream].most_recent_message_id,
+            MessageId(1)
+        );
 
         // receive message 0:
         // we don't care about receiving message 0 anymore, since we already have received a more recent message
         // gets discarded
-        single1.id = Some(MessageId(0));
-        receiver.buffer_recv(single1.clone().into())?;
-        assert_eq!(receiver.recv_message_buffer.len(), 0);
+        receiver.buffer_recv(stream_message(0, stream, 0, "hello").into())?;
+        assert_eq!(receiver.streams[&stream].recv_message_buffer.len(), 0);
         assert_eq!(receiver.read_message(), None);
+
+        // an older message of another stream is still received
+        receiver.buffer_recv(stream_message(2, StreamId(1), 0, "other").into())?;
+        assert_eq!(
+            receiver.read_message(),
+            Some(SingleData::new(Some(MessageId(2)), Bytes::from("other")))
+        );
         Ok(())
     }
 }
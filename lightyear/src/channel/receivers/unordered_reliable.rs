@@ -18,7 +18,7 @@ pub struct UnorderedReliableReceiver {
     // TODO: actually we could just use a VecDeque here?
     /// Buffer of the messages that we received, but haven't processed yet
     recv_message_buffer: BTreeMap<MessageId, SingleData>,
-    fragment_receiver: FragmentReceiver,
+    pub(crate) fragment_receiver: FragmentReceiver,
     /// Keep tracking of the message ids we have received, so we can update the oldest_pending_message_id
     received_message_ids: HashSet<MessageId>,
 }
@@ -28,7 +28,7 @@ impl UnorderedReliableReceiver {
         Self {
             pending_recv_message_id: MessageId(0),
             recv_message_buffer: BTreeMap::new(),
-            fragment_receiver: FragmentReceiver::new(),
+            fragment_receiver: FragmentReceiver::with_compression(),
             received_message_ids: HashSet::new(),
         }
     }
@@ -56,6 +56,8 @@ impl ChannelReceive for UnorderedReliableReceiver {
                     if let Some(message_id) = data.id {
                         // receive the message if we haven't received it already
                         if !self.received_message_ids.contains(&message_id) {
+                            // the message could have been a fragmented message that expired
+                            self.fragment_receiver.discard(message_id);
                             self.received_message_ids.insert(message_id);
                             entry.insert(data);
                         }
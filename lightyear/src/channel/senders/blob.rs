@@ -0,0 +1,354 @@
+//! Sender that streams large payloads (blobs) in chunks, with a limited window of chunks in flight
+use std::collections::{HashMap, VecDeque};
+
+use bevy::utils::Duration;
+use bytes::{BufMut, Bytes, BytesMut};
+use crossbeam_channel::{Receiver, Sender};
+use tracing::trace;
+
+use crate::channel::builder::BlobSettings;
+use crate::channel::senders::ChannelSend;
+use crate::packet::message::{FragmentData, MessageAck, MessageId, SingleData};
+use crate::packet::packet::FRAGMENT_SIZE;
+use crate::shared::ping::manager::PingManager;
+use crate::shared::tick_manager::TickManager;
+use crate::shared::time_manager::{TimeManager, WrappedTime};
+
+/// Identifies a blob sent on a given channel
+pub type BlobId = u32;
+
+/// Each chunk starts with a header: blob id, chunk index, total size of the blob (all u32)
+pub(crate) const BLOB_HEADER_BYTES: usize = 12;
+
+/// Number of bytes of the blob contained in each chunk.
+/// The chunk (header included) fits in a packet without being fragmented
+pub(crate) const BLOB_CHUNK_SIZE: usize = FRAGMENT_SIZE - BLOB_HEADER_BYTES;
+
+/// How much of a blob has been transferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlobProgress {
+    pub blob_id: BlobId,
+    /// Number of bytes that have been transferred (acked by the remote for outgoing blobs)
+    pub transferred_bytes: usize,
+    /// Total size of the blob in bytes
+    pub total_bytes: usize,
+}
+
+impl BlobProgress {
+    /// Fraction of the blob that has been transferred, between 0.0 and 1.0
+    pub fn fraction(&self) -> f32 {
+        if self.total_bytes == 0 {
+            return 1.0;
+        }
+        self.transferred_bytes as f32 / self.total_bytes as f32
+    }
+}
+
+/// Number of chunks needed to send a blob of `total_bytes`
+pub(crate) fn num_chunks(total_bytes: usize) -> usize {
+    // an empty blob still needs one chunk so that the remote receives it
+    std::cmp::max(1, (total_bytes + BLOB_CHUNK_SIZE - 1) / BLOB_CHUNK_SIZE)
+}
+
+pub(crate) fn encode_chunk_header(
+    buffer: &mut BytesMut,
+    blob_id: BlobId,
+    chunk_index: u32,
+    total_bytes: u32,
+) {
+    buffer.put_u32_le(blob_id);
+    buffer.put_u32_le(chunk_index);
+    buffer.put_u32_le(total_bytes);
+}
+
+/// A blob that has not been fully acked yet
+struct OutgoingBlob {
+    id: BlobId,
+    bytes: Bytes,
+    /// Index of the next chunk that has never been sent
+    next_chunk: usize,
+    acked_chunks: usize,
+    acked_bytes: usize,
+}
+
+impl OutgoingBlob {
+    fn num_chunks(&self) -> usize {
+        num_chunks(self.bytes.len())
+    }
+
+    fn chunk(&self, chunk_index: usize) -> Bytes {
+        let start = chunk_index * BLOB_CHUNK_SIZE;
+        let end = std::cmp::min(start + BLOB_CHUNK_SIZE, self.bytes.len());
+        let mut buffer = BytesMut::with_capacity(BLOB_HEADER_BYTES + end - start);
+        encode_chunk_header(
+            &mut buffer,
+            self.id,
+            chunk_index as u32,
+            self.bytes.len() as u32,
+        );
+        buffer.put_slice(&self.bytes[start..end]);
+        buffer.freeze()
+    }
+}
+
+/// A chunk that has been sent but not acked yet
+struct InFlightChunk {
+    blob_id: BlobId,
+    bytes: Bytes,
+    last_sent: WrappedTime,
+}
+
+/// A sender that splits blobs into chunks and makes sure that every chunk is received.
+///
+/// Only `window_size` chunks can be in flight at the same time, and the number of bytes sent
+/// per second is capped by the `bandwidth` setting.
+/// If a chunk is lost, only that chunk is resent.
+pub struct BlobSender {
+    settings: BlobSettings,
+    /// Blobs that have not been fully acked yet, in the order they were buffered
+    blobs: VecDeque<OutgoingBlob>,
+    next_blob_id: BlobId,
+    /// Message id to use for the next chunk to be sent
+    next_send_message_id: MessageId,
+    /// Chunks that have been sent but not acked yet
+    in_flight: HashMap<MessageId, InFlightChunk>,
+    /// list of chunks that we want to fit into packets and send
+    messages_to_send: VecDeque<SingleData>,
+    /// Number of bytes that we can still send (refilled according to the bandwidth)
+    budget: f32,
+    current_rtt: Duration,
+    current_time: WrappedTime,
+    /// Notified with the [`blob_message_id`] of each blob that was fully acked
+    ack_senders: Vec<Sender<MessageId>>,
+}
+
+/// Message id that identifies a blob in the ack notifications (the blob id, wrapped to a [`MessageId`])
+pub(crate) fn blob_message_id(blob_id: BlobId) -> MessageId {
+    MessageId(blob_id as u16)
+}
+
+impl BlobSender {
+    pub(crate) fn new(settings: BlobSettings) -> Self {
+        Self {
+            settings,
+            blobs: VecDeque::new(),
+            next_blob_id: 0,
+            next_send_message_id: MessageId(0),
+            in_flight: HashMap::new(),
+            messages_to_send: VecDeque::new(),
+            budget: 0.0,
+            current_rtt: Duration::default(),
+            current_time: WrappedTime::default(),
+            ack_senders: Vec::new(),
+        }
+    }
+
+    /// Queue a blob to be sent. Returns the id of the blob
+    pub(crate) fn send_blob(&mut self, bytes: Bytes) -> BlobId {
+        let id = self.next_blob_id;
+        self.next_blob_id = self.next_blob_id.wrapping_add(1);
+        self.blobs.push_back(OutgoingBlob {
+            id,
+            bytes,
+            next_chunk: 0,
+            acked_chunks: 0,
+            acked_bytes: 0,
+        });
+        id
+    }
+
+    /// Progress of the blobs that have not been fully acked yet
+    pub(crate) fn progress(&self) -> Vec<BlobProgress> {
+        self.blobs
+            .iter()
+            .map(|blob| BlobProgress {
+                blob_id: blob.id,
+                transferred_bytes: blob.acked_bytes,
+                total_bytes: blob.bytes.len(),
+            })
+            .collect()
+    }
+
+    /// Maximum number of bytes that can be accumulated in the budget, to avoid bursts
+    fn max_budget(&self) -> f32 {
+        // allow bursts of 100ms
+        f32::max(self.settings.bandwidth as f32 / 10.0, FRAGMENT_SIZE as f32)
+    }
+}
+
+impl ChannelSend for BlobSender {
+    fn update(&mut self, time_manager: &TimeManager, ping_manager: &PingManager, _: &TickManager) {
+        self.current_time = time_manager.current_time();
+        self.current_rtt = ping_manager.rtt();
+        self.budget = f32::min(
+            self.budget + self.settings.bandwidth as f32 * time_manager.delta().as_secs_f32(),
+            self.max_budget(),
+        );
+    }
+
+    /// Any message sent on a blob channel is sent as a blob.
+    /// The returned id is notified to the ack subscribers once the whole blob is acked
+    fn buffer_send(&mut self, message: Bytes) -> Option<MessageId> {
+        Some(blob_message_id(self.send_blob(message)))
+    }
+
+    fn send_packet(&mut self) -> (VecDeque<SingleData>, VecDeque<FragmentData>) {
+        (std::mem::take(&mut self.messages_to_send), VecDeque::new())
+    }
+
+    /// Collect the chunks that need to be resent because they were not acked,
+    /// then the new chunks, as long as the window and the bandwidth allow it
+    fn collect_messages_to_send(&mut self) {
+        let resend_delay = chrono::Duration::from_std(
+            self.settings
+                .reliable_settings
+                .resend_delay(self.current_rtt),
+        )
+        .unwrap();
+
+        // 1. resend the chunks that were lost
+        for (message_id, chunk) in self.in_flight.iter_mut() {
+            if self.budget <= 0.0 {
+                return;
+            }
+            if self.current_time - chunk.last_sent > resend_delay {
+                trace!(?message_id, blob_id = ?chunk.blob_id, "resending blob chunk");
+                self.messages_to_send
+                    .push_back(SingleData::new(Some(*message_id), chunk.bytes.clone()));
+                chunk.last_sent = self.current_time;
+                self.budget -= chunk.bytes.len() as f32;
+            }
+        }
+
+        // 2. send new chunks
+        for blob in self.blobs.iter_mut() {
+            while blob.next_chunk < blob.num_chunks() {
+                if self.budget <= 0.0 || self.in_flight.len() >= self.settings.window_size {
+                    return;
+                }
+                let bytes = blob.chunk(blob.next_chunk);
+                let message_id = self.next_send_message_id;
+                self.next_send_message_id += 1;
+                blob.next_chunk += 1;
+                self.budget -= bytes.len() as f32;
+                self.messages_to_send
+                    .push_back(SingleData::new(Some(message_id), bytes.clone()));
+                self.in_flight.insert(
+                    message_id,
+                    InFlightChunk {
+                        blob_id: blob.id,
+                        bytes,
+                        last_sent: self.current_time,
+                    },
+                );
+            }
+        }
+    }
+
+    fn notify_message_delivered(&mut self, message_ack: &MessageAck) {
+        let Some(chunk) = self.in_flight.remove(&message_ack.message_id) else {
+            return;
+        };
+        let Some(index) = self.blobs.iter().position(|b| b.id == chunk.blob_id) else {
+            return;
+        };
+        let blob = &mut self.blobs[index];
+        blob.acked_chunks += 1;
+        blob.acked_bytes += chunk.bytes.len() - BLOB_HEADER_BYTES;
+        if blob.acked_chunks == blob.num_chunks() {
+            trace!(blob_id = ?blob.id, "blob fully acked");
+            for sender in &self.ack_senders {
+                sender.send(blob_message_id(blob.id)).unwrap();
+            }
+            self.blobs.remove(index);
+        }
+    }
+
+    fn has_messages_to_send(&self) -> bool {
+        !self.messages_to_send.is_empty()
+    }
+
+    fn subscribe_acks(&mut self) -> Receiver<MessageId> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.ack_senders.push(sender);
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_sender_window_and_acks() {
+        let mut sender = BlobSender::new(BlobSettings {
+            window_size: 2,
+            bandwidth: usize::MAX,
+            ..Default::default()
+        });
+        sender.budget = f32::MAX;
+        let acks = sender.subscribe_acks();
+        let blob = Bytes::from(vec![1; 3 * BLOB_CHUNK_SIZE + 10]);
+        let blob_id = sender.send_blob(blob);
+
+        // only 2 chunks can be in flight
+        sender.collect_messages_to_send();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 2);
+        sender.collect_messages_to_send();
+        assert!(!sender.has_messages_to_send());
+
+        // ack the first chunk: a new chunk can be sent
+        sender.notify_message_delivered(&MessageAck {
+            message_id: MessageId(0),
+            fragment_id: None,
+        });
+        assert_eq!(
+            sender.progress(),
+            vec![BlobProgress {
+                blob_id,
+                transferred_bytes: BLOB_CHUNK_SIZE,
+                total_bytes: 3 * BLOB_CHUNK_SIZE + 10,
+            }]
+        );
+        sender.collect_messages_to_send();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].id, Some(MessageId(2)));
+
+        // ack all chunks
+        for i in 1..4 {
+            sender.collect_messages_to_send();
+            sender.notify_message_delivered(&MessageAck {
+                message_id: MessageId(i),
+                fragment_id: None,
+            });
+            // the blob is only acked once all its chunks are acked
+            assert_eq!(
+                acks.try_recv().ok(),
+                (i == 3).then_some(blob_message_id(blob_id))
+            );
+        }
+        assert!(sender.progress().is_empty());
+    }
+
+    #[test]
+    fn test_blob_sender_bandwidth() {
+        let mut sender = BlobSender::new(BlobSettings {
+            bandwidth: FRAGMENT_SIZE,
+            ..Default::default()
+        });
+        sender.send_blob(Bytes::from(vec![1; 10 * BLOB_CHUNK_SIZE]));
+
+        // no budget yet
+        sender.collect_messages_to_send();
+        assert!(!sender.has_messages_to_send());
+
+        // enough budget for one chunk
+        sender.budget = 1.0;
+        sender.collect_messages_to_send();
+        let (single, _) = sender.send_packet();
+        assert_eq!(single.len(), 1);
+        assert!(sender.budget <= 0.0);
+    }
+}
@@ -16,18 +16,15 @@ impl FragmentSender {
             fragment_size: FRAGMENT_SIZE,
         }
     }
+    /// Split the message into fragments.
+    /// A message smaller than the fragment size produces a single fragment (this can happen
+    /// for reliable messages that became small enough after being compressed)
     pub fn build_fragments(
         &self,
         fragment_message_id: MessageId,
         tick: Option<Tick>,
         fragment_bytes: Bytes,
     ) -> Vec<FragmentData> {
-        if fragment_bytes.len() <= FRAGMENT_SIZE {
-            panic!(
-                "Message size must be at least {} to need to be fragmented",
-                FRAGMENT_SIZE
-            );
-        }
         let chunks = fragment_bytes.chunks(self.fragment_size);
         let num_fragments = chunks.len();
         chunks
@@ -39,6 +36,7 @@ impl FragmentSender {
                 fragment_id: fragment_index as u8,
                 num_fragments: num_fragments as u8,
                 bytes: fragment_bytes.slice_ref(chunk),
+                compressed: false,
             })
             .collect::<_>()
     }
@@ -71,6 +69,7 @@ mod tests {
                 fragment_id: 0,
                 num_fragments: expected_num_fragments as u8,
                 bytes: bytes.slice(0..FRAGMENT_SIZE),
+                compressed: false,
             }
         );
         assert_eq!(
@@ -81,6 +80,7 @@ mod tests {
                 fragment_id: 1,
                 num_fragments: expected_num_fragments as u8,
                 bytes: bytes.slice(FRAGMENT_SIZE..2 * FRAGMENT_SIZE),
+                compressed: false,
             }
         );
         assert_eq!(
@@ -91,6 +91,7 @@ mod tests {
                 fragment_id: 2,
                 num_fragments: expected_num_fragments as u8,
                 bytes: bytes.slice(2 * FRAGMENT_SIZE..),
+                compressed: false,
             }
         );
     }
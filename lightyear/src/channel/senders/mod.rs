@@ -1,3 +1,4 @@
+use crate::packet::compression::Compressor;
 use crate::packet::message::{FragmentData, MessageAck, MessageId, SingleData};
 use crate::shared::ping::manager::PingManager;
 use crate::shared::tick_manager::TickManager;
@@ -7,6 +8,7 @@ use crossbeam_channel::Receiver;
 use enum_dispatch::enum_dispatch;
 use std::collections::VecDeque;
 
+pub(crate) mod blob;
 pub(crate) mod fragment_ack_receiver;
 pub(crate) mod fragment_sender;
 pub(crate) mod reliable;
@@ -53,6 +55,42 @@ pub trait ChannelSend {
     fn subscribe_acks(&mut self) -> Receiver<MessageId>;
 }
 
+/// Lets large senders be boxed inside [`ChannelSender`] to keep the enum small
+impl<T: ChannelSend + ?Sized> ChannelSend for Box<T> {
+    fn update(
+        &mut self,
+        time_manager: &TimeManager,
+        ping_manager: &PingManager,
+        tick_manager: &TickManager,
+    ) {
+        (**self).update(time_manager, ping_manager, tick_manager)
+    }
+
+    fn buffer_send(&mut self, message: Bytes) -> Option<MessageId> {
+        (**self).buffer_send(message)
+    }
+
+    fn send_packet(&mut self) -> (VecDeque<SingleData>, VecDeque<FragmentData>) {
+        (**self).send_packet()
+    }
+
+    fn collect_messages_to_send(&mut self) {
+        (**self).collect_messages_to_send()
+    }
+
+    fn notify_message_delivered(&mut self, message_ack: &MessageAck) {
+        (**self).notify_message_delivered(message_ack)
+    }
+
+    fn has_messages_to_send(&self) -> bool {
+        (**self).has_messages_to_send()
+    }
+
+    fn subscribe_acks(&mut self) -> Receiver<MessageId> {
+        (**self).subscribe_acks()
+    }
+}
+
 /// Enum dispatch lets us derive ChannelSend on each enum variant
 #[enum_dispatch(ChannelSend)]
 pub enum ChannelSender {
@@ -61,4 +99,23 @@ pub enum ChannelSender {
     SequencedUnreliable(sequenced_unreliable::SequencedUnreliableSender),
     Reliable(reliable::ReliableSender),
     TickUnreliable(tick_unreliable::TickUnreliableSender),
+    Blob(Box<blob::BlobSender>),
+}
+
+impl ChannelSender {
+    /// The compressor used to frame the fragmented messages (only for reliable senders)
+    pub(crate) fn compressor_mut(&mut self) -> Option<&mut Compressor> {
+        match self {
+            ChannelSender::Reliable(sender) => Some(&mut sender.compressor),
+            _ => None,
+        }
+    }
+
+    /// The messages that expired before being acked (only for reliable senders with a TTL)
+    pub(crate) fn take_expired_messages(&mut self) -> Vec<MessageId> {
+        match self {
+            ChannelSender::Reliable(sender) => sender.take_expired_messages(),
+            _ => Vec::new(),
+        }
+    }
 }
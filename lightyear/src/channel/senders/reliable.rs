@@ -1,14 +1,16 @@
 use bevy::utils::Duration;
 use std::collections::VecDeque;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use bytes::Bytes;
-use crossbeam_channel::Receiver;
+use crossbeam_channel::{Receiver, Sender};
 use tracing::trace;
 
 use crate::channel::builder::ReliableSettings;
 use crate::channel::senders::fragment_sender::FragmentSender;
 use crate::channel::senders::ChannelSend;
+use crate::channel::stream::{write_stream_header, StreamId};
+use crate::packet::compression::Compressor;
 use crate::packet::message::{FragmentData, MessageAck, MessageId, SingleData};
 use crate::shared::ping::manager::PingManager;
 use crate::shared::tick_manager::TickManager;
@@ -29,6 +31,13 @@ pub enum UnackedMessage {
         last_sent: Option<WrappedTime>,
     },
     Fragmented(Vec<FragmentAck>),
+    /// The message expired before being acked: we send an empty message with the same id instead,
+    /// so that the receiver knows that it can skip it
+    Skipped {
+        /// Empty message (only contains the stream header if the channel uses streams)
+        bytes: Bytes,
+        last_sent: Option<WrappedTime>,
+    },
 }
 
 /// A sender that makes sure to resend messages until it receives an ack
@@ -52,6 +61,21 @@ pub struct ReliableSender {
 
     /// Used to split a message into fragments if the message is too big
     fragment_sender: FragmentSender,
+    /// Used to compress the messages that need to be fragmented, if compression was negotiated
+    pub(crate) compressor: Compressor,
+
+    /// Senders notified when a message has been fully acked
+    ack_senders: Vec<Sender<MessageId>>,
+    /// Time at which each message was buffered, used to expire the messages when
+    /// [`ReliableSettings::message_ttl`] is set (oldest messages first)
+    buffered_times: VecDeque<(MessageId, WrappedTime)>,
+    /// Messages that expired before being acked
+    expired_messages: Vec<MessageId>,
+    /// Next message id to use in each stream, if the channel uses [`StreamId`]s
+    /// (only for ordered and sequenced channels)
+    streams: Option<HashMap<StreamId, MessageId>>,
+    /// Stream of the unacked messages, so that we can notify the receiver if they expire
+    message_streams: HashMap<MessageId, (StreamId, MessageId)>,
 
     current_rtt: Duration,
     current_time: WrappedTime,
@@ -67,42 +91,66 @@ impl ReliableSender {
             fragmented_messages_to_send: Default::default(),
             message_ids_to_send: Default::default(),
             fragment_sender: FragmentSender::new(),
+            compressor: Compressor::default(),
+            ack_senders: Vec::new(),
+            buffered_times: VecDeque::new(),
+            expired_messages: Vec::new(),
+            streams: None,
+            message_streams: HashMap::new(),
             current_rtt: Duration::default(),
             current_time: WrappedTime::default(),
         }
     }
-}
 
-// Stragegy:
-// - a Message is a single unified data structure that knows how to serialize itself
-// - a Packet can be a single packet, or a multi-fragment slice, or a single fragment of a slice (i.e. a fragment that needs to be resent)
-// - all messages know how to serialize themselves into a packet or a list of packets to send over the wire.
-//   that means they have the information to create their header (i.e. their PacketId or FragmentId)
-// - SEND = get a list of Messages to send
-// (either packets in the buffer, or packets we need to resend cuz they were not acked,
-// or because one of the fragments of the )
-// - (because once we have that list, that list knows how to serialize itself)
-impl ChannelSend for ReliableSender {
-    fn update(&mut self, time_manager: &TimeManager, ping_manager: &PingManager, _: &TickManager) {
-        self.current_time = time_manager.current_time();
-        self.current_rtt = ping_manager.rtt();
+    /// Sender for ordered or sequenced channels, where each message belongs to a [`StreamId`]
+    pub fn with_streams(reliable_settings: ReliableSettings) -> Self {
+        Self {
+            streams: Some(HashMap::new()),
+            ..Self::new(reliable_settings)
+        }
     }
 
-    /// Add a new message to the buffer of messages to be sent.
-    /// This is a client-facing function, to be called when you want to send a message
-    fn buffer_send(&mut self, message: Bytes) -> Option<MessageId> {
+    /// Buffer a message to be sent on the given stream.
+    /// The message is only ordered relative to the other messages of the same stream.
+    ///
+    /// Returns None if the channel does not use streams
+    pub(crate) fn buffer_send_to_stream(
+        &mut self,
+        message: Bytes,
+        stream: StreamId,
+    ) -> Option<MessageId> {
+        let streams = self.streams.as_mut()?;
+        let stream_message_id = streams.entry(stream).or_default();
+        let message = write_stream_header(stream, *stream_message_id, message.as_ref());
+        if self.reliable_settings.message_ttl.is_some() {
+            self.message_streams
+                .insert(self.next_send_message_id, (stream, *stream_message_id));
+        }
+        *stream_message_id += 1;
+        Some(self.buffer_message(message))
+    }
+
+    /// Add a new message (with its stream header, if any) to the buffer of unacked messages
+    fn buffer_message(&mut self, message: Bytes) -> MessageId {
         let message_id = self.next_send_message_id;
         let unacked_message = if message.len() > self.fragment_sender.fragment_size {
+            // big messages are compressed before being fragmented, if compression was negotiated
+            let compressed = self.compressor.compress_message(message.as_ref());
+            let is_compressed = compressed.is_some();
+            let bytes = compressed.map_or(message, Bytes::from);
             let fragments = self
                 .fragment_sender
-                .build_fragments(message_id, None, message);
+                .build_fragments(message_id, None, bytes);
             UnackedMessage::Fragmented(
                 fragments
                     .into_iter()
-                    .map(|fragment| FragmentAck {
-                        data: fragment,
-                        acked: false,
-                        last_sent: None,
+                    .map(|mut fragment| {
+                        fragment.compressed = is_compressed;
+                        FragmentAck {
+                            data: fragment,
+                            acked: false,
+                            last_sent: None,
+                        }
                     })
                     .collect(),
             )
@@ -113,8 +161,75 @@ impl ChannelSend for ReliableSender {
             }
         };
         self.unacked_messages.insert(message_id, unacked_message);
+        if self.reliable_settings.message_ttl.is_some() {
+            self.buffered_times
+                .push_back((message_id, self.current_time));
+        }
         self.next_send_message_id += 1;
-        Some(message_id)
+        message_id
+    }
+
+    /// Take the messages that expired before being acked since the last call
+    pub(crate) fn take_expired_messages(&mut self) -> Vec<MessageId> {
+        std::mem::take(&mut self.expired_messages)
+    }
+
+    /// Stop sending the messages that have not been acked within the TTL, and replace them
+    /// with a skip notification for the receiver
+    fn expire_messages(&mut self) {
+        let Some(ttl) = self.reliable_settings.message_ttl else {
+            return;
+        };
+        let ttl = chrono::Duration::from_std(ttl).unwrap();
+        while let Some((message_id, buffered_time)) = self.buffered_times.front() {
+            if self.current_time - *buffered_time <= ttl {
+                break;
+            }
+            let message_id = *message_id;
+            self.buffered_times.pop_front();
+            // the message has already been acked
+            let Some(unacked_message) = self.unacked_messages.get_mut(&message_id) else {
+                continue;
+            };
+            trace!(?message_id, "Reliable message expired");
+            // the receiver needs to know the stream of the skipped message
+            let bytes = match self.message_streams.remove(&message_id) {
+                Some((stream, stream_message_id)) => {
+                    write_stream_header(stream, stream_message_id, &[])
+                }
+                None => Bytes::new(),
+            };
+            *unacked_message = UnackedMessage::Skipped {
+                bytes,
+                last_sent: None,
+            };
+            self.expired_messages.push(message_id);
+        }
+    }
+}
+
+// Stragegy:
+// - a Message is a single unified data structure that knows how to serialize itself
+// - a Packet can be a single packet, or a multi-fragment slice, or a single fragment of a slice (i.e. a fragment that needs to be resent)
+// - all messages know how to serialize themselves into a packet or a list of packets to send over the wire.
+//   that means they have the information to create their header (i.e. their PacketId or FragmentId)
+// - SEND = get a list of Messages to send
+// (either packets in the buffer, or packets we need to resend cuz they were not acked,
+// or because one of the fragments of the )
+// - (because once we have that list, that list knows how to serialize itself)
+impl ChannelSend for ReliableSender {
+    fn update(&mut self, time_manager: &TimeManager, ping_manager: &PingManager, _: &TickManager) {
+        self.current_time = time_manager.current_time();
+        self.current_rtt = ping_manager.rtt();
+    }
+
+    /// Add a new message to the buffer of messages to be sent.
+    /// This is a client-facing function, to be called when you want to send a message
+    fn buffer_send(&mut self, message: Bytes) -> Option<MessageId> {
+        if self.streams.is_some() {
+            return self.buffer_send_to_stream(message, StreamId::default());
+        }
+        Some(self.buffer_message(message))
     }
 
     /// Take messages from the buffer of messages to be sent, and build a list of packets
@@ -148,6 +263,7 @@ impl ChannelSend for ReliableSender {
     /// Either because they have never been sent, or because they need to be resent
     /// Needs to be called before [`ReliableSender::send_packet`]
     fn collect_messages_to_send(&mut self) {
+        self.expire_messages();
         // resend delay is based on the rtt
         let resend_delay =
             chrono::Duration::from_std(self.reliable_settings.resend_delay(self.current_rtt))
@@ -184,6 +300,23 @@ impl ChannelSend for ReliableSender {
                         }
                     }
                 }
+                UnackedMessage::Skipped {
+                    bytes,
+                    ref mut last_sent,
+                } => {
+                    if should_send(last_sent) {
+                        let message_info = MessageAck {
+                            message_id: *message_id,
+                            fragment_id: None,
+                        };
+                        if !self.message_ids_to_send.contains(&message_info) {
+                            let message = SingleData::new(Some(*message_id), bytes.clone());
+                            self.single_messages_to_send.push_back(message);
+                            self.message_ids_to_send.insert(message_info);
+                            *last_sent = Some(self.current_time);
+                        }
+                    }
+                }
                 UnackedMessage::Fragmented(fragment_acks) => {
                     // only send the fragments that haven't been acked and should be resent
                     fragment_acks
@@ -209,27 +342,37 @@ impl ChannelSend for ReliableSender {
 
     fn notify_message_delivered(&mut self, message_ack: &MessageAck) {
         if let Some(unacked_message) = self.unacked_messages.get_mut(&message_ack.message_id) {
-            match unacked_message {
+            let fully_acked = match unacked_message {
                 UnackedMessage::Single { .. } => {
                     if message_ack.fragment_id.is_some() {
                         panic!(
                             "Received a message ack for a fragment but message is a single message"
                         )
                     }
-                    self.unacked_messages.remove(&message_ack.message_id);
+                    true
                 }
                 UnackedMessage::Fragmented(fragment_acks) => {
                     let Some(fragment_id) = message_ack.fragment_id else {
                         panic!("Received a message ack for a single message but message is a fragmented message")
                     };
-                    if !fragment_acks[fragment_id as usize].acked {
-                        fragment_acks[fragment_id as usize].acked = true;
-                        // TODO: use a variable to keep track of this?
-                        // all fragments were acked
-                        if fragment_acks.iter().all(|f| f.acked) {
-                            self.unacked_messages.remove(&message_ack.message_id);
-                        }
+                    fragment_acks[fragment_id as usize].acked = true;
+                    // TODO: use a variable to keep track of this?
+                    // all fragments were acked
+                    fragment_acks.iter().all(|f| f.acked)
+                }
+                UnackedMessage::Skipped { .. } => {
+                    // acks for the fragments of the expired message are not relevant anymore
+                    if message_ack.fragment_id.is_none() {
+                        self.unacked_messages.remove(&message_ack.message_id);
                     }
+                    return;
+                }
+            };
+            if fully_acked {
+                self.unacked_messages.remove(&message_ack.message_id);
+                self.message_streams.remove(&message_ack.message_id);
+                for sender in &self.ack_senders {
+                    sender.send(message_ack.message_id).unwrap();
                 }
             }
         }
@@ -239,8 +382,11 @@ impl ChannelSend for ReliableSender {
         !self.single_messages_to_send.is_empty() || !self.fragmented_messages_to_send.is_empty()
     }
 
+    /// Create a new receiver that will receive a message id when a message is acked
     fn subscribe_acks(&mut self) -> Receiver<MessageId> {
-        todo!()
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.ack_senders.push(sender);
+        receiver
     }
 }
 
@@ -260,6 +406,7 @@ mod tests {
         let mut sender = ReliableSender::new(ReliableSettings {
             rtt_resend_factor: 1.5,
             rtt_resend_min_delay: Duration::from_millis(100),
+            message_ttl: None,
         });
         sender.current_rtt = Duration::from_millis(100);
         sender.current_time = WrappedTime::new(0);
@@ -288,15 +435,66 @@ mod tests {
         );
 
         // Ack the first message
+        let acks = sender.subscribe_acks();
         sender.notify_message_delivered(&MessageAck {
             message_id: MessageId(0),
             fragment_id: None,
         });
         assert_eq!(sender.unacked_messages.len(), 0);
+        assert_eq!(acks.try_recv(), Ok(MessageId(0)));
+        // duplicate acks are not notified again
+        sender.notify_message_delivered(&MessageAck {
+            message_id: MessageId(0),
+            fragment_id: None,
+        });
+        assert!(acks.try_recv().is_err());
 
         // Advance by a time that is above the resend threshold
         sender.current_time += Duration::from_millis(200);
         // this time there are no new messages to send
         assert_eq!(sender.single_messages_to_send.len(), 1);
     }
+
+    #[test]
+    fn test_reliable_sender_message_ttl() {
+        let mut sender = ReliableSender::new(ReliableSettings {
+            rtt_resend_factor: 1.5,
+            rtt_resend_min_delay: Duration::from_millis(100),
+            message_ttl: Some(Duration::from_millis(250)),
+        });
+        sender.current_rtt = Duration::from_millis(100);
+        sender.current_time = WrappedTime::new(0);
+        let acks = sender.subscribe_acks();
+
+        let message = Bytes::from("hello");
+        sender.buffer_send(message.clone());
+        sender.collect_messages_to_send();
+        sender.send_packet();
+
+        // the message is resent while it has not expired
+        sender.current_time += Duration::from_millis(200);
+        sender.collect_messages_to_send();
+        assert_eq!(
+            sender.send_packet().0,
+            vec![SingleData::new(Some(MessageId(0)), message)]
+        );
+        assert!(sender.take_expired_messages().is_empty());
+
+        // the message expired: we send an empty message so that the receiver can skip it
+        sender.current_time += Duration::from_millis(100);
+        sender.collect_messages_to_send();
+        assert_eq!(
+            sender.send_packet().0,
+            vec![SingleData::new(Some(MessageId(0)), Bytes::new())]
+        );
+        assert_eq!(sender.take_expired_messages(), vec![MessageId(0)]);
+
+        // the skip notification is acked: the expired message is not reported as acked
+        sender.notify_message_delivered(&MessageAck {
+            message_id: MessageId(0),
+            fragment_id: None,
+        });
+        assert!(sender.unacked_messages.is_empty());
+        assert!(acks.try_recv().is_err());
+    }
 }
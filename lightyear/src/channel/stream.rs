@@ -0,0 +1,124 @@
+//! Independent streams of messages inside an ordered or sequenced channel
+//!
+//! Messages sent on [`OrderedReliable`](crate::prelude::ChannelMode::OrderedReliable) and
+//! [`SequencedReliable`](crate::prelude::ChannelMode::SequencedReliable) channels belong to a stream
+//! (the [`StreamId::default`] stream if none was specified).
+//! They are only ordered (or sequenced) relative to the other messages of the same stream, so a lost message
+//! does not block the messages of the other streams.
+use std::collections::HashSet;
+
+use anyhow::{bail, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::packet::message::{MessageId, SingleData};
+
+/// Identifies an independent stream of messages inside an ordered or sequenced channel
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StreamId(pub u16);
+
+/// Prepend the stream header (stream id and position of the message in the stream) to the message bytes
+pub(crate) fn write_stream_header(
+    stream: StreamId,
+    stream_message_id: MessageId,
+    message: &[u8],
+) -> Bytes {
+    let mut bytes = BytesMut::with_capacity(message.len() + 5);
+    // the stream id is a varint, since most channels only use the first few streams
+    let mut value = stream.0;
+    while value >= 0x80 {
+        bytes.put_u8((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.put_u8(value as u8);
+    bytes.put_u16_le(stream_message_id.0);
+    bytes.put_slice(message);
+    bytes.freeze()
+}
+
+/// Strip the stream header from the message bytes.
+/// Returns the stream of the message, and its position in the stream
+pub(crate) fn read_stream_header(data: &mut SingleData) -> anyhow::Result<(StreamId, MessageId)> {
+    let bytes = data.bytes.as_ref();
+    let mut stream = 0u16;
+    let mut index = 0;
+    loop {
+        let byte = *bytes.get(index).context("stream header is truncated")?;
+        if index == 2 && byte > 0x03 {
+            bail!("stream id overflows");
+        }
+        stream |= ((byte & 0x7f) as u16) << (7 * index);
+        index += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let id_bytes = bytes
+        .get(index..index + 2)
+        .context("stream header is truncated")?;
+    let stream_message_id = MessageId(u16::from_le_bytes([id_bytes[0], id_bytes[1]]));
+    data.bytes = data.bytes.slice(index + 2..);
+    Ok((StreamId(stream), stream_message_id))
+}
+
+/// Keeps track of the messages received on a reliable channel that uses streams.
+///
+/// The messages are ordered per stream, so we use the channel-wide message ids to ignore the messages
+/// (or the fragments of messages) that we already received.
+#[derive(Default)]
+pub(crate) struct ReceivedMessageIds {
+    /// All the messages before this one have been received
+    pending_message_id: MessageId,
+    /// Messages received after `pending_message_id`
+    received: HashSet<MessageId>,
+}
+
+impl ReceivedMessageIds {
+    pub(crate) fn contains(&self, message_id: MessageId) -> bool {
+        message_id < self.pending_message_id || self.received.contains(&message_id)
+    }
+
+    pub(crate) fn insert(&mut self, message_id: MessageId) {
+        if message_id < self.pending_message_id {
+            return;
+        }
+        self.received.insert(message_id);
+        while self.received.remove(&self.pending_message_id) {
+            self.pending_message_id += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_header() -> anyhow::Result<()> {
+        for stream in [
+            StreamId(0),
+            StreamId(127),
+            StreamId(300),
+            StreamId(u16::MAX),
+        ] {
+            let bytes = write_stream_header(stream, MessageId(513), b"hello");
+            let mut data = SingleData::new(Some(MessageId(2)), bytes);
+            assert_eq!(read_stream_header(&mut data)?, (stream, MessageId(513)));
+            assert_eq!(data.bytes.as_ref(), b"hello");
+        }
+        let mut truncated = SingleData::new(Some(MessageId(2)), Bytes::from_static(&[0x80]));
+        assert!(read_stream_header(&mut truncated).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_received_message_ids() {
+        let mut received = ReceivedMessageIds::default();
+        received.insert(MessageId(1));
+        assert!(!received.contains(MessageId(0)));
+        assert!(received.contains(MessageId(1)));
+        received.insert(MessageId(0));
+        assert!(received.contains(MessageId(0)));
+        assert_eq!(received.pending_message_id, MessageId(2));
+        assert!(received.received.is_empty());
+    }
+}
@@ -5,6 +5,7 @@ use std::fmt::Debug;
 
 use bevy::prelude::{Component, Entity};
 
+use crate::client::prediction::correction::CorrectionFn;
 use crate::prelude::{MapEntities, Named, Tick};
 
 /// Marks an entity that contains the server-updates that are received from the Server
@@ -32,9 +33,16 @@ pub trait LerpFn<C> {
 /// Defines how to do interpolation/correction for the component
 pub trait SyncMetadata<C> {
     type Interpolator: LerpFn<C> + 'static;
-    type Corrector: LerpFn<C> + 'static;
+    type Corrector: CorrectionFn<C> + 'static;
 
     fn mode() -> ComponentSyncMode;
+
+    /// Function used to project the component past the latest confirmed state when server updates stop
+    /// arriving (it is called with `t > 1.0`, for example [`LerpFn::lerp`]).
+    /// Extrapolation is disabled by default.
+    fn extrapolator() -> Option<fn(C, C, f32) -> C> {
+        None
+    }
 }
 
 #[derive(Debug, Default, PartialEq)]
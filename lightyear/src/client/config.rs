@@ -19,6 +19,9 @@ pub struct NetcodeConfig {
     /// A negative value means no timeout.
     /// This is used for Authentication::Manual tokens
     pub client_timeout_secs: i32,
+    /// Must be true to connect to a server that routes its clients to several server instances
+    /// (see [`Instances`](crate::server::instances::Instances))
+    pub session_epochs: bool,
 }
 
 impl Default for NetcodeConfig {
@@ -27,6 +30,7 @@ impl Default for NetcodeConfig {
             num_disconnect_packets: 10,
             keepalive_packet_send_rate: 1.0 / 10.0,
             client_timeout_secs: 10,
+            session_epochs: false,
         }
     }
 }
@@ -36,6 +40,7 @@ impl NetcodeConfig {
         crate::netcode::ClientConfig::default()
             .num_disconnect_packets(self.num_disconnect_packets)
             .packet_send_rate(self.keepalive_packet_send_rate)
+            .session_epochs(self.session_epochs)
     }
 }
 
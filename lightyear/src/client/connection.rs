@@ -4,15 +4,22 @@ use bevy::utils::Duration;
 use anyhow::Result;
 use bevy::ecs::component::Tick as BevyTick;
 use bevy::prelude::{Res, ResMut, Resource, World};
+use bytes::Bytes;
 use serde::Serialize;
-use tracing::{debug, info, trace, trace_span};
+use tracing::{debug, error, info, trace, trace_span};
 
-use crate::_reexport::{EntityUpdatesChannel, PingChannel};
+use crate::_reexport::{EntityActionsChannel, EntityUpdatesChannel, PingChannel};
+use crate::channel::senders::blob::{BlobId, BlobProgress};
 use crate::channel::senders::ChannelSend;
+use crate::channel::stream::StreamId;
+use crate::client::config::ClientConfig;
+use crate::client::scheduled::ScheduledMessages;
 use crate::client::sync::SyncConfig;
 use crate::connection::events::ConnectionEvents;
 use crate::connection::message::{ClientMessage, ServerMessage};
 use crate::inputs::native::input_buffer::InputBuffer;
+use crate::packet::compression::CompressionConfig;
+use crate::packet::message::MessageHandle;
 use crate::packet::message_manager::MessageManager;
 use crate::packet::packet_manager::Payload;
 use crate::prelude::{Channel, ChannelKind, MapEntities, Message, NetworkTarget};
@@ -23,10 +30,10 @@ use crate::shared::ping::manager::{PingConfig, PingManager};
 use crate::shared::ping::message::SyncMessage;
 use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
-use crate::shared::replication::ReplicationMessage;
 use crate::shared::replication::ReplicationMessageData;
-use crate::shared::tick_manager::Tick;
+use crate::shared::replication::{LateJoinMessage, ReplicationMessage};
 use crate::shared::tick_manager::TickManager;
+use crate::shared::tick_manager::{Tick, TimelineChange};
 use crate::shared::time_manager::TimeManager;
 
 use super::sync::SyncManager;
@@ -42,6 +49,14 @@ pub struct ConnectionManager<P: Protocol> {
     pub(crate) ping_manager: PingManager,
     pub(crate) input_buffer: InputBuffer<P::Input>,
     pub(crate) sync_manager: SyncManager,
+    /// Latest progress received from the server about the initial world state
+    pub(crate) world_load: Option<LateJoinMessage>,
+    /// True if we already emitted the `WorldLoadedEvent`
+    world_loaded: bool,
+    /// Changes of the timeline that must be applied to the [`TickManager`]
+    pending_timeline_changes: Vec<TimelineChange>,
+    /// Messages scheduled by the server, waiting for their tick
+    pub(crate) scheduled_messages: ScheduledMessages<P>,
     // TODO: maybe don't do any replication until connection is synced?
 }
 
@@ -90,9 +105,20 @@ impl<P: Protocol> ConnectionManager<P> {
         sync_config: SyncConfig,
         ping_config: &PingConfig,
         input_delay_ticks: u16,
+        compression: CompressionConfig,
     ) -> Self {
         // create the message manager and the channels
         let mut message_manager = MessageManager::new(channel_registry);
+        message_manager.set_compression(compression);
+        // offer our compression settings to the server (it will be sent once we are connected)
+        if let Some(offer) = message_manager.compression_offer() {
+            if let Err(e) = message_manager.buffer_send(
+                ClientMessage::<P>::Compression(offer),
+                ChannelKind::of::<EntityActionsChannel>(),
+            ) {
+                error!("Error sending compression offer: {}", e);
+            }
+        }
         // get the acks-tracker for entity updates
         let update_acks_tracker = message_manager
             .channels
@@ -100,7 +126,14 @@ impl<P: Protocol> ConnectionManager<P> {
             .unwrap()
             .sender
             .subscribe_acks();
-        let replication_sender = ReplicationSender::new(update_acks_tracker);
+        // get the acks-tracker for entity actions, to know when the ids of despawned entities can be reused
+        let actions_acks_tracker = message_manager
+            .channels
+            .get_mut(&ChannelKind::of::<EntityActionsChannel>())
+            .unwrap()
+            .sender
+            .subscribe_acks();
+        let replication_sender = ReplicationSender::new(update_acks_tracker, actions_acks_tracker);
         let replication_receiver = ReplicationReceiver::new();
         Self {
             message_manager,
@@ -109,14 +142,54 @@ impl<P: Protocol> ConnectionManager<P> {
             ping_manager: PingManager::new(ping_config),
             input_buffer: InputBuffer::default(),
             sync_manager: SyncManager::new(sync_config, input_delay_ticks),
+            world_load: None,
+            pending_timeline_changes: vec![],
+            scheduled_messages: ScheduledMessages::default(),
+            world_loaded: false,
             events: ConnectionEvents::default(),
         }
     }
 
+    /// Reset the connection when the server starts a new session, for example because the client was moved
+    /// to a different server instance. The entities replicated during the previous session are despawned,
+    /// and the client has to sync again with the new session.
+    pub(crate) fn reset(&mut self, world: &mut World) {
+        self.replication_receiver.reset(world);
+        let config = world.resource::<ClientConfig>();
+        *self = Self::new(
+            world.resource::<P>().channel_registry(),
+            config.sync.clone(),
+            &config.ping,
+            config.prediction.input_delay_ticks,
+            config.shared.compression.clone(),
+        );
+    }
+
     pub fn is_synced(&self) -> bool {
         self.sync_manager.is_synced()
     }
 
+    /// Latest estimate of the round-trip time to the server
+    pub fn rtt(&self) -> Duration {
+        self.ping_manager.rtt()
+    }
+
+    /// Latest estimate of the jitter of the connection to the server
+    pub fn jitter(&self) -> Duration {
+        self.ping_manager.jitter()
+    }
+
+    /// Take the timeline changes received from the server, that must be applied to the [`TickManager`]
+    pub(crate) fn take_timeline_changes(&mut self) -> Vec<TimelineChange> {
+        std::mem::take(&mut self.pending_timeline_changes)
+    }
+
+    /// Fraction (between 0.0 and 1.0) of the initial world state that has been received from the server,
+    /// or None if the server hasn't started sending it yet
+    pub fn world_load_progress(&self) -> Option<f32> {
+        self.world_load.map(|message| message.progress())
+    }
+
     pub(crate) fn received_new_server_tick(&self) -> bool {
         self.sync_manager.duration_since_latest_received_server_tick == Duration::default()
     }
@@ -152,12 +225,31 @@ impl<P: Protocol> ConnectionManager<P> {
     }
 
     /// Send a message to the server
-    pub fn send_message<C: Channel, M: Message>(&mut self, message: M) -> Result<()>
+    pub fn send_message<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+    ) -> Result<Option<MessageHandle>>
+    where
+        P::Message: From<M>,
+    {
+        let channel = ChannelKind::of::<C>();
+        self.buffer_message(message.into(), channel, NetworkTarget::None, None)
+    }
+
+    /// Send a message to the server on the given stream of an ordered or sequenced channel.
+    ///
+    /// The message is only ordered (or sequenced) relative to the other messages of the same stream,
+    /// so that a lost message does not block the messages of the other streams.
+    pub fn send_message_to_stream<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+        stream: StreamId,
+    ) -> Result<Option<MessageHandle>>
     where
         P::Message: From<M>,
     {
         let channel = ChannelKind::of::<C>();
-        self.buffer_message(message.into(), channel, NetworkTarget::None)
+        self.buffer_message(message.into(), channel, NetworkTarget::None, Some(stream))
     }
 
     /// Send a message to the server, the message should be re-broadcasted according to the `target`
@@ -165,20 +257,57 @@ impl<P: Protocol> ConnectionManager<P> {
         &mut self,
         message: M,
         target: NetworkTarget,
-    ) -> Result<()>
+    ) -> Result<Option<MessageHandle>>
+    where
+        P::Message: From<M>,
+    {
+        let channel = ChannelKind::of::<C>();
+        self.buffer_message(message.into(), channel, target, None)
+    }
+
+    /// Send a message to the server on the given stream of an ordered or sequenced channel,
+    /// the message should be re-broadcasted according to the `target`
+    pub fn send_message_to_target_on_stream<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+        target: NetworkTarget,
+        stream: StreamId,
+    ) -> Result<Option<MessageHandle>>
     where
         P::Message: From<M>,
     {
         let channel = ChannelKind::of::<C>();
-        self.buffer_message(message.into(), channel, target)
+        self.buffer_message(message.into(), channel, target, Some(stream))
+    }
+
+    /// Stream a large payload to the server on a [`ChannelMode::Blob`](crate::prelude::ChannelMode::Blob) channel.
+    /// The server will receive it as a [`BlobReceivedEvent`](crate::server::events::BlobReceivedEvent)
+    pub fn send_blob<C: Channel>(&mut self, bytes: impl Into<Bytes>) -> Result<BlobId> {
+        self.message_manager
+            .buffer_send_blob(bytes.into(), ChannelKind::of::<C>())
+    }
+
+    /// Progress of the blobs that are being sent to the server on the channel `C`
+    pub fn outgoing_blobs<C: Channel>(&self) -> Result<Vec<BlobProgress>> {
+        self.message_manager.outgoing_blobs(ChannelKind::of::<C>())
+    }
+
+    /// Progress of the blobs that are being received from the server on the channel `C`
+    pub fn incoming_blobs<C: Channel>(&self) -> Result<Vec<BlobProgress>> {
+        self.message_manager.incoming_blobs(ChannelKind::of::<C>())
     }
 
     pub(crate) fn buffer_message(
         &mut self,
-        message: P::Message,
+        mut message: P::Message,
         channel: ChannelKind,
         target: NetworkTarget,
-    ) -> Result<()> {
+        stream: Option<StreamId>,
+    ) -> Result<Option<MessageHandle>> {
+        // send the entities inside the message as NetEntities
+        self.replication_sender
+            .net_entities
+            .map_to_net(&mut message);
         // TODO: i know channel names never change so i should be able to get them as static
         // TODO: just have a channel registry enum as well?
         let channel_name = self
@@ -189,8 +318,8 @@ impl<P: Protocol> ConnectionManager<P> {
             .to_string();
         let message = ClientMessage::<P>::Message(message, target);
         message.emit_send_logs(&channel_name);
-        self.message_manager.buffer_send(message, channel)?;
-        Ok(())
+        self.message_manager
+            .buffer_send_tracked(message, channel, stream)
     }
 
     pub fn buffer_replication_messages(&mut self, tick: Tick, bevy_tick: BevyTick) -> Result<()> {
@@ -207,6 +336,10 @@ impl<P: Protocol> ConnectionManager<P> {
             .into_iter()
             .try_for_each(|(channel, group_id, message_data)| {
                 let should_track_ack = matches!(message_data, ReplicationMessageData::Updates(_));
+                let despawns = match &message_data {
+                    ReplicationMessageData::Actions(actions) => actions.despawned_entities(),
+                    ReplicationMessageData::Updates(_) => vec![],
+                };
                 let channel_name = self
                     .message_manager
                     .channel_registry
@@ -230,6 +363,12 @@ impl<P: Protocol> ConnectionManager<P> {
                         .updates_message_id_to_group_id
                         .insert(message_id, (group_id, bevy_tick));
                 }
+                // keep track of the despawned entities, so we can reuse their ids once the message is acked
+                if !despawns.is_empty() {
+                    self.replication_sender
+                        .actions_message_id_to_despawns
+                        .insert(message_id, despawns);
+                }
                 Ok(())
             })
     }
@@ -279,9 +418,14 @@ impl<P: Protocol> ConnectionManager<P> {
         world: &mut World,
         time_manager: &TimeManager,
         tick_manager: &TickManager,
-    ) -> ConnectionEvents<P> {
+    ) -> Result<ConnectionEvents<P>> {
         let _span = trace_span!("receive").entered();
-        for (channel_kind, messages) in self.message_manager.read_messages::<ServerMessage<P>>() {
+        for (channel_kind, bytes) in self.message_manager.read_blobs() {
+            self.events.push_blob(channel_kind, bytes);
+        }
+        let (acked, lost, expired) = self.message_manager.take_delivery_notifications();
+        self.events.push_message_delivery(acked, lost, expired);
+        for (channel_kind, messages) in self.message_manager.read_messages::<ServerMessage<P>>()? {
             let channel_name = self
                 .message_manager
                 .channel_registry
@@ -313,6 +457,38 @@ impl<P: Protocol> ConnectionManager<P> {
                             // buffer the replication message
                             self.replication_receiver.recv_message(replication, tick);
                         }
+                        ServerMessage::EntityMessage(message) => {
+                            // buffer the message until its replication group is up-to-date
+                            self.replication_receiver
+                                .recv_entity_message(message, channel_kind);
+                        }
+                        ServerMessage::Scheduled(mut scheduled) => {
+                            scheduled.message.map_entities(Box::new(
+                                &self.replication_receiver.remote_entity_map,
+                            ));
+                            // buffer the message until the client's timeline reaches its tick
+                            self.scheduled_messages.add(scheduled);
+                        }
+                        ServerMessage::LateJoin(message) => {
+                            trace!(?message, "Received late-join progress");
+                            // the progress messages are not ordered
+                            if self
+                                .world_load
+                                .map_or(true, |current| message.sent > current.sent)
+                            {
+                                self.world_load = Some(message);
+                            }
+                        }
+                        ServerMessage::Timeline(change) => {
+                            trace!(?change, "Received timeline change");
+                            self.pending_timeline_changes.push(change);
+                        }
+                        ServerMessage::Compression(offer) => {
+                            // the server accepted our settings
+                            if self.message_manager.negotiate_compression(&offer) {
+                                debug!(?offer, "Compression enabled");
+                            }
+                        }
                         ServerMessage::Sync(ref sync) => {
                             match sync {
                                 SyncMessage::Ping(ping) => {
@@ -329,9 +505,8 @@ impl<P: Protocol> ConnectionManager<P> {
                                     // - maybe we should just send both in Pong message?
                                     // update the tick generation from the time + tick information
                                     self.sync_manager.server_pong_tick = tick;
-                                    self.sync_manager.server_pong_generation = pong
-                                        .pong_sent_time
-                                        .tick_generation(tick_manager.config.tick_duration, tick);
+                                    self.sync_manager.server_pong_generation =
+                                        tick_manager.tick_generation(pong.pong_sent_time, tick);
                                     trace!(
                                         ?tick,
                                         generation = ?self.sync_manager.server_pong_generation,
@@ -348,27 +523,48 @@ impl<P: Protocol> ConnectionManager<P> {
                         self.replication_receiver.read_messages(tick_manager.tick())
                     {
                         trace!(?group, ?replication_list, "read replication messages");
-                        replication_list
-                            .into_iter()
-                            .for_each(|(tick, replication)| {
-                                // TODO: we could include the server tick when this replication_message was sent.
-                                self.replication_receiver.apply_world(
-                                    world,
-                                    tick,
-                                    replication,
-                                    group,
-                                    &mut self.events,
-                                );
-                            });
+                        for (tick, replication) in replication_list {
+                            // TODO: we could include the server tick when this replication_message was sent.
+                            self.replication_receiver.apply_world(
+                                world,
+                                tick,
+                                replication,
+                                group,
+                                &mut self.events,
+                            )?;
+                        }
                     }
                 }
             }
         }
 
+        // release the entity messages whose replication group has reached the state they refer to
+        if self.sync_manager.is_synced() {
+            for (channel_kind, message) in self.replication_receiver.read_entity_messages() {
+                self.events.push_message(channel_kind, message);
+            }
+        }
+
+        // the world is loaded once we have applied all the entities of the initial world state
+        if self.sync_manager.is_synced()
+            && !self.world_loaded
+            && self
+                .world_load
+                .map_or(false, |message| message.is_finished())
+        {
+            self.world_loaded = true;
+            self.events.push_world_loaded();
+        }
+
         // TODO: do i really need this? I could just create events in this function directly?
         //  why do i need to make events a field of the connection?
         //  is it because of push_connection?
-        std::mem::replace(&mut self.events, ConnectionEvents::new())
+        Ok(std::mem::replace(&mut self.events, ConnectionEvents::new()))
+    }
+
+    /// How much behind the server time the interpolation timeline currently is
+    pub fn interpolation_delay(&self) -> Duration {
+        self.sync_manager.interpolation_delay()
     }
 
     pub fn recv_packet(
@@ -386,16 +582,18 @@ impl<P: Protocol> ConnectionManager<P> {
             .map_or(true, |server_tick| tick >= server_tick)
         {
             trace!("new last recv server tick: {:?}", tick);
+            if let Some(previous_tick) = self.sync_manager.latest_received_server_tick {
+                self.sync_manager
+                    .record_server_update(tick, previous_tick, tick_manager);
+            }
             self.sync_manager.latest_received_server_tick = Some(tick);
             // TODO: add 'received_new_server_tick' ?
             // we probably actually physically received the packet some time between our last `receive` and now.
             // Let's add delta / 2 as a compromise
             self.sync_manager.duration_since_latest_received_server_tick = Duration::default();
             // self.sync_manager.duration_since_latest_received_server_tick = time_manager.delta() / 2;
-            self.sync_manager.update_server_time_estimate(
-                tick_manager.config.tick_duration,
-                self.ping_manager.rtt(),
-            );
+            self.sync_manager
+                .update_server_time_estimate(tick_manager, self.ping_manager.rtt());
         }
         trace!(?tick, last_server_tick = ?self.sync_manager.latest_received_server_tick, "Recv server packet");
         Ok(())
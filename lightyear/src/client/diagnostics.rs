@@ -1,8 +1,9 @@
+use crate::client::connection::ConnectionManager;
 use crate::client::resource::Client;
 use crate::prelude::{Io, Protocol};
 use crate::transport::io::{IoDiagnosticsPlugin, IoStats};
 use bevy::app::{App, Plugin, PostUpdate};
-use bevy::diagnostic::{Diagnostic, Diagnostics, RegisterDiagnostic};
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
 use bevy::prelude::{Real, Res, ResMut, Time};
 
 pub struct ClientDiagnosticsPlugin<P> {
@@ -17,12 +18,38 @@ impl<P> Default for ClientDiagnosticsPlugin<P> {
     }
 }
 
+impl<P> ClientDiagnosticsPlugin<P> {
+    /// Current interpolation delay, in milliseconds
+    pub const INTERPOLATION_DELAY: DiagnosticId =
+        DiagnosticId::from_u128(129451538238465263818713047625418934711);
+}
+
+fn interpolation_diagnostics_system<P: Protocol>(
+    connection: Res<ConnectionManager<P>>,
+    mut diagnostics: Diagnostics,
+) {
+    if !connection.is_synced() {
+        return;
+    }
+    diagnostics.add_measurement(ClientDiagnosticsPlugin::<P>::INTERPOLATION_DELAY, || {
+        connection.interpolation_delay().as_secs_f64() * 1000.0
+    });
+}
+
 fn io_diagnostics_system(mut io: ResMut<Io>, time: Res<Time<Real>>, mut diagnostics: Diagnostics) {
     IoDiagnosticsPlugin::update_diagnostics(&mut io.stats, &time, &mut diagnostics);
 }
 impl<P: Protocol> Plugin for ClientDiagnosticsPlugin<P> {
     fn build(&self, app: &mut App) {
         app.add_plugins(IoDiagnosticsPlugin);
-        app.add_systems(PostUpdate, io_diagnostics_system);
+        app.register_diagnostic(Diagnostic::new(
+            Self::INTERPOLATION_DELAY,
+            "interpolation delay (ms)",
+            IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN,
+        ));
+        app.add_systems(
+            PostUpdate,
+            (io_diagnostics_system, interpolation_diagnostics_system::<P>),
+        );
     }
 }
@@ -1,5 +1,7 @@
 //! Wrapper around [`ConnectionEvents`] that adds client-specific functionality
 //!
+use bevy::prelude::Event;
+
 use crate::connection::events::ConnectionEvents;
 
 pub type ConnectEvent = crate::shared::events::ConnectEvent<()>;
@@ -12,3 +14,12 @@ pub type ComponentUpdateEvent<C> = crate::shared::events::ComponentUpdateEvent<C
 pub type ComponentInsertEvent<C> = crate::shared::events::ComponentInsertEvent<C, ()>;
 pub type ComponentRemoveEvent<C> = crate::shared::events::ComponentRemoveEvent<C, ()>;
 pub type MessageEvent<M> = crate::shared::events::MessageEvent<M, ()>;
+pub type BlobReceivedEvent = crate::shared::events::BlobReceivedEvent<()>;
+pub type MessageAckedEvent = crate::shared::events::MessageAckedEvent<()>;
+pub type MessageLostEvent = crate::shared::events::MessageLostEvent<()>;
+pub type MessageExpiredEvent = crate::shared::events::MessageExpiredEvent<()>;
+
+/// Emitted once the initial state of the world has been received from the server
+/// (see [`LateJoinConfig`](crate::server::late_join::LateJoinConfig))
+#[derive(Event, Debug, Default)]
+pub struct WorldLoadedEvent;
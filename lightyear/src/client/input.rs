@@ -17,7 +17,7 @@ use crate::inputs::native::UserAction;
 use crate::prelude::TickManager;
 use crate::protocol::Protocol;
 use crate::shared::sets::{FixedUpdateSet, MainSet};
-use crate::shared::tick_manager::TickEvent;
+use crate::shared::tick_manager::{is_paused, TickEvent};
 
 #[derive(Debug, Clone)]
 pub struct InputConfig {
@@ -76,7 +76,10 @@ impl<P: Protocol> Plugin for InputPlugin<P> {
             (
                 FixedUpdateSet::TickUpdate,
                 // no need to keep buffering inputs during rollback
-                InputSystemSet::BufferInputs.run_if(not(is_in_rollback)),
+                // or while the simulation is paused
+                InputSystemSet::BufferInputs
+                    .run_if(not(is_in_rollback))
+                    .run_if(not(is_paused)),
                 InputSystemSet::WriteInputEvent,
                 FixedUpdateSet::Main,
                 InputSystemSet::ClearInputEvent,
@@ -93,7 +96,8 @@ impl<P: Protocol> Plugin for InputPlugin<P> {
                 // we send inputs only every send_interval
                 InputSystemSet::SendInputMessage
                     .in_set(MainSet::Send)
-                    .run_if(client_is_synced::<P>),
+                    .run_if(client_is_synced::<P>)
+                    .run_if(not(is_paused)),
                 MainSet::SendPackets,
             )
                 .chain(),
@@ -208,7 +212,7 @@ fn prepare_input_message<P: Protocol>(
 
     // we send redundant inputs, so that if a packet is lost, we can still recover
     let num_tick: u16 = ((config.shared.client_send_interval.as_nanos()
-        / config.shared.tick.tick_duration.as_nanos())
+        / tick_manager.tick_duration().as_nanos())
         + 1)
     .try_into()
     .unwrap();
@@ -228,11 +232,9 @@ fn prepare_input_message<P: Protocol>(
         // TODO: should we provide variants of each user-facing function, so that it pushes the error
         //  to the ConnectionEvents?
         debug!("sending input message: {:?}", message.end_tick);
-        connection
-            .send_message::<InputChannel, _>(message)
-            .unwrap_or_else(|err| {
-                error!("Error while sending input message: {:?}", err);
-            })
+        if let Err(err) = connection.send_message::<InputChannel, _>(message) {
+            error!("Error while sending input message: {:?}", err);
+        }
     }
     // NOTE: actually we keep the input values! because they might be needed when we rollback for client prediction
     // TODO: figure out when we can delete old inputs. Basically when the oldest prediction group tick has passed?
@@ -24,7 +24,7 @@ use crate::prelude::{MapEntities, TickManager};
 use crate::protocol::Protocol;
 use crate::shared::replication::components::PrePredicted;
 use crate::shared::sets::{FixedUpdateSet, MainSet};
-use crate::shared::tick_manager::TickEvent;
+use crate::shared::tick_manager::{is_paused, TickEvent};
 
 // TODO: the resource should have a generic param, but not the user-facing config struct
 #[derive(Debug, Clone, Resource)]
@@ -133,7 +133,7 @@ where
             FixedUpdate,
             (
                 FixedUpdateSet::TickUpdate,
-                InputSystemSet::BufferInputs,
+                InputSystemSet::BufferInputs.run_if(not(is_paused)),
                 FixedUpdateSet::Main,
             )
                 .chain(),
@@ -147,6 +147,7 @@ where
                 InputSystemSet::ReceiveTickEvents.run_if(client_is_synced::<P>),
                 InputSystemSet::SendInputMessage
                     .run_if(client_is_synced::<P>)
+                    .run_if(not(is_paused))
                     .in_set(MainSet::Send),
                 MainSet::SendPackets,
             )
@@ -490,7 +491,7 @@ fn prepare_input_message<P: Protocol, A: LeafwingUserAction>(
     // we send redundant inputs, so that if a packet is lost, we can still recover
     // A redundancy of 2 means that we can recover from 1 lost packet
     let num_tick: u16 = ((config.shared.client_send_interval.as_nanos()
-        / config.shared.tick.tick_duration.as_nanos())
+        / tick_manager.tick_duration().as_nanos())
         + 1)
     .try_into()
     .unwrap();
@@ -558,7 +559,7 @@ fn prepare_input_message<P: Protocol, A: LeafwingUserAction>(
                         &mut message,
                         tick,
                         message_len,
-                        InputTarget::Entity(server_entity),
+                        InputTarget::Entity(server_entity.to_placeholder()),
                     );
                 }
             } else {
@@ -596,11 +597,9 @@ fn prepare_input_message<P: Protocol, A: LeafwingUserAction>(
             "sending input message: {:?}",
             message.diffs
         );
-        connection
-            .send_message::<InputChannel, InputMessage<A>>(message)
-            .unwrap_or_else(|err| {
-                error!("Error while sending input message: {:?}", err);
-            })
+        if let Err(err) = connection.send_message::<InputChannel, InputMessage<A>>(message) {
+            error!("Error while sending input message: {:?}", err);
+        }
     }
 
     // NOTE: actually we keep the input values! because they might be needed when we rollback for client prediction
@@ -1,5 +1,6 @@
 use bevy::ecs::system::EntityCommands;
 use bevy::prelude::{Commands, Component, Entity, Mut, Query, Res, ResMut};
+use bevy::utils::Duration;
 use tracing::{info, trace};
 
 use crate::_reexport::ComponentProtocol;
@@ -30,6 +31,31 @@ pub struct InterpolateStatus<C: Component> {
     pub end: Option<(Tick, C)>,
     /// current interpolation tick
     pub current: Tick,
+    /// confirmed tick received before `start`, along with value.
+    /// Used to extrapolate past `start` when there is no `end` to interpolate towards
+    pub previous: Option<(Tick, C)>,
+    /// last value that was computed by extrapolation, if we are currently extrapolating
+    pub extrapolated: Option<C>,
+    /// tick at which we started blending back from an extrapolated value, along with that value
+    pub blend: Option<(Tick, C)>,
+}
+
+impl<C: Component> InterpolateStatus<C> {
+    pub(crate) fn new(current: Tick) -> Self {
+        Self {
+            start: None,
+            end: None,
+            current,
+            previous: None,
+            extrapolated: None,
+            blend: None,
+        }
+    }
+}
+
+/// Convert a duration to a number of ticks (rounded down)
+fn duration_to_ticks(duration: Duration, tick_duration: Duration) -> i16 {
+    (duration.as_secs_f32() / tick_duration.as_secs_f32()) as i16
 }
 
 /// At the end of each frame, interpolate the components between the last 2 confirmed server states
@@ -55,8 +81,17 @@ pub(crate) fn update_interpolate_status<C: SyncComponent, P: Protocol>(
     // how many ticks between each interpolation (add 1 to roughly take the ceil)
     let send_interval_delta_tick = (SEND_INTERVAL_TICK_FACTOR
         * config.shared.server_send_interval.as_secs_f32()
-        / config.shared.tick.tick_duration.as_secs_f32()) as i16
+        / tick_manager.tick_duration().as_secs_f32()) as i16
         + 1;
+    // if we extrapolate, we keep the start tick around for longer so that we can project from it
+    let extrapolation_delta_tick = if P::Components::has_extrapolation::<C>() {
+        duration_to_ticks(
+            config.interpolation.max_extrapolation,
+            tick_manager.tick_duration(),
+        )
+    } else {
+        0
+    };
 
     let current_interpolate_tick = connection
         .sync_manager
@@ -64,6 +99,7 @@ pub(crate) fn update_interpolate_status<C: SyncComponent, P: Protocol>(
     for (entity, component, mut status, mut history) in query.iter_mut() {
         let mut start = status.start.take();
         let mut end = status.end.take();
+        let mut previous = status.previous.take();
 
         // if the interpolation tick is beyond the previous end tick,
         // we need to replace start with end, and clear end
@@ -75,7 +111,7 @@ pub(crate) fn update_interpolate_status<C: SyncComponent, P: Protocol>(
                     ?current_interpolate_tick,
                     "interpolation is beyond previous end tick"
                 );
-                start = end.clone();
+                previous = std::mem::replace(&mut start, end.clone());
                 // TODO: this clone should be avoidable
                 if let Some(mut component) = component {
                     *component = end_value.clone();
@@ -99,7 +135,7 @@ pub(crate) fn update_interpolate_status<C: SyncComponent, P: Protocol>(
                     old_start = ?start.as_ref().map(|(tick, _)| tick),
                     new_start = ?new_tick,
                     "found more recent tick between start and interpolation tick");
-                start = new_start;
+                previous = std::mem::replace(&mut start, new_start);
             }
         }
 
@@ -158,10 +194,14 @@ pub(crate) fn update_interpolate_status<C: SyncComponent, P: Protocol>(
         if end.is_none() {
             let temp_start = std::mem::take(&mut start);
             if let Some((start_tick, _)) = temp_start {
-                if current_interpolate_tick - start_tick < send_interval_delta_tick {
+                if current_interpolate_tick - start_tick
+                    < send_interval_delta_tick + extrapolation_delta_tick
+                {
                     start = temp_start;
+                } else {
+                    // if it's been too long, reset the server tick to None
+                    previous = None;
                 }
-                // else (if it's been too long), reset the server tick to None
             }
         }
 
@@ -175,6 +215,7 @@ pub(crate) fn update_interpolate_status<C: SyncComponent, P: Protocol>(
             "update_interpolate_status");
         status.start = start;
         status.end = end;
+        status.previous = previous;
         status.current = current_interpolate_tick;
         if status.start.is_none() {
             trace!("no lerp start tick");
@@ -186,8 +227,10 @@ pub(crate) fn update_interpolate_status<C: SyncComponent, P: Protocol>(
 }
 
 pub(crate) fn interpolate<C: Component + Clone, P: Protocol>(
+    config: Res<ClientConfig>,
+    tick_manager: Res<TickManager>,
     mut commands: Commands,
-    mut query: Query<(Entity, Option<&mut C>, &InterpolateStatus<C>)>,
+    mut query: Query<(Entity, Option<&mut C>, &mut InterpolateStatus<C>)>,
 ) where
     P::Components: SyncMetadata<C>,
 {
@@ -199,31 +242,81 @@ pub(crate) fn interpolate<C: Component + Clone, P: Protocol>(
         }
     };
 
-    for (entity, component, status) in query.iter_mut() {
-        let entity_commands = commands.entity(entity);
+    let max_extrapolation_ticks = duration_to_ticks(
+        config.interpolation.max_extrapolation,
+        tick_manager.tick_duration(),
+    );
+    let blend_ticks = duration_to_ticks(
+        config.interpolation.extrapolation_blend,
+        tick_manager.tick_duration(),
+    );
+
+    for (entity, component, mut status) in query.iter_mut() {
+        let mut value = None;
+        let mut extrapolating = false;
         // NOTE: it is possible that we reach start_tick when end_tick is not set
         if let Some((start_tick, start_value)) = &status.start {
             if status.current == *start_tick {
                 trace!(?entity, ?start_tick, "setting component to start value");
-                set_value(entity_commands, component, start_value.clone());
-                continue;
-            }
-            if let Some((end_tick, end_value)) = &status.end {
+                value = Some(start_value.clone());
+            } else if let Some((end_tick, end_value)) = &status.end {
                 trace!(?entity, ?start_tick, interpolate_tick=?status.current, ?end_tick, "doing interpolation!");
                 if status.current == *end_tick {
-                    set_value(entity_commands, component, end_value.clone());
-                    continue;
-                }
-                if start_tick != end_tick {
+                    value = Some(end_value.clone());
+                } else if start_tick != end_tick {
                     let t =
                         (status.current - *start_tick) as f32 / (*end_tick - *start_tick) as f32;
-                    let value = P::Components::lerp(start_value.clone(), end_value.clone(), t);
-                    set_value(entity_commands, component, value);
+                    value = Some(P::Components::lerp(
+                        start_value.clone(),
+                        end_value.clone(),
+                        t,
+                    ));
+                } else {
+                    value = Some(start_value.clone());
+                }
+            } else if P::Components::has_extrapolation::<C>() && status.current > *start_tick {
+                // we have no server update to interpolate towards: project forward from the
+                // last 2 confirmed states, for a bounded amount of time
+                if let Some((previous_tick, previous_value)) = &status.previous {
+                    if previous_tick < start_tick {
+                        let ticks_past =
+                            std::cmp::min(status.current - *start_tick, max_extrapolation_ticks);
+                        let t = 1.0 + ticks_past as f32 / (*start_tick - *previous_tick) as f32;
+                        trace!(?entity, ?start_tick, interpolate_tick=?status.current, ?t, "doing extrapolation!");
+                        value = Some(P::Components::extrapolate(
+                            previous_value.clone(),
+                            start_value.clone(),
+                            t,
+                        ));
+                        extrapolating = true;
+                    }
+                }
+            }
+        }
+        let Some(mut value) = value else {
+            continue;
+        };
+
+        if extrapolating {
+            status.extrapolated = Some(value.clone());
+            status.blend = None;
+        } else {
+            // we were extrapolating and received new server updates: blend back smoothly
+            // from the extrapolated value instead of snapping to the interpolated value
+            if let Some(extrapolated) = status.extrapolated.take() {
+                status.blend = Some((status.current, extrapolated));
+            }
+            if let Some((blend_tick, blend_value)) = &status.blend {
+                let t =
+                    (status.current - *blend_tick) as f32 / std::cmp::max(blend_ticks, 1) as f32;
+                if t >= 1.0 {
+                    status.blend = None;
                 } else {
-                    set_value(entity_commands, component, start_value.clone());
+                    value = P::Components::lerp(blend_value.clone(), value, t);
                 }
             }
         }
+        set_value(commands.entity(entity), component, value);
     }
 }
 
@@ -492,3 +585,79 @@ pub(crate) fn interpolate<C: Component + Clone, P: Protocol>(
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::App;
+
+    use crate::prelude::client::ClientConfig;
+    use crate::prelude::{SharedConfig, TickConfig};
+    use crate::tests::protocol::*;
+
+    use super::*;
+
+    fn status(app: &mut App, entity: Entity) -> Mut<'_, InterpolateStatus<Component5>> {
+        app.world
+            .get_mut::<InterpolateStatus<Component5>>(entity)
+            .unwrap()
+    }
+
+    fn value(app: &App, entity: Entity) -> f32 {
+        app.world.get::<Component5>(entity).unwrap().0
+    }
+
+    #[test]
+    fn test_extrapolation() {
+        let tick_config = TickConfig::new(Duration::from_millis(10));
+        let mut app = App::new();
+        app.insert_resource(ClientConfig {
+            shared: SharedConfig {
+                tick: tick_config.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        app.insert_resource(TickManager::from_config(tick_config));
+        app.add_systems(bevy::prelude::Update, interpolate::<Component5, MyProtocol>);
+
+        let mut status_component = InterpolateStatus::new(Tick(4));
+        status_component.previous = Some((Tick(0), Component5(0.0)));
+        status_component.start = Some((Tick(2), Component5(2.0)));
+        let entity = app.world.spawn(status_component).id();
+
+        // no end: we project forward from the last 2 confirmed states
+        app.update();
+        assert_eq!(value(&app, entity), 4.0);
+        assert_eq!(status(&mut app, entity).extrapolated, Some(Component5(4.0)));
+
+        // extrapolation is bounded by `max_extrapolation` (200ms = 20 ticks)
+        status(&mut app, entity).current = Tick(40);
+        app.update();
+        assert_eq!(value(&app, entity), 22.0);
+
+        // new server updates arrive: we blend back from the extrapolated value
+        // over `extrapolation_blend` (100ms = 10 ticks)
+        {
+            let mut status = status(&mut app, entity);
+            status.start = Some((Tick(40), Component5(40.0)));
+            status.end = Some((Tick(60), Component5(60.0)));
+            status.current = Tick(42);
+        }
+        app.update();
+        assert_eq!(value(&app, entity), 22.0);
+        assert_eq!(
+            status(&mut app, entity).blend,
+            Some((Tick(42), Component5(22.0)))
+        );
+
+        status(&mut app, entity).current = Tick(47);
+        app.update();
+        assert_eq!(value(&app, entity), 34.5);
+
+        // the blend is over, we are back to pure interpolation
+        status(&mut app, entity).current = Tick(52);
+        app.update();
+        assert_eq!(value(&app, entity), 52.0);
+        assert_eq!(status(&mut app, entity).blend, None);
+    }
+}
@@ -107,13 +107,11 @@ pub(crate) fn add_component_history<C: SyncComponent, P: Protocol>(
                                 //  we can interpolate between. Otherwise it will look jarring if send_interval is low.
                                 // new_component,
                                 history,
-                                InterpolateStatus::<C> {
-                                    start: None,
-                                    end: None,
-                                    current: connection
+                                InterpolateStatus::<C>::new(
+                                    connection
                                         .sync_manager
                                         .interpolation_tick(tick_manager.as_ref()),
-                                },
+                                ),
                             ));
                         }
                         ComponentSyncMode::Once | ComponentSyncMode::Simple => {
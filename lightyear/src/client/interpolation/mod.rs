@@ -20,6 +20,7 @@ mod interpolate;
 pub mod interpolation_history;
 pub mod plugin;
 mod resource;
+pub mod visual_interpolation;
 
 pub struct LinearInterpolator;
 impl<C> LerpFn<C> for LinearInterpolator
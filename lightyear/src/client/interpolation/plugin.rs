@@ -18,6 +18,9 @@ use super::interpolation_history::{
 };
 use super::spawn_interpolated_entity;
 
+/// Number of server updates we need to receive before we start adapting the interpolation delay
+const MIN_ADAPTIVE_SAMPLES: u32 = 10;
+
 // TODO: maybe this is not an enum and user can specify multiple values, and we use the max delay between all of them?
 #[derive(Clone)]
 pub struct InterpolationDelay {
@@ -30,6 +33,10 @@ pub struct InterpolationDelay {
     /// The higher the server update_rate (i.e. smaller send_interval), the smaller the interpolation delay
     /// Set to 0.0 if you want to only use the Delay
     pub send_interval_ratio: f32,
+    /// If set, the client will continuously tune the interpolation delay from the observed
+    /// arrival jitter and gaps of server updates, instead of using a fixed delay.
+    /// The fixed delay is still used until we have received enough server updates.
+    pub adaptive: Option<AdaptiveInterpolationDelay>,
 }
 
 impl Default for InterpolationDelay {
@@ -37,10 +44,68 @@ impl Default for InterpolationDelay {
         Self {
             min_delay: Duration::from_millis(0),
             send_interval_ratio: 2.0,
+            adaptive: None,
         }
     }
 }
 
+/// Settings for tuning the interpolation delay at runtime
+#[derive(Clone, Debug)]
+pub struct AdaptiveInterpolationDelay {
+    /// How many multiples of the measured arrival jitter we add as margin on top of the
+    /// largest recent gap between server updates
+    pub jitter_multiplier: f32,
+    /// The interpolation delay will never be bigger than this
+    pub max_delay: Duration,
+    /// Smoothing factor (between 0.0 and 1.0) used to update the jitter/gap estimates
+    /// with each new server update. Higher values react faster but are noisier.
+    pub smoothing: f32,
+}
+
+impl Default for AdaptiveInterpolationDelay {
+    fn default() -> Self {
+        Self {
+            jitter_multiplier: 2.0,
+            max_delay: Duration::from_millis(500),
+            smoothing: 0.05,
+        }
+    }
+}
+
+/// Statistics about the arrival of server updates, used to compute the adaptive interpolation delay
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct UpdateArrivalStats {
+    /// Number of samples recorded
+    pub(crate) samples: u32,
+    /// Smoothed deviation between the observed and the expected interval between two server updates
+    pub(crate) jitter: Duration,
+    /// Largest recent interval between two server updates; it rises immediately but decays slowly
+    pub(crate) max_gap: Duration,
+}
+
+impl UpdateArrivalStats {
+    /// Record a new server update that arrived `gap` after the previous one,
+    /// when we expected it to arrive `expected` after the previous one (from the server ticks)
+    pub(crate) fn record(&mut self, gap: Duration, expected: Duration, smoothing: f32) {
+        let deviation = if gap > expected {
+            gap - expected
+        } else {
+            expected - gap
+        };
+        if self.samples == 0 {
+            self.jitter = deviation;
+            self.max_gap = gap;
+        } else {
+            self.jitter = self.jitter.mul_f32(1.0 - smoothing) + deviation.mul_f32(smoothing);
+            self.max_gap = std::cmp::max(
+                gap,
+                self.max_gap.mul_f32(1.0 - smoothing) + gap.mul_f32(smoothing),
+            );
+        }
+        self.samples = self.samples.saturating_add(1);
+    }
+}
+
 impl InterpolationDelay {
     pub fn with_min_delay(mut self, min_delay: Duration) -> Self {
         self.min_delay = min_delay;
@@ -52,12 +117,36 @@ impl InterpolationDelay {
         self
     }
 
+    pub fn with_adaptive(mut self, adaptive: AdaptiveInterpolationDelay) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
     /// How much behind the latest server update we want the interpolation time to be
     pub(crate) fn to_duration(&self, server_send_interval: Duration) -> Duration {
         // TODO: deal with server_send_interval = 0 (set to frame rate)
         let ratio_value = server_send_interval.mul_f32(self.send_interval_ratio);
         std::cmp::max(ratio_value, self.min_delay)
     }
+
+    /// The interpolation delay we should be converging towards, given the observed arrival of server updates
+    pub(crate) fn target_duration(
+        &self,
+        server_send_interval: Duration,
+        stats: &UpdateArrivalStats,
+    ) -> Duration {
+        match &self.adaptive {
+            Some(adaptive) if stats.samples >= MIN_ADAPTIVE_SAMPLES => {
+                let delay = std::cmp::max(stats.max_gap, server_send_interval)
+                    + stats.jitter.mul_f32(adaptive.jitter_multiplier);
+                delay.clamp(
+                    self.min_delay,
+                    std::cmp::max(self.min_delay, adaptive.max_delay),
+                )
+            }
+            _ => self.to_duration(server_send_interval),
+        }
+    }
 }
 
 /// How much behind the client time the interpolated entities are
@@ -69,6 +158,12 @@ pub struct InterpolationConfig {
     /// If true, disable the interpolation logic (but still keep the internal component history buffers)
     /// The user will have to manually implement
     pub custom_interpolation_logic: bool,
+    /// For components with an `extrapolator`, how far past the latest confirmed state we are allowed
+    /// to project the component when server updates stop arriving
+    pub max_extrapolation: Duration,
+    /// How long it takes to blend from the extrapolated value back to the interpolated value
+    /// once new server updates arrive
+    pub extrapolation_blend: Duration,
     // How long are we keeping the history of the confirmed entities so we can interpolate between them?
     // pub(crate) interpolation_buffer_size: Duration,
 }
@@ -79,6 +174,8 @@ impl Default for InterpolationConfig {
         Self {
             delay: InterpolationDelay::default(),
             custom_interpolation_logic: false,
+            max_extrapolation: Duration::from_millis(200),
+            extrapolation_blend: Duration::from_millis(100),
             // interpolation_buffer_size: Duration::from_millis(100),
         }
     }
@@ -89,6 +186,16 @@ impl InterpolationConfig {
         self.delay = delay;
         self
     }
+
+    pub fn with_max_extrapolation(mut self, max_extrapolation: Duration) -> Self {
+        self.max_extrapolation = max_extrapolation;
+        self
+    }
+
+    pub fn with_extrapolation_blend(mut self, extrapolation_blend: Duration) -> Self {
+        self.extrapolation_blend = extrapolation_blend;
+        self
+    }
 }
 
 pub struct InterpolationPlugin<P: Protocol> {
@@ -0,0 +1,266 @@
+//! Visually interpolate components between FixedUpdate ticks
+//!
+//! Entities that are simulated in FixedUpdate (for example Predicted entities) only change once per tick,
+//! so they appear to stutter if the frame rate is higher than the tick rate.
+//! This plugin keeps the values of a component at the previous and current ticks, and every frame
+//! writes a visual value interpolated between them using the overstep of the fixed timestep.
+//! The simulated value is restored at the start of the next frame, so the simulation state is left untouched.
+use std::marker::PhantomData;
+
+use bevy::prelude::{
+    App, Commands, Component, Entity, FixedUpdate, IntoSystemConfigs, IntoSystemSetConfigs, Plugin,
+    PostUpdate, PreUpdate, Query, Res, SystemSet, With, Without,
+};
+use bevy::transform::TransformSystem;
+use tracing::trace;
+
+use crate::client::components::LerpFn;
+use crate::client::interpolation::LinearInterpolator;
+use crate::client::prediction::plugin::PredictionSet;
+use crate::client::prediction::Predicted;
+use crate::prelude::TickManager;
+use crate::shared::sets::FixedUpdateSet;
+use crate::shared::time_manager::TimeManager;
+
+/// Plugin that visually interpolates the component `C` between FixedUpdate ticks,
+/// using the interpolation function `L`.
+///
+/// The interpolation is applied to all `Predicted` entities with the component `C`, and to any other entity
+/// (for example local non-networked entities) that has a [`VisualInterpolateStatus<C>`] component.
+pub struct VisualInterpolationPlugin<C, L = LinearInterpolator> {
+    _marker: PhantomData<(C, L)>,
+}
+
+impl<C, L> Default for VisualInterpolationPlugin<C, L> {
+    fn default() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum VisualInterpolationSet {
+    // PreUpdate Sets
+    /// Restore the simulated value of the components
+    Restore,
+    // FixedUpdate Sets
+    /// Store the simulated value of the components at the end of each tick
+    UpdateStatus,
+    // PostUpdate Sets
+    /// Write the visually interpolated value of the components
+    Interpolate,
+}
+
+/// Component that stores the values of the component `C` at the previous and current ticks
+#[derive(Component, Debug, PartialEq)]
+pub struct VisualInterpolateStatus<C: Component> {
+    /// Value of the component at the previous tick
+    pub previous_value: Option<C>,
+    /// Value of the component at the current tick
+    pub current_value: Option<C>,
+}
+
+impl<C: Component> Default for VisualInterpolateStatus<C> {
+    fn default() -> Self {
+        Self {
+            previous_value: None,
+            current_value: None,
+        }
+    }
+}
+
+impl<C, L> Plugin for VisualInterpolationPlugin<C, L>
+where
+    C: Component + Clone,
+    L: LerpFn<C> + Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        // SETS
+        app.configure_sets(
+            PreUpdate,
+            VisualInterpolationSet::Restore
+                .after(PredictionSet::RestoreVisualCorrection)
+                .before(PredictionSet::CheckRollback),
+        );
+        app.configure_sets(
+            FixedUpdate,
+            VisualInterpolationSet::UpdateStatus.after(FixedUpdateSet::Main),
+        );
+        app.configure_sets(
+            PostUpdate,
+            VisualInterpolationSet::Interpolate
+                .before(PredictionSet::VisualCorrection)
+                .before(TransformSystem::TransformPropagate),
+        );
+        // SYSTEMS
+        app.add_systems(
+            PreUpdate,
+            (
+                add_visual_interpolation_status::<C>,
+                restore_simulated_value::<C>,
+            )
+                .in_set(VisualInterpolationSet::Restore),
+        );
+        app.add_systems(
+            FixedUpdate,
+            update_visual_interpolation_status::<C>.in_set(VisualInterpolationSet::UpdateStatus),
+        );
+        app.add_systems(
+            PostUpdate,
+            visual_interpolation::<C, L>.in_set(VisualInterpolationSet::Interpolate),
+        );
+    }
+}
+
+/// Start visually interpolating the component on predicted entities
+fn add_visual_interpolation_status<C: Component>(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        (
+            With<Predicted>,
+            With<C>,
+            Without<VisualInterpolateStatus<C>>,
+        ),
+    >,
+) {
+    for entity in query.iter() {
+        commands
+            .entity(entity)
+            .insert(VisualInterpolateStatus::<C>::default());
+    }
+}
+
+/// At the start of the frame, restore the simulated value of the component
+/// (that was replaced by the visual value during the previous frame)
+pub(crate) fn restore_simulated_value<C: Component + Clone>(
+    mut query: Query<(&mut C, &VisualInterpolateStatus<C>)>,
+) {
+    for (mut component, status) in query.iter_mut() {
+        if let Some(current_value) = &status.current_value {
+            *component = current_value.clone();
+        }
+    }
+}
+
+/// At the end of each tick, store the simulated value of the component
+pub(crate) fn update_visual_interpolation_status<C: Component + Clone>(
+    mut query: Query<(&C, &mut VisualInterpolateStatus<C>)>,
+) {
+    for (component, mut status) in query.iter_mut() {
+        status.previous_value = std::mem::take(&mut status.current_value);
+        status.current_value = Some(component.clone());
+    }
+}
+
+/// Write the visual value of the component, interpolated between the previous and current ticks
+/// using the overstep of the fixed timestep
+pub(crate) fn visual_interpolation<C: Component + Clone, L: LerpFn<C>>(
+    time_manager: Res<TimeManager>,
+    tick_manager: Res<TickManager>,
+    mut query: Query<(&mut C, &VisualInterpolateStatus<C>)>,
+) {
+    let t = (time_manager.overstep().as_secs_f32() / tick_manager.tick_duration().as_secs_f32())
+        .clamp(0.0, 1.0);
+    for (mut component, status) in query.iter_mut() {
+        if let (Some(previous_value), Some(current_value)) =
+            (&status.previous_value, &status.current_value)
+        {
+            trace!(?t, "visually interpolating component");
+            *component = L::lerp(previous_value.clone(), current_value.clone(), t);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{Query, ResMut, Resource};
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct SimulatedTicks(u32);
+
+    fn move_component(mut ticks: ResMut<SimulatedTicks>, mut query: Query<&mut Component1>) {
+        ticks.0 += 1;
+        for mut c in query.iter_mut() {
+            c.0 += 1.0;
+        }
+    }
+
+    #[test]
+    fn test_visual_interpolation() {
+        // frames are shorter than ticks
+        let frame_duration = Duration::from_millis(4);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: false,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            LinkConditionerConfig {
+                incoming_latency: Duration::from_millis(20),
+                incoming_jitter: Duration::from_millis(0),
+                incoming_loss: 0.0,
+            },
+            frame_duration,
+        );
+        stepper
+            .client_app
+            .add_plugins(VisualInterpolationPlugin::<Component1>::default());
+        stepper
+            .client_app
+            .add_systems(FixedUpdate, move_component.in_set(FixedUpdateSet::Main));
+        stepper.client_app.init_resource::<SimulatedTicks>();
+        stepper.init();
+
+        // a local non-networked entity
+        let entity = stepper
+            .client_app
+            .world
+            .spawn((
+                Component1(0.0),
+                VisualInterpolateStatus::<Component1>::default(),
+            ))
+            .id();
+        let start_tick = stepper.client_app.world.resource::<SimulatedTicks>().0;
+
+        for _ in 0..20 {
+            stepper.frame_step();
+            let overstep = stepper
+                .client_app
+                .world
+                .resource::<TimeManager>()
+                .overstep();
+            let status = stepper
+                .client_app
+                .world
+                .get::<VisualInterpolateStatus<Component1>>(entity)
+                .unwrap();
+            let (Some(previous), Some(current)) = (&status.previous_value, &status.current_value)
+            else {
+                continue;
+            };
+            // the simulation state is not affected by the visual interpolation
+            let simulated_ticks = stepper.client_app.world.resource::<SimulatedTicks>().0;
+            assert_eq!(current.0, (simulated_ticks - start_tick) as f32);
+            assert_eq!(current.0 - previous.0, 1.0);
+            // the visual value is interpolated between the last 2 ticks
+            let t = overstep.as_secs_f32() / tick_duration.as_secs_f32();
+            let visual = stepper.client_app.world.get::<Component1>(entity).unwrap();
+            assert!((visual.0 - (previous.0 + t)).abs() < 1e-4);
+        }
+    }
+}
@@ -13,12 +13,18 @@ pub mod input;
 
 pub mod interpolation;
 
+pub mod networking;
+
+pub mod playback;
+
 pub mod plugin;
 
 pub mod prediction;
 
 pub mod resource;
 
+pub mod scheduled;
+
 pub mod sync;
 
 mod diagnostics;
@@ -0,0 +1,192 @@
+//! Expose the state of the connection with the server as a bevy [`States`]
+//!
+//! Users can react to the connection lifecycle with the usual bevy state hooks
+//! (`OnEnter(NetworkingState::Connected)`, `in_state(NetworkingState::Connecting)`, etc.)
+use bevy::prelude::*;
+use tracing::info;
+
+use crate::client::connection::ConnectionManager;
+use crate::client::systems::write_events;
+use crate::connection::events::ConnectionEvents;
+use crate::protocol::Protocol;
+
+/// State of the connection between the client and the server
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkingState {
+    /// The client is not connected to any server.
+    /// This is also the state after a disconnection, a timeout, or a connection denied by the server.
+    #[default]
+    Disconnected,
+    /// The client is going through the netcode handshake with the server
+    Connecting,
+    /// The client is connected, but its time and tick are not synced with the server yet
+    Syncing,
+    /// The client is connected and synced with the server
+    Connected,
+}
+
+impl NetworkingState {
+    fn is_connected(&self) -> bool {
+        matches!(self, NetworkingState::Syncing | NetworkingState::Connected)
+    }
+}
+
+/// Update the [`NetworkingState`] from the state of the netcode client and of the [`ConnectionManager`].
+///
+/// When the client loses the connection to the server (because of a call to `disconnect`, a timeout,
+/// or a disconnection from the server), the entities that were replicated from the server are despawned
+/// and the [`ConnectionManager`] is reset, so that the client can connect again with a clean state.
+/// The messages sent with a [`MessageHandle`](crate::packet::message::MessageHandle) that were not acked yet
+/// are reported as lost.
+pub(crate) fn update_networking_state<P: Protocol>(world: &mut World) {
+    let current = *world.resource::<State<NetworkingState>>().get();
+    let netcode = world.resource::<crate::netcode::Client>();
+    let target = if netcode.is_connected() {
+        if world.resource::<ConnectionManager<P>>().is_synced() {
+            NetworkingState::Connected
+        } else {
+            NetworkingState::Syncing
+        }
+    } else if netcode.is_pending() {
+        NetworkingState::Connecting
+    } else {
+        NetworkingState::Disconnected
+    };
+    if current.is_connected() && !target.is_connected() {
+        info!("Disconnected from the server, resetting the connection");
+        world.resource_scope(
+            |world: &mut World, mut connection: Mut<ConnectionManager<P>>| {
+                // the tracked messages that were not acked yet will never be acked
+                let (acked, lost, expired) = connection
+                    .message_manager
+                    .take_final_delivery_notifications();
+                let mut events = ConnectionEvents::<P>::new();
+                events.push_message_delivery(acked, lost, expired);
+                connection.reset(world);
+                write_events(world, &mut events);
+            },
+        );
+    }
+    if target != current {
+        world
+            .resource_mut::<NextState<NetworkingState>>()
+            .set(target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::client::resource::ClientMut;
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    fn state(stepper: &BevyStepper) -> NetworkingState {
+        *stepper
+            .client_app
+            .world
+            .resource::<State<NetworkingState>>()
+            .get()
+    }
+
+    #[test]
+    fn test_disconnect_and_reconnect() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: true,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let sync_config = SyncConfig::default().speedup_factor(1.0);
+        let prediction_config = PredictionConfig::default().disable(false);
+        let interpolation_config = InterpolationConfig::default();
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            sync_config,
+            prediction_config,
+            interpolation_config,
+            link_conditioner,
+            frame_duration,
+        );
+        assert_eq!(state(&stepper), NetworkingState::Disconnected);
+        stepper.init();
+        stepper.frame_step();
+        assert_eq!(state(&stepper), NetworkingState::Connected);
+
+        // replicate an entity to the client
+        stepper
+            .server_app
+            .world
+            .spawn((Component1(1.0), Replicate::default()));
+        for _ in 0..10 {
+            stepper.frame_step();
+        }
+        let mut query = stepper
+            .client_app
+            .world
+            .query_filtered::<Entity, With<Component1>>();
+        assert_eq!(query.iter(&stepper.client_app.world).count(), 1);
+
+        // disconnect: the replicated entities are despawned, and the messages that were not acked are lost
+        let handle =
+            stepper
+                .client_app
+                .world
+                .run_system_once(|mut client: ClientMut<MyProtocol>| {
+                    let handle = client
+                        .send_message::<OrderedChannel, _>(Message2(0))
+                        .unwrap()
+                        .unwrap();
+                    client.disconnect().unwrap();
+                    handle
+                });
+        let mut reader = bevy::ecs::event::ManualEventReader::<MessageLostEvent>::default();
+        let mut lost = vec![];
+        for _ in 0..2 {
+            stepper.frame_step();
+            let events = stepper
+                .client_app
+                .world
+                .resource::<Events<MessageLostEvent>>();
+            lost.extend(reader.read(events).map(|event| event.handle()));
+        }
+        assert_eq!(state(&stepper), NetworkingState::Disconnected);
+        assert_eq!(query.iter(&stepper.client_app.world).count(), 0);
+        assert_eq!(lost, vec![handle]);
+        assert!(!stepper
+            .client_app
+            .world
+            .resource::<ConnectionManager<MyProtocol>>()
+            .is_synced());
+
+        // reconnect with a new token, without rebuilding the App
+        let auth = stepper.auth.clone();
+        stepper
+            .client_app
+            .world
+            .run_system_once(move |mut client: ClientMut<MyProtocol>| {
+                client.connect_with_auth(auth.clone()).unwrap()
+            });
+        stepper.frame_step();
+        assert_eq!(state(&stepper), NetworkingState::Connecting);
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+        assert_eq!(state(&stepper), NetworkingState::Connected);
+        // the entity is replicated again
+        assert_eq!(query.iter(&stepper.client_app.world).count(), 1);
+    }
+}
@@ -0,0 +1,555 @@
+//! Play back a recording of the server's replication stream (see [`RecordingConfig`](crate::server::recording::RecordingConfig))
+//!
+//! The [`PlaybackPlugin`] is added to a client [`App`] that has the [`ClientPlugin`](crate::client::plugin::ClientPlugin) but
+//! does not connect to a server. Instead of receiving replication messages from the network, the recorded messages
+//! are applied to the world at the recorded pace. All recorded entities are interpolated, using the usual
+//! interpolation pipeline.
+//!
+//! The playback can be controlled via the [`Playback`] resource (pause, speed, seek).
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::DerefMut;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use bevy::prelude::{
+    App, IntoSystemConfigs, Mut, Plugin, PreUpdate, Resource, Time, Virtual, World,
+};
+use bevy::utils::Duration;
+use tracing::{debug, error, trace};
+
+use crate::_reexport::WrappedTime;
+use crate::client::config::ClientConfig;
+use crate::client::connection::ConnectionManager;
+use crate::client::systems::{receive, write_events};
+use crate::prelude::MainSet;
+use crate::protocol::Protocol;
+use crate::shared::replication::recording::Recording;
+
+/// Plugin that plays back a recording of the replication stream
+pub struct PlaybackPlugin<P: Protocol> {
+    // we add Mutex<Option> so that we can get ownership of the inner from an immutable reference
+    // in build()
+    playback: Mutex<Option<Playback<P>>>,
+}
+
+impl<P: Protocol> PlaybackPlugin<P> {
+    pub fn new(recording: Recording<P>) -> Self {
+        Self {
+            playback: Mutex::new(Some(Playback::new(recording))),
+        }
+    }
+
+    /// Load the recording from a file
+    ///
+    /// Returns an error if the file cannot be opened or is not a valid recording.
+    pub fn from_file(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            playback: Mutex::new(Some(Playback::from_file(path)?)),
+        })
+    }
+}
+
+impl<P: Protocol> Plugin for PlaybackPlugin<P> {
+    fn build(&self, app: &mut App) {
+        let resource = self.playback.lock().unwrap().deref_mut().take().unwrap();
+        app.insert_resource(resource).add_systems(
+            PreUpdate,
+            playback::<P>.in_set(MainSet::Receive).after(receive::<P>),
+        );
+    }
+}
+
+/// Resource used to control the playback of a recording
+#[derive(Resource)]
+pub struct Playback<P: Protocol> {
+    recording: Recording<P>,
+    /// Offset (in ticks since the first frame) of each frame
+    tick_offsets: Vec<u32>,
+    /// Index of the next frame to apply to the world
+    next_frame: usize,
+    /// Time since the start of the recording that is currently displayed
+    position: Duration,
+    paused: bool,
+    speed: f32,
+    /// If true, the world must be reset and the frames re-applied from the start (after seeking backwards)
+    needs_reset: bool,
+}
+
+impl<P: Protocol> Playback<P> {
+    pub fn new(recording: Recording<P>) -> Self {
+        Self {
+            tick_offsets: recording.tick_offsets(),
+            recording,
+            next_frame: 0,
+            position: Duration::default(),
+            paused: false,
+            speed: 1.0,
+            needs_reset: false,
+        }
+    }
+
+    /// Load a recording from a file
+    pub fn from_file(path: impl Into<PathBuf>) -> Result<Self> {
+        let file = File::open(path.into())?;
+        Ok(Self::new(Recording::read(BufReader::new(file))?))
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Set the speed of the playback (1.0 is the recorded speed)
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Time since the start of the recording that is currently displayed
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    /// Total duration of the recording
+    pub fn duration(&self) -> Duration {
+        self.recording.duration()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.duration()
+    }
+
+    /// Jump to a given time in the recording
+    pub fn seek(&mut self, position: Duration) {
+        let position = position.min(self.duration());
+        if position < self.position {
+            self.needs_reset = true;
+        }
+        self.position = position;
+    }
+
+    fn advance(&mut self, delta: Duration) {
+        if !self.paused {
+            self.position = (self.position + delta.mul_f32(self.speed)).min(self.duration());
+        }
+    }
+
+    /// Index of the frames that are not applied yet and whose time is before `until`
+    fn next_frames(&mut self, until: Duration) -> std::ops::Range<usize> {
+        let tick_duration = self.recording.tick_duration;
+        let start = self.next_frame;
+        while self
+            .tick_offsets
+            .get(self.next_frame)
+            .map_or(false, |offset| tick_duration * *offset <= until)
+        {
+            self.next_frame += 1;
+        }
+        start..self.next_frame
+    }
+
+    /// Apply the recorded frames up to `until` to the world
+    pub(crate) fn apply_frames(
+        &mut self,
+        world: &mut World,
+        connection: &mut ConnectionManager<P>,
+        until: Duration,
+    ) {
+        if self.needs_reset {
+            debug!("Resetting the world to seek backwards in the recording");
+            connection.replication_receiver.reset(world);
+            self.next_frame = 0;
+            self.needs_reset = false;
+        }
+        for index in self.next_frames(until) {
+            let frame = &self.recording.frames[index];
+            trace!(tick = ?frame.tick, "Applying recorded frame");
+            for message in frame.messages.iter().cloned() {
+                connection
+                    .replication_receiver
+                    .recv_message(message, frame.tick);
+            }
+            for (group, replication_list) in
+                connection.replication_receiver.read_messages(frame.tick)
+            {
+                for (tick, replication) in replication_list {
+                    if let Err(e) = connection.replication_receiver.apply_world(
+                        world,
+                        tick,
+                        replication,
+                        group,
+                        &mut connection.events,
+                    ) {
+                        error!("Error applying recorded frame: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Interpolation time corresponding to the current position
+    fn interpolation_time(&self) -> WrappedTime {
+        let start_tick = self
+            .recording
+            .frames
+            .first()
+            .map(|frame| frame.tick)
+            .unwrap_or_default();
+        WrappedTime::from_tick(start_tick, 0, self.recording.tick_duration) + self.position
+    }
+}
+
+/// Apply the recorded replication messages instead of the ones received from the network
+pub(crate) fn playback<P: Protocol>(world: &mut World) {
+    world.resource_scope(|world: &mut World, mut playback: Mut<Playback<P>>| {
+        world.resource_scope(
+            |world: &mut World, mut connection: Mut<ConnectionManager<P>>| {
+                let delta = world.resource::<Time<Virtual>>().delta();
+                let config = world.resource::<ClientConfig>();
+                // the replication messages need to be applied `delay` before they are displayed,
+                // so that we always have 2 snapshots to interpolate between
+                let delay = config
+                    .interpolation
+                    .delay
+                    .to_duration(config.shared.server_send_interval);
+
+                playback.advance(delta);
+                let until = playback.position + delay;
+                playback.apply_frames(world, &mut connection, until);
+
+                // drive the interpolation pipeline from the playback position
+                connection.sync_manager.synced = true;
+                connection.sync_manager.interpolation_time = playback.interpolation_time();
+
+                let mut events = std::mem::take(&mut connection.events);
+                write_events(world, &mut events);
+            },
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{With, World};
+    use bevy::utils::Duration;
+
+    use crate::client::interpolation::Interpolated;
+
+    use crate::prelude::client::*;
+    use crate::prelude::server::RecordingConfig;
+    use crate::prelude::*;
+    use crate::shared::replication::components::ShouldBeInterpolated;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_record_and_playback() -> Result<()> {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: true,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let path = std::env::temp_dir().join("lightyear_test_record_and_playback.lyrp");
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .start_recording(RecordingConfig::new(&path), tick_duration)?;
+
+        // spawn an entity that is only replicated to another client: the spectator still records it
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(0.0),
+                Replicate {
+                    replication_target: NetworkTarget::None,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        stepper.frame_step();
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(1.0));
+        stepper.frame_step();
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .stop_recording()?;
+
+        let mut playback = Playback::<MyProtocol>::from_file(&path)?;
+        assert_eq!(playback.recording.len(), 2);
+
+        // apply the recording to an empty world
+        let mut world = World::new();
+        let mut connection = ClientConnectionManager::new(
+            protocol().channel_registry(),
+            SyncConfig::default(),
+            &PingConfig::default(),
+            0,
+            CompressionConfig::default(),
+        );
+        playback.apply_frames(&mut world, &mut connection, Duration::default());
+        // the entity is the first one in the recording
+        let net_entity = NetEntity::from_index(0);
+        let local_entity = *connection
+            .replication_receiver
+            .remote_entity_map
+            .get_local(net_entity)
+            .unwrap();
+        assert_eq!(
+            world.get::<Component1>(local_entity),
+            Some(&Component1(0.0))
+        );
+        assert!(world.get::<ShouldBeInterpolated>(local_entity).is_some());
+
+        playback.apply_frames(&mut world, &mut connection, playback.duration());
+        assert_eq!(
+            world.get::<Component1>(local_entity),
+            Some(&Component1(1.0))
+        );
+
+        // seeking backwards replays the recording from the start
+        playback.position = playback.duration();
+        playback.seek(Duration::default());
+        playback.apply_frames(&mut world, &mut connection, Duration::default());
+        assert!(world.get_entity(local_entity).is_none());
+        let local_entity = *connection
+            .replication_receiver
+            .remote_entity_map
+            .get_local(net_entity)
+            .unwrap();
+        assert_eq!(
+            world.get::<Component1>(local_entity),
+            Some(&Component1(0.0))
+        );
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    fn new_stepper(
+        tick_duration: Duration,
+        interpolation_config: InterpolationConfig,
+    ) -> BevyStepper {
+        let shared_config = SharedConfig {
+            enable_replication: true,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            interpolation_config,
+            link_conditioner,
+            tick_duration,
+        )
+    }
+
+    // A recording started in the middle of a match contains the entities that were already replicated
+    #[test]
+    fn test_record_existing_entities() -> Result<()> {
+        let tick_duration = Duration::from_millis(10);
+        let mut stepper = new_stepper(tick_duration, InterpolationConfig::default());
+        stepper.init();
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(1.0), Component2(2.0), Replicate::default()))
+            .id();
+        // an entity that is not visible to any client is not recorded
+        stepper.server_app.world.spawn((
+            Component1(3.0),
+            Replicate {
+                replication_mode: ReplicationMode::Room,
+                ..Default::default()
+            },
+        ));
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        assert!(stepper.client_entity(server_entity).is_some());
+
+        let path = std::env::temp_dir().join("lightyear_test_record_existing_entities.lyrp");
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .start_recording(RecordingConfig::new(&path), tick_duration)?;
+        stepper.frame_step();
+        stepper
+            .server_app
+            .world
+            .entity_mut(server_entity)
+            .insert(Component1(4.0));
+        stepper.frame_step();
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .stop_recording()?;
+
+        let mut playback = Playback::<MyProtocol>::from_file(&path)?;
+        let mut world = World::new();
+        let mut connection = ClientConnectionManager::new(
+            protocol().channel_registry(),
+            SyncConfig::default(),
+            &PingConfig::default(),
+            0,
+            CompressionConfig::default(),
+        );
+        // the first frame contains the spawn and the current components of the entity
+        playback.apply_frames(&mut world, &mut connection, Duration::default());
+        let local_entity = *connection
+            .replication_receiver
+            .remote_entity_map
+            .get_local(NetEntity::from_index(0))
+            .unwrap();
+        assert_eq!(
+            world.get::<Component1>(local_entity),
+            Some(&Component1(1.0))
+        );
+        assert_eq!(
+            world.get::<Component2>(local_entity),
+            Some(&Component2(2.0))
+        );
+        assert!(world.get::<ShouldBeInterpolated>(local_entity).is_some());
+        assert_eq!(world.entities().len(), 1);
+
+        // the changes made after the recording started are recorded as usual
+        playback.apply_frames(&mut world, &mut connection, playback.duration());
+        assert_eq!(
+            world.get::<Component1>(local_entity),
+            Some(&Component1(4.0))
+        );
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    // The PlaybackPlugin applies the recording on a client app that is not connected,
+    // and the recorded entities are interpolated
+    #[test]
+    fn test_playback_plugin() -> Result<()> {
+        let tick_duration = Duration::from_millis(10);
+        let mut stepper = new_stepper(tick_duration, InterpolationConfig::default());
+        stepper.init();
+
+        let path = std::env::temp_dir().join("lightyear_test_playback_plugin.lyrp");
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .start_recording(RecordingConfig::new(&path), tick_duration)?;
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        // the value only changes every other tick
+        for i in 1..=10 {
+            stepper.frame_step();
+            stepper.frame_step();
+            stepper
+                .server_app
+                .world
+                .entity_mut(server_entity)
+                .insert(Component1(i as f32));
+        }
+        stepper.frame_step();
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .stop_recording()?;
+
+        // the recorded frames are applied ahead of the displayed position by the interpolation delay,
+        // so that there is always a snapshot to interpolate towards
+        let interpolation_config = InterpolationConfig {
+            delay: InterpolationDelay {
+                min_delay: tick_duration * 4,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut stepper = new_stepper(tick_duration, interpolation_config);
+        stepper
+            .client_app
+            .add_plugins(PlaybackPlugin::<MyProtocol>::from_file(&path)?);
+        let mut query = stepper
+            .client_app
+            .world
+            .query_filtered::<&Component1, With<Interpolated>>();
+        let mut values = vec![];
+        for _ in 0..60 {
+            stepper.frame_step();
+            if let Ok(value) = query.get_single(&stepper.client_app.world) {
+                values.push(value.0);
+            }
+        }
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<Playback<MyProtocol>>()
+            .is_finished());
+        // the interpolated entity goes through the recorded values, and the values in between
+        assert!(!values.is_empty());
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+        assert!(values.contains(&5.0));
+        assert!(values.contains(&5.5));
+        assert_eq!(values.last(), Some(&10.0));
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_playback_invalid_file() -> Result<()> {
+        let path = std::env::temp_dir().join("lightyear_test_playback_invalid_file.lyrp");
+        assert!(PlaybackPlugin::<MyProtocol>::from_file(&path).is_err());
+
+        std::fs::write(&path, b"not a recording")?;
+        assert!(PlaybackPlugin::<MyProtocol>::from_file(&path).is_err());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -11,12 +11,17 @@ use bevy::time::common_conditions::on_timer;
 use bevy::transform::TransformSystem;
 use bevy::utils::Duration;
 
-use crate::client::events::{ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent};
+use crate::client::events::{
+    BlobReceivedEvent, ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent,
+    MessageAckedEvent, MessageExpiredEvent, MessageLostEvent, WorldLoadedEvent,
+};
 use crate::client::input::InputPlugin;
 use crate::client::interpolation::plugin::InterpolationPlugin;
+use crate::client::networking::{update_networking_state, NetworkingState};
 use crate::client::prediction::plugin::{is_connected, is_in_rollback, PredictionPlugin};
 use crate::client::prediction::Rollback;
 use crate::client::resource::{Authentication, Client};
+use crate::client::scheduled::ScheduledPlugin;
 use crate::client::systems::{receive, send, sync_update};
 use crate::connection::events::ConnectionEvents;
 use crate::netcode::CONNECT_TOKEN_BYTES;
@@ -158,6 +163,7 @@ impl<P: Protocol> Plugin for ClientPlugin<P> {
                 config: config.client_config.shared.clone(),
             })
             .add_plugins(InputPlugin::<P>::default())
+            .add_plugins(ScheduledPlugin::<P>::default())
             .add_plugins(PredictionPlugin::<P>::new(config.client_config.prediction))
             .add_plugins(InterpolationPlugin::<P>::new(
                 config.client_config.interpolation.clone(),
@@ -176,6 +182,7 @@ impl<P: Protocol> Plugin for ClientPlugin<P> {
                 config.client_config.sync,
                 &config.client_config.ping,
                 config.client_config.prediction.input_delay_ticks,
+                config.client_config.shared.compression.clone(),
             ))
             .insert_resource(ConnectionEvents::<P>::new())
             .insert_resource(config.protocol)
@@ -227,17 +234,26 @@ impl<P: Protocol> Plugin for ClientPlugin<P> {
                 // run sync before send because some send systems need to know if the client is synced
                 (MainSet::Sync, MainSet::Send.run_if(is_ready_to_send)).chain(),
             )
+            // STATES //
+            .add_state::<NetworkingState>()
             // EVENTS //
             .add_event::<ConnectEvent>()
             .add_event::<DisconnectEvent>()
             .add_event::<EntitySpawnEvent>()
             .add_event::<EntityDespawnEvent>()
+            .add_event::<WorldLoadedEvent>()
+            .add_event::<BlobReceivedEvent>()
+            .add_event::<MessageAckedEvent>()
+            .add_event::<MessageLostEvent>()
+            .add_event::<MessageExpiredEvent>()
             // SYSTEMS //
             // .add_systems(Startup, init_netcode)
             .add_systems(
                 PreUpdate,
                 (
-                    receive::<P>.in_set(MainSet::Receive),
+                    (receive::<P>, update_networking_state::<P>)
+                        .chain()
+                        .in_set(MainSet::Receive),
                     apply_deferred.in_set(MainSet::ReceiveFlush),
                 ),
             )
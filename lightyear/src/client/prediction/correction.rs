@@ -6,61 +6,151 @@
 // - interpolate (provided)
 // - custom
 
-use bevy::prelude::{Commands, Component, Entity, Query, Res};
+use std::marker::PhantomData;
+use std::ops::{Add, Mul};
+
+use bevy::prelude::{Commands, Component, Entity, Query, Res, Time};
+use bevy::utils::Duration;
 use tracing::{debug, info};
 
 use crate::_reexport::ComponentProtocol;
 use crate::client::components::{LerpFn, SyncComponent, SyncMetadata};
 use crate::client::easings::{ease_out_quad, ease_out_quart};
+use crate::client::interpolation::NullInterpolator;
 use crate::client::resource::Client;
 use crate::prelude::{Tick, TickManager};
 use crate::protocol::Protocol;
 
-// TODO: instead of requiring the component to implement the correction, we could have a separate
-//  'type registry' that stores the correction function for each component type.
-//  or we register in the protocol (that is user defined), the correction function for each component type
-//  P::CorrectionFn<C> -> CorrectionFn<C>
-//  or something like P::Interpolate<C: ComponentKind> -> InterpolationFn<C>
+/// Everything a [`CorrectionFn`] can use to compute the visual value of a component
+/// while it is being corrected
+#[derive(Debug, Clone)]
+pub struct CorrectionContext<C> {
+    /// Visual value of the component when the correction started (i.e. the mispredicted value)
+    pub original_prediction: C,
+    /// Visual value of the component at the previous frame
+    pub previous_visual: C,
+    /// Predicted (corrected) value of the component at the previous frame.
+    /// This is `None` on the first frame of a correction
+    pub previous_predicted: Option<C>,
+    /// Current predicted (corrected) value of the component; the correction converges towards it
+    pub predicted: C,
+    /// Time elapsed since the previous frame
+    pub elapsed: Duration,
+    /// Total duration of the correction
+    pub duration: Duration,
+    /// Progress of the correction, between 0.0 and 1.0
+    pub t: f32,
+}
+
+impl<C> CorrectionContext<C>
+where
+    C: Clone + Add<C, Output = C> + Mul<f32, Output = C>,
+{
+    /// The visual offset (visual - predicted) at the previous frame.
+    /// We track the offset instead of the visual value so that the correction
+    /// follows the predicted entity if it moves fast.
+    pub fn previous_offset(&self) -> C {
+        let previous_predicted = self
+            .previous_predicted
+            .clone()
+            .unwrap_or_else(|| self.predicted.clone());
+        self.previous_visual.clone() + previous_predicted * -1.0
+    }
+}
 
-// pub trait CorrectionFn<C> {
-//     /// How do we perform the correction between the original Predicted state and the Corrected state?
-//     /// (t is the interpolation factor between the tick of the original Predicted state and the tick of the Corrected state)
-//     fn correct(predicted: C, corrected: C, t: f32) -> C;
-//
-//     // fn get_correction_final_tick(&self, prediction_tick: Tick) -> Tick;
-// }
+/// Defines how we visually correct a component from the mispredicted state
+/// to the new predicted state after a rollback
+pub trait CorrectionFn<C> {
+    /// Compute the visual value of the component for the current frame.
+    ///
+    /// `velocity` is kept between the frames of a correction; correctors can use it to store the rate of
+    /// change (per second) of the visual offset. It is `None` when a new correction starts.
+    fn correct(context: CorrectionContext<C>, velocity: &mut Option<C>) -> C;
+}
 
 /// We snapback instantly to the Corrected state
 pub struct InstantCorrector;
-impl<C: Component> LerpFn<C> for InstantCorrector {
-    fn lerp(predicted: C, corrected: C, t: f32) -> C {
+impl<C> CorrectionFn<C> for InstantCorrector {
+    fn correct(context: CorrectionContext<C>, _velocity: &mut Option<C>) -> C {
         // the correction is instant, so we just return the Corrected state
-        corrected
+        context.predicted
     }
-
-    // fn get_correction_final_tick(&self, prediction_tick: Tick) -> Tick {
-    //     // the correction is instant, so the final tick is the same as the prediction tick
-    //     prediction_tick
-    // }
 }
 
 /// We use the components interpolation behaviour to interpolate from the Predicted state to the
 /// Corrected state
-pub struct InterpolatedCorrector;
-// {
-//     The number of ticks that we will perform the correction over
-// correction_ticks: Tick,
-// }
-
-// impl<C: InterpolatedComponent<C>> CorrectionFn<C> for InterpolatedCorrector {
-//     fn correct(predicted: C, corrected: C, t: f32) -> C {
-//         C::lerp(predicted, corrected, t)
-//     }
-//     //
-//     // fn get_correction_final_tick(&self, prediction_tick: Tick) -> Tick {
-//     //     prediction_tick + self.correction_ticks
-//     // }
-// }
+///
+/// (When using the `corrector = "InterpolatedCorrector"` attribute, `L` is the component's interpolator)
+pub struct InterpolatedCorrector<L = NullInterpolator>(PhantomData<L>);
+
+impl<C, L: LerpFn<C>> CorrectionFn<C> for InterpolatedCorrector<L> {
+    fn correct(context: CorrectionContext<C>, _velocity: &mut Option<C>) -> C {
+        L::lerp(
+            context.original_prediction,
+            context.predicted,
+            ease_out_quad(context.t),
+        )
+    }
+}
+
+/// The visual offset decays exponentially towards the predicted value.
+///
+/// The decay rate is chosen so that the offset has shrunk to 1% at the end of the correction.
+/// Because the offset is applied on top of the current predicted value, fast-moving entities
+/// don't get pulled back towards their mispredicted position.
+pub struct ExponentialCorrector;
+
+impl<C> CorrectionFn<C> for ExponentialCorrector
+where
+    C: Clone + Add<C, Output = C> + Mul<f32, Output = C>,
+{
+    fn correct(context: CorrectionContext<C>, _velocity: &mut Option<C>) -> C {
+        let duration = context.duration.as_secs_f32();
+        if duration <= 0.0 {
+            return context.predicted;
+        }
+        let rate = RESIDUAL_OFFSET.ln().abs() / duration;
+        let decay = (-rate * context.elapsed.as_secs_f32()).exp();
+        let offset = context.previous_offset();
+        context.predicted + offset * decay
+    }
+}
+
+/// The visual offset is driven to zero by a critically-damped spring.
+///
+/// The spring keeps track of the velocity of the offset, so successive corrections
+/// blend smoothly instead of restarting from rest. Its stiffness is chosen so that the
+/// offset has settled at the end of the correction.
+pub struct SpringCorrector;
+
+impl<C> CorrectionFn<C> for SpringCorrector
+where
+    C: Clone + Add<C, Output = C> + Mul<f32, Output = C>,
+{
+    fn correct(context: CorrectionContext<C>, velocity: &mut Option<C>) -> C {
+        let duration = context.duration.as_secs_f32();
+        if duration <= 0.0 {
+            return context.predicted;
+        }
+        // angular frequency such that (1 + wt) * exp(-wt) = RESIDUAL_OFFSET at the end of the correction
+        let omega = SPRING_SETTLE_FACTOR / duration;
+        let dt = context.elapsed.as_secs_f32();
+        let offset = context.previous_offset();
+        let v = velocity.take().unwrap_or_else(|| offset.clone() * 0.0);
+        let decay = (-omega * dt).exp();
+        // closed-form update of a critically-damped spring
+        let temp = (v.clone() + offset.clone() * omega) * dt;
+        let new_offset = (offset + temp.clone()) * decay;
+        let new_velocity = (v + temp * -omega) * decay;
+        *velocity = Some(new_velocity);
+        context.predicted + new_offset
+    }
+}
+
+/// Fraction of the initial offset that remains at the end of a correction
+const RESIDUAL_OFFSET: f32 = 0.01;
+/// Solution of (1 + x) * exp(-x) = RESIDUAL_OFFSET
+const SPRING_SETTLE_FACTOR: f32 = 6.64;
 
 #[derive(Component, Debug)]
 pub struct Correction<C: Component> {
@@ -79,11 +169,15 @@ pub struct Correction<C: Component> {
     /// (interpolated between the original prediction and the final correction)
     /// and the final correction value
     pub current_correction: Option<C>,
+
+    /// State kept by the corrector between frames (for example the velocity of the visual offset)
+    pub velocity: Option<C>,
 }
 
 /// Visually update the component to the a value that is interpolated between the original prediction
 /// and the Corrected state
 pub(crate) fn get_visually_corrected_state<C: SyncComponent, P: Protocol>(
+    time: Res<Time>,
     tick_manager: Res<TickManager>,
     mut commands: Commands,
     mut query: Query<(Entity, &mut C, &mut Correction<C>)>,
@@ -92,10 +186,9 @@ pub(crate) fn get_visually_corrected_state<C: SyncComponent, P: Protocol>(
 {
     for (entity, mut component, mut correction) in query.iter_mut() {
         let current_tick = tick_manager.tick();
-        let mut t = (current_tick - correction.original_tick) as f32
-            / (correction.final_correction_tick - correction.original_tick) as f32;
+        let correction_ticks = correction.final_correction_tick - correction.original_tick;
+        let mut t = (current_tick - correction.original_tick) as f32 / correction_ticks as f32;
         t = t.clamp(0.0, 1.0);
-        let t = ease_out_quad(t);
         if t == 1.0 || &correction.original_prediction == component.as_ref() {
             debug!(
                 ?t,
@@ -106,15 +199,23 @@ pub(crate) fn get_visually_corrected_state<C: SyncComponent, P: Protocol>(
             commands.entity(entity).remove::<Correction<C>>();
         } else {
             debug!(?t, ?entity, start = ?correction.original_tick, end = ?correction.final_correction_tick, "Applying visual correction for {:?}", component.name());
+            // TODO: avoid all these clones
+            let context = CorrectionContext {
+                original_prediction: correction.original_prediction.clone(),
+                previous_visual: correction
+                    .current_visual
+                    .clone()
+                    .unwrap_or_else(|| correction.original_prediction.clone()),
+                previous_predicted: correction.current_correction.clone(),
+                predicted: component.clone(),
+                elapsed: time.delta(),
+                duration: tick_manager.tick_duration() * correction_ticks.max(0) as u32,
+                t,
+            };
             // store the current corrected value so that we can restore it at the start of the next frame
             correction.current_correction = Some(component.clone());
-            // TODO: avoid all these clones
             // visually update the component
-            let visual = P::Components::correct(
-                correction.original_prediction.clone(),
-                component.clone(),
-                t,
-            );
+            let visual = P::Components::correct(context, &mut correction.velocity);
             // store the current visual value
             correction.current_visual = Some(visual.clone());
             // set the component value to the visual value
@@ -125,12 +226,13 @@ pub(crate) fn get_visually_corrected_state<C: SyncComponent, P: Protocol>(
 
 /// At the start of the next frame, restore
 pub(crate) fn restore_corrected_state<C: SyncComponent>(
-    mut query: Query<(&mut C, &mut Correction<C>)>,
+    mut query: Query<(&mut C, &Correction<C>)>,
 ) {
-    for (mut component, mut correction) in query.iter_mut() {
-        if let Some(correction) = std::mem::take(&mut correction.current_correction) {
+    for (mut component, correction) in query.iter_mut() {
+        // NOTE: we keep the corrected value around, correctors can use it as the previous predicted value
+        if let Some(correction) = &correction.current_correction {
             debug!("restoring corrected component: {:?}", component.name());
-            *component = correction;
+            *component = correction.clone();
         } else {
             debug!(
                 "Corrected component was None so couldn't restore: {:?}",
@@ -144,3 +246,73 @@ pub(crate) fn restore_corrected_state<C: SyncComponent>(
 // - we compute the final_correction_tick = current_tick + correction_ticks
 // - during rollback, the Predicted entity will take the Corrected position.
 // - in PostUpdate, during the correction_ticks, we will interpolated between the old
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::protocol::Component1;
+
+    use super::*;
+
+    /// Simulate a correction where the predicted value moves at a constant speed,
+    /// and return the visual offset (visual - predicted) at each frame
+    fn simulate<F: CorrectionFn<Component1>>() -> Vec<f32> {
+        let frame = Duration::from_millis(10);
+        let duration = Duration::from_millis(100);
+        // we mispredicted by 10.0
+        let original_prediction = Component1(10.0);
+        let mut previous_visual = original_prediction.clone();
+        let mut previous_predicted = None;
+        let mut velocity = None;
+        let mut offsets = vec![];
+        for i in 0..10 {
+            // the entity moves fast
+            let predicted = Component1(100.0 * i as f32);
+            let visual = F::correct(
+                CorrectionContext {
+                    original_prediction: original_prediction.clone(),
+                    previous_visual: previous_visual.clone(),
+                    previous_predicted: previous_predicted.clone(),
+                    predicted: predicted.clone(),
+                    elapsed: frame,
+                    duration,
+                    t: i as f32 / 10.0,
+                },
+                &mut velocity,
+            );
+            offsets.push(visual.0 - predicted.0);
+            previous_visual = visual;
+            previous_predicted = Some(predicted);
+        }
+        offsets
+    }
+
+    #[test]
+    fn test_exponential_corrector() {
+        let offsets = simulate::<ExponentialCorrector>();
+        // the offset decays towards 0 and does not lag behind the moving entity
+        assert!(offsets[0] > 0.0 && offsets[0] < 10.0);
+        for window in offsets.windows(2) {
+            assert!(window[1] < window[0]);
+            assert!(window[1] > 0.0);
+        }
+        // only ~1% of the initial offset remains at the end of the correction
+        assert!(offsets[9] < 0.11);
+    }
+
+    #[test]
+    fn test_spring_corrector() {
+        let offsets = simulate::<SpringCorrector>();
+        // a critically-damped spring does not overshoot
+        for window in offsets.windows(2) {
+            assert!(window[1] <= window[0]);
+            assert!(window[1] >= 0.0);
+        }
+        assert!(offsets[9] < 0.11);
+    }
+
+    #[test]
+    fn test_instant_corrector() {
+        let offsets = simulate::<InstantCorrector>();
+        assert!(offsets.iter().all(|offset| *offset == 0.0));
+    }
+}
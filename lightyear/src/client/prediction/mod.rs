@@ -2,6 +2,7 @@
 use std::fmt::Debug;
 
 use bevy::prelude::*;
+use bevy::utils::EntityHashSet;
 use tracing::{error, info};
 
 pub use despawn::{PredictionDespawnCommandsExt, PredictionDespawnMarker};
@@ -34,10 +35,52 @@ pub struct Predicted {
     pub confirmed_entity: Option<Entity>,
 }
 
+/// Marker component to exclude a predicted entity from rollbacks.
+///
+/// The entity will never trigger a rollback, its components won't be reset to the confirmed state,
+/// and any changes made to it while a rollback is being re-simulated are reverted afterwards.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct DisableRollback;
+
 #[derive(Resource)]
 pub struct Rollback {
     pub state: RollbackState,
     // pub rollback_groups: EntityHashMap<ReplicationGroupId, RollbackState>,
+    /// Predicted entities whose confirmed state did not match the predicted history
+    pub(crate) mismatched: EntityHashSet<Entity>,
+    /// Entities that are rolled back during the current rollback
+    pub(crate) entities: EntityHashSet<Entity>,
+}
+
+impl Default for Rollback {
+    fn default() -> Self {
+        Self {
+            state: RollbackState::Default,
+            mismatched: EntityHashSet::default(),
+            entities: EntityHashSet::default(),
+        }
+    }
+}
+
+impl Rollback {
+    /// Returns true if we are currently running a rollback
+    pub fn is_rollback(&self) -> bool {
+        matches!(self.state, RollbackState::ShouldRollback { .. })
+    }
+
+    /// Returns true if the entity is being rolled back (reset to the confirmed state and re-simulated)
+    /// during the current rollback.
+    ///
+    /// Systems running in `FixedUpdate` can use this to skip entities that are not part of the rollback.
+    pub fn is_rolling_back(&self, entity: Entity) -> bool {
+        self.is_rollback() && self.entities.contains(&entity)
+    }
+
+    /// Iterate through the entities that are being rolled back during the current rollback.
+    /// Empty if we are not in rollback.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter().copied().filter(|_| self.is_rollback())
+    }
 }
 
 /// Resource that will track whether we should do rollback or not
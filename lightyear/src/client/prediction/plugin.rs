@@ -1,8 +1,8 @@
 use std::marker::PhantomData;
 
 use bevy::prelude::{
-    apply_deferred, App, FixedUpdate, IntoSystemConfigs, IntoSystemSetConfigs, Plugin, PostUpdate,
-    PreUpdate, Res, SystemSet,
+    apply_deferred, not, App, FixedUpdate, IntoSystemConfigs, IntoSystemSetConfigs, Plugin,
+    PostUpdate, PreUpdate, Res, SystemSet,
 };
 use bevy::transform::TransformSystem;
 
@@ -27,17 +27,30 @@ use crate::prelude::ReplicationSet;
 use crate::protocol::component::ComponentProtocol;
 use crate::protocol::Protocol;
 use crate::shared::sets::{FixedUpdateSet, MainSet};
+use crate::shared::tick_manager::is_paused;
 
 use super::predicted_history::{add_component_history, apply_confirmed_update};
 use super::rollback::{
     check_rollback, increment_rollback_tick, prepare_rollback, prepare_rollback_prespawn,
-    run_rollback,
+    restore_skipped_rollback_entities, run_rollback, select_rollback_entities,
 };
 use super::{
     clean_pre_predicted_entity, handle_pre_prediction, spawn_predicted_entity, ComponentSyncMode,
     Rollback, RollbackState,
 };
 
+/// Which predicted entities get rolled back when a rollback is triggered
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RollbackScope {
+    /// Roll back every predicted entity
+    #[default]
+    All,
+    /// Only roll back the predicted entities whose confirmed state did not match the predicted history,
+    /// as well as their descendants in the entity hierarchy.
+    /// The other predicted entities keep their predicted state.
+    Mismatched,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct PredictionConfig {
     /// If true, we completely disable the prediction plugin
@@ -55,6 +68,8 @@ pub struct PredictionConfig {
     /// (i.e. if the client is 10 ticks head and correction_ticks is 1.0, then the correction will be done over 10 ticks)
     // Number of ticks it will take to visually update the Predicted state to the new Corrected state
     pub correction_ticks_factor: f32,
+    /// Which predicted entities get rolled back when a rollback is triggered
+    pub rollback_scope: RollbackScope,
 }
 
 impl PredictionConfig {
@@ -74,6 +89,12 @@ impl PredictionConfig {
         self
     }
 
+    /// Update which predicted entities get rolled back when a rollback is triggered
+    pub fn with_rollback_scope(mut self, scope: RollbackScope) -> Self {
+        self.rollback_scope = scope;
+        self
+    }
+
     /// Update the amount of input delay (number of ticks)
     pub fn with_correction_ticks_factor(mut self, factor: f32) -> Self {
         self.correction_ticks_factor = factor;
@@ -184,7 +205,12 @@ where
                     // for SyncMode::Full, we need to check if we need to rollback.
                     check_rollback::<C, P>.in_set(PredictionSet::CheckRollback),
                     (prepare_rollback::<C, P>, prepare_rollback_prespawn::<C, P>)
+                        .after(select_rollback_entities)
                         .in_set(PredictionSet::PrepareRollback),
+                    // entities that were not rolled back should not be affected by the re-simulation
+                    restore_skipped_rollback_entities::<C>
+                        .after(run_rollback)
+                        .in_set(PredictionSet::Rollback),
                 ),
             );
             app.add_systems(
@@ -239,9 +265,7 @@ impl<P: Protocol> Plugin for PredictionPlugin<P> {
 
         // RESOURCES
         app.init_resource::<PredictionManager>();
-        app.insert_resource(Rollback {
-            state: RollbackState::Default,
-        });
+        app.init_resource::<Rollback>();
 
         // PreUpdate systems:
         // 1. Receive confirmed entities, add Confirmed and Predicted components
@@ -294,6 +318,7 @@ impl<P: Protocol> Plugin for PredictionPlugin<P> {
                     despawn_confirmed,
                 )
                     .in_set(PredictionSet::SpawnPrediction),
+                select_rollback_entities.in_set(PredictionSet::PrepareRollback),
                 run_rollback.in_set(PredictionSet::Rollback),
             ),
         );
@@ -320,7 +345,7 @@ impl<P: Protocol> Plugin for PredictionPlugin<P> {
                 // right away to avoid rollbacks
                 PredictionSet::SpawnHistory,
                 PredictionSet::SpawnHistoryFlush,
-                PredictionSet::UpdateHistory,
+                PredictionSet::UpdateHistory.run_if(not(is_paused)),
                 PredictionSet::IncrementRollbackTick.run_if(is_in_rollback),
             )
                 .chain(),
@@ -62,6 +62,15 @@ impl<T: SyncComponent> PredictionHistory<T> {
         self.buffer = ReadyBuffer::new();
     }
 
+    /// Get the most recent value recorded in the history
+    pub(crate) fn latest(&self) -> Option<&ComponentState<T>> {
+        self.buffer
+            .heap
+            .iter()
+            .max_by_key(|item| item.key)
+            .map(|item| &item.item)
+    }
+
     /// Get the value of the component at the specified tick.
     /// Clears the history buffer of all ticks older or equal than the specified tick.
     /// NOTE: Stores the returned value in the provided tick!!!
@@ -280,7 +289,7 @@ fn add_history<C: SyncComponent, P: Protocol>(
 
 /// After one fixed-update tick, we record the predicted component history for the current tick
 pub fn update_prediction_history<T: SyncComponent>(
-    mut query: Query<(Ref<T>, &mut PredictionHistory<T>)>,
+    mut query: Query<(Entity, Ref<T>, &mut PredictionHistory<T>)>,
     mut removed_component: RemovedComponents<T>,
     mut removed_entities: Query<&mut PredictionHistory<T>, Without<T>>,
     tick_manager: Res<TickManager>,
@@ -296,7 +305,11 @@ pub fn update_prediction_history<T: SyncComponent>(
     // update history if the predicted component changed
     // TODO: potentially change detection does not work during rollback!
     //  edit: looks like it does
-    for (component, mut history) in query.iter_mut() {
+    for (entity, component, mut history) in query.iter_mut() {
+        // entities that are not part of the rollback keep their history
+        if rollback.is_rollback() && !rollback.entities.contains(&entity) {
+            continue;
+        }
         // change detection works even when running the schedule for rollback (with no time increase)
         if component.is_changed() {
             history
@@ -305,6 +318,9 @@ pub fn update_prediction_history<T: SyncComponent>(
         }
     }
     for entity in removed_component.read() {
+        if rollback.is_rollback() && !rollback.entities.contains(&entity) {
+            continue;
+        }
         if let Ok(mut history) = removed_entities.get_mut(entity) {
             history.buffer.add_item(tick, ComponentState::Removed);
         }
@@ -377,6 +377,7 @@ mod tests {
     use bevy::prelude::Entity;
     use bevy::utils::{Duration, EntityHashMap};
     use std::collections::BinaryHeap;
+    use std::hash::{Hash, Hasher};
     use tracing_subscriber::fmt::format::FmtSpan;
 
     #[test]
@@ -423,7 +424,19 @@ mod tests {
 
         let current_tick = stepper.client_app.world.resource::<TickManager>().tick();
         let prediction_manager = stepper.client_app.world.resource::<PredictionManager>();
-        let expected_hash: u64 = 11844036307541615334;
+        // the default hash is computed from the spawn tick and the kinds of the protocol components of the entity.
+        // The built-in components (like PreSpawnedPlayerObject) are added after the components of the protocol,
+        // so the value changes whenever a component is added to the test protocol
+        let expected_hash: u64 = 13159749785163381459;
+        let mut hasher = seahash::SeaHasher::new();
+        current_tick.hash(&mut hasher);
+        let mut kinds = vec![
+            MyComponentsProtocolKind::Component1,
+            MyComponentsProtocolKind::PreSpawnedPlayerObject,
+        ];
+        kinds.sort();
+        kinds.iter().for_each(|kind| kind.hash(&mut hasher));
+        assert_eq!(hasher.finish(), expected_hash);
         assert_eq!(
             prediction_manager.prespawn_hash_to_entities,
             EntityHashMap::from_iter(vec![(
@@ -1,8 +1,9 @@
 use std::fmt::Debug;
 
+use bevy::hierarchy::HierarchyQueryExt;
 use bevy::prelude::{
-    Commands, DespawnRecursiveExt, DetectChanges, Entity, FixedUpdate, Query, Ref, Res, ResMut,
-    With, Without, World,
+    Children, Commands, DespawnRecursiveExt, DetectChanges, DetectChangesMut, Entity, FixedUpdate,
+    Or, Query, Ref, Res, ResMut, With, Without, World,
 };
 use bevy::utils::{EntityHashSet, HashSet};
 use tracing::{debug, error, info, trace, trace_span};
@@ -19,8 +20,9 @@ use crate::prelude::client::SyncMetadata;
 use crate::prelude::{PreSpawnedPlayerObject, TickManager};
 use crate::protocol::Protocol;
 
+use super::plugin::RollbackScope;
 use super::predicted_history::PredictionHistory;
-use super::{Predicted, Rollback, RollbackState};
+use super::{DisableRollback, Predicted, Rollback, RollbackState};
 
 #[allow(clippy::type_complexity)]
 #[allow(clippy::too_many_arguments)]
@@ -31,8 +33,16 @@ pub(crate) fn check_rollback<C: SyncComponent, P: Protocol>(
 
     // We also snap the value of the component to the server state if we are in rollback
     // We use Option<> because the predicted component could have been removed while it still exists in Confirmed
-    mut predicted_query: Query<&mut PredictionHistory<C>, (With<Predicted>, Without<Confirmed>)>,
+    mut predicted_query: Query<
+        &mut PredictionHistory<C>,
+        (
+            With<Predicted>,
+            Without<Confirmed>,
+            Without<DisableRollback>,
+        ),
+    >,
     confirmed_query: Query<(Entity, Option<&C>, Ref<Confirmed>)>,
+    config: Res<ClientConfig>,
     mut rollback: ResMut<Rollback>,
 ) where
     <P as Protocol>::ComponentKinds: FromType<C>,
@@ -91,10 +101,19 @@ pub(crate) fn check_rollback<C: SyncComponent, P: Protocol>(
         // that we should rollback (RollbackState::Default)
         // That is not the case, because if we do rollback we will need to snap the client entity to the server state
         // So either way we will need to do an operation.
-        match rollback.state {
+        // If we only roll back the mismatched entities, we need to keep comparing until we know that
+        // this entity is part of the rollback.
+        let compare = match rollback.state {
+            RollbackState::Default => true,
+            RollbackState::ShouldRollback { .. } => {
+                config.prediction.rollback_scope == RollbackScope::Mismatched
+                    && !rollback.mismatched.contains(&p)
+            }
+        };
+        match compare {
             // 3.a We are still not sure if we should do rollback. Compare history against confirmed
             // We rollback if there's no history (newly added predicted entity, or if there is a mismatch)
-            RollbackState::Default => {
+            true => {
                 let history_value = predicted_history.pop_until_tick(tick);
                 let predicted_exist = history_value.is_some();
                 let confirmed_exist = confirmed_component.is_some();
@@ -116,16 +135,19 @@ pub(crate) fn check_rollback<C: SyncComponent, P: Protocol>(
                    "Rollback check: mismatch for component between predicted and confirmed {:?} on tick {:?} for component {:?}. Current tick: {:?}",
                    confirmed_entity, tick, kind, current_tick
                    );
+                    rollback.mismatched.insert(p);
                     // TODO: try atomic enum update
-                    rollback.state = RollbackState::ShouldRollback {
-                        // we already rolled-back the state for the entity's latest_tick
-                        // after this we will start right away with a physics update, so we need to start taking the inputs from the next tick
-                        current_tick: tick + 1,
-                    };
+                    if !rollback.is_rollback() {
+                        rollback.state = RollbackState::ShouldRollback {
+                            // we already rolled-back the state for the entity's latest_tick
+                            // after this we will start right away with a physics update, so we need to start taking the inputs from the next tick
+                            current_tick: tick + 1,
+                        };
+                    }
                 }
             }
             // 3.b We already know we should do rollback (because of another entity/component), start the rollback
-            RollbackState::ShouldRollback { .. } => {
+            false => {
                 trace!(
                    "Rollback check: should roll back for component between predicted and confirmed on tick {:?} for component {:?}. Current tick: {:?}",
                    tick, kind, current_tick
@@ -187,6 +209,10 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
         let Some(p) = confirmed.predicted else {
             continue;
         };
+        // the entity is not part of this rollback; keep its predicted state and history
+        if !rollback.is_rolling_back(p) {
+            continue;
+        }
 
         // 1. Get the predicted entity, and it's history
         let Ok((predicted_entity, predicted_component, mut predicted_history, mut correction)) =
@@ -245,8 +271,9 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
                                         .unwrap_or_else(|| predicted_component.clone());
                                 correction.original_tick = current_tick;
                                 correction.final_correction_tick = final_correction_tick;
-                                // TODO: can set this to None, shouldnt make any diff
-                                correction.current_correction = Some(c.clone());
+                                // the predicted value changed because of the rollback, so there is no
+                                // meaningful previous predicted value for the correction
+                                correction.current_correction = None;
                             } else {
                                 debug!("inserting new correction");
                                 entity_mut.insert(Correction {
@@ -255,6 +282,7 @@ pub(crate) fn prepare_rollback<C: SyncComponent, P: Protocol>(
                                     final_correction_tick,
                                     current_visual: None,
                                     current_correction: None,
+                                    velocity: None,
                                 });
                             }
                         }
@@ -337,6 +365,7 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
             entities_to_despawn.extend(entities);
         }
     }
+    entities_to_despawn.retain(|entity| rollback.is_rolling_back(*entity));
     entities_to_despawn.iter().for_each(|entity| {
         debug!(
             ?entity,
@@ -350,7 +379,9 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
     for (prespawned_entity, predicted_component, mut predicted_history, mut correction) in
         predicted_query.iter_mut()
     {
-        if entities_to_despawn.contains(&prespawned_entity) {
+        if entities_to_despawn.contains(&prespawned_entity)
+            || !rollback.is_rolling_back(prespawned_entity)
+        {
             continue;
         }
 
@@ -385,8 +416,9 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
                                     .unwrap_or_else(|| predicted_component.clone());
                             correction.original_tick = current_tick;
                             correction.final_correction_tick = final_correction_tick;
-                            // TODO: can set this to None, shouldnt make any diff
-                            correction.current_correction = Some(c.clone());
+                            // the predicted value changed because of the rollback, so there is no
+                            // meaningful previous predicted value for the correction
+                            correction.current_correction = None;
                         } else {
                             debug!("inserting new correction");
                             commands.entity(prespawned_entity).insert(Correction {
@@ -395,6 +427,7 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
                                 final_correction_tick,
                                 current_visual: None,
                                 current_correction: None,
+                                velocity: None,
                             });
                         }
                     }
@@ -417,6 +450,90 @@ pub(crate) fn prepare_rollback_prespawn<C: SyncComponent, P: Protocol>(
     }
 }
 
+/// Select the entities that will be rolled back, according to the [`RollbackScope`].
+///
+/// Entities with [`DisableRollback`] are never rolled back.
+/// Pre-spawned entities have no confirmed state to compare against, so they are always rolled back.
+#[allow(clippy::type_complexity)]
+pub(crate) fn select_rollback_entities(
+    config: Res<ClientConfig>,
+    mut rollback: ResMut<Rollback>,
+    predicted_query: Query<
+        Entity,
+        (
+            Or<(With<Predicted>, With<PreSpawnedPlayerObject>)>,
+            Without<Confirmed>,
+            Without<DisableRollback>,
+        ),
+    >,
+    prespawned_query: Query<
+        Entity,
+        (
+            With<PreSpawnedPlayerObject>,
+            Without<Predicted>,
+            Without<DisableRollback>,
+        ),
+    >,
+    children_query: Query<&Children>,
+    disabled_query: Query<(), With<DisableRollback>>,
+) {
+    let rollback = rollback.as_mut();
+    let mismatched = std::mem::take(&mut rollback.mismatched);
+    rollback.entities.clear();
+    match config.prediction.rollback_scope {
+        RollbackScope::All => {
+            rollback.entities.extend(predicted_query.iter());
+        }
+        RollbackScope::Mismatched => {
+            rollback.entities.extend(prespawned_query.iter());
+            // the descendants of a mismatched entity depend on its state, so they get rolled back as well
+            for entity in mismatched {
+                rollback.entities.insert(entity);
+                rollback.entities.extend(
+                    children_query
+                        .iter_descendants(entity)
+                        .filter(|child| !disabled_query.contains(*child)),
+                );
+            }
+        }
+    }
+    debug!(num_entities = ?rollback.entities.len(), "Selected entities to roll back");
+}
+
+/// Entities that are not part of the rollback should not be affected by the re-simulation,
+/// so we revert them to their latest predicted state (their history is not updated during the rollback).
+#[allow(clippy::type_complexity)]
+pub(crate) fn restore_skipped_rollback_entities<C: SyncComponent>(
+    mut commands: Commands,
+    rollback: Res<Rollback>,
+    mut query: Query<
+        (Entity, Option<&mut C>, &PredictionHistory<C>),
+        (
+            Or<(With<Predicted>, With<PreSpawnedPlayerObject>)>,
+            Without<Confirmed>,
+        ),
+    >,
+) {
+    for (entity, component, history) in query.iter_mut() {
+        // NOTE: the rollback state has already been reset at this point, so we check the entities directly
+        if rollback.entities.contains(&entity) {
+            continue;
+        }
+        match (history.latest(), component) {
+            (Some(ComponentState::Updated(c)), Some(mut component)) => {
+                component.set_if_neq(c.clone());
+            }
+            (Some(ComponentState::Updated(c)), None) => {
+                commands.entity(entity).insert(c.clone());
+            }
+            (Some(ComponentState::Removed), Some(_)) => {
+                commands.entity(entity).remove::<C>();
+            }
+            _ => {}
+        }
+    }
+}
+
 pub(crate) fn run_rollback(world: &mut World) {
     let tick_manager = world.get_resource::<TickManager>().unwrap();
     let rollback = world.get_resource::<Rollback>().unwrap();
@@ -904,3 +1021,159 @@ pub(crate) fn increment_rollback_tick(mut rollback: ResMut<Rollback>) {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod rollback_scope_tests {
+    use bevy::prelude::*;
+    use bevy::utils::{Duration, EntityHashSet};
+
+    use crate::client::prediction::{DisableRollback, Predicted, Rollback, RollbackState};
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    #[derive(Resource, Default)]
+    struct RolledBackEntities(EntityHashSet<Entity>);
+
+    fn increment_component(
+        rollback: Res<Rollback>,
+        mut rolled_back: ResMut<RolledBackEntities>,
+        mut query: Query<&mut Component1, With<Predicted>>,
+    ) {
+        rolled_back.0.extend(rollback.entities());
+        for mut component in query.iter_mut() {
+            component.0 += 1.0;
+        }
+    }
+
+    #[test]
+    fn test_rollback_only_mismatched_entities() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: false,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let sync_config = SyncConfig::default().speedup_factor(1.0);
+        let prediction_config = PredictionConfig::default()
+            .disable(false)
+            .with_rollback_scope(RollbackScope::Mismatched);
+        let interpolation_config = InterpolationConfig::default();
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            sync_config,
+            prediction_config,
+            interpolation_config,
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+        stepper
+            .client_app
+            .init_resource::<RolledBackEntities>()
+            .add_systems(
+                FixedUpdate,
+                increment_component.in_set(FixedUpdateSet::Main),
+            );
+
+        // spawn 3 predicted entities: one that will mismatch, one that matches,
+        // and one that is excluded from rollback
+        let tick = stepper.client_app.world.resource::<TickManager>().tick();
+        let mut spawn_predicted = |disable_rollback: bool| {
+            let predicted = stepper
+                .client_app
+                .world
+                .spawn(Predicted {
+                    confirmed_entity: None,
+                })
+                .id();
+            if disable_rollback {
+                stepper
+                    .client_app
+                    .world
+                    .entity_mut(predicted)
+                    .insert(DisableRollback);
+            }
+            let confirmed = stepper
+                .client_app
+                .world
+                .spawn((
+                    Component1(0.0),
+                    Confirmed {
+                        predicted: Some(predicted),
+                        interpolated: None,
+                        tick,
+                    },
+                ))
+                .id();
+            stepper
+                .client_app
+                .world
+                .get_mut::<Predicted>(predicted)
+                .unwrap()
+                .confirmed_entity = Some(confirmed);
+            (confirmed, predicted)
+        };
+        let (confirmed_a, predicted_a) = spawn_predicted(false);
+        let (confirmed_b, predicted_b) = spawn_predicted(false);
+        let (confirmed_c, predicted_c) = spawn_predicted(true);
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+        let value = |stepper: &BevyStepper, entity: Entity| {
+            stepper
+                .client_app
+                .world
+                .get::<Component1>(entity)
+                .unwrap()
+                .0
+        };
+        let value_b = value(&stepper, predicted_b);
+        let value_c = value(&stepper, predicted_c);
+        assert!(value_b > 0.0);
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<RolledBackEntities>()
+            .0
+            .is_empty());
+
+        // the confirmed state of entity A mismatches: roll back 1 tick
+        let current_tick = stepper.client_app.world.resource::<TickManager>().tick();
+        for confirmed in [confirmed_a, confirmed_b, confirmed_c] {
+            stepper
+                .client_app
+                .world
+                .get_mut::<Confirmed>(confirmed)
+                .unwrap()
+                .tick = current_tick - 1;
+        }
+        let mut rollback = stepper.client_app.world.resource_mut::<Rollback>();
+        rollback.state = RollbackState::ShouldRollback { current_tick };
+        rollback.mismatched.insert(predicted_a);
+        stepper.frame_step();
+
+        // only A got rolled back
+        assert_eq!(
+            stepper.client_app.world.resource::<RolledBackEntities>().0,
+            EntityHashSet::from_iter([predicted_a])
+        );
+        // A was reset to the confirmed state, then re-simulated for 1 tick and simulated for 1 tick
+        assert_eq!(value(&stepper, predicted_a), 2.0);
+        // B and C are not affected by the rollback
+        assert_eq!(value(&stepper, predicted_b), value_b + 1.0);
+        assert_eq!(value(&stepper, predicted_c), value_c + 1.0);
+        assert!(!stepper
+            .client_app
+            .world
+            .resource::<Rollback>()
+            .is_rollback());
+    }
+}
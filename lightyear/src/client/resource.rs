@@ -2,7 +2,7 @@
 use bevy::utils::Duration;
 use std::net::SocketAddr;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use bevy::ecs::component::Tick as BevyTick;
 use bevy::ecs::system::SystemParam;
 use bevy::prelude::{Entity, Res, ResMut, Resource, World};
@@ -11,11 +11,12 @@ use tracing::{debug, trace, trace_span};
 
 use crate::_reexport::ReplicationSend;
 use crate::channel::builder::Channel;
+use crate::channel::stream::StreamId;
 use crate::connection::events::ConnectionEvents;
 use crate::inputs::native::input_buffer::InputBuffer;
 use crate::netcode::{Client as NetcodeClient, ClientId};
 use crate::netcode::{ConnectToken, Key};
-use crate::packet::message::Message;
+use crate::packet::message::{Message, MessageHandle};
 use crate::prelude::NetworkTarget;
 use crate::protocol::channel::ChannelKind;
 use crate::protocol::Protocol;
@@ -88,7 +89,7 @@ impl<'w, 's, P: Protocol> ClientMut<'w, 's, P> {
     }
 
     /// Receive messages from the server
-    pub(crate) fn receive(&mut self, world: &mut World) -> ConnectionEvents<P> {
+    pub(crate) fn receive(&mut self, world: &mut World) -> Result<ConnectionEvents<P>> {
         trace!("Receive server packets");
         self.connection
             .receive(world, &self.time_manager, &self.tick_manager)
@@ -110,6 +111,32 @@ impl<'w, 's, P: Protocol> ClientMut<'w, 's, P> {
         self.netcode.connect();
     }
 
+    /// Disconnect from the server.
+    ///
+    /// The [`NetworkingState`](crate::client::networking::NetworkingState) will switch to `Disconnected`,
+    /// and the entities that were replicated from the server will be despawned.
+    pub fn disconnect(&mut self) -> Result<()> {
+        self.netcode.disconnect(&mut self.io)?;
+        Ok(())
+    }
+
+    /// Start the connection process with a (possibly different) server, using new authentication.
+    ///
+    /// The current connection is closed first, if there is one. This lets the client switch servers
+    /// at runtime without rebuilding the App.
+    pub fn connect_with_auth(&mut self, auth: Authentication) -> Result<()> {
+        if self.netcode.is_connected() || self.netcode.is_pending() {
+            self.netcode.disconnect(&mut self.io)?;
+        }
+        let token = auth
+            .get_token(self.config.netcode.client_timeout_secs)
+            .context("could not generate a connect token")?;
+        let token_bytes = token.try_into_bytes()?;
+        *self.netcode = NetcodeClient::with_config(&token_bytes, self.config.netcode.build())?;
+        self.netcode.connect();
+        Ok(())
+    }
+
     // MESSAGES
 
     // TODO: i'm not event sure that is something we want.
@@ -122,23 +149,57 @@ impl<'w, 's, P: Protocol> ClientMut<'w, 's, P> {
         &mut self,
         message: M,
         target: NetworkTarget,
-    ) -> Result<()>
+    ) -> Result<Option<MessageHandle>>
     where
         P::Message: From<M>,
     {
         let channel = ChannelKind::of::<C>();
         self.connection
-            .buffer_message(message.into(), channel, target)
+            .buffer_message(message.into(), channel, target, None)
     }
 
     /// Send a message to the server
-    pub fn send_message<C: Channel, M: Message>(&mut self, message: M) -> Result<()>
+    pub fn send_message<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+    ) -> Result<Option<MessageHandle>>
     where
         P::Message: From<M>,
     {
         let channel = ChannelKind::of::<C>();
         self.connection
-            .buffer_message(message.into(), channel, NetworkTarget::None)
+            .buffer_message(message.into(), channel, NetworkTarget::None, None)
+    }
+
+    /// Send a message to the server on the given stream of an ordered or sequenced channel.
+    ///
+    /// The message is only ordered (or sequenced) relative to the other messages of the same stream,
+    /// so that a lost message does not block the messages of the other streams.
+    pub fn send_message_to_stream<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+        stream: StreamId,
+    ) -> Result<Option<MessageHandle>>
+    where
+        P::Message: From<M>,
+    {
+        self.connection
+            .send_message_to_stream::<C, M>(message, stream)
+    }
+
+    /// Send a message to the server on the given stream of an ordered or sequenced channel,
+    /// the message should be re-broadcasted according to the `target`
+    pub fn send_message_to_target_on_stream<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+        target: NetworkTarget,
+        stream: StreamId,
+    ) -> Result<Option<MessageHandle>>
+    where
+        P::Message: From<M>,
+    {
+        self.connection
+            .send_message_to_target_on_stream::<C, M>(message, target, stream)
     }
 
     // INPUTS
@@ -232,6 +293,12 @@ impl<'w, 's, P: Protocol> Client<'w, 's, P> {
         self.connection.sync_manager.is_synced()
     }
 
+    /// Fraction (between 0.0 and 1.0) of the initial world state that has been received from the server,
+    /// or None if the server hasn't started sending it yet
+    pub fn world_load_progress(&self) -> Option<f32> {
+        self.connection.world_load_progress()
+    }
+
     /// Returns the client id assigned by the server
     pub fn id(&self) -> ClientId {
         self.netcode.id()
@@ -280,7 +347,7 @@ impl<'w, 's, P: Protocol> Client<'w, 's, P> {
 }
 
 impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
-    fn new_connected_clients(&self) -> Vec<ClientId> {
+    fn new_connected_clients(&self, _entity: Entity) -> Vec<ClientId> {
         vec![]
     }
 
@@ -291,7 +358,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         target: NetworkTarget,
         system_current_tick: BevyTick,
     ) -> Result<()> {
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
         // trace!(?entity, "Send entity spawn for tick {:?}", self.tick());
         let replication_sender = &mut self.replication_sender;
         // update the collect changes tick
@@ -314,7 +381,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         target: NetworkTarget,
         system_current_tick: BevyTick,
     ) -> Result<()> {
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
         // trace!(?entity, "Send entity despawn for tick {:?}", self.tick());
         let replication_sender = &mut self.replication_sender;
         // update the collect changes tick
@@ -337,7 +404,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         system_current_tick: BevyTick,
     ) -> Result<()> {
         let kind: P::ComponentKinds = (&component).into();
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
         // debug!(
         //     ?entity,
         //     component = ?kind,
@@ -364,7 +431,7 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         system_current_tick: BevyTick,
     ) -> Result<()> {
         debug!(?entity, ?component_kind, "Sending RemoveComponent");
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
         // self.replication_sender
         //     .group_channels
         //     .entry(group)
@@ -385,12 +452,13 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         system_current_tick: BevyTick,
     ) -> Result<()> {
         let kind: P::ComponentKinds = (&component).into();
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
         // TODO: should we have additional state tracking so that we know we are in the process of sending this entity to clients?
+        let group_id = self.replication_sender.group_id(entity, group);
         let collect_changes_since_this_tick = self
             .replication_sender
             .group_channels
-            .entry(group)
+            .entry(group_id)
             .or_default()
             .collect_changes_since_this_tick;
         // send the update for all changes newer than the last ack bevy tick for the group
@@ -432,6 +500,11 @@ impl<'w, 's, P: Protocol> Client<'w, 's, P> {
             .sync_manager
             .interpolation_tick(&self.tick_manager)
     }
+
+    /// How much behind the server time the interpolation timeline currently is
+    pub fn interpolation_delay(&self) -> Duration {
+        self.connection.interpolation_delay()
+    }
     // // TODO: how to mock this in tests?
     // // TODO: actually we shouldn't use interpolation ticks, but use times directly, so we can take into account the overstep properly?
     // pub(crate) fn interpolated_tick(&mut self) -> Tick {
@@ -0,0 +1,375 @@
+//! Handles the events that the server schedules for a specific tick
+//!
+//! The server sends a message on a [`ChannelMode::Scheduled`](crate::prelude::ChannelMode::Scheduled) channel
+//! ahead of time, along with the tick at which it should happen. The client buffers the message and emits it
+//! as a [`ScheduledEvent`] during `FixedUpdate`, when its own timeline reaches that tick.
+use std::iter;
+
+use bevy::prelude::{
+    not, App, FixedUpdate, IntoSystemConfigs, IntoSystemSetConfigs, Mut, Plugin, SystemSet, World,
+};
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+use crate::client::connection::ConnectionManager;
+use crate::client::prediction::plugin::is_in_rollback;
+use crate::connection::events::IterMessageEvent;
+use crate::prelude::{Message, TickManager};
+use crate::protocol::message::{MessageKind, MessageProtocol};
+use crate::protocol::Protocol;
+use crate::shared::events::MessageEvent;
+use crate::shared::sets::FixedUpdateSet;
+use crate::shared::tick_manager::{is_paused, Tick};
+
+/// Timeline of the client on which a scheduled event is emitted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScheduleTimeline {
+    /// The event is emitted when the client's predicted tick reaches the scheduled tick.
+    /// Use this for events that affect predicted entities.
+    Predicted,
+    /// The event is emitted when the client's interpolation tick reaches the scheduled tick.
+    /// Use this for events that affect interpolated entities.
+    Interpolated,
+}
+
+/// A message that the server sends ahead of time, to be emitted at a given tick
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ScheduledMessage<M> {
+    /// Server tick at which the message should be emitted
+    pub(crate) tick: Tick,
+    pub(crate) timeline: ScheduleTimeline,
+    pub(crate) message: M,
+}
+
+/// Context of a [`ScheduledEvent`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScheduledContext {
+    tick: Tick,
+    timeline: ScheduleTimeline,
+    late: bool,
+}
+
+/// Emitted during `FixedUpdate` when the client's timeline reaches the tick scheduled by the server
+pub type ScheduledEvent<M> = MessageEvent<M, ScheduledContext>;
+
+impl<M: Message> MessageEvent<M, ScheduledContext> {
+    /// Tick for which the event was scheduled
+    pub fn tick(&self) -> Tick {
+        self.context().tick
+    }
+
+    /// Timeline on which the event was scheduled
+    pub fn timeline(&self) -> ScheduleTimeline {
+        self.context().timeline
+    }
+
+    /// True if the event was received after the client's timeline had already passed the scheduled tick,
+    /// in which case it is emitted as soon as possible instead of at the scheduled tick
+    pub fn is_late(&self) -> bool {
+        self.context().late
+    }
+}
+
+/// Buffer of the scheduled messages received from the server, waiting for their tick
+pub(crate) struct ScheduledMessages<P: Protocol> {
+    predicted: Vec<(Tick, P::Message)>,
+    interpolated: Vec<(Tick, P::Message)>,
+}
+
+impl<P: Protocol> Default for ScheduledMessages<P> {
+    fn default() -> Self {
+        Self {
+            predicted: Vec::new(),
+            interpolated: Vec::new(),
+        }
+    }
+}
+
+impl<P: Protocol> ScheduledMessages<P> {
+    pub(crate) fn add(&mut self, message: ScheduledMessage<P::Message>) {
+        let buffer = match message.timeline {
+            ScheduleTimeline::Predicted => &mut self.predicted,
+            ScheduleTimeline::Interpolated => &mut self.interpolated,
+        };
+        buffer.push((message.tick, message.message));
+    }
+
+    /// Pop the messages whose tick has been reached by their timeline
+    pub(crate) fn pop_ready(
+        &mut self,
+        predicted_tick: Tick,
+        interpolation_tick: Tick,
+    ) -> ScheduledEvents<P> {
+        let mut events = ScheduledEvents::default();
+        for (buffer, timeline, current_tick) in [
+            (
+                &mut self.predicted,
+                ScheduleTimeline::Predicted,
+                predicted_tick,
+            ),
+            (
+                &mut self.interpolated,
+                ScheduleTimeline::Interpolated,
+                interpolation_tick,
+            ),
+        ] {
+            let (ready, pending): (Vec<_>, Vec<_>) = std::mem::take(buffer)
+                .into_iter()
+                .partition(|(tick, _)| *tick <= current_tick);
+            *buffer = pending;
+            for (tick, message) in ready {
+                let context = ScheduledContext {
+                    tick,
+                    timeline,
+                    late: tick < current_tick,
+                };
+                trace!(?context, "Emitting scheduled event");
+                events.push(message, context);
+            }
+        }
+        events
+    }
+}
+
+/// Scheduled messages that are ready to be emitted as [`ScheduledEvent`]s
+pub(crate) struct ScheduledEvents<P: Protocol> {
+    messages: HashMap<MessageKind, Vec<(P::Message, ScheduledContext)>>,
+}
+
+impl<P: Protocol> Default for ScheduledEvents<P> {
+    fn default() -> Self {
+        Self {
+            messages: HashMap::default(),
+        }
+    }
+}
+
+impl<P: Protocol> ScheduledEvents<P> {
+    fn push(&mut self, message: P::Message, context: ScheduledContext) {
+        self.messages
+            .entry(message.kind())
+            .or_default()
+            .push((message, context));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+impl<P: Protocol> IterMessageEvent<P, ScheduledContext> for ScheduledEvents<P> {
+    fn into_iter_messages<M: Message>(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = (M, ScheduledContext)> + '_>
+    where
+        P::Message: TryInto<M, Error = ()>,
+    {
+        let message_kind = MessageKind::of::<M>();
+        if let Some(data) = self.messages.remove(&message_kind) {
+            return Box::new(data.into_iter().map(|(message, context)| {
+                // SAFETY: we checked via message kind that only messages of the type M
+                // are in the list
+                (message.try_into().unwrap(), context)
+            }));
+        }
+        Box::new(iter::empty())
+    }
+
+    fn has_messages<M: Message>(&self) -> bool {
+        self.messages.contains_key(&MessageKind::of::<M>())
+    }
+}
+
+/// System Set in which the [`ScheduledEvent`]s are emitted
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct ScheduledEventSet;
+
+pub(crate) struct ScheduledPlugin<P: Protocol> {
+    _marker: std::marker::PhantomData<P>,
+}
+
+impl<P: Protocol> Default for ScheduledPlugin<P> {
+    fn default() -> Self {
+        Self {
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<P: Protocol> Plugin for ScheduledPlugin<P> {
+    fn build(&self, app: &mut App) {
+        // EVENTS
+        P::Message::add_events::<ScheduledContext>(app);
+        // SETS
+        app.configure_sets(
+            FixedUpdate,
+            (
+                FixedUpdateSet::TickUpdate,
+                // the events are emitted only once: they are not emitted again during rollbacks
+                ScheduledEventSet
+                    .run_if(not(is_paused))
+                    .run_if(not(is_in_rollback)),
+                FixedUpdateSet::Main,
+            )
+                .chain(),
+        );
+        // SYSTEMS
+        app.add_systems(
+            FixedUpdate,
+            write_scheduled_events::<P>.in_set(ScheduledEventSet),
+        );
+    }
+}
+
+/// Emit the scheduled messages whose tick has been reached
+fn write_scheduled_events<P: Protocol>(world: &mut World) {
+    world.resource_scope(|world, mut connection: Mut<ConnectionManager<P>>| {
+        // the timelines are not meaningful until the client is synced with the server
+        if !connection.is_synced() {
+            return;
+        }
+        let tick_manager = world.resource::<TickManager>();
+        let predicted_tick = tick_manager.tick();
+        let interpolation_tick = connection.sync_manager.interpolation_tick(tick_manager);
+        let mut events = connection
+            .scheduled_messages
+            .pop_ready(predicted_tick, interpolation_tick);
+        if !events.is_empty() {
+            P::Message::push_message_events(world, &mut events);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Events;
+    use bevy::utils::Duration;
+
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_pop_ready() {
+        let mut buffer = ScheduledMessages::<MyProtocol>::default();
+        let message = |tick: Tick, timeline: ScheduleTimeline, value: u32| ScheduledMessage {
+            tick,
+            timeline,
+            message: MyMessageProtocol::Message2(Message2(value)),
+        };
+        buffer.add(message(Tick(10), ScheduleTimeline::Predicted, 0));
+        buffer.add(message(Tick(5), ScheduleTimeline::Interpolated, 1));
+        buffer.add(message(Tick(12), ScheduleTimeline::Predicted, 2));
+
+        // the predicted timeline did not reach its events yet, the interpolation timeline is late
+        let mut events = buffer.pop_ready(Tick(9), Tick(7));
+        let received: Vec<_> = events.into_iter_messages::<Message2>().collect();
+        assert_eq!(
+            received,
+            vec![(
+                Message2(1),
+                ScheduledContext {
+                    tick: Tick(5),
+                    timeline: ScheduleTimeline::Interpolated,
+                    late: true,
+                }
+            )]
+        );
+
+        // the predicted timeline reaches the first event exactly
+        let mut events = buffer.pop_ready(Tick(10), Tick(8));
+        let received: Vec<_> = events.into_iter_messages::<Message2>().collect();
+        assert_eq!(
+            received,
+            vec![(
+                Message2(0),
+                ScheduledContext {
+                    tick: Tick(10),
+                    timeline: ScheduleTimeline::Predicted,
+                    late: false,
+                }
+            )]
+        );
+        assert!(buffer.pop_ready(Tick(11), Tick(9)).is_empty());
+    }
+
+    // The server schedules an event ahead of time: the client emits it when its predicted tick reaches
+    // the scheduled tick
+    #[test]
+    fn test_scheduled_event() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(20),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default().disable(false),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let server_tick = stepper.server_tick();
+        let scheduled_tick = server_tick + 20;
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .send_scheduled_message::<ScheduledChannel, _>(
+                Message2(1),
+                scheduled_tick,
+                ScheduleTimeline::Predicted,
+                NetworkTarget::All,
+            )
+            .unwrap();
+        // the message cannot be sent on a channel that is not scheduled
+        assert!(stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .send_scheduled_message::<Channel1, _>(
+                Message2(2),
+                scheduled_tick,
+                ScheduleTimeline::Predicted,
+                NetworkTarget::All,
+            )
+            .is_err());
+
+        let mut received = vec![];
+        for _ in 0..40 {
+            stepper.frame_step();
+            let events = stepper
+                .client_app
+                .world
+                .resource::<Events<ScheduledEvent<Message2>>>();
+            for event in events.get_reader().read(events) {
+                received.push((
+                    event.message().clone(),
+                    event.tick(),
+                    event.is_late(),
+                    stepper.client_tick(),
+                ));
+            }
+            if !received.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(
+            received,
+            vec![(Message2(1), scheduled_tick, false, scheduled_tick)]
+        );
+    }
+}
@@ -7,7 +7,7 @@ use bevy::prelude::Res;
 use chrono::Duration as ChronoDuration;
 use tracing::{debug, info, trace, warn};
 
-use crate::client::interpolation::plugin::InterpolationDelay;
+use crate::client::interpolation::plugin::{InterpolationDelay, UpdateArrivalStats};
 use crate::client::resource::Client;
 use crate::packet::packet::PacketId;
 use crate::protocol::Protocol;
@@ -94,6 +94,12 @@ pub struct SyncManager {
     server_time_estimate: WrappedTime,
     pub(crate) interpolation_time: WrappedTime,
     interpolation_speed_ratio: f32,
+    /// Current interpolation delay. It only changes over time if `InterpolationDelay::adaptive` is set
+    interpolation_delay: Duration,
+    /// Arrivals of server updates (time since the previous update, expected time since the previous update)
+    /// that have not been used yet to update the arrival statistics
+    pending_update_arrivals: Vec<(Duration, Duration)>,
+    update_arrival_stats: UpdateArrivalStats,
 
     // ticks
     // TODO: see if this is correct; should we instead attach the tick on every update message?
@@ -121,6 +127,9 @@ impl SyncManager {
             server_time_estimate: WrappedTime::default(),
             interpolation_time: WrappedTime::default(),
             interpolation_speed_ratio: 1.0,
+            interpolation_delay: Duration::default(),
+            pending_update_arrivals: Vec::new(),
+            update_arrival_stats: UpdateArrivalStats::default(),
             // server tick
             latest_received_server_tick: None,
             estimated_interpolation_tick: Tick(0),
@@ -149,14 +158,20 @@ impl SyncManager {
         self.server_time_estimate += time_manager.delta();
         self.interpolation_time += time_manager.delta().mul_f32(self.interpolation_speed_ratio);
 
+        if let Some(adaptive) = &interpolation_delay.adaptive {
+            for (gap, expected) in self.pending_update_arrivals.drain(..) {
+                self.update_arrival_stats
+                    .record(gap, expected, adaptive.smoothing);
+            }
+        } else {
+            self.pending_update_arrivals.clear();
+        }
+
         // check if we are ready to finalize the handshake
         if !self.synced && ping_manager.sync_stats.len() >= self.config.handshake_pings as usize {
             self.synced = true;
-            self.interpolation_time = self.interpolation_objective(
-                interpolation_delay,
-                server_send_interval,
-                tick_manager,
-            );
+            self.interpolation_delay = interpolation_delay.to_duration(server_send_interval);
+            self.interpolation_time = self.interpolation_objective(tick_manager);
             debug!(
                 "interpolation_tick: {:?}",
                 self.interpolation_tick(tick_manager)
@@ -165,7 +180,12 @@ impl SyncManager {
         }
 
         if self.synced {
-            self.update_interpolation_time(interpolation_delay, server_send_interval, tick_manager);
+            self.update_interpolation_delay(
+                interpolation_delay,
+                server_send_interval,
+                time_manager.delta(),
+            );
+            self.update_interpolation_time(tick_manager);
         }
         None
     }
@@ -174,6 +194,28 @@ impl SyncManager {
         self.synced
     }
 
+    /// How much behind the server time the interpolation timeline currently is
+    pub fn interpolation_delay(&self) -> Duration {
+        self.interpolation_delay
+    }
+
+    /// Record that we received a packet with a more recent server tick than `previous_tick`.
+    /// This is used to tune the interpolation delay if `InterpolationDelay::adaptive` is set.
+    pub(crate) fn record_server_update(
+        &mut self,
+        tick: Tick,
+        previous_tick: Tick,
+        tick_manager: &TickManager,
+    ) {
+        let delta_ticks = tick - previous_tick;
+        if delta_ticks <= 0 {
+            return;
+        }
+        let expected = tick_manager.tick_duration() * delta_ticks as u32;
+        self.pending_update_arrivals
+            .push((self.duration_since_latest_received_server_tick, expected));
+    }
+
     /// Compute the current client time; we will make sure that the client tick is ahead of the server tick
     /// Even if it is wrapped around.
     /// (i.e. if client tick is 1, and server tick is 65535, we act as if the client tick was 65537)
@@ -202,11 +244,8 @@ impl SyncManager {
             self.server_latest_tick_generation()
         };
 
-        let res = WrappedTime::from_tick(
-            tick_manager.tick(),
-            generation,
-            tick_manager.config.tick_duration,
-        ) + time_manager.overstep();
+        let res =
+            tick_manager.tick_to_time(tick_manager.tick(), generation) + time_manager.overstep();
         // when getting time from ticks, don't forget the overstep
         debug!(
             ?generation,
@@ -233,13 +272,16 @@ impl SyncManager {
     /// Everytime we receive a new server update:
     /// Update the estimated current server time, computed from the time elapsed since the
     /// latest received server tick, and our estimate of the RTT
-    pub(crate) fn update_server_time_estimate(&mut self, tick_duration: Duration, rtt: Duration) {
+    pub(crate) fn update_server_time_estimate(
+        &mut self,
+        tick_manager: &TickManager,
+        rtt: Duration,
+    ) {
         // TODO: should we add the time since
         // SAFETY: by that point we have received at least one server packet, so the latest_received_server_tick is not None
-        let new_server_time_estimate = WrappedTime::from_tick(
+        let new_server_time_estimate = tick_manager.tick_to_time(
             self.latest_received_server_tick.unwrap(),
             self.server_latest_tick_generation(),
-            tick_duration,
         ) + self.duration_since_latest_received_server_tick;
 
         // instead of just using the latest_received_server_tick, we apply some smoothing
@@ -309,14 +351,7 @@ impl SyncManager {
         )
     }
 
-    pub(crate) fn interpolation_objective(
-        &self,
-        // TODO: make interpolation delay part of SyncConfig?
-        interpolation_delay: &InterpolationDelay,
-        // TODO: should we get this via an estimate?
-        server_send_interval: Duration,
-        tick_manager: &TickManager,
-    ) -> WrappedTime {
+    pub(crate) fn interpolation_objective(&self, tick_manager: &TickManager) -> WrappedTime {
         // // TODO: maybe integrate because of jitter?
         // let objective_time = WrappedTime::from_duration(
         //     self.latest_received_server_tick.0 as u32 * tick_manager.config.tick_duration
@@ -325,35 +360,43 @@ impl SyncManager {
         // let objective_time = self.server_time_estimate();
         // how much we want interpolation time to be behind the latest received server tick?
         // TODO: use a specified config margin + add std of time_between_server_updates?
-        let objective_delta =
-            chrono::Duration::from_std(interpolation_delay.to_duration(server_send_interval))
-                .unwrap();
+        let objective_delta = chrono::Duration::from_std(self.interpolation_delay).unwrap();
         // info!("objective_delta: {:?}", objective_delta);
         self.server_time_estimate() - objective_delta
     }
 
     pub(crate) fn interpolation_tick(&self, tick_manager: &TickManager) -> Tick {
         // TODO: check that this wraps correctly!
-        Tick(
-            (self.interpolation_time.elapsed.as_nanos()
-                / tick_manager.config.tick_duration.as_nanos()) as u16,
-        )
+        tick_manager.time_to_tick(self.interpolation_time)
     }
 
-    // TODO: only run when there's a change? (new server tick received or new ping received)
-    // TODO: change name to make it clear that we might modify speed
-    pub(crate) fn update_interpolation_time(
+    /// Move the interpolation delay towards its target value. The delay changes gradually, at a rate
+    /// that the interpolation timeline can follow by speeding up or slowing down (see `speedup_factor`)
+    pub(crate) fn update_interpolation_delay(
         &mut self,
         // TODO: make interpolation delay part of SyncConfig?
         interpolation_delay: &InterpolationDelay,
         // TODO: should we get this via an estimate?
-        server_update_rate: Duration,
-        tick_manager: &TickManager,
+        server_send_interval: Duration,
+        delta: Duration,
     ) {
+        let target =
+            interpolation_delay.target_duration(server_send_interval, &self.update_arrival_stats);
+        let max_change = delta.mul_f32((self.config.speedup_factor - 1.0).abs());
+        self.interpolation_delay = if target > self.interpolation_delay {
+            std::cmp::min(target, self.interpolation_delay + max_change)
+        } else {
+            std::cmp::max(target, self.interpolation_delay.saturating_sub(max_change))
+        };
+        trace!(?target, interpolation_delay = ?self.interpolation_delay, stats = ?self.update_arrival_stats, "interpolation delay");
+    }
+
+    // TODO: only run when there's a change? (new server tick received or new ping received)
+    // TODO: change name to make it clear that we might modify speed
+    pub(crate) fn update_interpolation_time(&mut self, tick_manager: &TickManager) {
         // for interpolation time, we don't need to use ticks (because we only need interpolation at the end
         // of the frame, not during the FixedUpdate schedule)
-        let objective_time =
-            self.interpolation_objective(interpolation_delay, server_update_rate, tick_manager);
+        let objective_time = self.interpolation_objective(tick_manager);
         let delta = objective_time - self.interpolation_time;
         trace!(
             ?objective_time,
@@ -363,8 +406,7 @@ impl SyncManager {
 
         let max_error_margin_time = chrono::Duration::from_std(
             tick_manager
-                .config
-                .tick_duration
+                .tick_duration()
                 .mul_f32(self.config.max_error_margin),
         )
         .unwrap();
@@ -409,7 +451,7 @@ impl SyncManager {
         // client ideal time
         let client_ideal_time = self.client_ideal_time(
             rtt,
-            tick_manager.config.tick_duration,
+            tick_manager.tick_duration(),
             jitter,
             self.input_delay_ticks,
         );
@@ -417,15 +459,13 @@ impl SyncManager {
         let error = current_prediction_time - client_ideal_time;
         let error_margin_time = chrono::Duration::from_std(
             tick_manager
-                .config
-                .tick_duration
+                .tick_duration()
                 .mul_f32(self.config.error_margin),
         )
         .unwrap();
         let max_error_margin_time = chrono::Duration::from_std(
             tick_manager
-                .config
-                .tick_duration
+                .tick_duration()
                 .mul_f32(self.config.max_error_margin),
         )
         .unwrap();
@@ -492,11 +532,11 @@ impl SyncManager {
         tick_manager: &mut TickManager,
         ping_manager: &PingManager,
     ) -> Option<TickEvent> {
-        let tick_duration = tick_manager.config.tick_duration;
+        let tick_duration = tick_manager.tick_duration();
         let rtt = ping_manager.rtt();
         let jitter = ping_manager.jitter();
         // recompute the server time estimate (using the rtt we just computed)
-        self.update_server_time_estimate(tick_duration, rtt);
+        self.update_server_time_estimate(tick_manager, rtt);
 
         // Compute how many ticks the client must be compared to server
         let client_ideal_time =
@@ -509,9 +549,9 @@ impl SyncManager {
         // }
 
         // TODO: should we add 1 to get the div_ceil?
-        // TODO: check that this wraps correctly?
-        let client_ideal_tick =
-            Tick((client_ideal_time.elapsed.as_nanos() / tick_duration.as_nanos()) as u16);
+        // (the tick durations might have changed over time, so we use the tick manager to convert time to tick)
+        let client_ideal_absolute_tick = tick_manager.time_to_absolute_tick(client_ideal_time);
+        let client_ideal_tick = Tick(client_ideal_absolute_tick as u16);
 
         let delta_tick = client_ideal_tick - tick_manager.tick();
         // Update client ticks
@@ -530,7 +570,7 @@ impl SyncManager {
                 "Finished syncing!"
             );
         }
-        Some(tick_manager.set_tick_to(client_ideal_tick))
+        Some(tick_manager.set_absolute_tick(client_ideal_absolute_tick))
     }
 }
 
@@ -542,6 +582,8 @@ mod tests {
     use crate::server::events::InputEvent;
     use crate::tests::protocol::*;
     use crate::tests::stepper::{BevyStepper, Step};
+    use bevy::ecs::event::ManualEventReader;
+    use bevy::ecs::system::SystemState;
     use bevy::prelude::*;
     use bevy::utils::Duration;
 
@@ -630,14 +672,276 @@ mod tests {
             stepper.frame_step();
         }
 
-        let client_entity = *stepper
+        let client_entity = stepper.client_entity(server_entity).unwrap();
+        dbg!(&stepper.client_app.world.get::<Component1>(client_entity));
+    }
+
+    #[test]
+    fn test_server_tick_duration_change() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: false,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(20),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            client::PredictionConfig::default(),
+            client::InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+
+        // the server halves its simulation rate
+        let new_tick_duration = Duration::from_millis(20);
+        let mut system_state: SystemState<ServerMut> =
+            SystemState::new(&mut stepper.server_app.world);
+        let change_tick = system_state
+            .get_mut(&mut stepper.server_app.world)
+            .set_tick_duration(new_tick_duration)
+            .unwrap();
+        system_state.apply(&mut stepper.server_app.world);
+        assert!(change_tick > stepper.server_tick());
+
+        let mut tick_events = ManualEventReader::<TickEvent>::default();
+        tick_events.clear(stepper.client_app.world.resource::<Events<TickEvent>>());
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+
+        // both sides use the new tick duration
+        for app in [&stepper.client_app, &stepper.server_app] {
+            assert_eq!(
+                app.world.resource::<TickManager>().tick_duration(),
+                new_tick_duration
+            );
+            assert_eq!(
+                app.world.resource::<Time<Fixed>>().timestep(),
+                new_tick_duration
+            );
+        }
+        // the client stayed in sync without snapping its tick
+        assert!(stepper
             .client_app
             .world
             .resource::<ClientConnectionManager>()
-            .replication_receiver
-            .remote_entity_map
-            .get_local(server_entity)
+            .is_synced());
+        assert_eq!(
+            tick_events
+                .read(stepper.client_app.world.resource::<Events<TickEvent>>())
+                .count(),
+            0
+        );
+        let client_ahead = stepper.client_tick() - stepper.server_tick();
+        assert!((1..5).contains(&client_ahead));
+        assert!(stepper.interpolation_tick() < stepper.server_tick());
+    }
+
+    #[derive(Resource, Default)]
+    struct SimulatedTicks(u32);
+
+    fn count_simulated_ticks(mut ticks: ResMut<SimulatedTicks>) {
+        ticks.0 += 1;
+    }
+
+    #[test]
+    fn test_server_pause_and_time_scale() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: false,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(20),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            client::PredictionConfig::default(),
+            client::InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        for app in [&mut stepper.client_app, &mut stepper.server_app] {
+            app.init_resource::<SimulatedTicks>();
+            app.add_systems(
+                FixedUpdate,
+                count_simulated_ticks.in_set(FixedUpdateSet::Main),
+            );
+        }
+        stepper.init();
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+        let mut tick_events = ManualEventReader::<TickEvent>::default();
+        tick_events.clear(stepper.client_app.world.resource::<Events<TickEvent>>());
+
+        // pause the simulation
+        let mut system_state: SystemState<ServerMut> =
+            SystemState::new(&mut stepper.server_app.world);
+        system_state
+            .get_mut(&mut stepper.server_app.world)
+            .pause()
             .unwrap();
-        dbg!(&stepper.client_app.world.get::<Component1>(client_entity));
+        system_state.apply(&mut stepper.server_app.world);
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+        let client_ticks = stepper.client_app.world.resource::<SimulatedTicks>().0;
+        let server_ticks = stepper.server_app.world.resource::<SimulatedTicks>().0;
+        let client_tick = stepper.client_tick();
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+        // the game logic doesn't run, but the ticks are still incremented
+        assert_eq!(
+            stepper.client_app.world.resource::<SimulatedTicks>().0,
+            client_ticks
+        );
+        assert_eq!(
+            stepper.server_app.world.resource::<SimulatedTicks>().0,
+            server_ticks
+        );
+        assert!(stepper.client_tick() - client_tick >= 45);
+
+        // resume the simulation
+        system_state
+            .get_mut(&mut stepper.server_app.world)
+            .resume()
+            .unwrap();
+        system_state.apply(&mut stepper.server_app.world);
+        for _ in 0..50 {
+            stepper.frame_step();
+        }
+        assert!(stepper.client_app.world.resource::<SimulatedTicks>().0 > client_ticks);
+        assert!(stepper.server_app.world.resource::<SimulatedTicks>().0 > server_ticks);
+
+        // slow-motion
+        system_state
+            .get_mut(&mut stepper.server_app.world)
+            .set_time_scale(0.5)
+            .unwrap();
+        system_state.apply(&mut stepper.server_app.world);
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+        assert_eq!(
+            stepper
+                .server_app
+                .world
+                .resource::<Time<Virtual>>()
+                .relative_speed(),
+            0.5
+        );
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource::<TimeManager>()
+                .base_relative_speed,
+            0.5
+        );
+
+        // the client never had to snap its tick
+        assert!(stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .is_synced());
+        assert_eq!(
+            tick_events
+                .read(stepper.client_app.world.resource::<Events<TickEvent>>())
+                .count(),
+            0
+        );
+    }
+
+    fn move_component(mut query: Query<&mut Component1>) {
+        for mut c in query.iter_mut() {
+            c.0 += 1.0;
+        }
+    }
+
+    /// Run a client that receives server updates every 50ms with the given jitter,
+    /// and return the interpolation delay it converged to
+    fn adaptive_interpolation_delay(jitter: Duration) -> Duration {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            server_send_interval: Duration::from_millis(50),
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(40),
+            incoming_jitter: jitter,
+            incoming_loss: 0.0,
+        };
+        let interpolation_config = client::InterpolationConfig::default().with_delay(
+            client::InterpolationDelay::default()
+                .with_adaptive(client::AdaptiveInterpolationDelay::default()),
+        );
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            client::PredictionConfig::default(),
+            interpolation_config,
+            link_conditioner,
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .add_systems(FixedUpdate, move_component.in_set(FixedUpdateSet::Main));
+        stepper.server_app.world.spawn((
+            Component1(0.0),
+            Replicate {
+                replication_target: NetworkTarget::All,
+                ..default()
+            },
+        ));
+        stepper.init();
+        // the fixed delay is used right after syncing: 2 * send_interval
+        let initial_delay = stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .interpolation_delay();
+        assert!((initial_delay.as_secs_f32() - 0.1).abs() < 1e-3);
+        for _ in 0..500 {
+            stepper.frame_step();
+        }
+        stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .interpolation_delay()
+    }
+
+    #[test]
+    fn test_adaptive_interpolation_delay() {
+        // on a stable connection, the delay shrinks towards the send interval
+        let stable_delay = adaptive_interpolation_delay(Duration::default());
+        assert!(stable_delay < Duration::from_millis(100));
+        assert!(stable_delay >= Duration::from_millis(50));
+
+        // with jitter, the delay grows to cover the late updates
+        let jittery_delay = adaptive_interpolation_delay(Duration::from_millis(30));
+        assert!(jittery_delay > stable_delay);
+        assert!(jittery_delay <= Duration::from_millis(500));
     }
 }
@@ -10,9 +10,15 @@ use tracing::{error, info, trace};
 use crate::_reexport::ReplicationSend;
 use crate::client::config::ClientConfig;
 use crate::client::connection::ConnectionManager;
-use crate::client::events::{EntityDespawnEvent, EntitySpawnEvent};
+use crate::client::events::{
+    BlobReceivedEvent, EntityDespawnEvent, EntitySpawnEvent, MessageAckedEvent,
+    MessageExpiredEvent, MessageLostEvent, WorldLoadedEvent,
+};
 use crate::client::resource::{Client, ClientMut};
-use crate::connection::events::{IterEntityDespawnEvent, IterEntitySpawnEvent};
+use crate::connection::events::{
+    ConnectionEvents, IterBlobReceivedEvent, IterEntityDespawnEvent, IterEntitySpawnEvent,
+    IterMessageDeliveryEvent,
+};
 use crate::prelude::{Io, TickManager, TimeManager};
 use crate::protocol::component::ComponentProtocol;
 use crate::protocol::message::MessageProtocol;
@@ -37,7 +43,7 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                         world.resource_scope(
                             |world: &mut World, mut time_manager: Mut<TimeManager>| {
                                 world.resource_scope(
-                                    |world: &mut World, tick_manager: Mut<TickManager>| {
+                                    |world: &mut World, mut tick_manager: Mut<TickManager>| {
                                         let delta = world.resource::<Time<Virtual>>().delta();
 
                                         // UPDATE: update client state, send keep-alives, receive packets from io, update connection sync state
@@ -47,6 +53,12 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                             .try_update(delta.as_secs_f64(), io.deref_mut())
                                             .unwrap();
 
+                                        // the server started a new session (we were moved to a different instance)
+                                        if netcode.take_epoch_change() {
+                                            info!("Server started a new session, resetting the connection");
+                                            connection.reset(world);
+                                        }
+
                                         // only start the connection (sending messages, sending pings, starting sync, etc.)
                                         // once we are connected
                                         if netcode.is_connected() {
@@ -57,68 +69,42 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                         }
 
                                         // RECV PACKETS: buffer packets into message managers
+                                        let mut received = Ok(());
                                         while let Some(mut reader) = netcode.recv() {
-                                            connection
-                                                .recv_packet(&mut reader, tick_manager.as_ref())
-                                                .unwrap();
+                                            received = connection
+                                                .recv_packet(&mut reader, tick_manager.as_ref());
+                                            if received.is_err() {
+                                                break;
+                                            }
                                         }
 
                                         // RECEIVE: receive packets from message managers
-                                        let mut events = connection.receive(
-                                            world,
-                                            time_manager.as_ref(),
-                                            tick_manager.as_ref(),
-                                        );
-
-                                        // HANDLE EVENTS
-                                        if !events.is_empty() {
-                                            // NOTE: maybe no need to send those events, because the client knows when it's connected/disconnected?
-                                            // if events.has_connection() {
-                                            //     let mut connect_event_writer =
-                                            //         world.get_resource_mut::<Events<ConnectEvent>>().unwrap();
-                                            //     debug!("Client connected event");
-                                            //     connect_event_writer.send(ConnectEvent::new(()));
-                                            // }
-                                            //
-                                            // if events.has_disconnection() {
-                                            //     let mut disconnect_event_writer =
-                                            //         world.get_resource_mut::<Events<DisconnectEvent>>().unwrap();
-                                            //     debug!("Client disconnected event");
-                                            //     disconnect_event_writer.send(DisconnectEvent::new(()));
-                                            // }
-
-                                            // Message Events
-                                            P::Message::push_message_events(world, &mut events);
-
-                                            // SpawnEntity event
-                                            if events.has_entity_spawn() {
-                                                let mut entity_spawn_event_writer = world
-                                                    .get_resource_mut::<Events<EntitySpawnEvent>>()
-                                                    .unwrap();
-                                                for (entity, _) in events.into_iter_entity_spawn() {
-                                                    entity_spawn_event_writer
-                                                        .send(EntitySpawnEvent::new(entity, ()));
-                                                }
-                                            }
-                                            // DespawnEntity event
-                                            if events.has_entity_despawn() {
-                                                let mut entity_despawn_event_writer = world
-                                                    .get_resource_mut::<Events<EntityDespawnEvent>>(
-                                                    )
-                                                    .unwrap();
-                                                for (entity, _) in events.into_iter_entity_despawn()
-                                                {
-                                                    entity_despawn_event_writer
-                                                        .send(EntityDespawnEvent::new(entity, ()));
-                                                }
+                                        let events = received.and_then(|_| {
+                                            connection.receive(
+                                                world,
+                                                time_manager.as_ref(),
+                                                tick_manager.as_ref(),
+                                            )
+                                        });
+                                        let mut events = match events {
+                                            Ok(events) => events,
+                                            Err(e) => {
+                                                // the server sent data that could not be decoded
+                                                error!("Received invalid data from the server, disconnecting: {}", e);
+                                                netcode
+                                                    .disconnect(io.deref_mut())
+                                                    .unwrap_or_else(|e| error!("Error disconnecting: {}", e));
+                                                return;
                                             }
+                                        };
 
-                                            // Update component events (updates, inserts, removes)
-                                            P::Components::push_component_events(
-                                                world,
-                                                &mut events,
-                                            );
+                                        // the server changed the timeline (tick duration, pause or time scale)
+                                        for change in connection.take_timeline_changes() {
+                                            tick_manager.apply_timeline_change(change);
                                         }
+
+                                        // HANDLE EVENTS
+                                        write_events(world, &mut events);
                                         trace!("finished recv");
                                     },
                                 )
@@ -132,6 +118,91 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
     );
 }
 
+/// Write the events received from the server into bevy [`Events`]
+pub(crate) fn write_events<P: Protocol>(world: &mut World, events: &mut ConnectionEvents<P>) {
+    if !events.is_empty() {
+        // NOTE: maybe no need to send those events, because the client knows when it's connected/disconnected?
+        // if events.has_connection() {
+        //     let mut connect_event_writer =
+        //         world.get_resource_mut::<Events<ConnectEvent>>().unwrap();
+        //     debug!("Client connected event");
+        //     connect_event_writer.send(ConnectEvent::new(()));
+        // }
+        //
+        // if events.has_disconnection() {
+        //     let mut disconnect_event_writer =
+        //         world.get_resource_mut::<Events<DisconnectEvent>>().unwrap();
+        //     debug!("Client disconnected event");
+        //     disconnect_event_writer.send(DisconnectEvent::new(()));
+        // }
+
+        // Message Events
+        P::Message::push_message_events(world, events);
+
+        // SpawnEntity event
+        if events.has_entity_spawn() {
+            let mut entity_spawn_event_writer = world
+                .get_resource_mut::<Events<EntitySpawnEvent>>()
+                .unwrap();
+            for (entity, _) in events.into_iter_entity_spawn() {
+                entity_spawn_event_writer.send(EntitySpawnEvent::new(entity, ()));
+            }
+        }
+        // DespawnEntity event
+        if events.has_entity_despawn() {
+            let mut entity_despawn_event_writer = world
+                .get_resource_mut::<Events<EntityDespawnEvent>>()
+                .unwrap();
+            for (entity, _) in events.into_iter_entity_despawn() {
+                entity_despawn_event_writer.send(EntityDespawnEvent::new(entity, ()));
+            }
+        }
+
+        // Update component events (updates, inserts, removes)
+        P::Components::push_component_events(world, events);
+
+        // BlobReceived events
+        if events.has_blobs() {
+            let mut blob_event_writer = world
+                .get_resource_mut::<Events<BlobReceivedEvent>>()
+                .unwrap();
+            for (channel_kind, bytes, _) in events.into_iter_blobs() {
+                blob_event_writer.send(BlobReceivedEvent::new(channel_kind, bytes, ()));
+            }
+        }
+
+        // MessageAcked, MessageLost and MessageExpired events
+        if events.has_message_delivery() {
+            let mut acked_event_writer = world
+                .get_resource_mut::<Events<MessageAckedEvent>>()
+                .unwrap();
+            for (handle, _) in events.into_iter_acked_messages() {
+                acked_event_writer.send(MessageAckedEvent::new(handle, ()));
+            }
+            let mut lost_event_writer = world
+                .get_resource_mut::<Events<MessageLostEvent>>()
+                .unwrap();
+            for (handle, _) in events.into_iter_lost_messages() {
+                lost_event_writer.send(MessageLostEvent::new(handle, ()));
+            }
+            let mut expired_event_writer = world
+                .get_resource_mut::<Events<MessageExpiredEvent>>()
+                .unwrap();
+            for (handle, _) in events.into_iter_expired_messages() {
+                expired_event_writer.send(MessageExpiredEvent::new(handle, ()));
+            }
+        }
+
+        // WorldLoaded event
+        if events.has_world_loaded() {
+            world
+                .get_resource_mut::<Events<WorldLoadedEvent>>()
+                .unwrap()
+                .send(WorldLoadedEvent);
+        }
+    }
+}
+
 pub fn send<P: Protocol>(
     mut io: ResMut<Io>,
     mut netcode: ResMut<crate::netcode::Client>,
@@ -156,6 +227,9 @@ pub fn send<P: Protocol>(
             .send(packet_byte.as_slice(), io.deref_mut())
             .unwrap();
     }
+    io.stats
+        .compression
+        .merge(&connection.message_manager.take_compression_stats());
 
     // no need to clear the connection, because we already std::mem::take it
     // client.connection.clear();
@@ -4,12 +4,13 @@ use std::iter;
 
 use bevy::prelude::{Component, Entity, Resource};
 use bevy::utils::HashMap;
+use bytes::Bytes;
 use tracing::trace;
 
 use crate::_reexport::{FromType, MessageProtocol};
 #[cfg(feature = "leafwing")]
 use crate::inputs::leafwing::{InputMessage, LeafwingUserAction};
-use crate::packet::message::Message;
+use crate::packet::message::{Message, MessageHandle};
 use crate::prelude::{Named, Tick};
 use crate::protocol::channel::ChannelKind;
 use crate::protocol::message::MessageKind;
@@ -21,6 +22,8 @@ pub struct ConnectionEvents<P: Protocol> {
     // netcode
     // we put disconnections outside of there because `ConnectionEvents` gets removed upon disconnection
     pub connection: bool,
+    /// The initial state of the world has been fully received (see [`crate::server::late_join`])
+    pub world_loaded: bool,
 
     // inputs (used only for leafwing messages for now)
     #[cfg(feature = "leafwing")]
@@ -28,6 +31,14 @@ pub struct ConnectionEvents<P: Protocol> {
 
     // messages
     pub messages: HashMap<MessageKind, HashMap<ChannelKind, Vec<P::Message>>>,
+    /// blobs that were fully received
+    pub blobs: Vec<(ChannelKind, Bytes)>,
+    /// sent messages that were acked by the remote
+    pub acked_messages: Vec<MessageHandle>,
+    /// sent messages that were lost
+    pub lost_messages: Vec<MessageHandle>,
+    /// sent messages that expired before being acked
+    pub expired_messages: Vec<MessageHandle>,
     // replication
     pub spawns: Vec<Entity>,
     pub despawns: Vec<Entity>,
@@ -66,11 +77,16 @@ impl<P: Protocol> ConnectionEvents<P> {
         Self {
             // netcode
             connection: false,
+            world_loaded: false,
             // inputs
             #[cfg(feature = "leafwing")]
             input_messages: HashMap::new(),
             // messages
             messages: HashMap::new(),
+            blobs: Vec::new(),
+            acked_messages: Vec::new(),
+            lost_messages: Vec::new(),
+            expired_messages: Vec::new(),
             // replication
             spawns: Vec::new(),
             despawns: Vec::new(),
@@ -85,9 +101,14 @@ impl<P: Protocol> ConnectionEvents<P> {
 
     pub fn clear(&mut self) {
         self.connection = false;
+        self.world_loaded = false;
         #[cfg(feature = "leafwing")]
         self.input_messages.clear();
         self.messages.clear();
+        self.blobs.clear();
+        self.acked_messages.clear();
+        self.lost_messages.clear();
+        self.expired_messages.clear();
         self.spawns.clear();
         self.despawns.clear();
         self.component_inserts.clear();
@@ -106,6 +127,16 @@ impl<P: Protocol> ConnectionEvents<P> {
         self.empty = false;
     }
 
+    /// If true, the initial state of the world has been fully received
+    pub fn has_world_loaded(&self) -> bool {
+        self.world_loaded
+    }
+
+    pub(crate) fn push_world_loaded(&mut self) {
+        self.world_loaded = true;
+        self.empty = false;
+    }
+
     pub fn is_empty(&self) -> bool {
         self.empty
     }
@@ -146,6 +177,28 @@ impl<P: Protocol> ConnectionEvents<P> {
         self.empty = false;
     }
 
+    pub(crate) fn push_blob(&mut self, channel_kind: ChannelKind, bytes: Bytes) {
+        trace!(size = ?bytes.len(), "Received blob");
+        self.blobs.push((channel_kind, bytes));
+        self.empty = false;
+    }
+
+    pub(crate) fn push_message_delivery(
+        &mut self,
+        acked: Vec<MessageHandle>,
+        lost: Vec<MessageHandle>,
+        expired: Vec<MessageHandle>,
+    ) {
+        if acked.is_empty() && lost.is_empty() && expired.is_empty() {
+            return;
+        }
+        trace!(?acked, ?lost, ?expired, "Sent messages delivery");
+        self.acked_messages.extend(acked);
+        self.lost_messages.extend(lost);
+        self.expired_messages.extend(expired);
+        self.empty = false;
+    }
+
     pub(crate) fn push_spawn(&mut self, entity: Entity) {
         trace!(?entity, "Received entity spawn");
         #[cfg(feature = "metrics")]
@@ -310,6 +363,57 @@ impl<P: Protocol> IterMessageEvent<P> for ConnectionEvents<P> {
     }
 }
 
+pub trait IterBlobReceivedEvent<Ctx: EventContext = ()> {
+    fn into_iter_blobs(&mut self) -> Box<dyn Iterator<Item = (ChannelKind, Bytes, Ctx)> + '_>;
+    fn has_blobs(&self) -> bool;
+}
+
+impl<P: Protocol> IterBlobReceivedEvent for ConnectionEvents<P> {
+    fn into_iter_blobs(&mut self) -> Box<dyn Iterator<Item = (ChannelKind, Bytes, ())> + '_> {
+        let blobs = std::mem::take(&mut self.blobs);
+        Box::new(
+            blobs
+                .into_iter()
+                .map(|(channel_kind, bytes)| (channel_kind, bytes, ())),
+        )
+    }
+
+    fn has_blobs(&self) -> bool {
+        !self.blobs.is_empty()
+    }
+}
+
+pub trait IterMessageDeliveryEvent<Ctx: EventContext = ()> {
+    fn into_iter_acked_messages(&mut self) -> Box<dyn Iterator<Item = (MessageHandle, Ctx)> + '_>;
+    fn into_iter_lost_messages(&mut self) -> Box<dyn Iterator<Item = (MessageHandle, Ctx)> + '_>;
+    fn into_iter_expired_messages(&mut self)
+        -> Box<dyn Iterator<Item = (MessageHandle, Ctx)> + '_>;
+    fn has_message_delivery(&self) -> bool;
+}
+
+impl<P: Protocol> IterMessageDeliveryEvent for ConnectionEvents<P> {
+    fn into_iter_acked_messages(&mut self) -> Box<dyn Iterator<Item = (MessageHandle, ())> + '_> {
+        let acked = std::mem::take(&mut self.acked_messages);
+        Box::new(acked.into_iter().map(|handle| (handle, ())))
+    }
+
+    fn into_iter_lost_messages(&mut self) -> Box<dyn Iterator<Item = (MessageHandle, ())> + '_> {
+        let lost = std::mem::take(&mut self.lost_messages);
+        Box::new(lost.into_iter().map(|handle| (handle, ())))
+    }
+
+    fn into_iter_expired_messages(&mut self) -> Box<dyn Iterator<Item = (MessageHandle, ())> + '_> {
+        let expired = std::mem::take(&mut self.expired_messages);
+        Box::new(expired.into_iter().map(|handle| (handle, ())))
+    }
+
+    fn has_message_delivery(&self) -> bool {
+        !self.acked_messages.is_empty()
+            || !self.lost_messages.is_empty()
+            || !self.expired_messages.is_empty()
+    }
+}
+
 pub trait IterEntitySpawnEvent<Ctx: EventContext = ()> {
     fn into_iter_entity_spawn(&mut self) -> Box<dyn Iterator<Item = (Entity, Ctx)> + '_>;
     fn has_entity_spawn(&self) -> bool;
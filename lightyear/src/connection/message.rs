@@ -9,10 +9,15 @@ use serde::{Deserialize, Serialize};
 use tracing::{info_span, trace};
 
 use crate::_reexport::{BitSerializable, MessageProtocol, ReadBuffer, WriteBuffer};
+use crate::client::scheduled::ScheduledMessage;
+use crate::packet::compression::CompressionOffer;
 use crate::prelude::{ChannelKind, NetworkTarget};
 use crate::protocol::Protocol;
 use crate::shared::ping::message::SyncMessage;
-use crate::shared::replication::{ReplicationMessage, ReplicationMessageData};
+use crate::shared::replication::{
+    EntityMessage, LateJoinMessage, ReplicationMessage, ReplicationMessageData,
+};
+use crate::shared::tick_manager::TimelineChange;
 
 pub(crate) struct MessageMetadata {
     pub(crate) target: NetworkTarget,
@@ -32,6 +37,24 @@ pub enum ClientMessage<P: Protocol> {
     // the reason why we include sync here instead of doing another MessageManager is so that
     // the sync messages can be added to packets that have other messages
     Sync(SyncMessage),
+    // only sent by the server, but present here so that ClientMessage and ServerMessage stay compatible
+    // (the server sends replication messages as ClientMessage)
+    #[bitcode_hint(frequency = 1)]
+    LateJoin(LateJoinMessage),
+    // only sent by the server
+    #[bitcode_hint(frequency = 1)]
+    Timeline(TimelineChange),
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    Compression(CompressionOffer),
+    // only sent by the server
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    EntityMessage(EntityMessage<P::Message>),
+    // only sent by the server
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    Scheduled(ScheduledMessage<P::Message>),
 }
 
 impl<P: Protocol> BitSerializable for ClientMessage<P> {
@@ -145,6 +168,23 @@ impl<P: Protocol> ClientMessage<P> {
                     metrics::increment_counter!("send_pong", "channel" => channel_name);
                 }
             },
+            ClientMessage::LateJoin(message) => {
+                trace!(channel = ?channel_name, ?message, "Sending late-join progress");
+            }
+            ClientMessage::Timeline(change) => {
+                trace!(channel = ?channel_name, ?change, "Sending timeline change");
+            }
+            ClientMessage::Compression(offer) => {
+                trace!(channel = ?channel_name, ?offer, "Sending compression offer");
+            }
+            ClientMessage::EntityMessage(message) => {
+                let message_name = message.message.name();
+                trace!(channel = ?channel_name, message = ?message_name, group_id = ?message.group_id, "Sending entity message");
+            }
+            ClientMessage::Scheduled(scheduled) => {
+                let message_name = scheduled.message.name();
+                trace!(channel = ?channel_name, message = ?message_name, tick = ?scheduled.tick, "Sending scheduled message");
+            }
         }
     }
 }
@@ -161,6 +201,24 @@ pub enum ServerMessage<P: Protocol> {
     // the sync messages can be added to packets that have other messages
     #[bitcode_hint(frequency = 1)]
     Sync(SyncMessage),
+    /// Progress of the initial world state sent to a newly connected client
+    #[bitcode_hint(frequency = 1)]
+    LateJoin(LateJoinMessage),
+    /// The server changed the duration of the ticks
+    #[bitcode_hint(frequency = 1)]
+    Timeline(TimelineChange),
+    /// The server accepted the compression settings of the client
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    Compression(CompressionOffer),
+    /// A message that is applied in the same tick as the replication group of an entity
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    EntityMessage(EntityMessage<P::Message>),
+    /// A message that the client emits when its timeline reaches a given tick
+    #[bitcode_hint(frequency = 1)]
+    #[bitcode(with_serde)]
+    Scheduled(ScheduledMessage<P::Message>),
 }
 
 impl<P: Protocol> BitSerializable for ServerMessage<P> {
@@ -273,6 +331,23 @@ impl<P: Protocol> ServerMessage<P> {
                     metrics::increment_counter!("send_pong", "channel" => channel_name);
                 }
             },
+            ServerMessage::LateJoin(message) => {
+                trace!(channel = ?channel_name, ?message, "Sending late-join progress");
+            }
+            ServerMessage::Timeline(change) => {
+                trace!(channel = ?channel_name, ?change, "Sending timeline change");
+            }
+            ServerMessage::Compression(offer) => {
+                trace!(channel = ?channel_name, ?offer, "Sending compression offer");
+            }
+            ServerMessage::EntityMessage(message) => {
+                let message_name = message.message.name();
+                trace!(channel = ?channel_name, message = ?message_name, group_id = ?message.group_id, "Sending entity message");
+            }
+            ServerMessage::Scheduled(scheduled) => {
+                let message_name = scheduled.message.name();
+                trace!(channel = ?channel_name, message = ?message_name, tick = ?scheduled.tick, "Sending scheduled message");
+            }
         }
     }
 }
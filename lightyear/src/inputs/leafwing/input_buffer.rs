@@ -286,6 +286,19 @@ impl<T: LeafwingUserAction> InputMessage<T> {
             .iter()
             .all(|(_, diffs)| diffs.iter().all(|diffs_per_tick| diffs_per_tick.is_empty()))
     }
+
+    /// Run `f` on every ActionDiff of the message, along with the tick of the diff.
+    /// The diffs for which `f` returns false are removed from the message
+    pub(crate) fn retain_diffs(&mut self, mut f: impl FnMut(Tick, &mut ActionDiff<T>) -> bool) {
+        let end_tick = self.end_tick;
+        for (_, diffs) in self.diffs.iter_mut() {
+            let start_tick = end_tick - diffs.len() as u16 + 1;
+            for (delta, diffs_for_tick) in diffs.iter_mut().enumerate() {
+                let tick = start_tick + Tick(delta as u16);
+                diffs_for_tick.retain_mut(|diff| f(tick, diff));
+            }
+        }
+    }
 }
 
 impl<T: LeafwingUserAction> InputBuffer<T> {
@@ -48,6 +48,20 @@ impl<T: UserAction> InputMessage<T> {
         }
         false
     }
+
+    /// Run `f` on every input of the message, along with the tick of the input.
+    /// The inputs for which `f` returns false are replaced with [`InputData::Absent`]
+    pub(crate) fn retain_inputs(&mut self, mut f: impl FnMut(Tick, &mut T) -> bool) {
+        let start_tick = Tick(self.end_tick.0) - self.inputs.len() as u16 + 1;
+        for (delta, input) in self.inputs.iter_mut().enumerate() {
+            let tick = start_tick + Tick(delta as u16);
+            if let InputData::Input(value) = input {
+                if !f(tick, value) {
+                    *input = InputData::Absent;
+                }
+            }
+        }
+    }
 }
 
 impl<T: UserAction> Default for InputBuffer<T> {
@@ -274,4 +288,41 @@ mod tests {
         assert_eq!(input_buffer.get(Tick(14)), Some(&0));
         assert_eq!(input_buffer.get(Tick(13)), None);
     }
+
+    #[test]
+    fn test_retain_inputs() {
+        let mut message = InputMessage {
+            end_tick: Tick(10),
+            inputs: vec![
+                InputData::Input(0),
+                InputData::Input(5),
+                InputData::SameAsPrecedent,
+                InputData::Input(20),
+            ],
+        };
+        // reject inputs above 10, clamp inputs above 3
+        message.retain_inputs(|_, input| {
+            if *input > 10 {
+                return false;
+            }
+            *input = (*input).min(3);
+            true
+        });
+        assert_eq!(
+            message.inputs,
+            vec![
+                InputData::Input(0),
+                InputData::Input(3),
+                InputData::SameAsPrecedent,
+                InputData::Absent,
+            ]
+        );
+
+        let mut input_buffer = InputBuffer::default();
+        input_buffer.update_from_message(message);
+        assert_eq!(input_buffer.get(Tick(7)), Some(&0));
+        assert_eq!(input_buffer.get(Tick(8)), Some(&3));
+        assert_eq!(input_buffer.get(Tick(9)), Some(&3));
+        assert_eq!(input_buffer.get(Tick(10)), None);
+    }
 }
@@ -65,15 +65,19 @@ pub mod prelude {
 
     pub use crate::channel::builder::TickBufferChannel;
     pub use crate::channel::builder::{
-        Channel, ChannelBuilder, ChannelContainer, ChannelDirection, ChannelMode, ChannelSettings,
-        DefaultUnorderedUnreliableChannel, ReliableSettings,
+        BlobSettings, Channel, ChannelBuilder, ChannelContainer, ChannelDirection, ChannelMode,
+        ChannelSettings, DefaultUnorderedUnreliableChannel, ReliableSettings,
     };
+    pub use crate::channel::senders::blob::{BlobId, BlobProgress};
+    pub use crate::channel::stream::StreamId;
     pub use crate::client::prediction::prespawn::PreSpawnedPlayerObject;
+    pub use crate::client::scheduled::ScheduleTimeline;
     #[cfg(feature = "leafwing")]
     pub use crate::inputs::leafwing::LeafwingUserAction;
     pub use crate::inputs::native::UserAction;
     pub use crate::netcode::{generate_key, ClientId, Key};
-    pub use crate::packet::message::Message;
+    pub use crate::packet::compression::{CompressionConfig, CompressionMode};
+    pub use crate::packet::message::{Message, MessageHandle};
     pub use crate::protocol::channel::{ChannelKind, ChannelRegistry};
     pub use crate::protocol::Protocol;
     pub use crate::protocolize;
@@ -85,9 +89,11 @@ pub mod prelude {
         NetworkTarget, ReplicationGroup, ReplicationMode, ShouldBePredicted,
     };
     pub use crate::shared::replication::entity_map::{EntityMapper, MapEntities, RemoteEntityMap};
+    pub use crate::shared::replication::net_entity::NetEntity;
+    pub use crate::shared::replication::recording::Recording;
     pub use crate::shared::sets::{FixedUpdateSet, MainSet, ReplicationSet};
     pub use crate::shared::tick_manager::TickManager;
-    pub use crate::shared::tick_manager::{Tick, TickConfig};
+    pub use crate::shared::tick_manager::{is_paused, Tick, TickConfig};
     pub use crate::shared::time_manager::TimeManager;
     pub use crate::transport::conditioner::LinkConditionerConfig;
     pub use crate::transport::io::{Io, IoConfig, TransportConfig};
@@ -100,22 +106,36 @@ pub mod prelude {
         pub use crate::client::config::ClientConfig;
         pub use crate::client::config::NetcodeConfig;
         pub use crate::client::events::{
-            ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            BlobReceivedEvent, ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent,
+            ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent,
+            MessageAckedEvent, MessageEvent, MessageExpiredEvent, MessageLostEvent,
+            WorldLoadedEvent,
         };
         pub use crate::client::input::{InputConfig, InputSystemSet};
         pub use crate::client::interpolation::interpolation_history::ConfirmedHistory;
         pub use crate::client::interpolation::plugin::{
-            InterpolationConfig, InterpolationDelay, InterpolationSet,
+            AdaptiveInterpolationDelay, InterpolationConfig, InterpolationDelay, InterpolationSet,
+        };
+        pub use crate::client::interpolation::visual_interpolation::{
+            VisualInterpolateStatus, VisualInterpolationPlugin, VisualInterpolationSet,
         };
         pub use crate::client::interpolation::{InterpolateStatus, Interpolated};
+        pub use crate::client::networking::NetworkingState;
+        pub use crate::client::playback::{Playback, PlaybackPlugin};
         pub use crate::client::plugin::{ClientPlugin, PluginConfig};
-        pub use crate::client::prediction::correction::Correction;
+        pub use crate::client::prediction::correction::{
+            Correction, CorrectionContext, CorrectionFn, ExponentialCorrector, SpringCorrector,
+        };
         pub use crate::client::prediction::plugin::is_in_rollback;
-        pub use crate::client::prediction::plugin::{PredictionConfig, PredictionSet};
+        pub use crate::client::prediction::plugin::{
+            PredictionConfig, PredictionSet, RollbackScope,
+        };
         pub use crate::client::prediction::predicted_history::{ComponentState, PredictionHistory};
-        pub use crate::client::prediction::{Predicted, PredictionDespawnCommandsExt};
+        pub use crate::client::prediction::{
+            DisableRollback, Predicted, PredictionDespawnCommandsExt, Rollback,
+        };
         pub use crate::client::resource::Authentication;
+        pub use crate::client::scheduled::{ScheduledEvent, ScheduledEventSet};
         pub use crate::client::sync::SyncConfig;
         pub use crate::netcode::Client as NetClient;
 
@@ -126,10 +146,18 @@ pub mod prelude {
         pub use crate::server::config::NetcodeConfig;
         pub use crate::server::config::ServerConfig;
         pub use crate::server::events::{
-            ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent, ConnectEvent,
-            DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent, MessageEvent,
+            BlobReceivedEvent, ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent,
+            ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent, InputEvent,
+            MessageAckedEvent, MessageEvent, MessageExpiredEvent, MessageLostEvent,
+        };
+        pub use crate::server::input_validation::{
+            InputValidation, InputValidationConfig, InputValidators, InputViolations,
+            SuspicionReason, SuspiciousClient,
         };
+        pub use crate::server::instances::{InstanceId, InstanceMoves, Instances, InstancesPlugin};
+        pub use crate::server::late_join::{LateJoinConfig, LateJoinPriority};
         pub use crate::server::plugin::{PluginConfig, ServerPlugin};
+        pub use crate::server::recording::{RecordingConfig, RecordingPerspective};
         pub use crate::server::room::{RoomId, RoomManager, RoomMut, RoomRef};
 
         #[cfg(feature = "leafwing")]
@@ -138,6 +166,7 @@ pub mod prelude {
         pub use wtransport::tls::Certificate;
 
         pub use crate::netcode::Server as NetServer;
+        pub use crate::netcode::{NetcodeServerStats, RateLimitConfig};
     }
 }
 
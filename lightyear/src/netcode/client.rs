@@ -16,7 +16,8 @@ use super::{
     bytes::Bytes,
     error::{Error, Result},
     packet::{
-        DisconnectPacket, KeepAlivePacket, Packet, PayloadPacket, RequestPacket, ResponsePacket,
+        DeniedReason, DisconnectPacket, KeepAlivePacket, Packet, PayloadPacket, RequestPacket,
+        ResponsePacket,
     },
     replay::ReplayProtection,
     token::{ChallengeToken, ConnectToken},
@@ -55,6 +56,7 @@ type Callback<Ctx> = Box<dyn FnMut(ClientState, ClientState, &mut Ctx) + Send +
 pub struct ClientConfig<Ctx> {
     num_disconnect_packets: usize,
     packet_send_rate: f64,
+    session_epochs: bool,
     context: Ctx,
     on_state_change: Option<Callback<Ctx>>,
 }
@@ -64,6 +66,7 @@ impl Default for ClientConfig<()> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            session_epochs: false,
             context: (),
             on_state_change: None,
         }
@@ -80,6 +83,7 @@ impl<Ctx> ClientConfig<Ctx> {
         Self {
             num_disconnect_packets: 10,
             packet_send_rate: PACKET_SEND_RATE_SEC,
+            session_epochs: false,
             context: ctx,
             on_state_change: None,
         }
@@ -96,6 +100,13 @@ impl<Ctx> ClientConfig<Ctx> {
         self.packet_send_rate = rate_seconds;
         self
     }
+    /// Expect the session epoch in front of every payload. <br>
+    /// This must be enabled to connect to a server that routes its clients to several server instances,
+    /// see [`Instances`](crate::server::instances::Instances). The default is `false`.
+    pub fn session_epochs(mut self, session_epochs: bool) -> Self {
+        self.session_epochs = session_epochs;
+        self
+    }
     /// Set a callback that will be called when the client changes states.
     pub fn on_state_change<F>(mut self, cb: F) -> Self
     where
@@ -141,8 +152,10 @@ pub enum ClientState {
     ConnectionRequestTimedOut,
     /// The client has timed out while waiting for a response from the server after sending a challenge response packet.
     ChallengeResponseTimedOut,
-    /// The server has denied the client's connection request, most likely due to the server being full.
+    /// The server has denied the client's connection request.
     ConnectionDenied,
+    /// The server has denied the client's connection request because it already has the maximum number of clients.
+    ServerFull,
     /// The client is disconnected from the server.
     Disconnected,
     /// The client is waiting for a response from the server after sending a connection request packet.
@@ -195,6 +208,9 @@ pub struct Client<Ctx = ()> {
     should_disconnect: bool,
     should_disconnect_state: ClientState,
     packet_queue: VecDeque<ReadWordBuffer>,
+    /// Session epoch of the payloads, which changes when the server moves the client to a different instance
+    epoch: u8,
+    epoch_changed: bool,
     cfg: ClientConfig<Ctx>,
 }
 
@@ -229,6 +245,8 @@ impl<Ctx> Client<Ctx> {
             should_disconnect: false,
             should_disconnect_state: ClientState::Disconnected,
             packet_queue: VecDeque::new(),
+            epoch: 0,
+            epoch_changed: false,
             cfg,
         })
     }
@@ -307,6 +325,9 @@ impl<Ctx> Client<Ctx> {
         self.should_disconnect_state = ClientState::Disconnected;
         self.challenge_token_sequence = 0;
         self.replay_protection = ReplayProtection::new();
+        self.packet_queue.clear();
+        self.epoch = 0;
+        self.epoch_changed = false;
     }
     fn reset(&mut self, new_state: ClientState) {
         self.sequence = 0;
@@ -375,11 +396,14 @@ impl<Ctx> Client<Ctx> {
         }
         match (packet, self.state) {
             (
-                Packet::Denied(_),
+                Packet::Denied(pkt),
                 ClientState::SendingConnectionRequest | ClientState::SendingChallengeResponse,
             ) => {
                 self.should_disconnect = true;
-                self.should_disconnect_state = ClientState::ConnectionDenied;
+                self.should_disconnect_state = match pkt.reason {
+                    DeniedReason::ServerFull => ClientState::ServerFull,
+                    DeniedReason::Denied => ClientState::ConnectionDenied,
+                };
             }
             (Packet::Challenge(pkt), ClientState::SendingConnectionRequest) => {
                 debug!("client received connection challenge packet from server");
@@ -398,7 +422,26 @@ impl<Ctx> Client<Ctx> {
             }
             (Packet::Payload(pkt), ClientState::Connected) => {
                 trace!("client received payload packet from server");
-                let reader = ReadWordBuffer::start_read(pkt.buf);
+                if !self.cfg.session_epochs {
+                    self.packet_queue
+                        .push_back(ReadWordBuffer::start_read(pkt.buf));
+                    return Ok(());
+                }
+                let Some((epoch, buf)) = pkt.buf.split_first() else {
+                    return Ok(());
+                };
+                if *epoch != self.epoch {
+                    // payloads from a previous epoch are dropped
+                    if epoch.wrapping_sub(self.epoch) > u8::MAX / 2 {
+                        trace!("client dropped payload from a previous epoch");
+                        return Ok(());
+                    }
+                    debug!(epoch, "client started a new session epoch");
+                    self.epoch = *epoch;
+                    self.epoch_changed = true;
+                    self.packet_queue.clear();
+                }
+                let reader = ReadWordBuffer::start_read(buf);
                 self.packet_queue.push_back(reader);
             }
             (Packet::Disconnect(_), ClientState::Connected) => {
@@ -585,8 +628,19 @@ impl<Ctx> Client<Ctx> {
         if buf.len() > MAX_PACKET_SIZE {
             return Err(Error::SizeMismatch(MAX_PACKET_SIZE, buf.len()));
         }
-        self.send_packet(PayloadPacket::create(buf), io)?;
-        Ok(())
+        if !self.cfg.session_epochs {
+            return self.send_packet(PayloadPacket::create(buf), io);
+        }
+        let mut payload = Vec::with_capacity(buf.len() + 1);
+        payload.push(self.epoch);
+        payload.extend_from_slice(buf);
+        self.send_packet(PayloadPacket::create(&payload), io)
+    }
+
+    /// Returns true (once) if the server started a new session epoch since the last call, for example because
+    /// the client was moved to a different server instance. The connection state must then be reset.
+    pub(crate) fn take_epoch_change(&mut self) -> bool {
+        std::mem::take(&mut self.epoch_changed)
     }
     /// Disconnects the client from the server.
     ///
@@ -13,6 +13,8 @@ pub enum Error {
     ClientNotFound,
     #[error("tried to send a packet to a client that isn't connected")]
     ClientNotConnected,
+    #[error("session epochs are not enabled on this server")]
+    SessionEpochsDisabled,
     #[error("clock went backwards (did you invent a time machine?): {0}")]
     SystemTime(#[from] std::time::SystemTimeError),
     #[error("invalid connect token: {0}")]
@@ -126,7 +126,9 @@ loop {
 pub use client::{Client, ClientConfig, ClientState};
 pub use crypto::{generate_key, try_generate_key, Key};
 pub use error::{Error, Result};
-pub use server::{Callback, ClientId, NetcodeServer, Server, ServerConfig};
+pub use rate_limit::{NetcodeServerStats, RateLimitConfig};
+pub(crate) use server::InstanceLink;
+pub use server::{Callback, ClientId, NetcodeServer, Server, ServerConfig, MAX_CLIENTS};
 pub use token::{ConnectToken, ConnectTokenBuilder, InvalidTokenError};
 
 mod bytes;
@@ -136,6 +138,7 @@ mod error;
 
 mod free_list;
 mod packet;
+mod rate_limit;
 mod replay;
 mod server;
 mod token;
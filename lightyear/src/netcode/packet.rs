@@ -156,22 +156,37 @@ impl Bytes for RequestPacket {
     }
 }
 
-pub struct DeniedPacket {}
+/// Reason why the server denied a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeniedReason {
+    Denied = 0,
+    /// The server already has the maximum number of clients
+    ServerFull = 1,
+}
+
+pub struct DeniedPacket {
+    pub reason: DeniedReason,
+}
 
 impl DeniedPacket {
-    pub fn create() -> Packet<'static> {
-        Packet::Denied(DeniedPacket {})
+    pub fn create(reason: DeniedReason) -> Packet<'static> {
+        Packet::Denied(DeniedPacket { reason })
     }
 }
 
 impl Bytes for DeniedPacket {
     type Error = io::Error;
-    fn write_to(&self, _writer: &mut impl WriteBytesExt) -> Result<(), Self::Error> {
-        Ok(())
+    fn write_to(&self, writer: &mut impl WriteBytesExt) -> Result<(), Self::Error> {
+        writer.write_u8(self.reason as u8)
     }
 
-    fn read_from(_reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
-        Ok(Self {})
+    fn read_from(reader: &mut impl byteorder::ReadBytesExt) -> Result<Self, io::Error> {
+        // packets without a reason are treated as a generic denial
+        let reason = match reader.read_u8() {
+            Ok(1) => DeniedReason::ServerFull,
+            _ => DeniedReason::Denied,
+        };
+        Ok(Self { reason })
     }
 }
 
@@ -583,7 +598,7 @@ mod tests {
         let sequence = 0u64;
         let mut replay_protection = ReplayProtection::new();
 
-        let packet = Packet::Denied(DeniedPacket {});
+        let packet = DeniedPacket::create(DeniedReason::ServerFull);
 
         let mut buf = [0u8; MAX_PKT_BUF_SIZE];
         let size = packet
@@ -600,9 +615,10 @@ mod tests {
         )
         .unwrap();
 
-        let Packet::Denied(_denied_pkt) = packet else {
+        let Packet::Denied(denied_pkt) = packet else {
             panic!("wrong packet type");
         };
+        assert_eq!(denied_pkt.reason, DeniedReason::ServerFull);
     }
 
     #[test]
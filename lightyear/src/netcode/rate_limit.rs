@@ -0,0 +1,369 @@
+//! Protect the netcode server against packet floods
+//!
+//! * every address that is not connected has a budget of packets and of connection requests per second (token buckets).
+//!   Packets from connected clients are not limited, so that clients behind the same NAT don't throttle each other.
+//! * the number of tracked addresses is bounded; when the table is full, the address that was tracked first is evicted
+//! * the number of connections that are still in the handshake is bounded
+//! * when the server is under load, connection requests are only processed (i.e. the connect token is decrypted)
+//!   if the same address already sent a connection request recently. Legitimate clients resend their connection requests
+//!   periodically, whereas a flood of spoofed addresses rarely sends two requests from the same address.
+//!   This check uses a fixed-size table, so it doesn't allocate any state per address.
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+
+/// Size of the table used to remember the addresses that sent a connection request while the server is under load
+const RETRY_TABLE_SIZE: usize = 1024;
+/// Minimum delay between the first connection request and its retry (clients resend requests every 0.1 seconds)
+const RETRY_MIN_DELAY_SECS: f64 = 0.05;
+/// Maximum delay between the first connection request and its retry
+const RETRY_MAX_DELAY_SECS: f64 = 1.0;
+/// Addresses that didn't send any packet for this duration have a full budget, so we can stop tracking them
+const ADDRESS_IDLE_SECS: f64 = 1.0;
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// Maximum number of packets per second accepted from a single ip address,
+    /// for the packets that don't come from a connected client
+    pub max_packets_per_second: u32,
+    /// Maximum number of connection requests per second accepted from a single ip address
+    pub max_connection_requests_per_second: u32,
+    /// Maximum number of ip addresses whose budget is tracked at the same time.
+    /// If the limit is reached, the address that has been tracked for the longest time is evicted.
+    pub max_tracked_addresses: usize,
+    /// Maximum number of connections that are still in the handshake (waiting for the client's challenge response)
+    pub max_pending_connections: usize,
+    /// Pending connections that didn't complete the handshake after this duration are removed
+    pub pending_connection_timeout_secs: f64,
+    /// Number of connection requests per second (across all addresses) above which the server is considered under load
+    pub load_threshold: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_packets_per_second: 500,
+            max_connection_requests_per_second: 20,
+            max_tracked_addresses: 4096,
+            max_pending_connections: 64,
+            pending_connection_timeout_secs: 5.0,
+            load_threshold: 100,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn with_max_packets_per_second(mut self, max_packets_per_second: u32) -> Self {
+        self.max_packets_per_second = max_packets_per_second;
+        self
+    }
+
+    pub fn with_max_connection_requests_per_second(mut self, max_requests: u32) -> Self {
+        self.max_connection_requests_per_second = max_requests;
+        self
+    }
+
+    pub fn with_max_tracked_addresses(mut self, max_tracked_addresses: usize) -> Self {
+        self.max_tracked_addresses = max_tracked_addresses;
+        self
+    }
+
+    pub fn with_max_pending_connections(mut self, max_pending_connections: usize) -> Self {
+        self.max_pending_connections = max_pending_connections;
+        self
+    }
+
+    pub fn with_pending_connection_timeout_secs(mut self, timeout_secs: f64) -> Self {
+        self.pending_connection_timeout_secs = timeout_secs;
+        self
+    }
+
+    pub fn with_load_threshold(mut self, load_threshold: u32) -> Self {
+        self.load_threshold = load_threshold;
+        self
+    }
+}
+
+/// Counters about the packets received by the netcode server, that can be used to detect attacks
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetcodeServerStats {
+    /// Number of packets received
+    pub packets_received: u64,
+    /// Number of packets from unconnected addresses dropped because their address exceeded its packet budget
+    pub packets_rate_limited: u64,
+    /// Number of connection requests received
+    pub connection_requests: u64,
+    /// Number of connection requests dropped because their address exceeded its connection request budget
+    pub connection_requests_rate_limited: u64,
+    /// Number of connection requests dropped while the server was under load, waiting for the client to retry
+    pub connection_requests_deferred: u64,
+    /// Number of connection requests dropped because the pending connection table was full
+    pub pending_connections_rejected: u64,
+    /// Number of pending connections removed because they didn't complete the handshake in time
+    pub pending_connections_timed_out: u64,
+    /// Whether the server is currently under load
+    pub under_load: bool,
+}
+
+/// Token bucket that refills at `rate` tokens per second, up to `rate` tokens
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: f64,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: f64) -> Self {
+        Self {
+            tokens: rate as f64,
+            last_refill: now,
+        }
+    }
+
+    fn try_take(&mut self, rate: u32, now: f64) -> bool {
+        let rate = rate as f64;
+        self.tokens = (self.tokens + (now - self.last_refill).max(0.0) * rate).min(rate);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AddressBudget {
+    packets: TokenBucket,
+    connection_requests: TokenBucket,
+    last_seen: f64,
+    /// When we started tracking the address
+    tracked_since: f64,
+}
+
+pub(crate) struct RateLimiter {
+    pub(crate) config: RateLimitConfig,
+    pub(crate) stats: NetcodeServerStats,
+    addresses: HashMap<IpAddr, AddressBudget>,
+    /// Tracked addresses in the order in which we started tracking them, used to evict addresses when the table is full.
+    /// Can contain stale entries for addresses that are not tracked anymore.
+    tracking_order: VecDeque<(IpAddr, f64)>,
+    /// Number of connection requests received since the start of the current window
+    requests_in_window: u32,
+    window_start: f64,
+    /// Addresses that sent a connection request while the server was under load, and when they sent it
+    retry_table: Vec<Option<(SocketAddr, f64)>>,
+    hasher: RandomState,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            stats: NetcodeServerStats::default(),
+            addresses: HashMap::default(),
+            tracking_order: VecDeque::default(),
+            requests_in_window: 0,
+            window_start: 0.0,
+            retry_table: vec![None; RETRY_TABLE_SIZE],
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Stop tracking the addresses that have been idle for a while, and update the load status
+    pub(crate) fn update(&mut self, now: f64, num_pending_connections: usize) {
+        self.addresses
+            .retain(|_, budget| now - budget.last_seen < ADDRESS_IDLE_SECS);
+        let addresses = &self.addresses;
+        self.tracking_order.retain(|(ip, tracked_since)| {
+            addresses
+                .get(ip)
+                .map_or(false, |budget| budget.tracked_since == *tracked_since)
+        });
+        if now - self.window_start >= 1.0 {
+            self.stats.under_load = self.requests_in_window > self.config.load_threshold;
+            self.requests_in_window = 0;
+            self.window_start = now;
+        }
+        if num_pending_connections >= self.config.max_pending_connections {
+            self.stats.under_load = true;
+        }
+    }
+
+    fn budget(&mut self, ip: IpAddr, now: f64) -> &mut AddressBudget {
+        if !self.addresses.contains_key(&ip) {
+            // the table is full: stop tracking the addresses that we started tracking first,
+            // instead of denying every new address (which would lock out legitimate clients during a flood)
+            while self.addresses.len() >= self.config.max_tracked_addresses.max(1) {
+                let Some((oldest, tracked_since)) = self.tracking_order.pop_front() else {
+                    break;
+                };
+                if self
+                    .addresses
+                    .get(&oldest)
+                    .map_or(false, |budget| budget.tracked_since == tracked_since)
+                {
+                    self.addresses.remove(&oldest);
+                }
+            }
+            self.tracking_order.push_back((ip, now));
+        }
+        let config = &self.config;
+        let budget = self.addresses.entry(ip).or_insert_with(|| AddressBudget {
+            packets: TokenBucket::new(config.max_packets_per_second, now),
+            connection_requests: TokenBucket::new(config.max_connection_requests_per_second, now),
+            last_seen: now,
+            tracked_since: now,
+        });
+        budget.last_seen = now;
+        budget
+    }
+
+    /// Returns true if the packet received from `addr` should be processed.
+    ///
+    /// Packets from connected clients are always processed.
+    pub(crate) fn allow_packet(&mut self, addr: SocketAddr, now: f64, connected: bool) -> bool {
+        self.stats.packets_received += 1;
+        if connected {
+            return true;
+        }
+        let rate = self.config.max_packets_per_second;
+        let allowed = self.budget(addr.ip(), now).packets.try_take(rate, now);
+        if !allowed {
+            self.stats.packets_rate_limited += 1;
+        }
+        allowed
+    }
+
+    /// Returns true if the connection request received from `addr` should be processed.
+    ///
+    /// This is checked before decrypting the connect token.
+    pub(crate) fn allow_connection_request(
+        &mut self,
+        addr: SocketAddr,
+        now: f64,
+        pending_table_full: bool,
+    ) -> bool {
+        self.stats.connection_requests += 1;
+        self.requests_in_window += 1;
+        if self.requests_in_window > self.config.load_threshold {
+            self.stats.under_load = true;
+        }
+        let rate = self.config.max_connection_requests_per_second;
+        let allowed = self
+            .budget(addr.ip(), now)
+            .connection_requests
+            .try_take(rate, now);
+        if !allowed {
+            self.stats.connection_requests_rate_limited += 1;
+            return false;
+        }
+        if pending_table_full {
+            self.stats.pending_connections_rejected += 1;
+            return false;
+        }
+        if self.stats.under_load && !self.check_retry(addr, now) {
+            self.stats.connection_requests_deferred += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Returns true if `addr` already sent a connection request recently.
+    /// Otherwise, remember that `addr` sent a connection request now.
+    fn check_retry(&mut self, addr: SocketAddr, now: f64) -> bool {
+        let mut hasher = self.hasher.build_hasher();
+        addr.hash(&mut hasher);
+        let slot = &mut self.retry_table[hasher.finish() as usize % RETRY_TABLE_SIZE];
+        match *slot {
+            Some((slot_addr, time)) if slot_addr == addr && now - time < RETRY_MIN_DELAY_SECS => {
+                // the address is resending requests too fast: keep waiting for a proper retry
+                false
+            }
+            Some((slot_addr, time)) if slot_addr == addr && now - time <= RETRY_MAX_DELAY_SECS => {
+                *slot = None;
+                true
+            }
+            _ => {
+                *slot = Some((addr, now));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_packet_rate_limit() {
+        let mut limiter =
+            RateLimiter::new(RateLimitConfig::default().with_max_packets_per_second(10));
+        for _ in 0..10 {
+            assert!(limiter.allow_packet(addr(1), 0.0, false));
+        }
+        // the budget is shared by all the ports of an ip address
+        assert!(!limiter.allow_packet(addr(2), 0.0, false));
+        // but connected clients are not limited
+        assert!(limiter.allow_packet(addr(3), 0.0, true));
+        // the budget refills over time
+        assert!(limiter.allow_packet(addr(1), 0.1, false));
+        assert!(!limiter.allow_packet(addr(1), 0.1, false));
+        assert_eq!(limiter.stats.packets_received, 14);
+        assert_eq!(limiter.stats.packets_rate_limited, 2);
+    }
+
+    #[test]
+    fn test_tracked_addresses_eviction() {
+        let mut limiter = RateLimiter::new(
+            RateLimitConfig::default()
+                .with_max_packets_per_second(1)
+                .with_max_tracked_addresses(2),
+        );
+        let ip = |i: u8| SocketAddr::from(([10, 0, 0, i], 1));
+        assert!(limiter.allow_packet(ip(1), 0.0, false));
+        assert!(limiter.allow_packet(ip(2), 0.0, false));
+        // the table is full: new addresses are still accepted, the oldest tracked address is evicted
+        assert!(limiter.allow_packet(ip(3), 0.0, false));
+        assert_eq!(limiter.addresses.len(), 2);
+        assert!(!limiter.addresses.contains_key(&ip(1).ip()));
+        assert!(!limiter.allow_packet(ip(3), 0.0, false));
+
+        // stale addresses are removed from the eviction queue
+        limiter.update(2.0, 0);
+        assert!(limiter.addresses.is_empty());
+        assert!(limiter.tracking_order.is_empty());
+    }
+
+    #[test]
+    fn test_connection_requests_under_load() {
+        let mut limiter = RateLimiter::new(RateLimitConfig::default().with_load_threshold(2));
+        // not under load: requests are accepted directly
+        assert!(limiter.allow_connection_request(addr(1), 0.0, false));
+        assert!(limiter.allow_connection_request(addr(2), 0.0, false));
+        // the pending table is full
+        assert!(!limiter.allow_connection_request(addr(3), 0.0, true));
+        assert_eq!(limiter.stats.pending_connections_rejected, 1);
+        assert!(limiter.stats.under_load);
+
+        // under load: the request is only accepted when the client retries
+        assert!(!limiter.allow_connection_request(addr(4), 0.0, false));
+        assert!(!limiter.allow_connection_request(addr(4), 0.01, false));
+        assert!(limiter.allow_connection_request(addr(4), 0.1, false));
+        assert_eq!(limiter.stats.connection_requests_deferred, 2);
+
+        // the load status is updated every second
+        limiter.update(1.0, 0);
+        assert!(limiter.stats.under_load);
+        limiter.update(2.0, 0);
+        assert!(!limiter.stats.under_load);
+        assert!(limiter.allow_connection_request(addr(5), 2.0, false));
+    }
+}
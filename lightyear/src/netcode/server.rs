@@ -11,7 +11,7 @@ use tracing::{debug, error, info, trace};
 use crate::serialize::reader::ReadBuffer;
 use crate::serialize::wordbuffer::reader::ReadWordBuffer;
 use crate::server::config::NetcodeConfig;
-use crate::transport::io::Io;
+use crate::transport::io::{Io, IoStats};
 use crate::transport::{PacketReceiver, PacketSender};
 
 use super::{
@@ -20,14 +20,16 @@ use super::{
     error::{Error, Result},
     generate_key,
     packet::{
-        ChallengePacket, DeniedPacket, DisconnectPacket, KeepAlivePacket, Packet, PayloadPacket,
-        RequestPacket, ResponsePacket,
+        ChallengePacket, DeniedPacket, DeniedReason, DisconnectPacket, KeepAlivePacket, Packet,
+        PayloadPacket, RequestPacket, ResponsePacket,
     },
+    rate_limit::{NetcodeServerStats, RateLimitConfig, RateLimiter},
     replay::ReplayProtection,
     token::{ChallengeToken, ConnectToken, ConnectTokenBuilder, ConnectTokenPrivate},
     MAC_BYTES, MAX_PACKET_SIZE, MAX_PKT_BUF_SIZE, PACKET_SEND_RATE_SEC,
 };
 
+/// Default maximum number of clients that can be connected to the server at the same time
 pub const MAX_CLIENTS: usize = 256;
 
 const CLIENT_TIMEOUT_SECS: i32 = 10;
@@ -89,6 +91,8 @@ struct Connection {
     send_key: Key,
     receive_key: Key,
     sequence: u64,
+    /// Session epoch written in front of every payload, see [`NetcodeServer::next_epoch`]
+    epoch: u8,
 }
 
 impl Connection {
@@ -125,17 +129,24 @@ struct ConnectionCache {
     // packet queue for all clients
     packet_queue: VecDeque<(ReadWordBuffer, ClientId)>,
 
+    // number of clients that completed the handshake
+    num_connected: usize,
+
     // corresponds to the server time
     time: f64,
 }
 
 impl ConnectionCache {
-    fn new(server_time: f64) -> Self {
+    fn new(server_time: f64, max_clients: usize) -> Self {
+        // the maps grow with the number of connected clients, so we don't preallocate them
+        // for servers that accept thousands of clients
+        let capacity = max_clients.min(MAX_CLIENTS);
         Self {
-            clients: HashMap::with_capacity(MAX_CLIENTS),
-            client_id_map: HashMap::with_capacity(MAX_CLIENTS),
-            replay_protection: HashMap::with_capacity(MAX_CLIENTS),
-            packet_queue: VecDeque::with_capacity(MAX_CLIENTS * 2),
+            clients: HashMap::with_capacity(capacity),
+            client_id_map: HashMap::with_capacity(capacity),
+            replay_protection: HashMap::with_capacity(capacity),
+            packet_queue: VecDeque::with_capacity(capacity * 2),
+            num_connected: 0,
             time: server_time,
         }
     }
@@ -153,6 +164,7 @@ impl ConnectionCache {
             existing.send_key = send_key;
             existing.receive_key = receive_key;
             existing.last_access_time = self.time;
+            existing.epoch = 0;
             return;
         }
         let conn = Connection {
@@ -167,6 +179,7 @@ impl ConnectionCache {
             send_key,
             receive_key,
             sequence: 0,
+            epoch: 0,
         };
         self.clients.insert(client_id, conn);
         self.replay_protection
@@ -174,6 +187,21 @@ impl ConnectionCache {
 
         self.client_id_map.insert(addr, client_id);
     }
+    /// Remove a connection that didn't complete the handshake
+    fn remove_pending(&mut self, client_id: ClientId) {
+        let Some(conn) = self.clients.get(&client_id) else {
+            return;
+        };
+        if conn.is_connected() {
+            return;
+        }
+        self.client_id_map.remove(&conn.addr);
+        self.replay_protection.remove(&client_id);
+        self.clients.remove(&client_id);
+    }
+    fn num_pending(&self) -> usize {
+        self.clients.len() - self.num_connected
+    }
     fn remove(&mut self, client_id: ClientId) {
         let Some(conn) = self.clients.get(&client_id) else {
             return;
@@ -184,6 +212,7 @@ impl ConnectionCache {
         self.client_id_map.remove(&conn.addr);
         self.replay_protection.remove(&client_id);
         self.clients.remove(&client_id);
+        self.num_connected -= 1;
     }
 
     fn ids(&self) -> Vec<ClientId> {
@@ -243,6 +272,9 @@ pub struct ServerConfig<Ctx> {
     token_expire_secs: i32,
     client_timeout_secs: i32,
     server_addr: SocketAddr,
+    max_clients: usize,
+    rate_limit: RateLimitConfig,
+    session_epochs: bool,
     context: Ctx,
     on_connect: Option<Callback<Ctx>>,
     on_disconnect: Option<Callback<Ctx>>,
@@ -256,6 +288,9 @@ impl Default for ServerConfig<()> {
             token_expire_secs: TOKEN_EXPIRE_SEC,
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            max_clients: MAX_CLIENTS,
+            rate_limit: RateLimitConfig::default(),
+            session_epochs: false,
             context: (),
             on_connect: None,
             on_disconnect: None,
@@ -276,6 +311,9 @@ impl<Ctx> ServerConfig<Ctx> {
             token_expire_secs: TOKEN_EXPIRE_SEC,
             client_timeout_secs: CLIENT_TIMEOUT_SECS,
             server_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            max_clients: MAX_CLIENTS,
+            rate_limit: RateLimitConfig::default(),
+            session_epochs: false,
             context: ctx,
             on_connect: None,
             on_disconnect: None,
@@ -311,6 +349,26 @@ impl<Ctx> ServerConfig<Ctx> {
         self.server_addr = server_addr;
         self
     }
+    /// Set the maximum number of clients that can be connected to the server at the same time. <br>
+    /// Clients that try to connect to a full server are denied, and transition to [`ClientState::ServerFull`](super::ClientState::ServerFull). <br>
+    /// The default is 256 clients.
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+    /// Set the limits used to protect the server against packet floods. <br>
+    /// See [`RateLimitConfig`] for the default values.
+    pub fn rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+    /// Write the session epoch in front of every payload (see [`NetcodeServer::next_epoch`]). <br>
+    /// This is only needed to route clients to several server instances, and the clients must enable it as well.
+    /// The default is `false`.
+    pub(crate) fn session_epochs(mut self, session_epochs: bool) -> Self {
+        self.session_epochs = session_epochs;
+        self
+    }
     /// Provide a callback that will be called when a client is connected to the server. <br>
     /// The callback will be called with the client index and the context that was provided (provide a `None` context if you don't need one).
     ///
@@ -378,6 +436,7 @@ pub struct NetcodeServer<Ctx = ()> {
     protocol_id: u64,
     conn_cache: ConnectionCache,
     token_entries: TokenEntries,
+    rate_limiter: RateLimiter,
     cfg: ServerConfig<Ctx>,
 }
 
@@ -394,8 +453,9 @@ impl NetcodeServer {
             token_sequence: 0,
             challenge_sequence: 0,
             challenge_key: crypto::generate_key(),
-            conn_cache: ConnectionCache::new(0.0),
+            conn_cache: ConnectionCache::new(0.0, MAX_CLIENTS),
             token_entries: TokenEntries::new(),
+            rate_limiter: RateLimiter::new(RateLimitConfig::default()),
             cfg: ServerConfig::default(),
         };
         // info!("server started on {}", server.io.local_addr());
@@ -428,8 +488,9 @@ impl<Ctx> NetcodeServer<Ctx> {
             token_sequence: 0,
             challenge_sequence: 0,
             challenge_key: crypto::generate_key(),
-            conn_cache: ConnectionCache::new(0.0),
+            conn_cache: ConnectionCache::new(0.0, cfg.max_clients),
             token_entries: TokenEntries::new(),
+            rate_limiter: RateLimiter::new(cfg.rate_limit.clone()),
             cfg,
         };
         // info!("server started on {}", server.addr());
@@ -487,10 +548,24 @@ impl<Ctx> NetcodeServer<Ctx> {
             Packet::KeepAlive(_) => self.touch_client(client_id),
             Packet::Payload(packet) => {
                 self.touch_client(client_id)?;
-                if let Some(idx) = client_id {
+                let Some(idx) = client_id else {
+                    return Ok(());
+                };
+                if !self.cfg.session_epochs {
                     self.conn_cache
                         .packet_queue
                         .push_back((ReadWordBuffer::start_read(packet.buf), idx));
+                    return Ok(());
+                }
+                // drop the payloads that were sent before the client switched to the current epoch
+                let epoch = self.conn_cache.clients.get(&idx).map(|conn| conn.epoch);
+                match packet.buf.split_first() {
+                    Some((packet_epoch, buf)) if Some(*packet_epoch) == epoch => {
+                        self.conn_cache
+                            .packet_queue
+                            .push_back((ReadWordBuffer::start_read(buf), idx));
+                    }
+                    _ => trace!("server dropped payload from a previous epoch"),
                 }
                 Ok(())
             }
@@ -619,10 +694,10 @@ impl<Ctx> NetcodeServer<Ctx> {
             debug!("server ignored connection request. connect token has already been used");
             return Ok(());
         };
-        if self.num_connected_clients() >= MAX_CLIENTS {
+        if self.num_connected_clients() >= self.cfg.max_clients {
             debug!("server denied connection request. server is full");
             self.send_to_addr(
-                DeniedPacket::create(),
+                DeniedPacket::create(DeniedReason::ServerFull),
                 from_addr,
                 token.server_to_client_key,
                 sender,
@@ -676,10 +751,10 @@ impl<Ctx> NetcodeServer<Ctx> {
             return Ok(());
         };
 
-        if self.num_connected_clients() >= MAX_CLIENTS {
+        if self.num_connected_clients() >= self.cfg.max_clients {
             debug!("server denied connection response. server is full");
             self.send_to_addr(
-                DeniedPacket::create(),
+                DeniedPacket::create(DeniedReason::ServerFull),
                 from_addr,
                 self.conn_cache
                     .clients
@@ -696,6 +771,7 @@ impl<Ctx> NetcodeServer<Ctx> {
             .get_mut(&id)
             .expect("invalid client id");
         client.connect();
+        self.conn_cache.num_connected += 1;
         client.last_send_time = self.time;
         client.last_receive_time = self.time;
         debug!(
@@ -712,6 +788,14 @@ impl<Ctx> NetcodeServer<Ctx> {
                 continue;
             };
             if !client.is_connected() {
+                if client.last_access_time
+                    + self.rate_limiter.config.pending_connection_timeout_secs
+                    < self.time
+                {
+                    debug!("server removed pending connection for client {id}");
+                    self.rate_limiter.stats.pending_connections_timed_out += 1;
+                    self.conn_cache.remove_pending(id);
+                }
                 continue;
             }
             if client.timeout.is_positive()
@@ -758,7 +842,22 @@ impl<Ctx> NetcodeServer<Ctx> {
         let (key, replay_protection) = match self.conn_cache.find_by_addr(&addr) {
             // Regardless of whether an entry in the connection cache exists for the client or not,
             // if the packet is a connection request we need to use the server's private key to decrypt it.
-            _ if buf[0] == Packet::REQUEST => (self.private_key, None),
+            _ if buf[0] == Packet::REQUEST => {
+                // check the budget of the address before decrypting the connect token
+                let pending_table_full = self.conn_cache.find_by_addr(&addr).is_none()
+                    && self.conn_cache.num_pending()
+                        >= self.rate_limiter.config.max_pending_connections;
+                if !self
+                    .rate_limiter
+                    .allow_connection_request(addr, self.time, pending_table_full)
+                {
+                    trace!(
+                        "server ignored connection request from {addr} because of rate limiting"
+                    );
+                    return Ok(());
+                }
+                (self.private_key, None)
+            }
             Some((client_id, _)) => (
                 // If the packet is not a connection request, use the receive key to decrypt it.
                 self.conn_cache
@@ -799,9 +898,21 @@ impl<Ctx> NetcodeServer<Ctx> {
         &mut self,
         sender: &mut impl PacketSender,
         receiver: &mut impl PacketReceiver,
+        stats: &mut IoStats,
     ) -> Result<()> {
         let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
         while let Some((buf, addr)) = receiver.recv().map_err(Error::from)? {
+            stats.bytes_received += buf.len();
+            stats.packets_received += 1;
+            // only the packets that don't come from a connected client are rate limited
+            let connected = self
+                .conn_cache
+                .find_by_addr(&addr)
+                .map_or(false, |(_, conn)| conn.is_connected());
+            if !self.rate_limiter.allow_packet(addr, self.time, connected) {
+                trace!("server ignored packet from {addr} because of rate limiting");
+                continue;
+            }
             self.recv_packet(buf, now, addr, sender)?;
         }
         Ok(())
@@ -828,12 +939,18 @@ impl<Ctx> NetcodeServer<Ctx> {
     pub fn try_update(&mut self, delta_ms: f64, io: &mut Io) -> Result<()> {
         self.time += delta_ms;
         self.conn_cache.update(delta_ms);
-        let (sender, receiver) = io.split();
-        self.recv_packets(sender, receiver)?;
+        let (sender, receiver, stats) = io.split_with_stats();
+        self.recv_packets(sender, receiver, stats)?;
         self.send_packets(io)?;
         self.check_for_timeouts();
+        self.rate_limiter
+            .update(self.time, self.conn_cache.num_pending());
         Ok(())
     }
+    /// Counters about the packets received by the server, that can be used to detect attacks
+    pub fn stats(&self) -> &NetcodeServerStats {
+        &self.rate_limiter.stats
+    }
     /// Receives a packet from a client, if one is available in the queue.
     ///
     /// The packet will be returned as a `Vec<u8>` along with the client index of the sender.
@@ -866,6 +983,24 @@ impl<Ctx> NetcodeServer<Ctx> {
     pub fn recv(&mut self) -> Option<(ReadWordBuffer, ClientId)> {
         self.conn_cache.packet_queue.pop_front()
     }
+
+    /// Starts a new session epoch for a client.
+    ///
+    /// When session epochs are enabled (see [`ServerConfig::session_epochs`]), every payload carries the epoch
+    /// of the session it belongs to. When the client receives a payload from a newer epoch, it resets its
+    /// connection state; the payloads of older epochs are dropped on both sides.
+    /// This is used to move a client to a different server instance without reconnecting.
+    pub(crate) fn next_epoch(&mut self, client_id: ClientId) -> Result<u8> {
+        if !self.cfg.session_epochs {
+            return Err(Error::SessionEpochsDisabled);
+        }
+        let Some(conn) = self.conn_cache.clients.get_mut(&client_id) else {
+            return Err(Error::ClientNotFound);
+        };
+        conn.epoch = conn.epoch.wrapping_add(1);
+        Ok(conn.epoch)
+    }
+
     /// Sends a packet to a client.
     ///
     /// The provided buffer must be smaller than [`MAX_PACKET_SIZE`].
@@ -882,12 +1017,18 @@ impl<Ctx> NetcodeServer<Ctx> {
             // still, in case a user somehow manages to obtain such index, we'll return an error.
             return Err(Error::ClientNotConnected);
         }
+        let epoch = conn.epoch;
         if !conn.is_confirmed() {
             // send a keep-alive packet to the client to confirm the connection
             self.send_to_client(KeepAlivePacket::create(client_id), client_id, io)?;
         }
-        let packet = PayloadPacket::create(buf);
-        self.send_to_client(packet, client_id, io)
+        if !self.cfg.session_epochs {
+            return self.send_to_client(PayloadPacket::create(buf), client_id, io);
+        }
+        let mut payload = Vec::with_capacity(buf.len() + 1);
+        payload.push(epoch);
+        payload.extend_from_slice(buf);
+        self.send_to_client(PayloadPacket::create(&payload), client_id, io)
     }
 
     /// Sends a packet to all connected clients.
@@ -984,11 +1125,7 @@ impl<Ctx> NetcodeServer<Ctx> {
 
     /// Gets the number of connected clients.
     pub fn num_connected_clients(&self) -> usize {
-        self.conn_cache
-            .clients
-            .iter()
-            .filter(|(_, c)| c.is_connected())
-            .count()
+        self.conn_cache.num_connected
     }
 
     /// Gets the address of a client.
@@ -1008,13 +1145,61 @@ pub(crate) struct NetcodeServerContext {
     pub(crate) disconnections: Vec<ClientId>,
 }
 
+/// Connection events and payloads exchanged between the gateway and a server instance,
+/// see [`Instances`](crate::server::instances::Instances)
+#[derive(Default)]
+pub(crate) struct InstanceLink {
+    /// Clients routed to this instance
+    pub(crate) clients: Vec<ClientId>,
+    /// Connections/disconnections that the instance hasn't processed yet
+    pub(crate) context: NetcodeServerContext,
+    /// Payloads received from the clients
+    pub(crate) recv_queue: VecDeque<(ReadWordBuffer, ClientId)>,
+    /// Payloads that the instance wants to send to the clients
+    pub(crate) send_queue: Vec<(Vec<u8>, ClientId)>,
+}
+
+impl InstanceLink {
+    pub(crate) fn connect(&mut self, client_id: ClientId) {
+        self.clients.push(client_id);
+        self.context.connections.push(client_id);
+    }
+
+    pub(crate) fn disconnect(&mut self, client_id: ClientId) {
+        self.clients.retain(|id| *id != client_id);
+        self.recv_queue.retain(|(_, id)| *id != client_id);
+        self.context.disconnections.push(client_id);
+    }
+}
+
+enum ServerKind {
+    /// Netcode server listening on the `Io`
+    Netcode(Box<NetcodeServer<NetcodeServerContext>>),
+    /// Server instance whose clients are routed by a gateway
+    Instance(InstanceLink),
+}
+
 #[derive(Resource)]
 pub struct Server {
-    server: NetcodeServer<NetcodeServerContext>,
+    server: ServerKind,
 }
 
 impl Server {
     pub(crate) fn new(server_addr: SocketAddr, config: NetcodeConfig) -> Self {
+        Self::with_session_epochs(server_addr, config, false)
+    }
+
+    /// Server listening on the network that routes its clients to server instances,
+    /// see [`Instances`](crate::server::instances::Instances)
+    pub(crate) fn gateway(server_addr: SocketAddr, config: NetcodeConfig) -> Self {
+        Self::with_session_epochs(server_addr, config, true)
+    }
+
+    fn with_session_epochs(
+        server_addr: SocketAddr,
+        config: NetcodeConfig,
+        session_epochs: bool,
+    ) -> Self {
         let private_key = config.private_key.unwrap_or(generate_key());
         // create context
         let context = NetcodeServerContext::default();
@@ -1028,21 +1213,83 @@ impl Server {
         cfg = cfg.keep_alive_send_rate(config.keep_alive_send_rate);
         cfg = cfg.num_disconnect_packets(config.num_disconnect_packets);
         cfg = cfg.server_addr(server_addr);
+        cfg = cfg.max_clients(config.max_clients);
+        cfg = cfg.rate_limit(config.rate_limit);
+        cfg = cfg.session_epochs(session_epochs);
         let server = NetcodeServer::with_config(config.protocol_id, private_key, cfg)
             .expect("Could not create server netcode");
 
-        Self { server }
+        Self {
+            server: ServerKind::Netcode(Box::new(server)),
+        }
+    }
+
+    /// Server that doesn't listen on the network, and receives its clients from a gateway instead
+    pub(crate) fn instance() -> Self {
+        Self {
+            server: ServerKind::Instance(InstanceLink::default()),
+        }
+    }
+
+    pub(crate) fn instance_link_mut(&mut self) -> Option<&mut InstanceLink> {
+        match &mut self.server {
+            ServerKind::Netcode(_) => None,
+            ServerKind::Instance(link) => Some(link),
+        }
+    }
+
+    /// Counters about the packets received by the netcode server (only for servers listening on the network)
+    pub fn stats(&self) -> Option<&NetcodeServerStats> {
+        match &self.server {
+            ServerKind::Netcode(server) => Some(server.stats()),
+            ServerKind::Instance(_) => None,
+        }
     }
 
     pub(crate) fn connected_client_ids(&self) -> Vec<ClientId> {
-        self.server.connected_client_ids()
+        match &self.server {
+            ServerKind::Netcode(server) => server.connected_client_ids(),
+            ServerKind::Instance(link) => link.clients.clone(),
+        }
     }
 
     pub fn recv(&mut self) -> Option<(ReadWordBuffer, ClientId)> {
-        self.server.recv()
+        match &mut self.server {
+            ServerKind::Netcode(server) => server.recv(),
+            ServerKind::Instance(link) => link.recv_queue.pop_front(),
+        }
     }
+
     pub(crate) fn send(&mut self, buf: &[u8], client_id: ClientId, io: &mut Io) -> Result<()> {
-        self.server.send(buf, client_id, io)
+        match &mut self.server {
+            ServerKind::Netcode(server) => server.send(buf, client_id, io),
+            ServerKind::Instance(link) => {
+                if !link.clients.contains(&client_id) {
+                    return Err(Error::ClientNotFound);
+                }
+                link.send_queue.push((buf.to_vec(), client_id));
+                Ok(())
+            }
+        }
+    }
+
+    /// Disconnect a client from the server
+    pub(crate) fn disconnect(&mut self, client_id: ClientId, io: &mut Io) -> Result<()> {
+        match &mut self.server {
+            ServerKind::Netcode(server) => server.disconnect(client_id, io),
+            ServerKind::Instance(link) => {
+                link.disconnect(client_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Start a new session epoch for the client (only for servers listening on the network)
+    pub(crate) fn next_epoch(&mut self, client_id: ClientId) -> Result<u8> {
+        match &mut self.server {
+            ServerKind::Netcode(server) => server.next_epoch(client_id),
+            ServerKind::Instance(_) => Err(Error::ClientNotFound),
+        }
     }
 
     pub(crate) fn try_update(
@@ -1053,8 +1300,13 @@ impl Server {
         // clear the list of new connections/disconnections
         // self.server.cfg.context.connections.clear();
         // self.server.cfg.context.disconnections.clear();
-        self.server.try_update(delta_ms, io)?;
-        Ok(std::mem::take(&mut self.server.cfg.context))
+        match &mut self.server {
+            ServerKind::Netcode(server) => {
+                server.try_update(delta_ms, io)?;
+                Ok(std::mem::take(&mut server.cfg.context))
+            }
+            ServerKind::Instance(link) => Ok(std::mem::take(&mut link.context)),
+        }
     }
 
     // /// Generate a connect token for a client with id `client_id`
@@ -1071,3 +1323,69 @@ impl Server {
     //         .unwrap()
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::netcode::{generate_key, Client, ClientState, ConnectToken};
+    use crate::prelude::TransportConfig;
+    use crate::transport::io::IoConfig;
+    use crate::transport::LOCAL_SOCKET;
+
+    use super::*;
+
+    /// Connect `num_clients` clients to a server that accepts at most `max_clients` clients
+    fn connect_clients(max_clients: usize, num_clients: u64) -> Vec<ClientState> {
+        let protocol_id = 0;
+        let private_key = generate_key();
+        let mut server = NetcodeServer::with_config(
+            protocol_id,
+            private_key,
+            ServerConfig::default().max_clients(max_clients),
+        )
+        .unwrap();
+        let mut channels = vec![];
+        let mut clients = vec![];
+        for client_id in 0..num_clients {
+            // every client has its own address
+            let addr = SocketAddr::from(([127, 0, 0, 1], 1000 + client_id as u16));
+            let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
+            let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
+            channels.push((addr, to_server_recv, from_server_send));
+            let io = IoConfig::from_transport(TransportConfig::LocalChannel {
+                send: to_server_send,
+                recv: from_server_recv,
+            })
+            .get_io();
+            let token = ConnectToken::build(LOCAL_SOCKET, protocol_id, client_id, private_key)
+                .generate()
+                .unwrap();
+            let mut client = Client::new(&token.try_into_bytes().unwrap()).unwrap();
+            client.connect();
+            clients.push((client, io));
+        }
+        let mut server_io =
+            IoConfig::from_transport(TransportConfig::Channels { channels }).get_io();
+
+        for _ in 0..20 {
+            for (client, io) in clients.iter_mut() {
+                client.update(0.1, io);
+            }
+            server.update(0.1, &mut server_io);
+        }
+        assert_eq!(
+            server.num_connected_clients(),
+            max_clients.min(num_clients as usize)
+        );
+        clients.iter().map(|(client, _)| client.state()).collect()
+    }
+
+    #[test]
+    fn test_server_full() {
+        let states = connect_clients(1, 2);
+        assert!(states.contains(&ClientState::Connected));
+        assert!(states.contains(&ClientState::ServerFull));
+
+        let states = connect_clients(3, 3);
+        assert!(states.iter().all(|state| *state == ClientState::Connected));
+    }
+}
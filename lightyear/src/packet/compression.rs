@@ -0,0 +1,579 @@
+/*!
+Optional compression of the data sent over a connection.
+
+Compression is negotiated when the connection is established: each peer that enabled compression sends a
+[`CompressionOffer`], and a peer only starts compressing the data it sends once it has received an offer
+that is compatible with its own [`CompressionConfig`]. Peers that don't enable compression never send an offer,
+so they never receive compressed data.
+
+Until compression is negotiated (and for the data that doesn't get smaller when compressed), the packets
+are sent exactly as they would be without compression, so that peers that don't support compression can still
+read them.
+
+Compressed data is identified with packet types that were never sent by peers that don't support compression:
+- a compressed packet starts with [`PacketType::Compressed`], followed by the compressed bytes of the full packet
+- the messages sent on reliable channels that are big enough to be fragmented are compressed before being split into
+fragments. The fragments of a compressed message are sent in packets of type [`PacketType::DataFragmentCompressed`],
+and the message is decompressed once all the fragments have been received.
+*/
+use std::sync::Arc;
+
+use anyhow::bail;
+use bitcode::encoding::Fixed;
+use serde::{Deserialize, Serialize};
+
+use crate::netcode::MAX_PACKET_SIZE;
+use crate::packet::packet::FRAGMENT_SIZE;
+use crate::packet::packet_manager::Payload;
+use crate::packet::packet_type::PacketType;
+use crate::serialize::reader::ReadBuffer;
+use crate::serialize::wordbuffer::reader::ReadWordBuffer;
+use crate::serialize::wordbuffer::writer::WriteWordBuffer;
+use crate::serialize::writer::WriteBuffer;
+
+/// Maximum size of a message that is decompressed after being reassembled from fragments
+const MAX_FRAGMENTED_MESSAGE_SIZE: usize = u8::MAX as usize * FRAGMENT_SIZE;
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMode {
+    /// The data is never compressed
+    #[default]
+    None,
+    /// The data is compressed with [LZ4](https://github.com/lz4/lz4), which is very fast
+    Lz4,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    pub mode: CompressionMode,
+    /// Payloads smaller than this number of bytes are never compressed, because the gains
+    /// would be too small
+    pub threshold: usize,
+    /// Optional pre-trained dictionary used to improve the compression of small payloads.
+    /// The client and the server must use the exact same dictionary, otherwise compression is not enabled.
+    pub dictionary: Option<Arc<Vec<u8>>>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            mode: CompressionMode::None,
+            threshold: 64,
+            dictionary: None,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Compress the data with LZ4
+    pub fn lz4() -> Self {
+        Self {
+            mode: CompressionMode::Lz4,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        self.dictionary = Some(Arc::new(dictionary));
+        self
+    }
+
+    /// Hash identifying the dictionary, so that peers can check that they use the same one
+    fn dictionary_id(&self) -> Option<u64> {
+        self.dictionary.as_ref().map(|dictionary| {
+            use std::hash::Hasher;
+            let mut hasher = seahash::SeaHasher::new();
+            hasher.write(dictionary.as_slice());
+            hasher.finish()
+        })
+    }
+}
+
+/// Message sent by a peer to indicate the compression settings it supports
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionOffer {
+    pub mode: CompressionMode,
+    pub dictionary_id: Option<u64>,
+}
+
+/// Statistics about the compression of the data sent and received on a connection
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Number of bytes sent, before compression
+    pub bytes_sent_uncompressed: usize,
+    /// Number of bytes sent, after compression (including the payloads that were left uncompressed)
+    pub bytes_sent_compressed: usize,
+    /// Number of payloads that were sent compressed
+    pub payloads_compressed: usize,
+    /// Number of bytes received, before decompression
+    pub bytes_received_compressed: usize,
+    /// Number of bytes received, after decompression
+    pub bytes_received_uncompressed: usize,
+    /// Number of compressed payloads that were received
+    pub payloads_decompressed: usize,
+}
+
+impl CompressionStats {
+    pub(crate) fn merge(&mut self, other: &CompressionStats) {
+        self.bytes_sent_uncompressed += other.bytes_sent_uncompressed;
+        self.bytes_sent_compressed += other.bytes_sent_compressed;
+        self.payloads_compressed += other.payloads_compressed;
+        self.bytes_received_compressed += other.bytes_received_compressed;
+        self.bytes_received_uncompressed += other.bytes_received_uncompressed;
+        self.payloads_decompressed += other.payloads_decompressed;
+    }
+}
+
+/// Compresses the data sent on a connection, if compression was negotiated with the remote peer
+#[derive(Default, Debug, Clone)]
+pub(crate) struct Compressor {
+    config: CompressionConfig,
+    /// True if the remote peer accepted our compression settings, so that we can compress the data we send
+    enabled: bool,
+    pub(crate) stats: CompressionStats,
+}
+
+impl Compressor {
+    pub(crate) fn new(config: CompressionConfig) -> Self {
+        Self {
+            config,
+            enabled: false,
+            stats: CompressionStats::default(),
+        }
+    }
+
+    /// The offer to send to the remote peer, if compression is enabled locally
+    pub(crate) fn offer(&self) -> Option<CompressionOffer> {
+        if self.config.mode == CompressionMode::None {
+            return None;
+        }
+        Some(CompressionOffer {
+            mode: self.config.mode,
+            dictionary_id: self.config.dictionary_id(),
+        })
+    }
+
+    /// Enable compression of the data we send if the remote offer is compatible with our settings.
+    /// Returns true if compression is enabled
+    pub(crate) fn negotiate(&mut self, remote: &CompressionOffer) -> bool {
+        self.enabled = self.offer() == Some(*remote);
+        self.enabled
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Compress the bytes into a block, if compression was negotiated and the bytes are big enough
+    fn compress_block(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        if !self.enabled || bytes.len() < self.config.threshold {
+            return None;
+        }
+        // the negotiation guarantees that the remote peer uses the same dictionary
+        match &self.config.dictionary {
+            Some(dictionary) => Some(lz4_flex::block::compress_prepend_size_with_dict(
+                bytes, dictionary,
+            )),
+            None => Some(lz4_flex::block::compress_prepend_size(bytes)),
+        }
+    }
+
+    fn decompress_block(&self, block: &[u8], max_size: usize) -> anyhow::Result<Vec<u8>> {
+        let (size, compressed) = lz4_flex::block::uncompressed_size(block)?;
+        if size > max_size {
+            bail!(
+                "decompressed size {} exceeds the maximum of {}",
+                size,
+                max_size
+            );
+        }
+        match &self.config.dictionary {
+            Some(dictionary) => Ok(lz4_flex::block::decompress_with_dict(
+                compressed, size, dictionary,
+            )?),
+            None => Ok(lz4_flex::block::decompress(compressed, size)?),
+        }
+    }
+
+    /// Compress an encoded packet if compression was negotiated and if that makes it smaller.
+    /// Otherwise the packet is sent unchanged
+    pub(crate) fn compress_packet(&mut self, payload: Payload) -> anyhow::Result<Payload> {
+        self.stats.bytes_sent_uncompressed += payload.len();
+        if let Some(block) = self.compress_block(&payload) {
+            let mut writer = WriteWordBuffer::with_capacity(block.len() + 4);
+            writer.encode(&PacketType::Compressed, Fixed)?;
+            writer.encode(block.as_slice(), Fixed)?;
+            let compressed: Payload = writer.finish_write().into();
+            if compressed.len() < payload.len() {
+                self.stats.bytes_sent_compressed += compressed.len();
+                self.stats.payloads_compressed += 1;
+                return Ok(compressed);
+            }
+        }
+        self.stats.bytes_sent_compressed += payload.len();
+        Ok(payload)
+    }
+
+    /// Check if a received packet is compressed.
+    /// Returns the decompressed packet bytes if the packet was compressed, or None if the packet
+    /// can be read directly from the reader (in which case nothing was read from the reader)
+    pub(crate) fn decompress_packet(
+        &mut self,
+        reader: &mut impl ReadBuffer,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        // a packet starts with its packet type, whether it is compressed or not
+        let bits = reader.peek_bits()?;
+        let packet_type =
+            ReadWordBuffer::start_read(&bits.to_le_bytes()).decode::<PacketType>(Fixed)?;
+        if packet_type != PacketType::Compressed {
+            return Ok(None);
+        }
+        reader.decode::<PacketType>(Fixed)?;
+        let block = reader.decode::<Vec<u8>>(Fixed)?;
+        let bytes = self.decompress_block(&block, MAX_PACKET_SIZE)?;
+        self.stats.bytes_received_compressed += block.len();
+        self.stats.bytes_received_uncompressed += bytes.len();
+        self.stats.payloads_decompressed += 1;
+        Ok(Some(bytes))
+    }
+
+    /// Compress a message that is about to be fragmented, if compression was negotiated and if that
+    /// makes it smaller. Returns None if the message should be sent unchanged
+    pub(crate) fn compress_message(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        self.stats.bytes_sent_uncompressed += message.len();
+        if let Some(block) = self.compress_block(message) {
+            if block.len() < message.len() {
+                self.stats.bytes_sent_compressed += block.len();
+                self.stats.payloads_compressed += 1;
+                return Some(block);
+            }
+        }
+        self.stats.bytes_sent_compressed += message.len();
+        None
+    }
+
+    /// Decompress a message that was reassembled from the fragments of a compressed message
+    pub(crate) fn decompress_message(&mut self, block: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let bytes = self.decompress_block(block, MAX_FRAGMENTED_MESSAGE_SIZE)?;
+        self.stats.bytes_received_compressed += block.len();
+        self.stats.bytes_received_uncompressed += bytes.len();
+        self.stats.payloads_decompressed += 1;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::Duration;
+    use bytes::Bytes;
+
+    use crate::channel::builder::ReliableSettings;
+    use crate::channel::receivers::fragment_receiver::FragmentReceiver;
+    use crate::channel::senders::reliable::ReliableSender;
+    use crate::channel::senders::ChannelSend;
+    use crate::packet::message_manager::MessageManager;
+    use crate::packet::packet::{Packet, PacketData};
+    use crate::packet::packet_manager::{PacketBuilder, PACKET_BUFFER_CAPACITY};
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::serialize::wordbuffer::reader::ReadWordBuffer;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    fn compressible_bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 7) as u8).collect()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let mut compressor = Compressor::new(CompressionConfig::lz4());
+        let remote = Compressor::new(CompressionConfig::lz4().with_dictionary(vec![1, 2, 3]));
+        assert!(!compressor.negotiate(&remote.offer().unwrap()));
+        let remote = Compressor::new(CompressionConfig::lz4());
+        assert!(compressor.negotiate(&remote.offer().unwrap()));
+
+        // compression is disabled locally
+        let mut compressor = Compressor::new(CompressionConfig::default());
+        assert!(compressor.offer().is_none());
+        assert!(!compressor.negotiate(&remote.offer().unwrap()));
+    }
+
+    #[test]
+    fn test_packet_round_trip() -> anyhow::Result<()> {
+        let dictionary = compressible_bytes(100);
+        let config = CompressionConfig::lz4().with_dictionary(dictionary);
+        let mut sender = Compressor::new(config.clone());
+        let mut receiver = Compressor::new(config);
+        sender.negotiate(&receiver.offer().unwrap());
+
+        // small packets are sent unchanged
+        let mut packet_manager = PacketBuilder::new();
+        let packet = packet_manager.build_new_single_packet();
+        let payload = packet_manager.encode_packet(&packet)?;
+        assert_eq!(sender.compress_packet(payload.clone())?, payload);
+        let mut reader = ReadWordBuffer::start_read(&payload);
+        assert_eq!(receiver.decompress_packet(&mut reader)?, None);
+        assert_eq!(
+            packet_manager.decode_packet(&mut reader)?.header(),
+            packet.header()
+        );
+
+        let payload = compressible_bytes(1000);
+        let compressed = sender.compress_packet(payload.clone())?;
+        assert!(compressed.len() < payload.len());
+        let mut reader = ReadWordBuffer::start_read(&compressed);
+        assert_eq!(receiver.decompress_packet(&mut reader)?, Some(payload));
+        assert_eq!(sender.stats.payloads_compressed, 1);
+        assert_eq!(receiver.stats.payloads_decompressed, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_round_trip() -> anyhow::Result<()> {
+        let mut sender = Compressor::new(CompressionConfig::lz4());
+        let mut receiver = Compressor::default();
+
+        // compression was not negotiated, the message is sent unchanged
+        let message = compressible_bytes(2000);
+        assert_eq!(sender.compress_message(&message), None);
+
+        sender.negotiate(&CompressionOffer {
+            mode: CompressionMode::Lz4,
+            dictionary_id: None,
+        });
+        let compressed = sender.compress_message(&message).unwrap();
+        assert!(compressed.len() < message.len());
+        assert_eq!(receiver.decompress_message(&compressed)?, message);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reliable_fragmented_message() -> anyhow::Result<()> {
+        let config = CompressionConfig::lz4();
+        let mut sender = ReliableSender::new(ReliableSettings::default());
+        sender.compressor = Compressor::new(config.clone());
+        sender
+            .compressor
+            .negotiate(&Compressor::new(config).offer().unwrap());
+        let mut receiver = FragmentReceiver::with_compression();
+
+        // the message becomes small enough to fit in a single fragment once compressed
+        let message = Bytes::from(compressible_bytes(3 * FRAGMENT_SIZE));
+        sender.buffer_send(message.clone());
+        sender.collect_messages_to_send();
+        let (single, fragments) = sender.send_packet();
+        assert!(single.is_empty());
+        assert_eq!(fragments.len(), 1);
+        assert!(fragments[0].compressed);
+
+        // the compression flag of the fragment is sent in the packet type
+        let mut packet_manager = PacketBuilder::new();
+        let packet = packet_manager.build_new_fragment_packet(0, fragments[0].clone());
+        assert_eq!(
+            packet.header().get_packet_type(),
+            PacketType::DataFragmentCompressed
+        );
+        let payload = packet_manager.encode_packet(&packet)?;
+        let Packet {
+            data: PacketData::Fragmented(fragmented_packet),
+            ..
+        } = packet_manager.decode_packet(&mut ReadWordBuffer::start_read(&payload))?
+        else {
+            panic!("expected a fragmented packet");
+        };
+        let received = receiver
+            .receive_fragment(fragmented_packet.fragment, None)?
+            .unwrap();
+        assert_eq!(received.bytes, message);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_oversized_block() {
+        let mut sender = Compressor::new(CompressionConfig::lz4());
+        sender.enabled = true;
+        let compressed = sender
+            .compress_message(&vec![0; MAX_FRAGMENTED_MESSAGE_SIZE + 1])
+            .unwrap();
+        assert!(Compressor::default()
+            .decompress_message(&compressed)
+            .is_err());
+    }
+
+    /// A peer that doesn't support compression (or didn't negotiate it) must be able to talk
+    /// to a peer that enabled compression: the packets must keep the layout they had before compression
+    /// was added to the protocol
+    #[test]
+    fn test_uncompressed_peer() -> anyhow::Result<()> {
+        let protocol = protocol();
+        let channel_kind = ChannelKind::of::<OrderedChannel>();
+        let mut new_peer = MessageManager::new(protocol.channel_registry());
+        new_peer.set_compression(CompressionConfig::lz4().with_threshold(0));
+        // the old peer never sends a compression offer, so compression is never negotiated
+        let mut old_peer = MessageManager::new(protocol.channel_registry());
+
+        let small = MyMessageProtocol::Message1(Message1("a".to_string()));
+        let big = MyMessageProtocol::Message1(Message1("a".repeat(3 * FRAGMENT_SIZE)));
+        new_peer.buffer_send(small.clone(), channel_kind)?;
+        new_peer.buffer_send(big.clone(), channel_kind)?;
+        let packets = new_peer.send_packets(Tick(0))?;
+        assert_eq!(new_peer.take_compression_stats().payloads_compressed, 0);
+        for payload in packets.iter() {
+            // the packets are encoded exactly like the packets of a peer without compression
+            let packet = Packet::decode(&mut ReadWordBuffer::start_read(payload))?;
+            assert_ne!(
+                packet.header().get_packet_type(),
+                PacketType::DataFragmentCompressed
+            );
+            let mut writer = WriteWordBuffer::with_capacity(PACKET_BUFFER_CAPACITY);
+            packet.encode(&mut writer)?;
+            assert_eq!(writer.finish_write(), payload.as_slice());
+            assert!(payload.len() <= MAX_PACKET_SIZE);
+
+            old_peer.recv_packet(&mut ReadWordBuffer::start_read(payload))?;
+        }
+        let received = old_peer.read_messages::<MyMessageProtocol>()?;
+        assert_eq!(
+            received.get(&channel_kind).unwrap(),
+            &vec![(Tick(0), small.clone()), (Tick(0), big.clone())]
+        );
+
+        // the peer with compression can read the packets of the peer without compression
+        old_peer.buffer_send(small.clone(), channel_kind)?;
+        old_peer.buffer_send(big.clone(), channel_kind)?;
+        for payload in old_peer.send_packets(Tick(0))? {
+            new_peer.recv_packet(&mut ReadWordBuffer::start_read(&payload))?;
+        }
+        let received = new_peer.read_messages::<MyMessageProtocol>()?;
+        assert_eq!(
+            received.get(&channel_kind).unwrap(),
+            &vec![(Tick(0), small), (Tick(0), big)]
+        );
+        Ok(())
+    }
+
+    fn setup(client: CompressionConfig, server: CompressionConfig) -> BevyStepper {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            compression: server,
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        // the client uses its own compression settings
+        stepper
+            .client_app
+            .world
+            .resource_mut::<ClientConfig>()
+            .shared
+            .compression = client;
+        stepper.client_app.world.resource_scope(
+            |world, mut connection: bevy::prelude::Mut<ClientConnectionManager>| {
+                connection.reset(world);
+            },
+        );
+        stepper
+    }
+
+    /// Send a big message from the server on an unreliable and a reliable channel,
+    /// and check that the client receives it intact
+    fn check_big_message(stepper: &mut BevyStepper) {
+        let message = Message1("lightyear".repeat(300));
+        let client_id = stepper.client_id;
+        let mut server = stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>();
+        server
+            .send_message::<Channel1, _>(client_id, message.clone())
+            .unwrap();
+        server
+            .send_message::<OrderedChannel, _>(client_id, message.clone())
+            .unwrap();
+        stepper.frame_step();
+        stepper.frame_step();
+        let events = stepper
+            .client_app
+            .world
+            .resource::<bevy::prelude::Events<MessageEvent<Message1>>>();
+        let received: Vec<_> = events
+            .get_reader()
+            .read(events)
+            .map(|event| event.message().clone())
+            .collect();
+        assert_eq!(received, vec![message.clone(), message]);
+    }
+
+    fn is_compression_enabled(stepper: &BevyStepper) -> (bool, bool) {
+        let client = stepper
+            .client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .message_manager
+            .is_compression_enabled();
+        let server = stepper
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>()
+            .connection(stepper.client_id)
+            .unwrap()
+            .message_manager
+            .is_compression_enabled();
+        (client, server)
+    }
+
+    #[test]
+    fn test_compression_negotiated() {
+        let config = CompressionConfig::lz4().with_dictionary(b"lightyear".repeat(10));
+        let mut stepper = setup(config.clone(), config);
+        stepper.init();
+        stepper.frame_step();
+        assert_eq!(is_compression_enabled(&stepper), (true, true));
+
+        check_big_message(&mut stepper);
+        let stats = stepper
+            .server_app
+            .world
+            .resource::<Io>()
+            .stats()
+            .compression;
+        assert!(stats.payloads_compressed > 0);
+        assert!(stats.bytes_sent_compressed < stats.bytes_sent_uncompressed);
+    }
+
+    #[test]
+    fn test_compression_disabled_on_client() {
+        let mut stepper = setup(CompressionConfig::default(), CompressionConfig::lz4());
+        stepper.init();
+        stepper.frame_step();
+        assert_eq!(is_compression_enabled(&stepper), (false, false));
+
+        check_big_message(&mut stepper);
+        let stats = stepper
+            .server_app
+            .world
+            .resource::<Io>()
+            .stats()
+            .compression;
+        assert_eq!(stats.payloads_compressed, 0);
+    }
+}
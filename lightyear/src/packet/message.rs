@@ -1,10 +1,13 @@
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use anyhow::bail;
 use bytes::Bytes;
 
 use bitcode::encoding::{Fixed, Gamma};
 
 use crate::packet::packet::FRAGMENT_SIZE;
+use crate::protocol::channel::ChannelKind;
 use crate::protocol::EventContext;
 use crate::serialize::reader::ReadBuffer;
 use crate::serialize::writer::WriteBuffer;
@@ -24,6 +27,39 @@ wrapping_id!(MessageId);
 
 pub type FragmentIndex = u8;
 
+/// Handle to a message that was sent on a channel that tracks acknowledgements.
+///
+/// A [`MessageAckedEvent`](crate::shared::events::MessageAckedEvent) is emitted with this handle once the remote
+/// peer has received the message, or a [`MessageLostEvent`](crate::shared::events::MessageLostEvent) if the
+/// message was lost on an [`UnorderedUnreliableWithAcks`](crate::prelude::ChannelMode::UnorderedUnreliableWithAcks) channel
+/// or if the connection was closed before the message was acked,
+/// or a [`MessageExpiredEvent`](crate::shared::events::MessageExpiredEvent) if it expired on a reliable channel
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct MessageHandle {
+    pub(crate) channel: ChannelKind,
+    pub(crate) message_id: MessageId,
+    /// Unique id of the handle, because message ids wrap around and are reused by new connections
+    pub(crate) id: u64,
+}
+
+/// Id of the next [`MessageHandle`]
+static NEXT_MESSAGE_HANDLE_ID: AtomicU64 = AtomicU64::new(0);
+
+impl MessageHandle {
+    pub(crate) fn new(channel: ChannelKind, message_id: MessageId) -> Self {
+        Self {
+            channel,
+            message_id,
+            id: NEXT_MESSAGE_HANDLE_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// The channel the message was sent on
+    pub fn channel(&self) -> ChannelKind {
+        self.channel
+    }
+}
+
 /// Struct to keep track of which messages/slices have been received by the remote
 #[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
 pub(crate) struct MessageAck {
@@ -226,6 +262,9 @@ pub struct FragmentData {
     pub num_fragments: FragmentIndex,
     /// Bytes data associated with the message that is too big
     pub bytes: Bytes,
+    /// True if the message was compressed before being fragmented.
+    /// This is not written with the fragment: it is stored in the type of the packet that contains the fragment
+    pub compressed: bool,
 }
 
 impl FragmentData {
@@ -258,6 +297,13 @@ impl FragmentData {
         let tick = reader.decode::<Option<Tick>>(Fixed)?;
         let fragment_id = reader.decode::<FragmentIndex>(Gamma)?;
         let num_fragments = reader.decode::<FragmentIndex>(Gamma)?;
+        if fragment_id >= num_fragments {
+            bail!(
+                "invalid fragment id {} for a message with {} fragments",
+                fragment_id,
+                num_fragments
+            );
+        }
         let bytes = if fragment_id == num_fragments - 1 {
             // let num_bytes = reader.decode::<usize>(Gamma)?;
             // let num_bytes_non_zero = std::num::NonZeroUsize::new(num_bytes)
@@ -282,6 +328,7 @@ impl FragmentData {
             fragment_id,
             num_fragments,
             bytes,
+            compressed: false,
         })
     }
 
@@ -393,6 +440,7 @@ mod tests {
             fragment_id: 2,
             num_fragments: 3,
             bytes: bytes.clone(),
+            compressed: false,
         };
         let mut writer = WriteWordBuffer::with_capacity(10);
         let _a = data.encode(&mut writer).unwrap();
@@ -1,12 +1,19 @@
 use std::collections::{BTreeMap, HashMap, VecDeque};
 
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
+use bytes::Bytes;
+use crossbeam_channel::Receiver;
 use tracing::trace;
 
-use crate::channel::builder::ChannelContainer;
-use crate::channel::receivers::ChannelReceive;
-use crate::channel::senders::ChannelSend;
-use crate::packet::message::{FragmentData, MessageAck, MessageId, SingleData};
+use crate::channel::builder::{ChannelContainer, ChannelMode};
+use crate::channel::receivers::{ChannelReceive, ChannelReceiver};
+use crate::channel::senders::blob::{BlobId, BlobProgress};
+use crate::channel::senders::{ChannelSend, ChannelSender};
+use crate::channel::stream::StreamId;
+use crate::packet::compression::{
+    CompressionConfig, CompressionOffer, CompressionStats, Compressor,
+};
+use crate::packet::message::{FragmentData, MessageAck, MessageHandle, MessageId, SingleData};
 use crate::packet::packet::{Packet, PacketId};
 use crate::packet::packet_manager::{PacketBuilder, Payload, PACKET_BUFFER_CAPACITY};
 use crate::protocol::channel::{ChannelKind, ChannelRegistry};
@@ -37,19 +44,97 @@ pub struct MessageManager {
     /// reliable senders can stop trying to send a message that has already been received
     packet_to_message_ack_map: HashMap<PacketId, HashMap<ChannelKind, Vec<MessageAck>>>,
     writer: WriteWordBuffer,
+    /// Frames (and optionally compresses) the packets
+    compressor: Compressor,
+    /// Notified with the id of the messages that were acked, for each channel that tracks acks
+    ack_receivers: HashMap<ChannelKind, Receiver<MessageId>>,
+    /// Messages whose delivery we want to be notified of
+    tracked_messages: HashMap<(ChannelKind, MessageId), MessageHandle>,
+    /// Tracked messages that were acked since the last call to `take_delivery_notifications`
+    acked_messages: Vec<MessageHandle>,
+    /// Tracked messages that were lost since the last call to `take_delivery_notifications`
+    lost_messages: Vec<MessageHandle>,
 }
 
+/// Tracked messages that were acked, lost, or that expired (see [`MessageManager::take_delivery_notifications`])
+pub(crate) type DeliveryNotifications =
+    (Vec<MessageHandle>, Vec<MessageHandle>, Vec<MessageHandle>);
+
 impl MessageManager {
     pub fn new(channel_registry: &ChannelRegistry) -> Self {
+        let mut channels = channel_registry.channels();
+        let ack_receivers = channels
+            .iter_mut()
+            .filter(|(_, channel)| {
+                channel.setting.mode.is_watching_acks()
+                    && !matches!(channel.setting.mode, ChannelMode::Blob(_))
+            })
+            .map(|(channel_kind, channel)| (*channel_kind, channel.sender.subscribe_acks()))
+            .collect();
         Self {
             packet_manager: PacketBuilder::new(),
-            channels: channel_registry.channels(),
+            channels,
             channel_registry: channel_registry.clone(),
             packet_to_message_ack_map: HashMap::new(),
             writer: WriteWordBuffer::with_capacity(PACKET_BUFFER_CAPACITY),
+            compressor: Compressor::default(),
+            ack_receivers,
+            tracked_messages: HashMap::new(),
+            acked_messages: Vec::new(),
+            lost_messages: Vec::new(),
+        }
+    }
+
+    /// Set the compression settings used for the packets and the fragmented reliable messages.
+    /// The data is only compressed once the remote peer accepted our settings via
+    /// [`negotiate_compression`](Self::negotiate_compression)
+    pub(crate) fn set_compression(&mut self, config: CompressionConfig) {
+        self.compressor = Compressor::new(config.clone());
+        for channel in self.channels.values_mut() {
+            if let Some(compressor) = channel.sender.compressor_mut() {
+                *compressor = Compressor::new(config.clone());
+            }
+            if let Some(compressor) = channel.receiver.compressor_mut() {
+                *compressor = Compressor::new(config.clone());
+            }
         }
     }
 
+    /// The compression offer to send to the remote peer, if compression is enabled
+    pub(crate) fn compression_offer(&self) -> Option<CompressionOffer> {
+        self.compressor.offer()
+    }
+
+    /// Start compressing the data we send if the offer of the remote peer is compatible with our settings.
+    /// Returns true if compression was enabled
+    pub(crate) fn negotiate_compression(&mut self, offer: &CompressionOffer) -> bool {
+        for channel in self.channels.values_mut() {
+            if let Some(compressor) = channel.sender.compressor_mut() {
+                compressor.negotiate(offer);
+            }
+        }
+        self.compressor.negotiate(offer)
+    }
+
+    /// True if the data we send is compressed
+    pub fn is_compression_enabled(&self) -> bool {
+        self.compressor.is_enabled()
+    }
+
+    /// Take the compression statistics accumulated since the last call
+    pub(crate) fn take_compression_stats(&mut self) -> CompressionStats {
+        let mut stats = std::mem::take(&mut self.compressor.stats);
+        for channel in self.channels.values_mut() {
+            if let Some(compressor) = channel.sender.compressor_mut() {
+                stats.merge(&std::mem::take(&mut compressor.stats));
+            }
+            if let Some(compressor) = channel.receiver.compressor_mut() {
+                stats.merge(&std::mem::take(&mut compressor.stats));
+            }
+        }
+        stats
+    }
+
     /// Update book-keeping
     pub fn update(
         &mut self,
@@ -57,7 +142,29 @@ impl MessageManager {
         ping_manager: &PingManager,
         tick_manager: &TickManager,
     ) {
-        self.packet_manager.header_manager.update(time_manager);
+        let lost_packets = self.packet_manager.header_manager.update(time_manager);
+        for lost_packet in lost_packets {
+            let Some(message_map) = self.packet_to_message_ack_map.remove(&lost_packet) else {
+                continue;
+            };
+            for (channel_kind, message_acks) in message_map {
+                // reliable channels will send the message again
+                if !self.channels.get(&channel_kind).map_or(false, |channel| {
+                    channel.setting.mode == ChannelMode::UnorderedUnreliableWithAcks
+                }) {
+                    continue;
+                }
+                for message_ack in message_acks {
+                    if let Some(handle) = self
+                        .tracked_messages
+                        .remove(&(channel_kind, message_ack.message_id))
+                    {
+                        trace!(?handle, "Tracked message was lost");
+                        self.lost_messages.push(handle);
+                    }
+                }
+            }
+        }
         for channel in self.channels.values_mut() {
             channel
                 .sender
@@ -72,6 +179,18 @@ impl MessageManager {
         &mut self,
         message: M,
         channel_kind: ChannelKind,
+    ) -> anyhow::Result<Option<MessageId>> {
+        self.buffer_send_to_stream(message, channel_kind, None)
+    }
+
+    /// Buffer a message to be sent on this connection, on the given stream of an ordered or sequenced channel
+    /// (or on the default stream if `stream` is None).
+    /// Returns the message id associated with the message, if there is one
+    pub fn buffer_send_to_stream<M: BitSerializable>(
+        &mut self,
+        message: M,
+        channel_kind: ChannelKind,
+        stream: Option<StreamId>,
     ) -> anyhow::Result<Option<MessageId>> {
         let channel = self
             .channels
@@ -80,7 +199,118 @@ impl MessageManager {
         self.writer.start_write();
         message.encode(&mut self.writer)?;
         let message_bytes: Vec<u8> = self.writer.finish_write().into();
-        Ok(channel.sender.buffer_send(message_bytes.into()))
+        // empty messages are used to notify the receiver that an expired message was skipped
+        if message_bytes.is_empty() && channel.setting.mode.message_ttl().is_some() {
+            bail!("Cannot send an empty message on a channel with a message TTL");
+        }
+        let Some(stream) = stream else {
+            return Ok(channel.sender.buffer_send(message_bytes.into()));
+        };
+        let ChannelSender::Reliable(sender) = &mut channel.sender else {
+            bail!("Channel does not support streams");
+        };
+        sender
+            .buffer_send_to_stream(message_bytes.into(), stream)
+            .map(Some)
+            .context("Channel does not support streams")
+    }
+
+    /// Buffer a message to be sent on this connection, and keep track of its delivery if the channel tracks acks
+    /// (see [`take_delivery_notifications`](Self::take_delivery_notifications)).
+    /// Returns a handle to the message if its delivery is tracked
+    pub(crate) fn buffer_send_tracked<M: BitSerializable>(
+        &mut self,
+        message: M,
+        channel_kind: ChannelKind,
+        stream: Option<StreamId>,
+    ) -> anyhow::Result<Option<MessageHandle>> {
+        let message_id = self.buffer_send_to_stream(message, channel_kind, stream)?;
+        if !self.ack_receivers.contains_key(&channel_kind) {
+            return Ok(None);
+        }
+        Ok(message_id.map(|message_id| {
+            let handle = MessageHandle::new(channel_kind, message_id);
+            // message ids wrap around: if the previous message with the same id is still tracked,
+            // the sender dropped it without it being acked, so it will never be acked
+            if let Some(previous) = self
+                .tracked_messages
+                .insert((channel_kind, message_id), handle)
+            {
+                trace!(handle = ?previous, "Tracked message id was reused before the message was acked");
+                self.lost_messages.push(previous);
+            }
+            handle
+        }))
+    }
+
+    /// Take the tracked messages that were acked, the ones that were lost and the ones that expired since the last call.
+    /// Messages are only reported lost on [`ChannelMode::UnorderedUnreliableWithAcks`] channels, when the packet
+    /// that contains them was not acked in time; reliable channels keep sending them until they are acked,
+    /// or until they expire if the channel has a [`message_ttl`](crate::channel::builder::ReliableSettings::message_ttl).
+    pub(crate) fn take_delivery_notifications(&mut self) -> DeliveryNotifications {
+        let mut expired_messages = vec![];
+        for (channel_kind, channel) in self.channels.iter_mut() {
+            for message_id in channel.sender.take_expired_messages() {
+                if let Some(handle) = self.tracked_messages.remove(&(*channel_kind, message_id)) {
+                    trace!(?handle, "Tracked message expired");
+                    expired_messages.push(handle);
+                }
+            }
+        }
+        (
+            std::mem::take(&mut self.acked_messages),
+            std::mem::take(&mut self.lost_messages),
+            expired_messages,
+        )
+    }
+
+    /// Take the delivery notifications when the connection is closed: the tracked messages that were not
+    /// acked yet will never be acked, so they are reported as lost
+    pub(crate) fn take_final_delivery_notifications(&mut self) -> DeliveryNotifications {
+        let (acked, mut lost, expired) = self.take_delivery_notifications();
+        lost.extend(self.tracked_messages.drain().map(|(_, handle)| handle));
+        (acked, lost, expired)
+    }
+
+    /// Buffer a blob to be streamed on this connection.
+    /// The channel must be a [`ChannelMode::Blob`] channel.
+    pub fn buffer_send_blob(
+        &mut self,
+        bytes: Bytes,
+        channel_kind: ChannelKind,
+    ) -> anyhow::Result<BlobId> {
+        let channel = self
+            .channels
+            .get_mut(&channel_kind)
+            .context("Channel not found")?;
+        let ChannelSender::Blob(sender) = &mut channel.sender else {
+            bail!("Channel is not a blob channel");
+        };
+        Ok(sender.send_blob(bytes))
+    }
+
+    /// Progress of the blobs that are being sent on the channel
+    pub fn outgoing_blobs(&self, channel_kind: ChannelKind) -> anyhow::Result<Vec<BlobProgress>> {
+        let channel = self
+            .channels
+            .get(&channel_kind)
+            .context("Channel not found")?;
+        let ChannelSender::Blob(sender) = &channel.sender else {
+            bail!("Channel is not a blob channel");
+        };
+        Ok(sender.progress())
+    }
+
+    /// Progress of the blobs that are being received on the channel
+    pub fn incoming_blobs(&self, channel_kind: ChannelKind) -> anyhow::Result<Vec<BlobProgress>> {
+        let channel = self
+            .channels
+            .get(&channel_kind)
+            .context("Channel not found")?;
+        let ChannelReceiver::Blob(receiver) = &channel.receiver else {
+            bail!("Channel is not a blob channel");
+        };
+        Ok(receiver.progress())
     }
 
     /// Prepare buckets from the internal send buffers, and return the bytes to send
@@ -133,6 +363,7 @@ impl MessageManager {
 
             // Step 2. Get the packets to send over the network
             let payload = self.packet_manager.encode_packet(&packet)?;
+            let payload = self.compressor.compress_packet(payload)?;
             bytes.push(payload);
             // io.send(payload, &self.remote_addr)?;
 
@@ -170,8 +401,13 @@ impl MessageManager {
     /// Update the acks, and put the messages from the packets in internal buffers
     /// Returns the tick of the packet
     pub fn recv_packet(&mut self, reader: &mut impl ReadBuffer) -> anyhow::Result<Tick> {
-        // Step 1. Parse the packet
-        let packet: Packet = self.packet_manager.decode_packet(reader)?;
+        // Step 1. Parse the packet (decompressing it if needed)
+        let packet: Packet = match self.compressor.decompress_packet(reader)? {
+            Some(bytes) => self
+                .packet_manager
+                .decode_packet(&mut ReadWordBuffer::start_read(&bytes))?,
+            None => self.packet_manager.decode_packet(reader)?,
+        };
         let tick = packet.header().tick;
         trace!(?packet, "Received packet");
 
@@ -202,6 +438,14 @@ impl MessageManager {
                 }
             }
         }
+        for (channel_kind, receiver) in self.ack_receivers.iter() {
+            for message_id in receiver.try_iter() {
+                if let Some(handle) = self.tracked_messages.remove(&(*channel_kind, message_id)) {
+                    trace!(?handle, "Tracked message was acked");
+                    self.acked_messages.push(handle);
+                }
+            }
+        }
 
         // Step 4. Put the messages from the packet in the internal buffers for each channel
         for (channel_net_id, messages) in packet.data.contents() {
@@ -232,16 +476,33 @@ impl MessageManager {
     /// Read all the messages in the internal buffers that are ready to be processed
     // TODO: this is where naia converts the messages to events and pushes them to an event queue
     //  let be conservative and just return the messages right now. We could switch to an iterator
-    pub fn read_messages<M: BitSerializable>(&mut self) -> HashMap<ChannelKind, Vec<(Tick, M)>> {
+    ///
+    /// Returns an error if a message cannot be decoded (the remote peer sent invalid data)
+    pub fn read_messages<M: BitSerializable>(
+        &mut self,
+    ) -> anyhow::Result<HashMap<ChannelKind, Vec<(Tick, M)>>> {
         let mut map = HashMap::new();
         for (channel_kind, channel) in self.channels.iter_mut() {
+            // blobs are not messages, they are read with `read_blobs`
+            if matches!(channel.setting.mode, ChannelMode::Blob(_)) {
+                continue;
+            }
             let mut messages = vec![];
+            let skip_empty = channel.setting.mode.message_ttl().is_some();
             while let Some(single_data) = channel.receiver.read_message() {
                 trace!(?channel_kind, "reading message: {:?}", single_data);
+                // the sender gave up on this message because it expired
+                if skip_empty && single_data.bytes.is_empty() {
+                    continue;
+                }
                 let mut reader = ReadWordBuffer::start_read(single_data.bytes.as_ref());
-                let message = M::decode(&mut reader).expect("Could not decode message");
-                // TODO: why do we need finish read? to check for errors?
-                // reader.finish_read()?;
+                let message = M::decode(&mut reader).with_context(|| {
+                    format!("Could not decode message on channel {:?}", channel_kind)
+                })?;
+                // check that the message was read entirely (and not past the end of the bytes)
+                reader.finish_read().with_context(|| {
+                    format!("Could not decode message on channel {:?}", channel_kind)
+                })?;
 
                 // SAFETY: when we receive the message, we set the tick of the message to the header tick
                 // so every message has a tick
@@ -251,7 +512,22 @@ impl MessageManager {
                 map.insert(*channel_kind, messages);
             }
         }
-        map
+        Ok(map)
+    }
+
+    /// Read all the blobs that have been fully received
+    pub fn read_blobs(&mut self) -> Vec<(ChannelKind, Bytes)> {
+        let mut blobs = vec![];
+        for (channel_kind, channel) in self.channels.iter_mut() {
+            if !matches!(channel.setting.mode, ChannelMode::Blob(_)) {
+                continue;
+            }
+            while let Some(single_data) = channel.receiver.read_message() {
+                trace!(?channel_kind, size = ?single_data.bytes.len(), "reading blob");
+                blobs.push((*channel_kind, single_data.bytes));
+            }
+        }
+        blobs
     }
 }
 
@@ -311,7 +587,7 @@ mod tests {
             server_message_manager
                 .recv_packet(&mut ReadWordBuffer::start_read(packet_byte.as_slice()))?;
         }
-        let mut data = server_message_manager.read_messages();
+        let mut data = server_message_manager.read_messages()?;
         assert_eq!(
             data.get(&channel_kind_1).unwrap(),
             &vec![(Tick(0), message.clone())]
@@ -322,7 +598,7 @@ mod tests {
         );
 
         // Confirm what happens if we try to receive but there is nothing on the io
-        data = server_message_manager.read_messages();
+        data = server_message_manager.read_messages()?;
         assert!(data.is_empty());
 
         // Check the state of the packet headers
@@ -412,7 +688,7 @@ mod tests {
             server_message_manager
                 .recv_packet(&mut ReadWordBuffer::start_read(packet_byte.as_slice()))?;
         }
-        let mut data = server_message_manager.read_messages();
+        let mut data = server_message_manager.read_messages()?;
         assert_eq!(
             data.get(&channel_kind_1).unwrap(),
             &vec![(Tick(0), message.clone())]
@@ -423,7 +699,7 @@ mod tests {
         );
 
         // Confirm what happens if we try to receive but there is nothing on the io
-        data = server_message_manager.read_messages();
+        data = server_message_manager.read_messages()?;
         assert!(data.is_empty());
 
         // Check the state of the packet headers
@@ -520,4 +796,395 @@ mod tests {
         assert_eq!(update_acks_tracker.try_recv()?, message_id);
         Ok(())
     }
+
+    #[test]
+    fn test_delivery_notifications() -> anyhow::Result<()> {
+        let protocol = protocol();
+        let mut time_manager = TimeManager::new(Duration::default());
+        let ping_manager = PingManager::new(&PingConfig::default());
+        let tick_manager = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+        let mut client_message_manager = MessageManager::new(protocol.channel_registry());
+        let mut server_message_manager = MessageManager::new(protocol.channel_registry());
+
+        // the delivery of messages is only tracked on channels that track acks
+        assert!(client_message_manager
+            .buffer_send_tracked(
+                MyMessageProtocol::Message2(Message2(0)),
+                Channel1::kind(),
+                None
+            )?
+            .is_none());
+        let acked_handle = client_message_manager
+            .buffer_send_tracked(
+                MyMessageProtocol::Message2(Message2(1)),
+                Channel2::kind(),
+                None,
+            )?
+            .unwrap();
+        assert_eq!(acked_handle.channel(), Channel2::kind());
+        for payload in client_message_manager.send_packets(Tick(0))? {
+            server_message_manager.recv_packet(&mut ReadWordBuffer::start_read(&payload))?;
+        }
+
+        // the server sends back a packet that acks the message
+        server_message_manager
+            .buffer_send(MyMessageProtocol::Message2(Message2(1)), Channel2::kind())?;
+        for payload in server_message_manager.send_packets(Tick(0))? {
+            client_message_manager.recv_packet(&mut ReadWordBuffer::start_read(&payload))?;
+        }
+        assert_eq!(
+            client_message_manager.take_delivery_notifications(),
+            (vec![acked_handle], vec![], vec![])
+        );
+
+        // the packet that contains the message is lost
+        let lost_handle = client_message_manager
+            .buffer_send_tracked(
+                MyMessageProtocol::Message2(Message2(2)),
+                Channel2::kind(),
+                None,
+            )?
+            .unwrap();
+        client_message_manager.send_packets(Tick(0))?;
+        time_manager.update(Duration::from_secs(6));
+        client_message_manager.update(&time_manager, &ping_manager, &tick_manager);
+        assert_eq!(
+            client_message_manager.take_delivery_notifications(),
+            (vec![], vec![lost_handle], vec![])
+        );
+        assert!(client_message_manager.packet_to_message_ack_map.is_empty());
+        Ok(())
+    }
+
+    // Tracked messages that the sender drops without them being acked are reported as lost
+    #[test]
+    fn test_delivery_notifications_dropped_messages() -> anyhow::Result<()> {
+        let protocol = protocol();
+        let mut message_manager = MessageManager::new(protocol.channel_registry());
+
+        // the message id wraps around before the first message is acked
+        let first_handle = message_manager
+            .buffer_send_tracked(
+                MyMessageProtocol::Message2(Message2(0)),
+                Channel2::kind(),
+                None,
+            )?
+            .unwrap();
+        for _ in 0..u16::MAX {
+            message_manager
+                .buffer_send(MyMessageProtocol::Message2(Message2(1)), Channel2::kind())?;
+        }
+        let second_handle = message_manager
+            .buffer_send_tracked(
+                MyMessageProtocol::Message2(Message2(2)),
+                Channel2::kind(),
+                None,
+            )?
+            .unwrap();
+        assert_eq!(first_handle.message_id, second_handle.message_id);
+        assert_ne!(first_handle, second_handle);
+        assert_eq!(
+            message_manager.take_delivery_notifications(),
+            (vec![], vec![first_handle], vec![])
+        );
+
+        // the connection is closed before the messages are acked
+        let reliable_handle = message_manager
+            .buffer_send_tracked(
+                MyMessageProtocol::Message2(Message2(3)),
+                OrderedChannel::kind(),
+                None,
+            )?
+            .unwrap();
+        let (acked, mut lost, expired) = message_manager.take_final_delivery_notifications();
+        lost.sort_by_key(|handle| handle.id);
+        assert_eq!(
+            (acked, lost, expired),
+            (vec![], vec![second_handle, reliable_handle], vec![])
+        );
+        assert!(message_manager.tracked_messages.is_empty());
+        Ok(())
+    }
+
+    // A lost message only blocks the messages of its own stream
+    #[test]
+    fn test_ordered_streams() -> anyhow::Result<()> {
+        let protocol = protocol();
+        let mut client_message_manager = MessageManager::new(protocol.channel_registry());
+        let mut server_message_manager = MessageManager::new(protocol.channel_registry());
+        let channel_kind = OrderedChannel::kind();
+        let chat = StreamId(0);
+        let entity = StreamId(1);
+
+        // streams are only supported on ordered and sequenced channels
+        assert!(client_message_manager
+            .buffer_send_to_stream(
+                MyMessageProtocol::Message2(Message2(0)),
+                Channel1::kind(),
+                Some(chat)
+            )
+            .is_err());
+
+        // the first chat message is lost
+        client_message_manager.buffer_send_to_stream(
+            MyMessageProtocol::Message2(Message2(0)),
+            channel_kind,
+            Some(chat),
+        )?;
+        client_message_manager.send_packets(Tick(0))?;
+
+        client_message_manager.buffer_send_to_stream(
+            MyMessageProtocol::Message2(Message2(1)),
+            channel_kind,
+            Some(chat),
+        )?;
+        client_message_manager.buffer_send_to_stream(
+            MyMessageProtocol::Message2(Message2(2)),
+            channel_kind,
+            Some(entity),
+        )?;
+        for payload in client_message_manager.send_packets(Tick(0))? {
+            server_message_manager.recv_packet(&mut ReadWordBuffer::start_read(&payload))?;
+        }
+        // the entity stream is not blocked by the lost chat message
+        let messages = server_message_manager.read_messages::<MyMessageProtocol>()?;
+        assert_eq!(
+            messages.get(&channel_kind).unwrap(),
+            &vec![(Tick(0), MyMessageProtocol::Message2(Message2(2)))]
+        );
+        Ok(())
+    }
+
+    // A reliable message that is not acked before its TTL is skipped, without blocking the ordered channel
+    #[test]
+    fn test_expired_message_skipped() -> anyhow::Result<()> {
+        let protocol = protocol();
+        let mut time_manager = TimeManager::new(Duration::default());
+        let ping_manager = PingManager::new(&PingConfig::default());
+        let tick_manager = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+        let mut client_message_manager = MessageManager::new(protocol.channel_registry());
+        let mut server_message_manager = MessageManager::new(protocol.channel_registry());
+        let channel_kind = OrderedChannel::kind();
+
+        // the first message is lost
+        let expired_handle = client_message_manager
+            .buffer_send_tracked(MyMessageProtocol::Message2(Message2(0)), channel_kind, None)?
+            .unwrap();
+        client_message_manager.send_packets(Tick(0))?;
+
+        // the second message is received, but has to wait for the first one
+        time_manager.update(Duration::from_millis(150));
+        client_message_manager.update(&time_manager, &ping_manager, &tick_manager);
+        client_message_manager.buffer_send_tracked(
+            MyMessageProtocol::Message2(Message2(1)),
+            channel_kind,
+            None,
+        )?;
+        for payload in client_message_manager.send_packets(Tick(0))? {
+            server_message_manager.recv_packet(&mut ReadWordBuffer::start_read(&payload))?;
+        }
+        assert!(server_message_manager
+            .read_messages::<MyMessageProtocol>()?
+            .is_empty());
+
+        // the first message expires: the server is notified that it can skip it
+        time_manager.update(Duration::from_millis(100));
+        client_message_manager.update(&time_manager, &ping_manager, &tick_manager);
+        for payload in client_message_manager.send_packets(Tick(0))? {
+            server_message_manager.recv_packet(&mut ReadWordBuffer::start_read(&payload))?;
+        }
+        let messages = server_message_manager.read_messages::<MyMessageProtocol>()?;
+        assert_eq!(
+            messages.get(&channel_kind).unwrap(),
+            &vec![(Tick(0), MyMessageProtocol::Message2(Message2(1)))]
+        );
+        assert_eq!(
+            client_message_manager.take_delivery_notifications(),
+            (vec![], vec![], vec![expired_handle])
+        );
+        Ok(())
+    }
+
+    // The server is notified when the client receives a message sent on a channel that tracks acks
+    #[test]
+    fn test_message_acked_event() {
+        use crate::tests::stepper::{BevyStepper, Step};
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            client::SyncConfig::default(),
+            client::PredictionConfig::default(),
+            client::InterpolationConfig::default(),
+            LinkConditionerConfig {
+                incoming_latency: Duration::from_millis(20),
+                incoming_jitter: Duration::default(),
+                incoming_loss: 0.0,
+            },
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = stepper.client_id;
+        let handle = stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .send_message::<Channel2, _>(client_id, Message2(1))
+            .unwrap()
+            .unwrap();
+        let mut reader =
+            bevy::ecs::event::ManualEventReader::<server::MessageAckedEvent>::default();
+        let mut acked = vec![];
+        for _ in 0..10 {
+            stepper.frame_step();
+            let events = stepper
+                .server_app
+                .world
+                .resource::<bevy::prelude::Events<server::MessageAckedEvent>>();
+            acked.extend(
+                reader
+                    .read(events)
+                    .map(|event| (event.handle(), *event.context())),
+            );
+        }
+        assert_eq!(acked, vec![(handle, client_id)]);
+    }
+
+    // The tracked messages that were not acked when a client disconnects are reported as lost
+    #[test]
+    fn test_message_lost_on_disconnect() {
+        use crate::tests::stepper::{BevyStepper, Step};
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            client::SyncConfig::default(),
+            client::PredictionConfig::default(),
+            client::InterpolationConfig::default(),
+            LinkConditionerConfig {
+                incoming_latency: Duration::default(),
+                incoming_jitter: Duration::default(),
+                incoming_loss: 0.0,
+            },
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = stepper.client_id;
+        let handle = stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>()
+            .send_message::<OrderedChannel, _>(client_id, Message2(1))
+            .unwrap()
+            .unwrap();
+        stepper.server_app.world.resource_scope(
+            |world, mut netcode: bevy::prelude::Mut<crate::netcode::Server>| {
+                netcode
+                    .disconnect(client_id, world.resource_mut::<Io>().as_mut())
+                    .unwrap();
+            },
+        );
+        let mut reader = bevy::ecs::event::ManualEventReader::<server::MessageLostEvent>::default();
+        let mut lost = vec![];
+        for _ in 0..2 {
+            stepper.frame_step();
+            let events = stepper
+                .server_app
+                .world
+                .resource::<bevy::prelude::Events<server::MessageLostEvent>>();
+            lost.extend(
+                reader
+                    .read(events)
+                    .map(|event| (event.handle(), *event.context())),
+            );
+        }
+        assert_eq!(lost, vec![(handle, client_id)]);
+    }
+
+    #[test]
+    fn test_send_message_to_stream_from_system_params() {
+        use crate::client::resource::ClientMut;
+        use crate::server::resource::ServerMut;
+        use crate::tests::stepper::{BevyStepper, Step};
+        use bevy::ecs::system::RunSystemOnce;
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            tick: TickConfig::new(frame_duration),
+            ..Default::default()
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            client::SyncConfig::default(),
+            client::PredictionConfig::default(),
+            client::InterpolationConfig::default(),
+            LinkConditionerConfig {
+                incoming_latency: Duration::default(),
+                incoming_jitter: Duration::default(),
+                incoming_loss: 0.0,
+            },
+            frame_duration,
+        );
+        stepper.init();
+
+        let client_id = stepper.client_id;
+        stepper
+            .client_app
+            .world
+            .run_system_once(|mut client: ClientMut<MyProtocol>| {
+                client
+                    .send_message_to_stream::<OrderedChannel, _>(Message2(1), StreamId(1))
+                    .unwrap();
+            });
+        stepper
+            .server_app
+            .world
+            .run_system_once(move |mut server: ServerMut<MyProtocol>| {
+                server
+                    .send_message_to_stream::<OrderedChannel, _>(
+                        client_id,
+                        Message2(2),
+                        StreamId(1),
+                    )
+                    .unwrap();
+                server
+                    .send_message_to_target_on_stream::<OrderedChannel, _>(
+                        Message2(3),
+                        NetworkTarget::All,
+                        StreamId(2),
+                    )
+                    .unwrap();
+            });
+        stepper.frame_step();
+        stepper.frame_step();
+
+        let events = stepper
+            .server_app
+            .world
+            .resource::<bevy::prelude::Events<server::MessageEvent<Message2>>>();
+        let received: Vec<_> = events
+            .get_reader()
+            .read(events)
+            .map(|event| event.message().clone())
+            .collect();
+        assert_eq!(received, vec![Message2(1)]);
+        let events = stepper
+            .client_app
+            .world
+            .resource::<bevy::prelude::Events<client::MessageEvent<Message2>>>();
+        let mut received: Vec<_> = events
+            .get_reader()
+            .read(events)
+            .map(|event| event.message().0)
+            .collect();
+        received.sort();
+        assert_eq!(received, vec![2, 3]);
+    }
 }
@@ -7,6 +7,7 @@ use tracing::trace;
 use crate::channel::builder::ChannelContainer;
 use crate::channel::receivers::ChannelReceive;
 use crate::channel::senders::ChannelSend;
+use crate::packet::compression::Compressor;
 use crate::packet::message::MessageAck;
 use crate::packet::packet::{Packet, PacketId};
 use crate::packet::packet_manager::PacketBuilder;
@@ -33,6 +34,8 @@ pub struct MessageReceiver<M: BitSerializable> {
     /// Map to keep track of which messages have been sent in which packets, so that
     /// reliable senders can stop trying to send a message that has already been received
     packet_to_message_ack_map: HashMap<PacketId, HashMap<ChannelKind, Vec<MessageAck>>>,
+    /// Reads the frame of the packets (and decompresses them if needed)
+    compressor: Compressor,
 
     // MessageManager works because we only are only sending a single enum type
     _marker: PhantomData<M>,
@@ -45,6 +48,7 @@ impl<M: BitSerializable> MessageReceiver<M> {
             channels: channel_registry.channels(),
             channel_registry: channel_registry.clone(),
             packet_to_message_ack_map: HashMap::new(),
+            compressor: Compressor::default(),
             _marker: Default::default(),
         }
     }
@@ -66,8 +70,13 @@ impl<M: BitSerializable> MessageReceiver<M> {
     /// Update the acks, and put the messages from the packets in internal buffers
     /// Returns the tick of the packet
     pub fn recv_packet(&mut self, reader: &mut impl ReadBuffer) -> anyhow::Result<Tick> {
-        // Step 1. Parse the packet
-        let packet: Packet = self.packet_manager.decode_packet(reader)?;
+        // Step 1. Parse the packet (decompressing it if needed)
+        let packet: Packet = match self.compressor.decompress_packet(reader)? {
+            Some(bytes) => self
+                .packet_manager
+                .decode_packet(&mut ReadWordBuffer::start_read(&bytes))?,
+            None => self.packet_manager.decode_packet(reader)?,
+        };
         let tick = packet.header().tick;
         trace!(?packet, "Received packet");
 
@@ -309,8 +309,10 @@ impl Packet {
                     data: PacketData::Single(single_packet),
                 })
             }
-            PacketType::DataFragment => {
-                let fragmented_packet = FragmentedPacket::decode(reader)?;
+            PacketType::DataFragment | PacketType::DataFragmentCompressed => {
+                let mut fragmented_packet = FragmentedPacket::decode(reader)?;
+                fragmented_packet.fragment.compressed =
+                    packet_type == PacketType::DataFragmentCompressed;
                 Ok(Self {
                     header,
                     data: PacketData::Fragmented(fragmented_packet),
@@ -480,6 +482,7 @@ mod tests {
             fragment_id: 2,
             num_fragments: 3,
             bytes: bytes.clone(),
+            compressed: false,
         };
         let mut packet = FragmentedPacket::new(*channel_id, fragment.clone());
 
@@ -518,6 +521,7 @@ mod tests {
             fragment_id: 2,
             num_fragments: 3,
             bytes: bytes.clone(),
+            compressed: false,
         };
         let packet = FragmentedPacket::new(*channel_id, fragment.clone());
 
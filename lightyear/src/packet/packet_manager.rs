@@ -116,9 +116,13 @@ impl PacketBuilder {
         // self.try_write_buffer
         //     .serialize(packet.header())
         //     .expect("Failed to serialize header, this should never happen");
-        let header = self
-            .header_manager
-            .prepare_send_packet_header(PacketType::DataFragment);
+        // the packet type indicates if the fragmented message was compressed
+        let packet_type = if fragment_data.compressed {
+            PacketType::DataFragmentCompressed
+        } else {
+            PacketType::DataFragment
+        };
+        let header = self.header_manager.prepare_send_packet_header(packet_type);
         let is_last_fragment = fragment_data.is_last_fragment();
         let packet = FragmentedPacket::new(channel_id, fragment_data);
 
@@ -10,14 +10,17 @@ pub enum PacketType {
     // A packet sent to maintain the connection by preventing a timeout
     #[bitcode_hint(frequency = 50)]
     KeepAlive,
-    // A Ping message, used to calculate RTT. Must be responded to with a Pong
-    // message
+    // A packet containing a fragment of a message that was compressed before being fragmented.
+    // Only sent once compression has been negotiated with the remote peer.
+    // (this variant replaces the Ping packet type, which was never sent, so that the encoding
+    // of the other packet types is unchanged)
     #[bitcode_hint(frequency = 1)]
-    Ping,
-    // A Pong message, used to calculate RTT. Must be the response to all Ping
-    // messages
+    DataFragmentCompressed,
+    // A compressed packet: the packet type is followed by the compressed bytes of the full packet.
+    // Only sent once compression has been negotiated with the remote peer.
+    // (this variant replaces the Pong packet type, which was never sent)
     #[bitcode_hint(frequency = 1)]
-    Pong,
+    Compressed,
     // A packet containing actual data, but which is fragmented into multiple parts
     #[bitcode_hint(frequency = 5)]
     DataFragment,
@@ -2,11 +2,12 @@ use std::any::TypeId;
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
 
-use bevy::prelude::{App, Component, Entity, EntityWorldMut, World};
+use bevy::prelude::{App, Component, Entity, EntityRef, EntityWorldMut, World};
 use bevy::utils::{EntityHashSet, HashMap};
 use cfg_if::cfg_if;
 
 use crate::_reexport::{InstantCorrector, NullInterpolator};
+use crate::client::prediction::correction::{CorrectionContext, CorrectionFn};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
@@ -43,6 +44,9 @@ pub trait ComponentProtocol:
     /// Map from the type-id to the component kind for each component in the protocol
     fn type_ids() -> HashMap<TypeId, <Self::Protocol as Protocol>::ComponentKinds>;
 
+    /// Copy all the components of the protocol that are present on an entity
+    fn components_of(entity: &EntityRef) -> Vec<Self>;
+
     /// Apply a ComponentInsert to an entity
     fn insert(self, entity: &mut EntityWorldMut);
 
@@ -102,6 +106,14 @@ pub trait ComponentProtocol:
         TypeId::of::<<Self as SyncMetadata<C>>::Corrector>() != TypeId::of::<InstantCorrector>()
     }
 
+    /// If false, we don't extrapolate past the latest confirmed state
+    fn has_extrapolation<C>() -> bool
+    where
+        Self: SyncMetadata<C>,
+    {
+        <Self as SyncMetadata<C>>::extrapolator().is_some()
+    }
+
     /// Get the sync mode for the component
     fn lerp<C>(start: C, other: C, t: f32) -> C
     where
@@ -110,11 +122,21 @@ pub trait ComponentProtocol:
         <Self as SyncMetadata<C>>::Interpolator::lerp(start, other, t)
     }
 
-    fn correct<C>(predicted: C, corrected: C, t: f32) -> C
+    fn correct<C>(context: CorrectionContext<C>, velocity: &mut Option<C>) -> C
     where
         Self: SyncMetadata<C>,
     {
-        <Self as SyncMetadata<C>>::Corrector::lerp(predicted, corrected, t)
+        <Self as SyncMetadata<C>>::Corrector::correct(context, velocity)
+    }
+
+    fn extrapolate<C>(previous: C, last: C, t: f32) -> C
+    where
+        Self: SyncMetadata<C>,
+    {
+        match <Self as SyncMetadata<C>>::extrapolator() {
+            Some(extrapolator) => extrapolator(previous, last, t),
+            None => last,
+        }
     }
 }
 
@@ -38,20 +38,14 @@ impl ReadBuffer for ReadWordBuffer {
 
     // fn deserialize<T: DeserializeOwned>(&mut self) -> anyhow::Result<T> {
     //     self.with_dependent_mut(|buffer, reader| {
-    //         let reader = reader
-    //             .0
-    //             .as_mut()
-    //             .map_or_else(|| panic!("no reader"), |(reader, _)| reader);
+    //         let (reader, _) = reader.0.as_mut().context("no reader")?;
     //         deserialize_compat(Fixed, reader).context("error deserializing")
     //     })
     // }
 
     fn deserialize<T: DeserializeOwned>(&mut self) -> anyhow::Result<T> {
         self.with_dependent_mut(|_buffer, reader| {
-            let reader = reader
-                .0
-                .as_mut()
-                .map_or_else(|| panic!("no reader"), |(reader, _)| reader);
+            let (reader, _) = reader.0.as_mut().context("no reader")?;
             let with_gamma =
                 OnlyGammaDecode::<T>::decode(Fixed, reader).context("error deserializing")?;
             Ok(with_gamma.0)
@@ -60,10 +54,7 @@ impl ReadBuffer for ReadWordBuffer {
 
     fn decode<T: Decode>(&mut self, encoding: impl Encoding) -> anyhow::Result<T> {
         self.with_dependent_mut(|_buffer, reader| {
-            let reader = reader
-                .0
-                .as_mut()
-                .map_or_else(|| panic!("no reader"), |(reader, _)| reader);
+            let (reader, _) = reader.0.as_mut().context("no reader")?;
             T::decode(encoding, reader).context("error decoding")
         })
     }
@@ -97,7 +88,10 @@ impl BitRead for ReadWordBuffer {
     }
 
     fn peek_bits(&mut self) -> anyhow::Result<Word> {
-        todo!()
+        self.with_dependent_mut(|_buffer, reader| {
+            let (reader, _) = reader.0.as_mut().context("no reader")?;
+            reader.peek_bits().context("error peeking bits")
+        })
     }
 
     fn read_bit(&mut self) -> anyhow::Result<bool> {
@@ -110,10 +104,7 @@ impl BitRead for ReadWordBuffer {
 
     fn read_bytes(&mut self, len: NonZeroUsize) -> anyhow::Result<&[u8]> {
         self.with_dependent_mut(|_buffer, reader| {
-            let reader = reader
-                .0
-                .as_mut()
-                .map_or_else(|| panic!("no reader"), |(reader, _)| reader);
+            let (reader, _) = reader.0.as_mut().context("no reader")?;
             reader.read_bytes(len).context("error reading bytes")
         })
     }
@@ -168,6 +168,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_after_finish_read() -> anyhow::Result<()> {
+        use super::*;
+        use crate::serialize::writer::WriteBuffer;
+
+        let mut buffer = WriteWordBuffer::with_capacity(1);
+        buffer.serialize(&true)?;
+        let bytes = buffer.finish_write();
+
+        let mut read_buffer = ReadWordBuffer::start_read(bytes);
+        assert!(read_buffer.deserialize::<bool>()?);
+        read_buffer.finish_read()?;
+        // the reader was consumed: reading again returns an error instead of panicking
+        assert!(read_buffer.deserialize::<bool>().is_err());
+        assert!(read_buffer.finish_read().is_err());
+        Ok(())
+    }
+
     // #[test]
     // fn test_write_gamma() -> anyhow::Result<()> {
     //     use super::*;
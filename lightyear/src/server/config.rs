@@ -2,7 +2,9 @@
 use bevy::prelude::Resource;
 use bevy::utils::Duration;
 
-use crate::netcode::Key;
+use crate::netcode::{Key, RateLimitConfig, MAX_CLIENTS};
+use crate::server::input_validation::InputValidationConfig;
+use crate::server::late_join::LateJoinConfig;
 use crate::shared::config::SharedConfig;
 use crate::shared::ping::manager::PingConfig;
 
@@ -15,6 +17,10 @@ pub struct NetcodeConfig {
     pub client_timeout_secs: i32,
     pub protocol_id: u64,
     pub private_key: Option<Key>,
+    /// Maximum number of clients that can be connected to the server at the same time
+    pub max_clients: usize,
+    /// Limits used to protect the server against packet floods
+    pub rate_limit: RateLimitConfig,
 }
 
 impl Default for NetcodeConfig {
@@ -25,6 +31,8 @@ impl Default for NetcodeConfig {
             client_timeout_secs: 10,
             protocol_id: 0,
             private_key: None,
+            max_clients: MAX_CLIENTS,
+            rate_limit: RateLimitConfig::default(),
         }
     }
 }
@@ -43,6 +51,16 @@ impl NetcodeConfig {
         self.client_timeout_secs = client_timeout_secs;
         self
     }
+
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -72,4 +90,6 @@ pub struct ServerConfig {
     pub shared: SharedConfig,
     pub netcode: NetcodeConfig,
     pub ping: PingConfig,
+    pub late_join: LateJoinConfig,
+    pub input_validation: InputValidationConfig,
 }
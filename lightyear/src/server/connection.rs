@@ -5,15 +5,24 @@ use anyhow::{Context, Result};
 use bevy::ecs::component::Tick as BevyTick;
 use bevy::prelude::{Entity, Res, ResMut, Resource, World};
 use bevy::utils::{EntityHashMap, Entry, HashMap, HashSet};
+use bytes::Bytes;
 use serde::Serialize;
-use tracing::{debug, debug_span, info, trace, trace_span};
+use tracing::{debug, debug_span, error, info, trace, trace_span};
 
-use crate::_reexport::{EntityUpdatesChannel, InputMessageKind, MessageProtocol, PingChannel};
+use crate::_reexport::{
+    EntityActionsChannel, EntityUpdatesChannel, InputMessageKind, MessageProtocol, PingChannel,
+};
+use crate::channel::builder::ChannelMode;
+use crate::channel::senders::blob::{BlobId, BlobProgress};
 use crate::channel::senders::ChannelSend;
+use crate::channel::stream::StreamId;
+use crate::client::scheduled::{ScheduleTimeline, ScheduledMessage};
 use crate::connection::events::ConnectionEvents;
 use crate::connection::message::{ClientMessage, ServerMessage};
-use crate::inputs::native::input_buffer::InputBuffer;
+use crate::inputs::native::input_buffer::{InputBuffer, InputMessage};
 use crate::netcode::ClientId;
+use crate::packet::compression::CompressionConfig;
+use crate::packet::message::MessageHandle;
 use crate::packet::message_manager::MessageManager;
 use crate::packet::packet_manager::Payload;
 use crate::prelude::{Channel, ChannelKind, MapEntities, Message};
@@ -21,15 +30,18 @@ use crate::protocol::channel::ChannelRegistry;
 use crate::protocol::Protocol;
 use crate::serialize::reader::ReadBuffer;
 use crate::server::events::ServerEvents;
+use crate::server::input_validation::ClientInputValidation;
+use crate::server::late_join::LateJoinState;
+use crate::server::recording::{RecordingConfig, ReplicationRecorder};
 use crate::shared::ping::manager::{PingConfig, PingManager};
 use crate::shared::ping::message::SyncMessage;
 use crate::shared::replication::components::{NetworkTarget, Replicate};
 use crate::shared::replication::receive::ReplicationReceiver;
 use crate::shared::replication::send::ReplicationSender;
-use crate::shared::replication::ReplicationMessage;
 use crate::shared::replication::ReplicationMessageData;
-use crate::shared::tick_manager::Tick;
+use crate::shared::replication::{EntityMessage, ReplicationMessage};
 use crate::shared::tick_manager::TickManager;
+use crate::shared::tick_manager::{Tick, TimelineChange};
 use crate::shared::time_manager::TimeManager;
 
 #[derive(Resource)]
@@ -46,6 +58,17 @@ pub struct ConnectionManager<P: Protocol> {
     // list of clients that connected since the last time we sent replication messages
     // (we want to keep track of them because we need to replicate the entire world state to them)
     pub(crate) new_clients: Vec<ClientId>,
+    /// Clients that are still receiving the initial state of the world
+    pub(crate) late_join: HashMap<ClientId, LateJoinState>,
+    /// Entities whose initial state is sent during the current send interval, and the clients to send them to
+    pub(crate) late_join_batch: EntityHashMap<Entity, Vec<ClientId>>,
+
+    /// Records the replication stream to a file, if a recording is in progress
+    pub(crate) recorder: Option<ReplicationRecorder<P>>,
+    /// Compression settings used for every new connection
+    compression: CompressionConfig,
+    /// Clients that sent a packet or a message that could not be decoded; they will be disconnected
+    invalid_clients: Vec<ClientId>,
 }
 
 /// Do some regular cleanup on the internals of replication:
@@ -90,16 +113,47 @@ pub(crate) fn replication_clean<P: Protocol>(
 }
 
 impl<P: Protocol> ConnectionManager<P> {
-    pub fn new(channel_registry: ChannelRegistry) -> Self {
+    pub fn new(channel_registry: ChannelRegistry, compression: CompressionConfig) -> Self {
         Self {
             connections: HashMap::default(),
             channel_registry,
             events: ServerEvents::new(),
             replicate_component_cache: EntityHashMap::default(),
             new_clients: vec![],
+            late_join: HashMap::default(),
+            late_join_batch: EntityHashMap::default(),
+            recorder: None,
+            compression,
+            invalid_clients: vec![],
         }
     }
 
+    /// Start recording the replication stream to a file.
+    /// Any recording that was already in progress is stopped.
+    pub fn start_recording(
+        &mut self,
+        config: RecordingConfig,
+        tick_duration: Duration,
+    ) -> Result<()> {
+        self.stop_recording()?;
+        info!(path = ?config.path, perspective = ?config.perspective, "Start recording");
+        self.recorder = Some(ReplicationRecorder::new(config, tick_duration)?);
+        Ok(())
+    }
+
+    /// Stop the current recording (if any) and flush it to disk
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if let Some(recorder) = self.recorder.take() {
+            info!("Stop recording");
+            recorder.finish()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
     /// Find the list of clients that should receive the replication message
     pub(crate) fn apply_replication(
         &mut self,
@@ -141,6 +195,25 @@ impl<P: Protocol> ConnectionManager<P> {
         }
     }
 
+    /// Remove from the target the clients that haven't received the initial state of the entity yet.
+    /// They will receive the latest state of the entity when its turn comes in the late-join queue.
+    pub(crate) fn exclude_loading_clients(
+        &self,
+        entity: Entity,
+        mut target: NetworkTarget,
+    ) -> NetworkTarget {
+        let loading_clients: Vec<ClientId> = self
+            .late_join
+            .iter()
+            .filter(|(_, state)| state.is_pending(entity))
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        if !loading_clients.is_empty() {
+            target.exclude(loading_clients);
+        }
+        target
+    }
+
     pub(crate) fn connection(&self, client_id: ClientId) -> Result<&Connection<P>> {
         self.connections
             .get(&client_id)
@@ -159,14 +232,32 @@ impl<P: Protocol> ConnectionManager<P> {
         });
     }
 
-    pub(crate) fn add(&mut self, client_id: ClientId, ping_config: &PingConfig) {
+    pub(crate) fn add(
+        &mut self,
+        client_id: ClientId,
+        ping_config: &PingConfig,
+        tick_manager: &TickManager,
+    ) {
         if let Entry::Vacant(e) = self.connections.entry(client_id) {
             #[cfg(feature = "metrics")]
             metrics::increment_gauge!("connected_clients", 1.0);
 
             info!("New connection from id: {}", client_id);
             let mut connection = Connection::new(&self.channel_registry, ping_config);
+            // compression is only used once the client sent compatible settings
+            connection
+                .message_manager
+                .set_compression(self.compression.clone());
             connection.events.push_connection();
+            // the client needs to know the timeline (tick durations, pause, time scale) to be able to sync with the server
+            for change in tick_manager.timeline_changes() {
+                if let Err(e) = connection.message_manager.buffer_send(
+                    ServerMessage::<P>::Timeline(change),
+                    ChannelKind::of::<EntityActionsChannel>(),
+                ) {
+                    error!("Error sending timeline change: {}", e);
+                }
+            }
             self.new_clients.push(client_id);
             e.insert(connection);
         } else {
@@ -180,7 +271,16 @@ impl<P: Protocol> ConnectionManager<P> {
 
         info!("Client {} disconnected", client_id);
         self.events.push_disconnects(client_id);
-        self.connections.remove(&client_id);
+        if let Some(mut connection) = self.connections.remove(&client_id) {
+            // the tracked messages that were not acked yet will never be acked
+            let (acked, lost, expired) = connection
+                .message_manager
+                .take_final_delivery_notifications();
+            let mut events = ConnectionEvents::new();
+            events.push_message_delivery(acked, lost, expired);
+            self.events.push_events(client_id, events);
+        }
+        self.late_join.remove(&client_id);
     }
 
     /// Get the inputs for all clients for the given tick
@@ -224,6 +324,7 @@ impl<P: Protocol> ConnectionManager<P> {
         message: P::Message,
         channel: ChannelKind,
         target: NetworkTarget,
+        stream: Option<StreamId>,
     ) -> Result<()> {
         // Rc is fine because the copies are all created on the same thread
         // let message = Rc::new(message);
@@ -233,7 +334,10 @@ impl<P: Protocol> ConnectionManager<P> {
             // TODO: here we should avoid the clone, it's the same message.. just use Rc?
             //  need to update the ServerMessage enum to use Rc<P::Message>!
             //  or serialize first, so we can use Bytes? where would the buffer be?
-            .try_for_each(|(_, c)| c.buffer_message(message.clone(), channel))
+            .try_for_each(|(_, c)| {
+                c.buffer_message(message.clone(), channel, stream)
+                    .map(|_| ())
+            })
     }
 
     /// Queues up a message to be sent to all clients
@@ -246,20 +350,185 @@ impl<P: Protocol> ConnectionManager<P> {
         M: Clone,
         P::Message: From<M>,
     {
-        self.buffer_message(message.into(), ChannelKind::of::<C>(), target)
+        self.buffer_message(message.into(), ChannelKind::of::<C>(), target, None)
+    }
+
+    /// Queues up a message to be sent to all clients in the `target`, on the given stream of an ordered or sequenced channel.
+    ///
+    /// The message is only ordered (or sequenced) relative to the other messages of the same stream,
+    /// so that a lost message does not block the messages of the other streams.
+    pub fn send_message_to_target_on_stream<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+        target: NetworkTarget,
+        stream: StreamId,
+    ) -> Result<()>
+    where
+        M: Clone,
+        P::Message: From<M>,
+    {
+        self.buffer_message(message.into(), ChannelKind::of::<C>(), target, Some(stream))
     }
 
-    /// Queues up a message to be sent to a client
+    /// Queues up a message to be sent to a client.
+    /// Returns a handle to track the delivery of the message, if the channel tracks acks
     pub fn send_message<C: Channel, M: Message>(
         &mut self,
         client_id: ClientId,
         message: M,
+    ) -> Result<Option<MessageHandle>>
+    where
+        M: Clone,
+        P::Message: From<M>,
+    {
+        let Some(connection) = self.connections.get_mut(&client_id) else {
+            return Ok(None);
+        };
+        connection.buffer_message(message.into(), ChannelKind::of::<C>(), None)
+    }
+
+    /// Queues up a message to be sent to a client, on the given stream of an ordered or sequenced channel.
+    ///
+    /// The message is only ordered (or sequenced) relative to the other messages of the same stream,
+    /// so that a lost message does not block the messages of the other streams.
+    pub fn send_message_to_stream<C: Channel, M: Message>(
+        &mut self,
+        client_id: ClientId,
+        message: M,
+        stream: StreamId,
+    ) -> Result<Option<MessageHandle>>
+    where
+        M: Clone,
+        P::Message: From<M>,
+    {
+        let Some(connection) = self.connections.get_mut(&client_id) else {
+            return Ok(None);
+        };
+        connection.buffer_message(message.into(), ChannelKind::of::<C>(), Some(stream))
+    }
+
+    /// Queues up a message bound to the replication group of `entity`.
+    ///
+    /// The client only receives the message once the entity's group has been replicated up to the
+    /// state that was sent when the message was buffered; the entities inside the message are mapped
+    /// to the client's local entities. The message is dropped for clients that the entity is not replicated to.
+    pub fn send_entity_message<C: Channel, M: Message>(
+        &mut self,
+        entity: Entity,
+        message: M,
+        target: NetworkTarget,
+    ) -> Result<()>
+    where
+        M: Clone,
+        P::Message: From<M>,
+    {
+        let message: P::Message = message.into();
+        let channel = ChannelKind::of::<C>();
+        self.connections
+            .iter_mut()
+            .filter(|(id, _)| target.should_send_to(id))
+            .for_each(|(_, c)| {
+                c.pending_entity_messages
+                    .push((entity, message.clone(), channel))
+            });
+        Ok(())
+    }
+
+    /// Queues up a message that the clients will emit as a [`ScheduledEvent`](crate::client::scheduled::ScheduledEvent)
+    /// when their `timeline` reaches `tick`.
+    ///
+    /// The channel must be a [`ChannelMode::Scheduled`] channel.
+    pub fn send_scheduled_message<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+        tick: Tick,
+        timeline: ScheduleTimeline,
+        target: NetworkTarget,
     ) -> Result<()>
     where
         M: Clone,
         P::Message: From<M>,
     {
-        self.send_message_to_target::<C, M>(message, NetworkTarget::Only(vec![client_id]))
+        let channel = ChannelKind::of::<C>();
+        let builder = self
+            .channel_registry
+            .get_builder_from_kind(&channel)
+            .context("Channel not found")?;
+        if !matches!(builder.settings.mode, ChannelMode::Scheduled(_)) {
+            anyhow::bail!("scheduled messages can only be sent on a Scheduled channel");
+        }
+        let message: P::Message = message.into();
+        self.connections
+            .iter_mut()
+            .filter(|(id, _)| target.should_send_to(id))
+            .try_for_each(|(_, c)| {
+                let mut message = message.clone();
+                c.replication_sender.net_entities.map_to_net(&mut message);
+                let message = ServerMessage::<P>::Scheduled(ScheduledMessage {
+                    tick,
+                    timeline,
+                    message,
+                });
+                message.emit_send_logs(
+                    c.message_manager
+                        .channel_registry
+                        .name(&channel)
+                        .unwrap_or("unknown"),
+                );
+                c.message_manager.buffer_send(message, channel).map(|_| ())
+            })
+    }
+
+    /// Stream a large payload to a client on a [`ChannelMode::Blob`](crate::prelude::ChannelMode::Blob) channel.
+    /// The client will receive it as a [`BlobReceivedEvent`](crate::client::events::BlobReceivedEvent)
+    pub fn send_blob<C: Channel>(
+        &mut self,
+        client_id: ClientId,
+        bytes: impl Into<Bytes>,
+    ) -> Result<BlobId> {
+        self.connection_mut(client_id)?
+            .message_manager
+            .buffer_send_blob(bytes.into(), ChannelKind::of::<C>())
+    }
+
+    /// Progress of the blobs that are being sent to a client on the channel `C`
+    pub fn outgoing_blobs<C: Channel>(&self, client_id: ClientId) -> Result<Vec<BlobProgress>> {
+        self.connection(client_id)?
+            .message_manager
+            .outgoing_blobs(ChannelKind::of::<C>())
+    }
+
+    /// Progress of the blobs that are being received from a client on the channel `C`
+    pub fn incoming_blobs<C: Channel>(&self, client_id: ClientId) -> Result<Vec<BlobProgress>> {
+        self.connection(client_id)?
+            .message_manager
+            .incoming_blobs(ChannelKind::of::<C>())
+    }
+
+    /// Send a change of the timeline (tick duration, pause state or time scale) to all clients
+    pub(crate) fn buffer_timeline_change(&mut self, change: TimelineChange) -> Result<()> {
+        self.connections.values_mut().try_for_each(|connection| {
+            connection
+                .message_manager
+                .buffer_send(
+                    ServerMessage::<P>::Timeline(change),
+                    ChannelKind::of::<EntityActionsChannel>(),
+                )
+                .map(|_| ())
+        })
+    }
+
+    /// How long to wait before applying a timeline change, so that every client receives it
+    /// before its own tick (which is ahead of the server tick) reaches the change
+    pub(crate) fn timeline_change_delay(&self, send_interval: Duration) -> Duration {
+        // the message takes rtt/2 to reach the client, which is itself ahead of the server
+        // by roughly rtt/2 plus a margin of jitter
+        self.connections
+            .values()
+            .map(|c| c.ping_manager.rtt() + c.ping_manager.jitter() * 4)
+            .max()
+            .unwrap_or_default()
+            + send_interval
     }
 
     /// Buffer all the replication messages to send.
@@ -271,9 +540,56 @@ impl<P: Protocol> ConnectionManager<P> {
         bevy_tick: BevyTick,
     ) -> Result<()> {
         let _span = trace_span!("buffer_replication_messages").entered();
-        self.connections
-            .values_mut()
-            .try_for_each(move |c| c.buffer_replication_messages(tick, bevy_tick))
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(tick, bevy_tick)?;
+        }
+        for connection in self.connections.values_mut() {
+            connection.buffer_replication_messages(tick, bevy_tick)?;
+            // entity messages are buffered after the replication messages, so that they can refer
+            // to the replication state that was just sent
+            connection.buffer_entity_messages(&self.replicate_component_cache)?;
+        }
+        // notify the loading clients of their progress (after the replication messages)
+        for (client_id, state) in self.late_join.iter_mut() {
+            if let Some(message) = state.take_message() {
+                trace!(?client_id, ?message, "Sending late-join progress");
+                if let Some(connection) = self.connections.get_mut(client_id) {
+                    connection.message_manager.buffer_send(
+                        ServerMessage::<P>::LateJoin(message),
+                        ChannelKind::of::<EntityActionsChannel>(),
+                    )?;
+                }
+            }
+        }
+        self.late_join.retain(|_, state| !state.is_finished());
+        Ok(())
+    }
+
+    /// Buffer a packet received from a client.
+    ///
+    /// If the packet cannot be decoded, the client is marked as invalid (see [`Self::take_invalid_clients`])
+    /// and its next packets are ignored
+    pub(crate) fn recv_packet(
+        &mut self,
+        client_id: ClientId,
+        reader: &mut impl ReadBuffer,
+        tick_manager: &TickManager,
+    ) {
+        if self.invalid_clients.contains(&client_id) {
+            return;
+        }
+        let result = self
+            .connection_mut(client_id)
+            .and_then(|connection| connection.recv_packet(reader, tick_manager));
+        if let Err(e) = result {
+            error!(?client_id, "Received an invalid packet: {}", e);
+            self.invalid_clients.push(client_id);
+        }
+    }
+
+    /// Take the clients that sent a packet or a message that could not be decoded, so that they can be disconnected
+    pub(crate) fn take_invalid_clients(&mut self) -> Vec<ClientId> {
+        std::mem::take(&mut self.invalid_clients)
     }
 
     pub fn receive(
@@ -288,16 +604,25 @@ impl<P: Protocol> ConnectionManager<P> {
             .iter_mut()
             .for_each(|(client_id, connection)| {
                 let _span = trace_span!("receive", ?client_id).entered();
+                if self.invalid_clients.contains(client_id) {
+                    return;
+                }
                 // receive
-                let events = connection.receive(world, time_manager, tick_manager);
-                self.events.push_events(*client_id, events);
+                match connection.receive(world, time_manager, tick_manager) {
+                    Ok(events) => self.events.push_events(*client_id, events),
+                    Err(e) => {
+                        error!(?client_id, "Received an invalid message: {}", e);
+                        self.invalid_clients.push(*client_id);
+                        return;
+                    }
+                }
 
                 // rebroadcast messages
                 messages_to_rebroadcast
                     .extend(std::mem::take(&mut connection.messages_to_rebroadcast));
             });
         for (message, target, channel_kind) in messages_to_rebroadcast {
-            self.buffer_message(message, channel_kind, target)?;
+            self.buffer_message(message, channel_kind, target, None)?;
         }
         Ok(())
     }
@@ -316,10 +641,16 @@ pub struct Connection<P: Protocol> {
     /// Stores the last input we have received from the client.
     /// In case we are missing the client input for a tick, we will fallback to using this.
     pub(crate) last_input: Option<P::Input>,
+    /// Input messages received from the client that still need to be validated
+    pub(crate) pending_input_messages: Vec<InputMessage<P::Input>>,
+    /// Tracks the input violations committed by the client
+    pub(crate) input_validation: ClientInputValidation,
     // TODO: maybe don't do any replication until connection is synced?
 
     // messages that we have received that need to be rebroadcasted to other clients
     pub(crate) messages_to_rebroadcast: Vec<(P::Message, NetworkTarget, ChannelKind)>,
+    /// Entity messages that will be sent with the next replication messages
+    pub(crate) pending_entity_messages: Vec<(Entity, P::Message, ChannelKind)>,
 }
 
 impl<P: Protocol> Connection<P> {
@@ -333,7 +664,14 @@ impl<P: Protocol> Connection<P> {
             .unwrap()
             .sender
             .subscribe_acks();
-        let replication_sender = ReplicationSender::new(update_acks_tracker);
+        // get the acks-tracker for entity actions, to know when the ids of despawned entities can be reused
+        let actions_acks_tracker = message_manager
+            .channels
+            .get_mut(&ChannelKind::of::<EntityActionsChannel>())
+            .unwrap()
+            .sender
+            .subscribe_acks();
+        let replication_sender = ReplicationSender::new(update_acks_tracker, actions_acks_tracker);
         let replication_receiver = ReplicationReceiver::new();
         Self {
             message_manager,
@@ -342,8 +680,11 @@ impl<P: Protocol> Connection<P> {
             ping_manager: PingManager::new(ping_config),
             input_buffer: InputBuffer::default(),
             last_input: None,
+            pending_input_messages: vec![],
+            input_validation: ClientInputValidation::default(),
             events: ConnectionEvents::default(),
             messages_to_rebroadcast: vec![],
+            pending_entity_messages: vec![],
         }
     }
 
@@ -355,9 +696,14 @@ impl<P: Protocol> Connection<P> {
 
     pub(crate) fn buffer_message(
         &mut self,
-        message: P::Message,
+        mut message: P::Message,
         channel: ChannelKind,
-    ) -> Result<()> {
+        stream: Option<StreamId>,
+    ) -> Result<Option<MessageHandle>> {
+        // send the entities inside the message as NetEntities
+        self.replication_sender
+            .net_entities
+            .map_to_net(&mut message);
         // TODO: i know channel names never change so i should be able to get them as static
         // TODO: just have a channel registry enum as well?
         let channel_name = self
@@ -368,8 +714,8 @@ impl<P: Protocol> Connection<P> {
             .to_string();
         let message = ServerMessage::<P>::Message(message);
         message.emit_send_logs(&channel_name);
-        self.message_manager.buffer_send(message, channel)?;
-        Ok(())
+        self.message_manager
+            .buffer_send_tracked(message, channel, stream)
     }
 
     pub(crate) fn buffer_replication_messages(
@@ -382,6 +728,10 @@ impl<P: Protocol> Connection<P> {
             .into_iter()
             .try_for_each(|(channel, group_id, message_data)| {
                 let should_track_ack = matches!(message_data, ReplicationMessageData::Updates(_));
+                let despawns = match &message_data {
+                    ReplicationMessageData::Actions(actions) => actions.despawned_entities(),
+                    ReplicationMessageData::Updates(_) => vec![],
+                };
                 let channel_name = self
                     .message_manager
                     .channel_registry
@@ -404,10 +754,57 @@ impl<P: Protocol> Connection<P> {
                         .updates_message_id_to_group_id
                         .insert(message_id, (group_id, bevy_tick));
                 }
+                // keep track of the despawned entities, so we can reuse their ids once the message is acked
+                if !despawns.is_empty() {
+                    self.replication_sender
+                        .actions_message_id_to_despawns
+                        .insert(message_id, despawns);
+                }
                 Ok(())
             })
     }
 
+    /// Buffer the pending entity messages, tagged with the latest replication state sent for the group
+    /// of their entity. Messages for entities that are not replicated to the client are dropped.
+    pub(crate) fn buffer_entity_messages(
+        &mut self,
+        replicate_component_cache: &EntityHashMap<Entity, Replicate<P>>,
+    ) -> Result<()> {
+        for (entity, mut message, channel) in std::mem::take(&mut self.pending_entity_messages) {
+            let Some((group_id, tick)) =
+                replicate_component_cache
+                    .get(&entity)
+                    .and_then(|replicate| {
+                        self.replication_sender
+                            .replicated_group(entity, replicate.replication_group)
+                    })
+            else {
+                debug!(
+                    ?entity,
+                    "Dropping entity message: the entity is not replicated to the client"
+                );
+                continue;
+            };
+            self.replication_sender
+                .net_entities
+                .map_to_net(&mut message);
+            let channel_name = self
+                .message_manager
+                .channel_registry
+                .name(&channel)
+                .unwrap_or("unknown")
+                .to_string();
+            let message = ServerMessage::<P>::EntityMessage(EntityMessage {
+                group_id,
+                tick,
+                message,
+            });
+            message.emit_send_logs(&channel_name);
+            self.message_manager.buffer_send(message, channel)?;
+        }
+        Ok(())
+    }
+
     /// Send packets that are ready to be sent
     pub fn send_packets(
         &mut self,
@@ -451,9 +848,14 @@ impl<P: Protocol> Connection<P> {
         world: &mut World,
         time_manager: &TimeManager,
         tick_manager: &TickManager,
-    ) -> ConnectionEvents<P> {
+    ) -> Result<ConnectionEvents<P>> {
         let _span = trace_span!("receive").entered();
-        for (channel_kind, messages) in self.message_manager.read_messages::<ClientMessage<P>>() {
+        for (channel_kind, bytes) in self.message_manager.read_blobs() {
+            self.events.push_blob(channel_kind, bytes);
+        }
+        let (acked, lost, expired) = self.message_manager.take_delivery_notifications();
+        self.events.push_message_delivery(acked, lost, expired);
+        for (channel_kind, messages) in self.message_manager.read_messages::<ClientMessage<P>>()? {
             let channel_name = self
                 .message_manager
                 .channel_registry
@@ -489,9 +891,11 @@ impl<P: Protocol> Connection<P> {
                                     self.events.push_input_message(message);
                                 }
                                 InputMessageKind::Native => {
-                                    let input_message = message.try_into().unwrap();
+                                    let input_message: InputMessage<P::Input> =
+                                        message.try_into().unwrap();
                                     debug!("Received input message: {:?}", input_message.end_tick);
-                                    self.input_buffer.update_from_message(input_message);
+                                    // the inputs are validated before being added to the input buffer
+                                    self.pending_input_messages.push(input_message);
                                 }
                                 InputMessageKind::None => {
                                     // buffer the message
@@ -516,6 +920,32 @@ impl<P: Protocol> Connection<P> {
                                 }
                             }
                         }
+                        ClientMessage::LateJoin(_) => {
+                            error!("Received a late-join message from a client, ignoring");
+                        }
+                        ClientMessage::Timeline(_) => {
+                            error!("Received a timeline change from a client, ignoring");
+                        }
+                        ClientMessage::EntityMessage(_) => {
+                            error!("Received an entity message from a client, ignoring");
+                        }
+                        ClientMessage::Scheduled(_) => {
+                            error!("Received a scheduled message from a client, ignoring");
+                        }
+                        ClientMessage::Compression(offer) => {
+                            if self.message_manager.negotiate_compression(&offer) {
+                                debug!(?offer, "Compression enabled");
+                                // reply with our own settings so that the client also starts compressing
+                                if let Err(e) = self.message_manager.buffer_send(
+                                    ServerMessage::<P>::Compression(offer),
+                                    ChannelKind::of::<EntityActionsChannel>(),
+                                ) {
+                                    error!("Error sending compression offer: {}", e);
+                                }
+                            } else {
+                                debug!(?offer, "Client compression settings are not compatible, compression disabled");
+                            }
+                        }
                     }
                 }
             }
@@ -525,25 +955,23 @@ impl<P: Protocol> Connection<P> {
                 self.replication_receiver.read_messages(tick_manager.tick())
             {
                 trace!(?group, ?replication_list, "read replication messages");
-                replication_list
-                    .into_iter()
-                    .for_each(|(tick, replication)| {
-                        // TODO: we could include the server tick when this replication_message was sent.
-                        self.replication_receiver.apply_world(
-                            world,
-                            tick,
-                            replication,
-                            group,
-                            &mut self.events,
-                        );
-                    });
+                for (tick, replication) in replication_list {
+                    // TODO: we could include the server tick when this replication_message was sent.
+                    self.replication_receiver.apply_world(
+                        world,
+                        tick,
+                        replication,
+                        group,
+                        &mut self.events,
+                    )?;
+                }
             }
         }
 
         // TODO: do i really need this? I could just create events in this function directly?
         //  why do i need to make events a field of the connection?
         //  is it because of push_connection?
-        std::mem::replace(&mut self.events, ConnectionEvents::new())
+        Ok(std::mem::replace(&mut self.events, ConnectionEvents::new()))
     }
 
     pub fn recv_packet(
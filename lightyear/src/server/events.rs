@@ -2,6 +2,7 @@
 use std::collections::HashMap;
 
 use bevy::prelude::{Component, Entity};
+use bytes::Bytes;
 use tracing::trace;
 
 use crate::_reexport::{
@@ -10,12 +11,14 @@ use crate::_reexport::{
 #[cfg(feature = "leafwing")]
 use crate::connection::events::IterInputMessageEvent;
 use crate::connection::events::{
-    ConnectionEvents, IterEntityDespawnEvent, IterEntitySpawnEvent, IterMessageEvent,
+    ConnectionEvents, IterBlobReceivedEvent, IterEntityDespawnEvent, IterEntitySpawnEvent,
+    IterMessageDeliveryEvent, IterMessageEvent,
 };
 #[cfg(feature = "leafwing")]
 use crate::inputs::leafwing::{InputMessage, LeafwingUserAction};
 use crate::netcode::ClientId;
-use crate::packet::message::Message;
+use crate::packet::message::{Message, MessageHandle};
+use crate::protocol::channel::ChannelKind;
 use crate::protocol::Protocol;
 
 #[derive(Debug)]
@@ -184,6 +187,64 @@ impl<P: Protocol> IterMessageEvent<P, ClientId> for ServerEvents<P> {
     }
 }
 
+impl<P: Protocol> IterBlobReceivedEvent<ClientId> for ServerEvents<P> {
+    fn into_iter_blobs(&mut self) -> Box<dyn Iterator<Item = (ChannelKind, Bytes, ClientId)> + '_> {
+        Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
+            let client_id = *client_id;
+            events
+                .into_iter_blobs()
+                .map(move |(channel_kind, bytes, _)| (channel_kind, bytes, client_id))
+        }))
+    }
+
+    fn has_blobs(&self) -> bool {
+        self.events
+            .iter()
+            .any(|(_, connection_events)| connection_events.has_blobs())
+    }
+}
+
+impl<P: Protocol> IterMessageDeliveryEvent<ClientId> for ServerEvents<P> {
+    fn into_iter_acked_messages(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = (MessageHandle, ClientId)> + '_> {
+        Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
+            let client_id = *client_id;
+            events
+                .into_iter_acked_messages()
+                .map(move |(handle, _)| (handle, client_id))
+        }))
+    }
+
+    fn into_iter_lost_messages(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = (MessageHandle, ClientId)> + '_> {
+        Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
+            let client_id = *client_id;
+            events
+                .into_iter_lost_messages()
+                .map(move |(handle, _)| (handle, client_id))
+        }))
+    }
+
+    fn into_iter_expired_messages(
+        &mut self,
+    ) -> Box<dyn Iterator<Item = (MessageHandle, ClientId)> + '_> {
+        Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
+            let client_id = *client_id;
+            events
+                .into_iter_expired_messages()
+                .map(move |(handle, _)| (handle, client_id))
+        }))
+    }
+
+    fn has_message_delivery(&self) -> bool {
+        self.events
+            .iter()
+            .any(|(_, connection_events)| connection_events.has_message_delivery())
+    }
+}
+
 impl<P: Protocol> IterEntitySpawnEvent<ClientId> for ServerEvents<P> {
     fn into_iter_entity_spawn(&mut self) -> Box<dyn Iterator<Item = (Entity, ClientId)> + '_> {
         Box::new(self.events.iter_mut().flat_map(|(client_id, events)| {
@@ -306,6 +367,10 @@ pub type ComponentRemoveEvent<C> = crate::shared::events::ComponentRemoveEvent<C
 #[cfg(feature = "leafwing")]
 pub(crate) type InputMessageEvent<A> = crate::shared::events::InputMessageEvent<A, ClientId>;
 pub type MessageEvent<M> = crate::shared::events::MessageEvent<M, ClientId>;
+pub type BlobReceivedEvent = crate::shared::events::BlobReceivedEvent<ClientId>;
+pub type MessageAckedEvent = crate::shared::events::MessageAckedEvent<ClientId>;
+pub type MessageLostEvent = crate::shared::events::MessageLostEvent<ClientId>;
+pub type MessageExpiredEvent = crate::shared::events::MessageExpiredEvent<ClientId>;
 
 #[cfg(test)]
 mod tests {
@@ -1,16 +1,22 @@
 //! Handles client-generated inputs
 use bevy::prelude::{
-    App, EventReader, EventWriter, FixedUpdate, IntoSystemConfigs, IntoSystemSetConfigs, Plugin,
-    Res, ResMut, SystemSet,
+    not, App, EventReader, EventWriter, FixedUpdate, IntoSystemConfigs, IntoSystemSetConfigs,
+    Plugin, PreUpdate, Real, Res, ResMut, SystemSet, Time,
 };
+use tracing::debug;
 
 use crate::netcode::ClientId;
-use crate::prelude::TickManager;
+use crate::prelude::{MainSet, TickManager};
 use crate::protocol::Protocol;
+use crate::server::config::ServerConfig;
 use crate::server::connection::ConnectionManager;
+use crate::server::input_validation::{
+    InputValidation, InputValidators, SuspicionReason, SuspiciousClient,
+};
 use crate::server::resource::Server;
 use crate::shared::events::InputEvent;
 use crate::shared::sets::FixedUpdateSet;
+use crate::shared::tick_manager::is_paused;
 
 // - ClientInputs:
 // - inputs will be sent via a special message
@@ -43,6 +49,8 @@ impl<P: Protocol> Default for InputPlugin<P> {
 
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum InputSystemSet {
+    /// PreUpdate system to validate the input messages received from clients and add them to the input buffers
+    ValidateInputs,
     /// FixedUpdate system to get any inputs from the client. This should be run before the game/physics logic
     WriteInputEvents,
     /// System Set to clear the input events (otherwise bevy clears events every frame, not every tick)
@@ -53,19 +61,29 @@ impl<P: Protocol> Plugin for InputPlugin<P> {
     fn build(&self, app: &mut App) {
         // EVENTS
         app.add_event::<InputEvent<P::Input, ClientId>>();
+        // RESOURCES
+        app.init_resource::<InputValidators<P::Input>>();
         // SETS
+        app.configure_sets(
+            PreUpdate,
+            InputSystemSet::ValidateInputs.after(MainSet::ReceiveFlush),
+        );
         app.configure_sets(
             FixedUpdate,
             (
                 FixedUpdateSet::TickUpdate,
-                InputSystemSet::WriteInputEvents,
+                InputSystemSet::WriteInputEvents.run_if(not(is_paused)),
                 FixedUpdateSet::Main,
                 InputSystemSet::ClearInputEvents,
             )
                 .chain(),
         );
 
-        // insert the input buffer resource
+        // SYSTEMS
+        app.add_systems(
+            PreUpdate,
+            validate_input_messages::<P>.in_set(InputSystemSet::ValidateInputs),
+        );
         app.add_systems(
             FixedUpdate,
             write_input_event::<P>.in_set(InputSystemSet::WriteInputEvents),
@@ -78,6 +96,57 @@ impl<P: Protocol> Plugin for InputPlugin<P> {
     }
 }
 
+/// Validate the input messages received from the clients, and add the valid inputs to the input buffers
+fn validate_input_messages<P: Protocol>(
+    config: Res<ServerConfig>,
+    time: Res<Time<Real>>,
+    tick_manager: Res<TickManager>,
+    validators: Res<InputValidators<P::Input>>,
+    mut connection_manager: ResMut<ConnectionManager<P>>,
+    mut suspicious_client_events: EventWriter<SuspiciousClient>,
+) {
+    let now = time.elapsed();
+    for (client_id, connection) in connection_manager.connections.iter_mut() {
+        for mut message in std::mem::take(&mut connection.pending_input_messages) {
+            if let Err(reason) = connection.input_validation.check_message(
+                &config.input_validation,
+                message.end_tick,
+                tick_manager.as_ref(),
+                now,
+            ) {
+                debug!(?client_id, ?reason, end_tick = ?message.end_tick, "dropping input message");
+                suspicious_client_events.send(SuspiciousClient {
+                    client_id: *client_id,
+                    reason,
+                    violations: connection.input_validation.violations,
+                });
+                continue;
+            }
+            let mut rejected_ticks = vec![];
+            message.retain_inputs(|tick, input| {
+                let valid = validators.validate(*client_id, tick, input) == InputValidation::Accept;
+                if !valid {
+                    rejected_ticks.push(tick);
+                }
+                valid
+            });
+            // inputs are resent in several messages, only report the ones we didn't reject before
+            if connection
+                .input_validation
+                .record_rejected_inputs(rejected_ticks)
+            {
+                debug!(?client_id, end_tick = ?message.end_tick, "rejected client input");
+                suspicious_client_events.send(SuspiciousClient {
+                    client_id: *client_id,
+                    reason: SuspicionReason::RejectedInput,
+                    violations: connection.input_validation.violations,
+                });
+            }
+            connection.input_buffer.update_from_message(message);
+        }
+    }
+}
+
 // Create a system that reads from the input buffer and returns the inputs of all clients for the current tick.
 // The only tricky part is that events are cleared every frame, but we want to clear every tick instead
 // Do it in this system because we want an input for every tick
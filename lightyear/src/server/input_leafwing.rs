@@ -5,14 +5,22 @@ use bevy::prelude::*;
 use leafwing_input_manager::prelude::*;
 
 use crate::connection::events::IterInputMessageEvent;
-use crate::inputs::leafwing::input_buffer::{ActionDiffBuffer, InputBuffer, InputTarget};
+use crate::inputs::leafwing::input_buffer::{
+    ActionDiff, ActionDiffBuffer, InputBuffer, InputTarget,
+};
 use crate::inputs::leafwing::{InputMessage, LeafwingUserAction};
 use crate::prelude::{MainSet, TickManager};
 use crate::protocol::Protocol;
+use crate::server::config::ServerConfig;
 use crate::server::connection::ConnectionManager;
 use crate::server::events::InputMessageEvent;
+use crate::server::input_validation::{
+    InputValidation, InputValidators, SuspicionReason, SuspiciousClient,
+};
 use crate::server::resource::Server;
+use crate::shared::replication::net_entity::NetEntity;
 use crate::shared::sets::FixedUpdateSet;
+use crate::shared::tick_manager::is_paused;
 
 pub struct LeafwingInputPlugin<P: Protocol, A: LeafwingUserAction> {
     protocol_marker: std::marker::PhantomData<P>,
@@ -57,6 +65,7 @@ where
         // EVENTS
         app.add_event::<InputMessageEvent<A>>();
         // RESOURCES
+        app.init_resource::<InputValidators<ActionDiff<A>>>();
         // app.init_resource::<GlobalActions<A>>();
         // TODO: add a resource tracking the action-state of all clients
         // PLUGINS
@@ -67,7 +76,7 @@ where
             FixedUpdate,
             (
                 FixedUpdateSet::TickUpdate,
-                InputSystemSet::Update,
+                InputSystemSet::Update.run_if(not(is_paused)),
                 FixedUpdateSet::Main,
             )
                 .chain(),
@@ -122,20 +131,85 @@ fn add_action_diff_buffer<A: LeafwingUserAction>(
 
 fn update_action_diff_buffers<P: Protocol, A: LeafwingUserAction>(
     // mut global: Option<ResMut<ActionDiffBuffer<A>>>,
+    config: Res<ServerConfig>,
+    time: Res<Time<Real>>,
+    tick_manager: Res<TickManager>,
+    validators: Res<InputValidators<ActionDiff<A>>>,
     mut connection_manager: ResMut<ConnectionManager<P>>,
+    mut suspicious_client_events: EventWriter<SuspiciousClient>,
     // TODO: currently we do not handle entities that are controlled by multiple clients
     mut query: Query<&mut ActionDiffBuffer<A>>,
 ) where
     P::Message: TryInto<InputMessage<A>, Error = ()>,
 {
+    let now = time.elapsed();
     // let manager = &mut server.connection_manager;
-    for (mut message, client_id) in connection_manager.events.into_iter_input_messages::<A>() {
+    let messages: Vec<_> = connection_manager
+        .events
+        .into_iter_input_messages::<A>()
+        .collect();
+    for (mut message, client_id) in messages {
         debug!(action = ?A::short_type_path(), ?message.end_tick, ?message.diffs, "received input message");
+        // validate the input message before using it
+        let Ok(connection) = connection_manager.connection_mut(client_id) else {
+            continue;
+        };
+        if let Err(reason) = connection.input_validation.check_message(
+            &config.input_validation,
+            message.end_tick,
+            tick_manager.as_ref(),
+            now,
+        ) {
+            debug!(?client_id, ?reason, end_tick = ?message.end_tick, "dropping input message");
+            suspicious_client_events.send(SuspiciousClient {
+                client_id,
+                reason,
+                violations: connection.input_validation.violations,
+            });
+            continue;
+        }
+        let mut rejected_ticks = vec![];
+        message.retain_diffs(|tick, diff| {
+            let valid = validators.validate(client_id, tick, diff) == InputValidation::Accept;
+            if !valid {
+                rejected_ticks.push(tick);
+            }
+            valid
+        });
+        // inputs are resent in several messages, only report the ones we didn't reject before
+        if connection
+            .input_validation
+            .record_rejected_inputs(rejected_ticks)
+        {
+            debug!(?client_id, end_tick = ?message.end_tick, "rejected client input");
+            suspicious_client_events.send(SuspiciousClient {
+                client_id,
+                reason: SuspicionReason::RejectedInput,
+                violations: connection.input_validation.violations,
+            });
+        }
 
         for (target, diffs) in std::mem::take(&mut message.diffs) {
+            let target = match target {
+                // for non-pre predicted entities, the client sends the NetEntity that we allocated for the entity
+                InputTarget::Entity(entity) => {
+                    let Some(entity) = connection
+                        .replication_sender
+                        .net_entities
+                        .get_local(NetEntity::from_placeholder(entity))
+                    else {
+                        info!(
+                            ?entity,
+                            "received input message for unrecognized net entity"
+                        );
+                        continue;
+                    };
+                    InputTarget::Entity(entity)
+                }
+                target => target,
+            };
             match target {
                 // for pre-predicted entities, we already did the mapping on server side upon receiving the message
-                // for non-pre predicted entities, the mapping was already done on client side
                 InputTarget::Entity(entity) | InputTarget::PrePredictedEntity(entity) => {
                     debug!("received input for entity: {:?}", entity);
                     if let Ok(mut buffer) = query.get_mut(entity) {
@@ -270,14 +344,7 @@ mod tests {
             .is_some());
 
         // check that the entity is replicated, including the ActionState component
-        let client_entity = *stepper
-            .client_app
-            .world
-            .resource::<Connection>()
-            .replication_receiver
-            .remote_entity_map
-            .get_local(server_entity)
-            .unwrap();
+        let client_entity = stepper.client_entity(server_entity).unwrap();
         assert!(stepper
             .client_app
             .world
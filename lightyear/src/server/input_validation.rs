@@ -0,0 +1,435 @@
+//! Validate the inputs sent by clients before they are used by the server
+//!
+//! Clients are not trusted: a modified client could send inputs that are impossible in the game (e.g. moving too fast),
+//! flood the server with input messages, or send inputs for ticks that are far away from the current server tick.
+//!
+//! - users can register validators with [`InputValidators`] to reject or clamp inputs for each client
+//! - input floods and inputs outside of the expected tick window are detected and dropped
+//! - every violation emits a [`SuspiciousClient`] event, and the client can be kicked once it accumulated
+//!   too many violations (see [`InputValidationConfig::kick_threshold`])
+use bevy::prelude::{EventReader, Res, ResMut, Resource};
+use bevy::utils::Duration;
+use tracing::{error, info};
+
+use crate::netcode::ClientId;
+use crate::prelude::{Io, Tick, TickManager};
+use crate::protocol::Protocol;
+use crate::server::config::ServerConfig;
+use crate::server::connection::ConnectionManager;
+
+/// Result of an input validator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputValidation {
+    /// The input is valid (it might have been modified by the validator, for example clamped)
+    Accept,
+    /// The input is invalid and will be discarded
+    Reject,
+}
+
+type InputValidator<A> = Box<dyn Fn(ClientId, Tick, &mut A) -> InputValidation + Send + Sync>;
+
+/// Validators that are run on every input received from a client.
+///
+/// A validator receives the id of the client, the tick of the input and a mutable reference to the input,
+/// so it can either clamp the input in place or reject it.
+///
+/// Clients re-send their inputs for the last few ticks in every input message (to compensate for packet loss),
+/// so a validator can be called several times for the same input.
+///
+/// For native inputs, `A` is the protocol's input type; for leafwing inputs, `A` is [`ActionDiff<A>`](crate::inputs::leafwing::input_buffer::ActionDiff).
+#[derive(Resource)]
+pub struct InputValidators<A> {
+    validators: Vec<InputValidator<A>>,
+}
+
+impl<A> Default for InputValidators<A> {
+    fn default() -> Self {
+        Self {
+            validators: Vec::new(),
+        }
+    }
+}
+
+impl<A> InputValidators<A> {
+    /// Register a new validator
+    pub fn add(
+        &mut self,
+        validator: impl Fn(ClientId, Tick, &mut A) -> InputValidation + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    /// Run all the validators on the input. The input is rejected as soon as one validator rejects it.
+    pub(crate) fn validate(
+        &self,
+        client_id: ClientId,
+        tick: Tick,
+        input: &mut A,
+    ) -> InputValidation {
+        for validator in &self.validators {
+            if validator(client_id, tick, input) == InputValidation::Reject {
+                return InputValidation::Reject;
+            }
+        }
+        InputValidation::Accept
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InputValidationConfig {
+    /// Input messages whose last tick is further ahead of the server tick than this duration are dropped
+    pub max_time_ahead: Duration,
+    /// Input messages whose last tick is further behind the server tick than this duration are dropped
+    pub max_time_behind: Duration,
+    /// Maximum number of input messages that a client can send per second.
+    /// Any additional input message is dropped.
+    pub max_messages_per_second: u32,
+    /// If set, clients are disconnected once their total number of violations reaches this value
+    pub kick_threshold: Option<u32>,
+}
+
+impl Default for InputValidationConfig {
+    fn default() -> Self {
+        Self {
+            max_time_ahead: Duration::from_secs(1),
+            max_time_behind: Duration::from_secs(1),
+            max_messages_per_second: 300,
+            kick_threshold: None,
+        }
+    }
+}
+
+impl InputValidationConfig {
+    pub fn with_max_time_ahead(mut self, max_time_ahead: Duration) -> Self {
+        self.max_time_ahead = max_time_ahead;
+        self
+    }
+
+    pub fn with_max_time_behind(mut self, max_time_behind: Duration) -> Self {
+        self.max_time_behind = max_time_behind;
+        self
+    }
+
+    pub fn with_max_messages_per_second(mut self, max_messages_per_second: u32) -> Self {
+        self.max_messages_per_second = max_messages_per_second;
+        self
+    }
+
+    pub fn with_kick_threshold(mut self, kick_threshold: u32) -> Self {
+        self.kick_threshold = Some(kick_threshold);
+        self
+    }
+}
+
+/// Number of input violations committed by a client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputViolations {
+    /// Number of inputs that were rejected by a validator
+    pub rejected_inputs: u32,
+    /// Number of input messages that were dropped because the client sent too many input messages
+    pub floods: u32,
+    /// Number of input messages that were dropped because they were too far from the server tick
+    pub out_of_window: u32,
+}
+
+impl InputViolations {
+    pub fn total(&self) -> u32 {
+        self.rejected_inputs + self.floods + self.out_of_window
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuspicionReason {
+    /// An input was rejected by a validator
+    RejectedInput,
+    /// The client sent too many input messages
+    InputFlood,
+    /// The client sent inputs for ticks too far from the server tick
+    InputOutsideWindow,
+}
+
+/// Event emitted every time a client commits an input violation
+#[derive(bevy::prelude::Event, Debug, Clone, PartialEq)]
+pub struct SuspiciousClient {
+    pub client_id: ClientId,
+    pub reason: SuspicionReason,
+    /// Total violations committed by the client so far (including this one)
+    pub violations: InputViolations,
+}
+
+/// Input validation state for a single client
+#[derive(Debug, Default)]
+pub(crate) struct ClientInputValidation {
+    pub(crate) violations: InputViolations,
+    /// Number of input messages received since the start of the current window
+    messages_in_window: u32,
+    window_start: Option<Duration>,
+    /// Most recent tick for which an input was rejected
+    last_rejected_tick: Option<Tick>,
+}
+
+impl ClientInputValidation {
+    /// Check an input message received at `now` whose last input is for `end_tick`.
+    ///
+    /// Returns the reason why the message should be dropped, if any.
+    pub(crate) fn check_message(
+        &mut self,
+        config: &InputValidationConfig,
+        end_tick: Tick,
+        tick_manager: &TickManager,
+        now: Duration,
+    ) -> Result<(), SuspicionReason> {
+        // flood detection
+        match self.window_start {
+            Some(start) if now.saturating_sub(start) < Duration::from_secs(1) => {}
+            _ => {
+                self.window_start = Some(now);
+                self.messages_in_window = 0;
+            }
+        }
+        self.messages_in_window += 1;
+        if self.messages_in_window > config.max_messages_per_second {
+            self.violations.floods += 1;
+            return Err(SuspicionReason::InputFlood);
+        }
+
+        // tick window
+        let tick_duration = tick_manager.tick_duration().as_secs_f32();
+        let max_ticks_ahead = (config.max_time_ahead.as_secs_f32() / tick_duration) as i32;
+        let max_ticks_behind = (config.max_time_behind.as_secs_f32() / tick_duration) as i32;
+        let delta = (end_tick - tick_manager.tick()) as i32;
+        if delta > max_ticks_ahead || -delta > max_ticks_behind {
+            self.violations.out_of_window += 1;
+            return Err(SuspicionReason::InputOutsideWindow);
+        }
+        Ok(())
+    }
+
+    /// Record the ticks of the inputs that were rejected in an input message.
+    ///
+    /// Clients resend each input in several messages, so every rejected tick is only counted once.
+    /// Returns true if new rejected inputs were recorded.
+    pub(crate) fn record_rejected_inputs(&mut self, mut ticks: Vec<Tick>) -> bool {
+        ticks.sort();
+        ticks.dedup();
+        let last_rejected_tick = self.last_rejected_tick;
+        let mut recorded = false;
+        for tick in ticks
+            .into_iter()
+            .filter(|tick| last_rejected_tick.map_or(true, |last| *tick > last))
+        {
+            self.violations.rejected_inputs += 1;
+            self.last_rejected_tick = Some(tick);
+            recorded = true;
+        }
+        recorded
+    }
+}
+
+/// Disconnect the clients that committed too many input violations
+pub(crate) fn kick_suspicious_clients<P: Protocol>(
+    config: Res<ServerConfig>,
+    connection_manager: Res<ConnectionManager<P>>,
+    mut netcode: ResMut<crate::netcode::Server>,
+    mut io: ResMut<Io>,
+    mut events: EventReader<SuspiciousClient>,
+) {
+    let Some(kick_threshold) = config.input_validation.kick_threshold else {
+        events.clear();
+        return;
+    };
+    for event in events.read() {
+        // the client might have already been kicked by a previous event
+        if event.violations.total() < kick_threshold
+            || connection_manager.connection(event.client_id).is_err()
+        {
+            continue;
+        }
+        info!(client_id = ?event.client_id, violations = ?event.violations, "kicking suspicious client");
+        netcode
+            .disconnect(event.client_id, &mut io)
+            .unwrap_or_else(|e| error!("Error disconnecting suspicious client: {}", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::ManualEventReader;
+    use bevy::prelude::{EventReader, Events, FixedUpdate, IntoSystemConfigs, Res, ResMut};
+    use bevy::utils::Duration;
+
+    use crate::client::input::InputSystemSet;
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::server::events::InputEvent;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_rejected_inputs_counted_once() {
+        let mut validation = ClientInputValidation::default();
+        assert!(validation.record_rejected_inputs(vec![Tick(5), Tick(3)]));
+        // the same inputs are resent in the next message
+        assert!(!validation.record_rejected_inputs(vec![Tick(3), Tick(5)]));
+        assert!(validation.record_rejected_inputs(vec![Tick(5), Tick(6)]));
+        assert_eq!(validation.violations.rejected_inputs, 3);
+    }
+
+    #[derive(Resource, Default)]
+    struct ClientInput(i16);
+
+    #[derive(Resource, Default)]
+    struct ServerInputs(Vec<Option<MyInput>>);
+
+    fn press_input(
+        input: Res<ClientInput>,
+        mut connection: ResMut<ClientConnectionManager>,
+        tick_manager: Res<TickManager>,
+    ) {
+        connection.add_input(MyInput(input.0), tick_manager.tick());
+    }
+
+    fn read_inputs(mut inputs: ResMut<ServerInputs>, mut events: EventReader<InputEvent<MyInput>>) {
+        for event in events.read() {
+            inputs.0.push(event.input().clone());
+        }
+    }
+
+    /// Step the apps for `frames` frames, and return the `SuspiciousClient` events emitted on the server
+    fn step_and_read_events(
+        stepper: &mut BevyStepper,
+        reader: &mut ManualEventReader<SuspiciousClient>,
+        frames: usize,
+    ) -> Vec<SuspiciousClient> {
+        let mut events = vec![];
+        for _ in 0..frames {
+            stepper.frame_step();
+            events.extend(
+                reader
+                    .read(
+                        stepper
+                            .server_app
+                            .world
+                            .resource::<Events<SuspiciousClient>>(),
+                    )
+                    .cloned(),
+            );
+        }
+        events
+    }
+
+    #[test]
+    fn test_input_validation() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: false,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(20),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.client_app.init_resource::<ClientInput>();
+        stepper.client_app.add_systems(
+            FixedUpdate,
+            press_input.in_set(InputSystemSet::BufferInputs),
+        );
+        stepper.server_app.init_resource::<ServerInputs>();
+        stepper
+            .server_app
+            .add_systems(FixedUpdate, read_inputs.in_set(FixedUpdateSet::Main));
+        // reject negative inputs, clamp the others
+        stepper
+            .server_app
+            .world
+            .resource_mut::<InputValidators<MyInput>>()
+            .add(|_, _, input| {
+                if input.0 < 0 {
+                    return InputValidation::Reject;
+                }
+                input.0 = input.0.min(10);
+                InputValidation::Accept
+            });
+        stepper.init();
+        let mut reader = ManualEventReader::<SuspiciousClient>::default();
+
+        // valid inputs are clamped
+        stepper.client_app.world.resource_mut::<ClientInput>().0 = 20;
+        let events = step_and_read_events(&mut stepper, &mut reader, 20);
+        assert!(events.is_empty());
+        assert_eq!(
+            stepper.server_app.world.resource::<ServerInputs>().0.last(),
+            Some(&Some(MyInput(10)))
+        );
+
+        // invalid inputs are rejected
+        stepper.client_app.world.resource_mut::<ClientInput>().0 = -5;
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerInputs>()
+            .0
+            .clear();
+        let events = step_and_read_events(&mut stepper, &mut reader, 20);
+        assert!(!stepper
+            .server_app
+            .world
+            .resource::<ServerInputs>()
+            .0
+            .contains(&Some(MyInput(-5))));
+        assert!(!events.is_empty());
+        assert!(events
+            .iter()
+            .all(|e| e.reason == SuspicionReason::RejectedInput && e.client_id == 111));
+
+        // inputs outside of the tick window are dropped
+        stepper.client_app.world.resource_mut::<ClientInput>().0 = 1;
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConfig>()
+            .input_validation
+            .max_time_ahead = Duration::default();
+        let events = step_and_read_events(&mut stepper, &mut reader, 2);
+        assert!(!events.is_empty());
+        assert!(events
+            .iter()
+            .all(|e| e.reason == SuspicionReason::InputOutsideWindow));
+
+        // clients that flood the server get kicked
+        let violations = events.last().unwrap().violations;
+        assert!(violations.rejected_inputs > 0);
+        assert!(violations.out_of_window > 0);
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConfig>()
+            .input_validation = InputValidationConfig::default()
+            .with_max_messages_per_second(10)
+            .with_kick_threshold(violations.total() + 5);
+        let events = step_and_read_events(&mut stepper, &mut reader, 40);
+        assert!(events
+            .iter()
+            .any(|e| e.reason == SuspicionReason::InputFlood));
+        assert!(stepper
+            .server_app
+            .world
+            .resource::<ConnectionManager<MyProtocol>>()
+            .connection(111)
+            .is_err());
+    }
+}
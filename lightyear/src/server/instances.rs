@@ -0,0 +1,443 @@
+//! Run several independent server instances (for example one lobby and many matches) behind a single socket
+//!
+//! The gateway [`App`] adds the [`InstancesPlugin`], which listens on the network and routes every client to one
+//! of the server instances. Each instance is a separate [`World`], built from an [`App`] with its own
+//! [`ServerPlugin`](crate::server::plugin::ServerPlugin): it has its own tick, replication, rooms, etc.
+//! and only sees the clients that are routed to it.
+//!
+//! Clients can be moved from one instance to another (for example from the lobby to a match) with
+//! [`Instances::move_client`] or from inside an instance with [`InstanceMoves::move_client`].
+//! The client is disconnected from its previous instance and connected to the new one; on the client side,
+//! the connection is reset (the entities replicated by the previous instance are despawned, and the client
+//! syncs again with the new instance) without going through the netcode handshake again.
+//!
+//! To detect these moves, the clients of the gateway must enable
+//! [`NetcodeConfig::session_epochs`](crate::client::config::NetcodeConfig::session_epochs).
+use std::ops::DerefMut;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use bevy::app::PluginsState;
+use bevy::prelude::{App, Main, Plugin as PluginType, PreUpdate, Res, ResMut, Resource, Time};
+use bevy::prelude::{Virtual, World};
+use bevy::utils::{Duration, HashMap};
+use tracing::{debug, error, trace};
+
+use crate::netcode::{ClientId, InstanceLink};
+use crate::server::config::NetcodeConfig;
+use crate::transport::io::Io;
+
+pub type InstanceId = u32;
+
+/// Plugin that routes the clients connected to the gateway to the server instances
+pub struct InstancesPlugin {
+    // we add Mutex<Option> so that we can get ownership of the inner from an immutable reference
+    // in build()
+    config: Mutex<Option<(NetcodeConfig, Io)>>,
+}
+
+impl InstancesPlugin {
+    pub fn new(netcode: NetcodeConfig, io: Io) -> Self {
+        Self {
+            config: Mutex::new(Some((netcode, io))),
+        }
+    }
+}
+
+impl PluginType for InstancesPlugin {
+    fn build(&self, app: &mut App) {
+        let (netcode, io) = self.config.lock().unwrap().take().unwrap();
+        app.insert_resource(Instances::new(netcode, io))
+            .add_systems(PreUpdate, update_instances);
+    }
+}
+
+/// Resource inserted in every instance, to move clients to other instances
+#[derive(Resource, Default, Debug)]
+pub struct InstanceMoves {
+    moves: Vec<(ClientId, InstanceId)>,
+}
+
+impl InstanceMoves {
+    /// Move a client of this instance to another instance, at the end of the current frame
+    pub fn move_client(&mut self, client_id: ClientId, instance: InstanceId) {
+        self.moves.push((client_id, instance));
+    }
+}
+
+/// The server instances running behind the gateway
+#[derive(Resource)]
+pub struct Instances {
+    netcode: crate::netcode::Server,
+    io: Io,
+    instances: HashMap<InstanceId, World>,
+    /// Instance that each connected client is routed to
+    clients: HashMap<ClientId, InstanceId>,
+    /// Instance that newly connected clients are routed to
+    default_instance: Option<InstanceId>,
+}
+
+impl Instances {
+    fn new(netcode: NetcodeConfig, io: Io) -> Self {
+        Self {
+            netcode: crate::netcode::Server::gateway(io.local_addr(), netcode),
+            io,
+            instances: HashMap::default(),
+            clients: HashMap::default(),
+            default_instance: None,
+        }
+    }
+
+    /// Add a server instance.
+    ///
+    /// The `app` must contain the [`ServerPlugin`](crate::server::plugin::ServerPlugin); its [`Io`] is never used
+    /// (for example use a [`TransportConfig::LocalChannel`](crate::transport::io::TransportConfig::LocalChannel)),
+    /// since the clients are routed to the instance by the gateway.
+    pub fn add_instance(&mut self, id: InstanceId, mut app: App) {
+        if app.plugins_state() != PluginsState::Cleaned {
+            app.finish();
+            app.cleanup();
+        }
+        let mut world = std::mem::take(&mut app.world);
+        world.insert_resource(crate::netcode::Server::instance());
+        world.init_resource::<InstanceMoves>();
+        if self.instances.insert(id, world).is_some() {
+            error!(?id, "Replaced an existing server instance");
+        }
+    }
+
+    /// Remove a server instance. The clients that were routed to it are not routed to any instance anymore,
+    /// until they are moved to another instance.
+    pub fn remove_instance(&mut self, id: InstanceId) -> Option<World> {
+        self.clients.retain(|_, instance| *instance != id);
+        if self.default_instance == Some(id) {
+            self.default_instance = None;
+        }
+        self.instances.remove(&id)
+    }
+
+    /// Set the instance that newly connected clients are routed to (for example the lobby)
+    pub fn set_default_instance(&mut self, id: InstanceId) {
+        self.default_instance = Some(id);
+    }
+
+    pub fn instance(&self, id: InstanceId) -> Option<&World> {
+        self.instances.get(&id)
+    }
+
+    pub fn instance_mut(&mut self, id: InstanceId) -> Option<&mut World> {
+        self.instances.get_mut(&id)
+    }
+
+    pub fn instance_ids(&self) -> impl Iterator<Item = InstanceId> + '_ {
+        self.instances.keys().copied()
+    }
+
+    /// Instance that the client is currently routed to
+    pub fn client_instance(&self, client_id: ClientId) -> Option<InstanceId> {
+        self.clients.get(&client_id).copied()
+    }
+
+    /// Move a client to another instance.
+    ///
+    /// The client gets disconnected from its current instance and connected to the new one.
+    pub fn move_client(&mut self, client_id: ClientId, instance: InstanceId) -> Result<()> {
+        if !self.instances.contains_key(&instance) {
+            return Err(anyhow!("server instance {instance} does not exist"));
+        }
+        if let Some(previous) = self.clients.get(&client_id).copied() {
+            if previous == instance {
+                return Ok(());
+            }
+            if let Some(link) = Self::link(&mut self.instances, previous) {
+                link.disconnect(client_id);
+            }
+            // the client needs to reset its connection, and the packets of the previous instance must be dropped
+            self.netcode.next_epoch(client_id)?;
+        }
+        debug!(?client_id, ?instance, "Routing client to server instance");
+        self.clients.insert(client_id, instance);
+        if let Some(link) = Self::link(&mut self.instances, instance) {
+            link.connect(client_id);
+        }
+        Ok(())
+    }
+
+    fn link(
+        instances: &mut HashMap<InstanceId, World>,
+        id: InstanceId,
+    ) -> Option<&mut InstanceLink> {
+        Self::link_of(instances.get_mut(&id)?)
+    }
+
+    fn link_of(world: &mut World) -> Option<&mut InstanceLink> {
+        world
+            .get_resource_mut::<crate::netcode::Server>()?
+            .into_inner()
+            .instance_link_mut()
+    }
+
+    /// Receive the packets from the network, run every instance for one frame, and send their packets
+    fn update(&mut self, delta: Duration) {
+        // an error of the gateway must not stop the instances
+        let context = self
+            .netcode
+            .try_update(delta.as_secs_f64(), &mut self.io)
+            .unwrap_or_else(|e| {
+                error!("Error updating netcode server: {}", e);
+                Default::default()
+            });
+        for client_id in context.connections {
+            match self.default_instance {
+                Some(instance) => self
+                    .move_client(client_id, instance)
+                    .unwrap_or_else(|e| error!("Could not route client {client_id}: {e}")),
+                None => error!(
+                    ?client_id,
+                    "No default server instance to route the client to"
+                ),
+            }
+        }
+        for client_id in context.disconnections {
+            if let Some(instance) = self.clients.remove(&client_id) {
+                if let Some(link) = Self::link(&mut self.instances, instance) {
+                    link.disconnect(client_id);
+                }
+            }
+        }
+
+        // route the received packets to the instances
+        while let Some((payload, client_id)) = self.netcode.recv() {
+            let Some(instance) = self.clients.get(&client_id).copied() else {
+                trace!(?client_id, "Dropping packet from a client without instance");
+                continue;
+            };
+            if let Some(link) = Self::link(&mut self.instances, instance) {
+                link.recv_queue.push_back((payload, client_id));
+            }
+        }
+
+        let mut moves = vec![];
+        for (id, world) in self.instances.iter_mut() {
+            world.run_schedule(Main);
+            world.clear_trackers();
+            moves.append(&mut world.resource_mut::<InstanceMoves>().moves);
+
+            // send the packets of the instance (only to the clients that are still routed to it)
+            let Some(link) = Self::link_of(world) else {
+                continue;
+            };
+            for (payload, client_id) in std::mem::take(&mut link.send_queue) {
+                if self.clients.get(&client_id) != Some(id) {
+                    continue;
+                }
+                self.netcode
+                    .send(payload.as_slice(), client_id, &mut self.io)
+                    .unwrap_or_else(|e| error!("Error sending packet: {}", e));
+            }
+        }
+
+        for (client_id, instance) in moves {
+            self.move_client(client_id, instance)
+                .unwrap_or_else(|e| error!("Could not move client {client_id}: {e}"));
+        }
+    }
+}
+
+pub(crate) fn update_instances(mut instances: ResMut<Instances>, time: Res<Time<Virtual>>) {
+    instances.deref_mut().update(time.delta());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+
+    use bevy::prelude::Real;
+    use bevy::time::TimeUpdateStrategy;
+    use bevy::MinimalPlugins;
+
+    use crate::netcode::generate_key;
+    use crate::prelude::client::{
+        Authentication, ClientConfig, InputConfig, InterpolationConfig, PredictionConfig,
+        SyncConfig,
+    };
+    use crate::prelude::server::{ServerConfig, ServerPlugin};
+    use crate::prelude::*;
+    use crate::tests::protocol::*;
+
+    use super::*;
+
+    const LOBBY: InstanceId = 0;
+    const MATCH: InstanceId = 1;
+
+    fn instance_app(shared_config: SharedConfig, value: f32) -> App {
+        let (send, recv) = crossbeam_channel::unbounded();
+        let io = IoConfig::from_transport(TransportConfig::LocalChannel { recv, send }).get_io();
+        let config = ServerConfig {
+            shared: shared_config,
+            ..Default::default()
+        };
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(ServerPlugin::new(server::PluginConfig::new(
+                config,
+                io,
+                protocol(),
+            )));
+        app.world.spawn((Component1(value), Replicate::default()));
+        app
+    }
+
+    fn client_values(client_app: &mut App) -> Vec<f32> {
+        client_app
+            .world
+            .query::<&Component1>()
+            .iter(&client_app.world)
+            .map(|c| c.0)
+            .collect()
+    }
+
+    fn is_connected(instances: &Instances, id: InstanceId, client_id: ClientId) -> bool {
+        instances
+            .instance(id)
+            .unwrap()
+            .resource::<ServerConnectionManager>()
+            .connection(client_id)
+            .is_ok()
+    }
+
+    #[test]
+    fn test_move_client_between_instances() {
+        let frame_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: true,
+            tick: TickConfig::new(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let addr = SocketAddr::from_str("127.0.0.1:0").unwrap();
+        let (from_server_send, from_server_recv) = crossbeam_channel::unbounded();
+        let (to_server_send, to_server_recv) = crossbeam_channel::unbounded();
+        let client_io = IoConfig::from_transport(TransportConfig::LocalChannel {
+            send: to_server_send,
+            recv: from_server_recv,
+        })
+        .get_io();
+        let gateway_io = IoConfig::from_transport(TransportConfig::Channels {
+            channels: vec![(addr, to_server_recv, from_server_send)],
+        })
+        .get_io();
+        let private_key = generate_key();
+        let client_id = 111;
+
+        // gateway with a lobby and a match instance, each with its own entity
+        let mut gateway_app = App::new();
+        gateway_app
+            .add_plugins(MinimalPlugins)
+            .add_plugins(InstancesPlugin::new(
+                NetcodeConfig::default().with_key(private_key),
+                gateway_io,
+            ));
+        let mut instances = gateway_app.world.resource_mut::<Instances>();
+        instances.add_instance(LOBBY, instance_app(shared_config.clone(), 1.0));
+        instances.add_instance(MATCH, instance_app(shared_config.clone(), 2.0));
+        instances.set_default_instance(LOBBY);
+
+        let mut client_app = App::new();
+        let config = ClientConfig {
+            shared: shared_config,
+            input: InputConfig::default(),
+            netcode: client::NetcodeConfig {
+                session_epochs: true,
+                ..Default::default()
+            },
+            ping: PingConfig::default(),
+            sync: SyncConfig::default(),
+            prediction: PredictionConfig::default(),
+            interpolation: InterpolationConfig::default(),
+        };
+        let auth = Authentication::Manual {
+            server_addr: addr,
+            protocol_id: 0,
+            private_key,
+            client_id,
+        };
+        client_app
+            .add_plugins(MinimalPlugins)
+            .add_plugins(client::ClientPlugin::new(client::PluginConfig::new(
+                config,
+                client_io,
+                protocol(),
+                auth,
+            )));
+
+        let mut current_time = bevy::utils::Instant::now();
+        client_app
+            .world
+            .resource_mut::<Time<Real>>()
+            .update_with_instant(current_time);
+        gateway_app
+            .world
+            .resource_mut::<Time<Real>>()
+            .update_with_instant(current_time);
+        for id in [LOBBY, MATCH] {
+            gateway_app
+                .world
+                .resource_mut::<Instances>()
+                .instance_mut(id)
+                .unwrap()
+                .resource_mut::<Time<Real>>()
+                .update_with_instant(current_time);
+        }
+        let mut frame_step = |client_app: &mut App, gateway_app: &mut App| {
+            current_time += frame_duration;
+            client_app.insert_resource(TimeUpdateStrategy::ManualInstant(current_time));
+            gateway_app.insert_resource(TimeUpdateStrategy::ManualInstant(current_time));
+            let mut instances = gateway_app.world.resource_mut::<Instances>();
+            for id in [LOBBY, MATCH] {
+                instances
+                    .instance_mut(id)
+                    .unwrap()
+                    .insert_resource(TimeUpdateStrategy::ManualInstant(current_time));
+            }
+            mock_instant::MockClock::advance(frame_duration);
+            client_app.update();
+            gateway_app.update();
+        };
+
+        client_app
+            .world
+            .resource_mut::<crate::netcode::Client>()
+            .connect();
+        for _ in 0..100 {
+            frame_step(&mut client_app, &mut gateway_app);
+        }
+        // the client is only connected to the lobby
+        assert_eq!(client_values(&mut client_app), vec![1.0]);
+        let instances = gateway_app.world.resource::<Instances>();
+        assert_eq!(instances.client_instance(client_id), Some(LOBBY));
+        assert!(is_connected(instances, LOBBY, client_id));
+        assert!(!is_connected(instances, MATCH, client_id));
+
+        // the lobby moves the client to the match
+        gateway_app
+            .world
+            .resource_mut::<Instances>()
+            .instance_mut(LOBBY)
+            .unwrap()
+            .resource_mut::<InstanceMoves>()
+            .move_client(client_id, MATCH);
+        for _ in 0..100 {
+            frame_step(&mut client_app, &mut gateway_app);
+        }
+        assert_eq!(client_values(&mut client_app), vec![2.0]);
+        assert!(client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .is_synced());
+        let instances = gateway_app.world.resource::<Instances>();
+        assert_eq!(instances.client_instance(client_id), Some(MATCH));
+        assert!(!is_connected(instances, LOBBY, client_id));
+        assert!(is_connected(instances, MATCH, client_id));
+    }
+}
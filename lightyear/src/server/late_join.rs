@@ -0,0 +1,369 @@
+//! Stream the initial state of the world to newly connected clients
+//!
+//! When a client connects, every entity that should be replicated to it has to be spawned on the client.
+//! Instead of sending all of them in one burst (which can flood the reliable channel on big worlds), the server
+//! can spread the initial state over several send intervals, sending the most relevant entities first.
+//!
+//! The client is notified of the loading progress, see
+//! [`Client::world_load_progress`](crate::client::resource::Client::world_load_progress) and
+//! [`WorldLoadedEvent`](crate::client::events::WorldLoadedEvent).
+use std::cmp::Ordering;
+
+use bevy::prelude::{Entity, Mut, World};
+use bevy::utils::EntityHashSet;
+use tracing::{debug, trace};
+
+use crate::netcode::ClientId;
+use crate::protocol::Protocol;
+use crate::server::config::ServerConfig;
+use crate::server::connection::ConnectionManager;
+use crate::server::room::RoomManager;
+use crate::shared::replication::components::ReplicationMode;
+use crate::shared::replication::LateJoinMessage;
+
+/// Function used to prioritize the entities sent to a newly connected client.
+/// Entities with a higher priority are sent first.
+pub type LateJoinPriority = fn(&World, ClientId, Entity) -> f32;
+
+#[derive(Clone, Default)]
+pub struct LateJoinConfig {
+    /// Maximum number of entities whose initial state is sent to a newly connected client per send interval.
+    /// If `None`, the entire world is sent at once.
+    pub entities_per_send: Option<usize>,
+    /// Optional function used to prioritize the entities (for example by distance to the client's player).
+    /// Entities that share a room with the client are always sent first.
+    pub priority: Option<LateJoinPriority>,
+}
+
+impl LateJoinConfig {
+    pub fn with_entities_per_send(mut self, entities_per_send: usize) -> Self {
+        self.entities_per_send = Some(entities_per_send);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: LateJoinPriority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+}
+
+/// Keeps track of the entities that still need to be sent to a newly connected client
+#[derive(Debug)]
+pub(crate) struct LateJoinState {
+    /// Entities that have not been sent yet, sorted by increasing priority
+    queue: Vec<Entity>,
+    /// Same entities as `queue`, for fast lookups
+    pending: EntityHashSet<Entity>,
+    sent: u32,
+    total: u32,
+    /// True if the progress changed since we last notified the client
+    dirty: bool,
+}
+
+impl LateJoinState {
+    pub(crate) fn new(queue: Vec<Entity>) -> Self {
+        Self {
+            pending: queue.iter().copied().collect(),
+            total: queue.len() as u32,
+            queue,
+            sent: 0,
+            dirty: true,
+        }
+    }
+
+    /// Returns true if the initial state of the entity has not been sent to the client yet
+    pub(crate) fn is_pending(&self, entity: Entity) -> bool {
+        self.pending.contains(&entity)
+    }
+
+    /// Pop the next entities to send (the ones with the highest priority)
+    pub(crate) fn next_batch(&mut self, max_entities: Option<usize>) -> Vec<Entity> {
+        let len = max_entities.map_or(self.queue.len(), |max| max.min(self.queue.len()));
+        let batch = self.queue.split_off(self.queue.len() - len);
+        for entity in &batch {
+            self.pending.remove(entity);
+        }
+        self.sent += batch.len() as u32;
+        self.dirty |= !batch.is_empty();
+        batch
+    }
+
+    /// Return the progress message to send to the client, if the progress changed
+    pub(crate) fn take_message(&mut self) -> Option<LateJoinMessage> {
+        if !std::mem::take(&mut self.dirty) {
+            return None;
+        }
+        Some(LateJoinMessage {
+            sent: self.sent,
+            total: self.total,
+        })
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.queue.is_empty() && !self.dirty
+    }
+}
+
+/// Select the entities whose initial state will be sent to the loading clients during this send interval
+pub(crate) fn prepare_late_join<P: Protocol>(world: &mut World) {
+    world.resource_scope(
+        |world: &mut World, mut manager: Mut<ConnectionManager<P>>| {
+            let manager = &mut *manager;
+            let config = &world.resource::<ServerConfig>().late_join;
+            let room_manager = world.resource::<RoomManager>();
+            manager.late_join_batch.clear();
+
+            // start loading the world for the newly connected clients
+            for client_id in std::mem::take(&mut manager.new_clients) {
+                // entities in Room mode are only sent if the client is already in one of their rooms;
+                // the other ones are replicated when the client joins their room
+                let mut entities: Vec<(bool, f32, Entity)> = manager
+                    .replicate_component_cache
+                    .iter()
+                    .filter(|(entity, replicate)| {
+                        replicate.replication_target.should_send_to(&client_id)
+                            && match replicate.replication_mode {
+                                ReplicationMode::NetworkTarget => true,
+                                ReplicationMode::Room => {
+                                    room_manager.shares_room(client_id, **entity)
+                                }
+                            }
+                    })
+                    .map(|(entity, _)| {
+                        let priority = config
+                            .priority
+                            .map_or(0.0, |priority| priority(world, client_id, *entity));
+                        (
+                            room_manager.shares_room(client_id, *entity),
+                            priority,
+                            *entity,
+                        )
+                    })
+                    .collect();
+                entities.sort_by(|a, b| {
+                    (a.0, a.1)
+                        .partial_cmp(&(b.0, b.1))
+                        .unwrap_or(Ordering::Equal)
+                });
+                debug!(
+                    ?client_id,
+                    num_entities = entities.len(),
+                    "Start sending the initial world state"
+                );
+                manager.late_join.insert(
+                    client_id,
+                    LateJoinState::new(entities.into_iter().map(|(_, _, e)| e).collect()),
+                );
+            }
+
+            for (client_id, state) in manager.late_join.iter_mut() {
+                for entity in state.next_batch(config.entities_per_send) {
+                    // the entity might have been despawned since the client connected
+                    let Some(replicate) = manager.replicate_component_cache.get(&entity) else {
+                        continue;
+                    };
+                    // or the client might have left the entity's rooms
+                    if replicate.replication_mode == ReplicationMode::Room
+                        && !room_manager.shares_room(*client_id, entity)
+                    {
+                        continue;
+                    }
+                    trace!(?client_id, ?entity, "Send initial entity state");
+                    manager
+                        .late_join_batch
+                        .entry(entity)
+                        .or_default()
+                        .push(*client_id);
+                }
+            }
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::ManualEventReader;
+    use bevy::prelude::Events;
+    use bevy::utils::Duration;
+
+    use crate::client::events::WorldLoadedEvent;
+    use crate::prelude::client::*;
+    use crate::prelude::*;
+    use crate::server::room::RoomId;
+    use crate::tests::protocol::*;
+    use crate::tests::stepper::{BevyStepper, Step};
+
+    use super::*;
+
+    #[test]
+    fn test_late_join_streaming() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: true,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConfig>()
+            .late_join = LateJoinConfig::default()
+            .with_entities_per_send(2)
+            .with_priority(|world, _, entity| world.get::<Component1>(entity).unwrap().0);
+        for i in 0..5 {
+            stepper
+                .server_app
+                .world
+                .spawn((Component1(i as f32), Replicate::default()));
+        }
+
+        stepper
+            .client_app
+            .world
+            .resource_mut::<crate::netcode::Client>()
+            .connect();
+        let mut batches = vec![];
+        let mut world_loaded_events = 0;
+        let mut reader = ManualEventReader::<WorldLoadedEvent>::default();
+        for _ in 0..100 {
+            stepper.frame_step();
+            let server_world = &stepper.server_app.world;
+            let mut batch: Vec<f32> = server_world
+                .resource::<ServerConnectionManager>()
+                .late_join_batch
+                .keys()
+                .map(|entity| server_world.get::<Component1>(*entity).unwrap().0)
+                .collect();
+            batch.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            if !batch.is_empty() && batches.last() != Some(&batch) {
+                batches.push(batch);
+            }
+            world_loaded_events += reader
+                .read(
+                    stepper
+                        .client_app
+                        .world
+                        .resource::<Events<WorldLoadedEvent>>(),
+                )
+                .count();
+        }
+        // the entities are streamed 2 by 2, the ones with the highest priority first
+        assert_eq!(batches, vec![vec![3.0, 4.0], vec![1.0, 2.0], vec![0.0]]);
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .query::<&Component1>()
+                .iter(&stepper.client_app.world)
+                .count(),
+            5
+        );
+        assert_eq!(world_loaded_events, 1);
+        assert_eq!(
+            stepper
+                .client_app
+                .world
+                .resource::<ClientConnectionManager>()
+                .world_load_progress(),
+            Some(1.0)
+        );
+        assert!(stepper
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>()
+            .late_join
+            .is_empty());
+    }
+
+    #[test]
+    fn test_late_join_room() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: true,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(0),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default(),
+            PredictionConfig::default(),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        let client_id = stepper.client_id;
+        // the client is put in the entity's room before it connects
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(1.0),
+                Replicate {
+                    replication_mode: ReplicationMode::Room,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        // an entity in a room that the client is not in
+        let other_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(2.0),
+                Replicate {
+                    replication_mode: ReplicationMode::Room,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        {
+            let mut room_manager = stepper.server_app.world.resource_mut::<RoomManager>();
+            let mut room = room_manager.room_mut(RoomId(0));
+            room.add_client(client_id);
+            room.add_entity(server_entity);
+            room_manager.room_mut(RoomId(1)).add_entity(other_entity);
+        }
+        for _ in 0..5 {
+            stepper.frame_step();
+        }
+
+        stepper
+            .client_app
+            .world
+            .resource_mut::<crate::netcode::Client>()
+            .connect();
+        for _ in 0..100 {
+            stepper.frame_step();
+        }
+
+        // only the entity that shares a room with the client is replicated
+        let client_values: Vec<f32> = stepper
+            .client_app
+            .world
+            .query::<&Component1>()
+            .iter(&stepper.client_app.world)
+            .map(|c| c.0)
+            .collect();
+        assert_eq!(client_values, vec![1.0]);
+    }
+}
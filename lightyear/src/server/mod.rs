@@ -11,8 +11,16 @@ pub mod events;
 
 mod input;
 
+pub mod input_validation;
+
+pub mod instances;
+
+pub mod late_join;
+
 pub mod plugin;
 
+pub mod recording;
+
 pub mod resource;
 
 pub mod room;
@@ -17,9 +17,15 @@ use crate::protocol::message::MessageProtocol;
 use crate::protocol::Protocol;
 use crate::server::connection::replication_clean;
 use crate::server::connection::ConnectionManager;
-use crate::server::events::{ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent};
+use crate::server::events::{
+    BlobReceivedEvent, ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent,
+    MessageAckedEvent, MessageExpiredEvent, MessageLostEvent,
+};
 use crate::server::input::InputPlugin;
+use crate::server::input_validation::{kick_suspicious_clients, SuspiciousClient};
+use crate::server::late_join::prepare_late_join;
 use crate::server::prediction::compute_hash;
+use crate::server::recording::record_snapshot;
 use crate::server::resource::Server;
 use crate::server::room::RoomPlugin;
 use crate::server::systems::clear_events;
@@ -73,6 +79,7 @@ impl<P: Protocol> PluginType for ServerPlugin<P> {
         );
 
         let tick_duration = config.server_config.shared.tick.tick_duration;
+        let compression = config.server_config.shared.compression.clone();
         // TODO: have better constants for clean_interval?
         let clean_interval = tick_duration * (i16::MAX as u32 / 3);
 
@@ -100,6 +107,7 @@ impl<P: Protocol> PluginType for ServerPlugin<P> {
             .insert_resource(netserver)
             .insert_resource(ConnectionManager::<P>::new(
                 config.protocol.channel_registry().clone(),
+                compression,
             ))
             .insert_resource(config.protocol)
             // .insert_resource(server)
@@ -146,6 +154,11 @@ impl<P: Protocol> PluginType for ServerPlugin<P> {
             .add_event::<DisconnectEvent>()
             .add_event::<EntitySpawnEvent>()
             .add_event::<EntityDespawnEvent>()
+            .add_event::<BlobReceivedEvent>()
+            .add_event::<MessageAckedEvent>()
+            .add_event::<MessageLostEvent>()
+            .add_event::<MessageExpiredEvent>()
+            .add_event::<SuspiciousClient>()
             // SYSTEMS //
             .add_systems(
                 PreUpdate,
@@ -158,7 +171,19 @@ impl<P: Protocol> PluginType for ServerPlugin<P> {
                 PostUpdate,
                 (
                     compute_hash::<P>.in_set(ReplicationSet::SetPreSpawnedHash),
+                    // select the entities to send to the newly connected clients before replicating
+                    prepare_late_join::<P>
+                        .in_set(MainSet::Send)
+                        .before(ReplicationSet::SendEntityUpdates)
+                        .before(ReplicationSet::SendComponentUpdates),
+                    // record the entities that existed before the recording started
+                    record_snapshot::<P>
+                        .in_set(MainSet::Send)
+                        .before(ReplicationSet::SendEntityUpdates)
+                        .before(ReplicationSet::SendComponentUpdates),
                     send::<P>.in_set(MainSet::SendPackets),
+                    // disconnect the suspicious clients after the packets of this frame have been sent
+                    kick_suspicious_clients::<P>.after(MainSet::SendPackets),
                     clear_events::<P>.in_set(MainSet::ClearEvents),
                 ),
             )
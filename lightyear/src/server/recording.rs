@@ -0,0 +1,309 @@
+//! Record the replication stream sent by the server to a file, so that it can be played back later
+//! (for match replays or killcams). See [`PlaybackPlugin`](crate::client::playback::PlaybackPlugin)
+//! to play the recording back.
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use bevy::ecs::component::Tick as BevyTick;
+use bevy::prelude::{Entity, EntityRef, Mut, World};
+use bevy::utils::{Duration, EntityHashMap, EntityHashSet};
+use tracing::trace;
+
+use crate::_reexport::FromType;
+use crate::netcode::ClientId;
+use crate::prelude::{NetworkTarget, PreSpawnedPlayerObject, Tick};
+use crate::protocol::component::ComponentProtocol;
+use crate::protocol::Protocol;
+use crate::server::connection::ConnectionManager;
+use crate::server::room::ClientVisibility;
+use crate::shared::replication::components::{
+    Replicate, ReplicationGroup, ReplicationMode, ShouldBeInterpolated, ShouldBePredicted,
+};
+use crate::shared::replication::recording::{RecordedFrame, RecordingWriter};
+use crate::shared::replication::send::ReplicationSender;
+use crate::shared::replication::{ReplicationMessage, ReplicationMessageData};
+
+/// From whose point of view the replication stream is recorded
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordingPerspective {
+    /// Record every replicated entity, regardless of replication targets.
+    /// Entities that use [`ReplicationMode::Room`](crate::prelude::ReplicationMode::Room) are recorded
+    /// once they are visible to at least one client.
+    Spectator,
+    /// Record only what is replicated to this client
+    Client(ClientId),
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordingConfig {
+    /// File where the recording will be written
+    pub path: PathBuf,
+    pub perspective: RecordingPerspective,
+}
+
+impl RecordingConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            perspective: RecordingPerspective::Spectator,
+        }
+    }
+
+    pub fn with_perspective(mut self, perspective: RecordingPerspective) -> Self {
+        self.perspective = perspective;
+        self
+    }
+}
+
+/// Records the replication stream of the server.
+///
+/// The recorder acts as an additional remote that never loses packets: it has its own [`ReplicationSender`]
+/// so that the recorded messages do not depend on the acks of any real client.
+/// All recorded entities get the [`ShouldBeInterpolated`] component, so that the recording can be played back
+/// with the interpolation pipeline.
+///
+/// Entities that were replicated before the recording started are captured with their current components
+/// in the first recorded frame (see [`record_snapshot`]).
+pub(crate) struct ReplicationRecorder<P: Protocol> {
+    perspective: RecordingPerspective,
+    replication_sender: ReplicationSender<P>,
+    writer: RecordingWriter,
+    /// Entities that are currently spawned in the recording
+    recorded_entities: EntityHashSet<Entity>,
+    /// Inserts received for entities that are not spawned in the recording yet. The entity spawn and component
+    /// insert systems are not ordered, so the spawn can arrive after the inserts during the same tick
+    pending_inserts: EntityHashMap<Entity, Vec<P::Components>>,
+    /// Bevy tick at which we last wrote a frame; we only record component updates that happened after it
+    last_record_tick: Option<BevyTick>,
+    /// True until the entities that were replicated before the recording started have been recorded
+    needs_snapshot: bool,
+}
+
+impl<P: Protocol> ReplicationRecorder<P> {
+    pub(crate) fn new(config: RecordingConfig, tick_duration: Duration) -> Result<Self> {
+        let file = BufWriter::new(File::create(&config.path)?);
+        // the recorder never receives acks
+        let (_, updates_ack_tracker) = crossbeam_channel::unbounded();
+        let (_, actions_ack_tracker) = crossbeam_channel::unbounded();
+        Ok(Self {
+            perspective: config.perspective,
+            replication_sender: ReplicationSender::new(updates_ack_tracker, actions_ack_tracker),
+            writer: RecordingWriter::new(file, tick_duration)?,
+            recorded_entities: EntityHashSet::default(),
+            pending_inserts: EntityHashMap::default(),
+            last_record_tick: None,
+            needs_snapshot: true,
+        })
+    }
+
+    /// Returns true if a replication message with this target should be part of the recording
+    pub(crate) fn should_record(&self, target: &NetworkTarget) -> bool {
+        match &self.perspective {
+            RecordingPerspective::Spectator => true,
+            RecordingPerspective::Client(client_id) => target.should_send_to(client_id),
+        }
+    }
+
+    pub(crate) fn is_spectator(&self) -> bool {
+        self.perspective == RecordingPerspective::Spectator
+    }
+
+    pub(crate) fn record_entity_spawn(&mut self, entity: Entity, group: ReplicationGroup) {
+        // the same spawn can be sent to multiple clients; we only record it once
+        if !self.recorded_entities.insert(entity) {
+            return;
+        }
+        self.replication_sender.prepare_entity_spawn(entity, group);
+        self.replication_sender.prepare_component_insert(
+            entity,
+            group,
+            P::Components::from(ShouldBeInterpolated),
+        );
+        for component in self.pending_inserts.remove(&entity).unwrap_or_default() {
+            self.record_component_insert(entity, group, component);
+        }
+    }
+
+    /// Record the spawn and the current components of an entity that was already replicated
+    /// when the recording started
+    fn record_existing_entity(&mut self, entity: EntityRef, replicate: &Replicate<P>) {
+        let target = match replicate.replication_mode {
+            ReplicationMode::NetworkTarget => replicate.replication_target.clone(),
+            // the entity is only replicated to the clients that share a room with it
+            ReplicationMode::Room => {
+                let clients: Vec<ClientId> = replicate
+                    .replication_clients_cache
+                    .iter()
+                    .filter(|(client_id, visibility)| {
+                        **visibility != ClientVisibility::Lost
+                            && replicate.replication_target.should_send_to(client_id)
+                    })
+                    .map(|(client_id, _)| *client_id)
+                    .collect();
+                if clients.is_empty() {
+                    return;
+                }
+                NetworkTarget::Only(clients)
+            }
+        };
+        if !self.should_record(&target) {
+            return;
+        }
+        let group = replicate.replication_group;
+        self.record_entity_spawn(entity.id(), group);
+        for component in P::Components::components_of(&entity) {
+            let kind: P::ComponentKinds = (&component).into();
+            if replicate
+                .kind_target(&kind, target.clone())
+                .map_or(false, |target| self.should_record(&target))
+            {
+                self.record_component_insert(entity.id(), group, component);
+            }
+        }
+    }
+
+    pub(crate) fn record_entity_despawn(&mut self, entity: Entity, group: ReplicationGroup) {
+        if self.recorded_entities.remove(&entity) {
+            self.replication_sender
+                .prepare_entity_despawn(entity, group);
+        }
+    }
+
+    pub(crate) fn record_component_insert(
+        &mut self,
+        entity: Entity,
+        group: ReplicationGroup,
+        component: P::Components,
+    ) {
+        let kind: P::ComponentKinds = (&component).into();
+        // prediction is not possible during playback: every entity is interpolated
+        if kind == <P::ComponentKinds as FromType<ShouldBePredicted>>::from_type()
+            || kind == <P::ComponentKinds as FromType<PreSpawnedPlayerObject>>::from_type()
+            || kind == <P::ComponentKinds as FromType<ShouldBeInterpolated>>::from_type()
+        {
+            return;
+        }
+        if !self.recorded_entities.contains(&entity) {
+            self.pending_inserts
+                .entry(entity)
+                .or_default()
+                .push(component);
+            return;
+        }
+        if self.is_pending(entity, group, kind) {
+            return;
+        }
+        self.replication_sender
+            .prepare_component_insert(entity, group, component);
+    }
+
+    pub(crate) fn record_component_remove(
+        &mut self,
+        entity: Entity,
+        group: ReplicationGroup,
+        kind: P::ComponentKinds,
+    ) {
+        if !self.recorded_entities.contains(&entity) || self.is_pending(entity, group, kind) {
+            return;
+        }
+        self.replication_sender
+            .prepare_component_remove(entity, group, kind);
+    }
+
+    pub(crate) fn record_entity_update(
+        &mut self,
+        entity: Entity,
+        group: ReplicationGroup,
+        component: P::Components,
+        component_change_tick: BevyTick,
+        system_current_tick: BevyTick,
+    ) {
+        if !self.recorded_entities.contains(&entity) {
+            return;
+        }
+        if self.last_record_tick.map_or(true, |tick| {
+            component_change_tick.is_newer_than(tick, system_current_tick)
+        }) {
+            self.replication_sender
+                .prepare_entity_update(entity, group, component);
+        }
+    }
+
+    /// Write all the replication messages buffered for this tick to the recording
+    pub(crate) fn record(&mut self, tick: Tick, bevy_tick: BevyTick) -> Result<()> {
+        self.last_record_tick = Some(bevy_tick);
+        // the remaining inserts are for entities that were spawned before the recording started
+        self.pending_inserts.clear();
+        let messages: Vec<_> = self
+            .replication_sender
+            .finalize(tick)
+            .into_iter()
+            .map(|(_, group_id, data)| ReplicationMessage { group_id, data })
+            .collect();
+        if messages.is_empty() {
+            return Ok(());
+        }
+        // the recording never loses messages: the ids of despawned entities can be reused right away
+        for message in &messages {
+            if let ReplicationMessageData::Actions(actions) = &message.data {
+                for net_entity in actions.despawned_entities() {
+                    self.replication_sender.net_entities.free(net_entity);
+                }
+            }
+        }
+        self.replication_sender.clear_recycled_groups();
+        trace!(?tick, num_messages = messages.len(), "Recording frame");
+        self.writer.write_frame(&RecordedFrame { tick, messages })
+    }
+
+    pub(crate) fn finish(mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    /// Check if the component is already part of the message being recorded for this tick
+    /// (the same insert can be sent to multiple clients)
+    fn is_pending(
+        &mut self,
+        entity: Entity,
+        group: ReplicationGroup,
+        kind: P::ComponentKinds,
+    ) -> bool {
+        let Some(net_entity) = self.replication_sender.net_entities.get(entity) else {
+            return false;
+        };
+        let group_id = self.replication_sender.group_id(entity, group);
+        self.replication_sender
+            .pending_unique_components
+            .get(&group_id)
+            .and_then(|entities| entities.get(&net_entity))
+            .map_or(false, |kinds| kinds.contains(&kind))
+    }
+}
+
+/// Record the entities that were already replicated when the recording started, so that a recording
+/// started in the middle of a match can be played back on its own
+pub(crate) fn record_snapshot<P: Protocol>(world: &mut World) {
+    world.resource_scope(
+        |world: &mut World, mut manager: Mut<ConnectionManager<P>>| {
+            let Some(recorder) = manager.recorder.as_mut() else {
+                return;
+            };
+            if !recorder.needs_snapshot {
+                return;
+            }
+            recorder.needs_snapshot = false;
+            let mut query = world.query::<(EntityRef, &Replicate<P>)>();
+            for (entity, replicate) in query.iter(world) {
+                recorder.record_existing_entity(entity, replicate);
+            }
+            trace!(
+                num_entities = recorder.recorded_entities.len(),
+                "Recorded the entities that existed before the recording started"
+            );
+            // the snapshot contains the current value of the components: only record the changes that happen after it
+            recorder.last_record_tick = Some(world.change_tick());
+        },
+    );
+}
@@ -14,11 +14,13 @@ use tracing::{debug, debug_span, error, info, trace, trace_span};
 
 use crate::_reexport::FromType;
 use crate::channel::builder::Channel;
-use crate::netcode::{generate_key, ClientId, ConnectToken};
-use crate::packet::message::Message;
+use crate::channel::stream::StreamId;
+use crate::netcode::{generate_key, ClientId, ConnectToken, NetcodeServerStats};
+use crate::packet::message::{Message, MessageHandle};
 use crate::prelude::PreSpawnedPlayerObject;
 use crate::protocol::channel::ChannelKind;
 use crate::protocol::Protocol;
+use crate::server::recording::RecordingConfig;
 use crate::server::room::{RoomId, RoomManager, RoomMut, RoomRef};
 use crate::shared::replication::components::{NetworkTarget, Replicate};
 use crate::shared::replication::components::{ShouldBeInterpolated, ShouldBePredicted};
@@ -99,7 +101,8 @@ impl<'w, 's, P: Protocol> ServerMut<'w, 's, P> {
         for client_id in context.connections.iter().copied() {
             // let client_addr = self.netcode.client_addr(client_id).unwrap();
             // info!("New connection from {} (id: {})", client_addr, client_id);
-            self.connection_manager.add(client_id, &self.config.ping);
+            self.connection_manager
+                .add(client_id, &self.config.ping, &self.tick_manager);
         }
 
         // handle disconnections
@@ -115,8 +118,7 @@ impl<'w, 's, P: Protocol> ServerMut<'w, 's, P> {
         while let Some((mut reader, client_id)) = self.netcode.recv() {
             // TODO: use connection to apply on BOTH message manager and replication manager
             self.connection_manager
-                .connection_mut(client_id)?
-                .recv_packet(&mut reader, &self.tick_manager)?;
+                .recv_packet(client_id, &mut reader, &self.tick_manager);
         }
         Ok(())
     }
@@ -128,6 +130,12 @@ impl<'w, 's, P: Protocol> ServerMut<'w, 's, P> {
             .unwrap_or_else(|e| {
                 error!("Error during receive: {}", e);
             });
+        // disconnect the clients that sent packets or messages that could not be decoded
+        for client_id in self.connection_manager.take_invalid_clients() {
+            self.netcode
+                .disconnect(client_id, &mut self.io)
+                .unwrap_or_else(|e| error!("Error disconnecting client: {}", e));
+        }
     }
 
     // MESSAGES
@@ -146,7 +154,28 @@ impl<'w, 's, P: Protocol> ServerMut<'w, 's, P> {
             debug_span!("send_message", channel = ?C::type_name(), message = ?message.name(), ?target)
                 .entered();
         self.connection_manager
-            .buffer_message(message.into(), ChannelKind::of::<C>(), target)
+            .buffer_message(message.into(), ChannelKind::of::<C>(), target, None)
+    }
+
+    /// Queues up a message to be sent to all clients in the `target`, on the given stream of an ordered or sequenced channel.
+    ///
+    /// The message is only ordered (or sequenced) relative to the other messages of the same stream,
+    /// so that a lost message does not block the messages of the other streams.
+    pub fn send_message_to_target_on_stream<C: Channel, M: Message>(
+        &mut self,
+        message: M,
+        target: NetworkTarget,
+        stream: StreamId,
+    ) -> Result<()>
+    where
+        M: Clone,
+        P::Message: From<M>,
+    {
+        let _span =
+            debug_span!("send_message", channel = ?C::type_name(), message = ?message.name(), ?target, ?stream)
+                .entered();
+        self.connection_manager
+            .send_message_to_target_on_stream::<C, M>(message, target, stream)
     }
 
     /// Queues up a message to be sent to a client
@@ -154,12 +183,117 @@ impl<'w, 's, P: Protocol> ServerMut<'w, 's, P> {
         &mut self,
         client_id: ClientId,
         message: M,
-    ) -> Result<()>
+    ) -> Result<Option<MessageHandle>>
     where
         M: Clone,
         P::Message: From<M>,
     {
-        self.send_message_to_target::<C, M>(message, NetworkTarget::Only(vec![client_id]))
+        let _span =
+            debug_span!("send_message", channel = ?C::type_name(), message = ?message.name(), ?client_id)
+                .entered();
+        self.connection_manager
+            .send_message::<C, M>(client_id, message)
+    }
+
+    /// Queues up a message to be sent to a client, on the given stream of an ordered or sequenced channel.
+    ///
+    /// The message is only ordered (or sequenced) relative to the other messages of the same stream,
+    /// so that a lost message does not block the messages of the other streams.
+    pub fn send_message_to_stream<C: Channel, M: Message>(
+        &mut self,
+        client_id: ClientId,
+        message: M,
+        stream: StreamId,
+    ) -> Result<Option<MessageHandle>>
+    where
+        M: Clone,
+        P::Message: From<M>,
+    {
+        let _span =
+            debug_span!("send_message", channel = ?C::type_name(), message = ?message.name(), ?client_id, ?stream)
+                .entered();
+        self.connection_manager
+            .send_message_to_stream::<C, M>(client_id, message, stream)
+    }
+
+    // TICK
+
+    /// Change the duration of the ticks at runtime (for example to lower the simulation rate under heavy load,
+    /// or for slow-motion effects).
+    ///
+    /// The change is broadcast to all clients. It only takes effect after a small delay, so that
+    /// the clients (whose ticks are ahead of the server's) can apply it at the same tick as the server.
+    /// Returns the tick at which the new duration takes effect
+    pub fn set_tick_duration(&mut self, tick_duration: Duration) -> Result<Tick> {
+        let start_tick = self.tick_change_start();
+        let change = self
+            .tick_manager
+            .schedule_tick_duration(start_tick, tick_duration);
+        info!(tick = ?change.tick(), ?tick_duration, "Changing the tick duration");
+        self.connection_manager.buffer_timeline_change(change)?;
+        Ok(change.tick())
+    }
+
+    /// Pause the simulation on the server and on all clients.
+    ///
+    /// The ticks keep being incremented, but the [`FixedUpdateSet::Main`](crate::prelude::FixedUpdateSet::Main)
+    /// systems don't run, and the clients stop sending inputs.
+    /// Returns the first tick that is paused
+    pub fn pause(&mut self) -> Result<Tick> {
+        let start_tick = self.tick_change_start();
+        let change = self.tick_manager.schedule_pause(start_tick, true);
+        info!(tick = ?change.tick(), "Pausing the simulation");
+        self.connection_manager.buffer_timeline_change(change)?;
+        Ok(change.tick())
+    }
+
+    /// Resume the simulation after a [`pause`](Self::pause).
+    /// Returns the first tick that is not paused anymore
+    pub fn resume(&mut self) -> Result<Tick> {
+        let start_tick = self.tick_change_start();
+        let change = self.tick_manager.schedule_pause(start_tick, false);
+        info!(tick = ?change.tick(), "Resuming the simulation");
+        self.connection_manager.buffer_timeline_change(change)?;
+        Ok(change.tick())
+    }
+
+    /// Change the speed of the game time relative to the real time (for example 0.5 for slow-motion),
+    /// on the server and on all clients.
+    /// Returns the tick at which the new speed takes effect
+    pub fn set_time_scale(&mut self, time_scale: f32) -> Result<Tick> {
+        let start_tick = self.tick_change_start();
+        let change = self
+            .tick_manager
+            .schedule_time_scale(start_tick, time_scale);
+        info!(tick = ?change.tick(), ?time_scale, "Changing the time scale");
+        self.connection_manager.buffer_timeline_change(change)?;
+        Ok(change.tick())
+    }
+
+    /// Tick at which a change of the tick duration, pause state or time scale can take effect
+    fn tick_change_start(&self) -> Tick {
+        let delay = self
+            .connection_manager
+            .timeline_change_delay(self.config.shared.server_send_interval);
+        // add a margin of a couple of ticks
+        let delay_ticks =
+            (delay.as_nanos() / self.tick_manager.tick_duration().as_nanos()) as i16 + 2;
+        self.tick_manager.tick() + delay_ticks
+    }
+
+    // RECORDING
+
+    /// Start recording the replication stream to a file, to be played back later with the
+    /// [`PlaybackPlugin`](crate::client::playback::PlaybackPlugin)
+    pub fn start_recording(&mut self, config: RecordingConfig) -> Result<()> {
+        let tick_duration = self.tick_manager.tick_duration();
+        self.connection_manager
+            .start_recording(config, tick_duration)
+    }
+
+    /// Stop the current recording and flush it to disk
+    pub fn stop_recording(&mut self) -> Result<()> {
+        self.connection_manager.stop_recording()
     }
 
     // ROOM
@@ -237,6 +371,11 @@ impl<'w, 's, P: Protocol> Server<'w, 's, P> {
         &self.io
     }
 
+    /// Counters about the packets received by the netcode server, that can be used to detect attacks
+    pub fn netcode_stats(&self) -> Option<&NetcodeServerStats> {
+        self.netcode.stats()
+    }
+
     // INPUTS
 
     // // TODO: exposed only for debugging
@@ -253,8 +392,11 @@ pub struct ServerContext {
 }
 
 impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
-    fn new_connected_clients(&self) -> Vec<ClientId> {
-        self.new_clients.clone()
+    fn new_connected_clients(&self, entity: Entity) -> Vec<ClientId> {
+        self.late_join_batch
+            .get(&entity)
+            .cloned()
+            .unwrap_or_default()
     }
 
     fn prepare_entity_spawn(
@@ -264,7 +406,12 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         target: NetworkTarget,
         system_current_tick: BevyTick,
     ) -> Result<()> {
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
+        if let Some(recorder) = self.recorder.as_mut() {
+            if recorder.should_record(&target) {
+                recorder.record_entity_spawn(entity, group);
+            }
+        }
         // debug!(?entity, "Spawning entity");
         // TODO: should we have additional state tracking so that we know we are in the process of sending this entity to clients?
         self.apply_replication(target).try_for_each(|client_id| {
@@ -308,7 +455,16 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         target: NetworkTarget,
         system_current_tick: BevyTick,
     ) -> Result<()> {
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
+        if let Some(recorder) = self.recorder.as_mut() {
+            // for the spectator, ignore despawns caused by a client losing visibility of the entity
+            // (the entity is removed from the cache only when it is actually despawned)
+            let is_despawned = !self.replicate_component_cache.contains_key(&entity);
+            if recorder.should_record(&target) && (is_despawned || !recorder.is_spectator()) {
+                recorder.record_entity_despawn(entity, group);
+            }
+        }
+        let target = self.exclude_loading_clients(entity, target);
         self.apply_replication(target).try_for_each(|client_id| {
             // trace!(
             //     ?entity,
@@ -360,7 +516,13 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
             actual_target = replicate.prediction_target.clone();
         }
 
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
+        if let Some(recorder) = self.recorder.as_mut() {
+            if recorder.should_record(&actual_target) {
+                recorder.record_component_insert(entity, group, component.clone());
+            }
+        }
+        let actual_target = self.exclude_loading_clients(entity, actual_target);
         self.apply_replication(actual_target)
             .try_for_each(|client_id| {
                 // trace!(
@@ -390,7 +552,13 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
         system_current_tick: BevyTick,
     ) -> Result<()> {
         debug!(?entity, ?component_kind, "Sending RemoveComponent");
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
+        if let Some(recorder) = self.recorder.as_mut() {
+            if recorder.should_record(&target) {
+                recorder.record_component_remove(entity, group, component_kind);
+            }
+        }
+        let target = self.exclude_loading_clients(entity, target);
         self.apply_replication(target).try_for_each(|client_id| {
             let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
             // TODO: I don't think it's actually correct to only correct the changes since that action.
@@ -428,13 +596,26 @@ impl<P: Protocol> ReplicationSend<P> for ConnectionManager<P> {
             "Prepare entity update"
         );
 
-        let group = replicate.group_id(Some(entity));
+        let group = replicate.replication_group;
+        if let Some(recorder) = self.recorder.as_mut() {
+            if recorder.should_record(&target) {
+                recorder.record_entity_update(
+                    entity,
+                    group,
+                    component.clone(),
+                    component_change_tick,
+                    system_current_tick,
+                );
+            }
+        }
+        let target = self.exclude_loading_clients(entity, target);
         self.apply_replication(target).try_for_each(|client_id| {
             // TODO: should we have additional state tracking so that we know we are in the process of sending this entity to clients?
             let replication_sender = &mut self.connection_mut(client_id)?.replication_sender;
+            let group_id = replication_sender.group_id(entity, group);
             let collect_changes_since_this_tick = replication_sender
                 .group_channels
-                .entry(group)
+                .entry(group_id)
                 .or_default()
                 .collect_changes_since_this_tick;
             // send the update for all changes newer than the last ack bevy tick for the group
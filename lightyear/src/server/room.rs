@@ -147,6 +147,17 @@ impl RoomManager {
         }
     }
 
+    /// Returns true if the client and the entity are in at least one common room
+    pub(crate) fn shares_room(&self, client_id: ClientId, entity: Entity) -> bool {
+        let (Some(client_rooms), Some(entity_rooms)) = (
+            self.data.client_to_rooms.get(&client_id),
+            self.data.entity_to_rooms.get(&entity),
+        ) else {
+            return false;
+        };
+        !client_rooms.is_disjoint(entity_rooms)
+    }
+
     fn add_client(&mut self, room_id: RoomId, client_id: ClientId) {
         self.data
             .client_to_rooms
@@ -609,14 +620,7 @@ mod tests {
                 .len(),
             1
         );
-        let client_entity = *stepper
-            .client_app
-            .world
-            .resource::<ClientConnectionManager>()
-            .replication_receiver
-            .remote_entity_map
-            .get_local(server_entity)
-            .unwrap();
+        let client_entity = stepper.client_entity(server_entity).unwrap();
 
         // Remove the entity from the room
         stepper
@@ -779,14 +783,7 @@ mod tests {
                 .len(),
             1
         );
-        let client_entity = *stepper
-            .client_app
-            .world
-            .resource::<ClientConnectionManager>()
-            .replication_receiver
-            .remote_entity_map
-            .get_local(server_entity)
-            .unwrap();
+        let client_entity = stepper.client_entity(server_entity).unwrap();
 
         // Remove the client from the room
         stepper
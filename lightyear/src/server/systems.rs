@@ -7,13 +7,18 @@ use tracing::{debug, error, info, trace, trace_span};
 
 use crate::_reexport::ComponentProtocol;
 use crate::client::resource::ClientMut;
-use crate::connection::events::{IterEntityDespawnEvent, IterEntitySpawnEvent};
+use crate::connection::events::{
+    IterBlobReceivedEvent, IterEntityDespawnEvent, IterEntitySpawnEvent, IterMessageDeliveryEvent,
+};
 use crate::prelude::{Io, TickManager, TimeManager};
 use crate::protocol::message::MessageProtocol;
 use crate::protocol::Protocol;
 use crate::server::config::ServerConfig;
 use crate::server::connection::ConnectionManager;
-use crate::server::events::{ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent};
+use crate::server::events::{
+    BlobReceivedEvent, ConnectEvent, DisconnectEvent, EntityDespawnEvent, EntitySpawnEvent,
+    MessageAckedEvent, MessageExpiredEvent, MessageLostEvent,
+};
 use crate::server::resource::{Server, ServerMut};
 use crate::server::room::RoomManager;
 use crate::shared::replication::ReplicationSend;
@@ -49,7 +54,7 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                             for client_id in context.connections.iter().copied() {
                                                 // let client_addr = self.netcode.client_addr(client_id).unwrap();
                                                 // info!("New connection from {} (id: {})", client_addr, client_id);
-                                                connection_manager.add(client_id, &world.resource::<ServerConfig>().ping);
+                                                connection_manager.add(client_id, &world.resource::<ServerConfig>().ping, tick_manager.as_ref());
                                             }
 
                                             // handle disconnections
@@ -61,11 +66,7 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                             // RECV_PACKETS: buffer packets into message managers
                                             while let Some((mut reader, client_id)) = netcode.recv() {
                                                 // TODO: use connection to apply on BOTH message manager and replication manager
-                                                connection_manager
-                                                    .connection_mut(client_id)
-                                                    .expect("connection not found")
-                                                    .recv_packet(&mut reader, tick_manager.as_ref())
-                                                    .expect("could not recv packet");
+                                                connection_manager.recv_packet(client_id, &mut reader, tick_manager.as_ref());
                                             }
 
                                             // RECEIVE: read messages and parse them into events
@@ -75,6 +76,14 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                     error!("Error during receive: {}", e);
                                                 });
 
+                                            // disconnect the clients that sent packets or messages that could not be decoded
+                                            for client_id in connection_manager.take_invalid_clients() {
+                                                info!(?client_id, "Disconnecting client that sent invalid data");
+                                                netcode
+                                                    .disconnect(client_id, io.deref_mut())
+                                                    .unwrap_or_else(|e| error!("Error disconnecting client: {}", e));
+                                            }
+
                                             // EVENTS: Write the received events into bevy events
                                             if !connection_manager.events.is_empty() {
                                                 // TODO: write these as systems? might be easier to also add the events to the app
@@ -125,6 +134,38 @@ pub(crate) fn receive<P: Protocol>(world: &mut World) {
                                                     }
                                                 }
 
+                                                // BlobReceived Events
+                                                if connection_manager.events.has_blobs() {
+                                                    let mut blob_event_writer = world
+                                                        .get_resource_mut::<Events<BlobReceivedEvent>>()
+                                                        .unwrap();
+                                                    for (channel_kind, bytes, client_id) in connection_manager.events.into_iter_blobs() {
+                                                        blob_event_writer.send(BlobReceivedEvent::new(channel_kind, bytes, client_id));
+                                                    }
+                                                }
+
+                                                // MessageAcked, MessageLost and MessageExpired Events
+                                                if connection_manager.events.has_message_delivery() {
+                                                    let mut acked_event_writer = world
+                                                        .get_resource_mut::<Events<MessageAckedEvent>>()
+                                                        .unwrap();
+                                                    for (handle, client_id) in connection_manager.events.into_iter_acked_messages() {
+                                                        acked_event_writer.send(MessageAckedEvent::new(handle, client_id));
+                                                    }
+                                                    let mut lost_event_writer = world
+                                                        .get_resource_mut::<Events<MessageLostEvent>>()
+                                                        .unwrap();
+                                                    for (handle, client_id) in connection_manager.events.into_iter_lost_messages() {
+                                                        lost_event_writer.send(MessageLostEvent::new(handle, client_id));
+                                                    }
+                                                    let mut expired_event_writer = world
+                                                        .get_resource_mut::<Events<MessageExpiredEvent>>()
+                                                        .unwrap();
+                                                    for (handle, client_id) in connection_manager.events.into_iter_expired_messages() {
+                                                        expired_event_writer.send(MessageExpiredEvent::new(handle, client_id));
+                                                    }
+                                                }
+
                                                 // Update component events (updates, inserts, removes)
                                                 P::Components::push_component_events(world, &mut connection_manager.events);
                                             }
@@ -165,6 +206,9 @@ pub(crate) fn send<P: Protocol>(
             for packet_byte in connection.send_packets(&time_manager, &tick_manager)? {
                 netserver.send(packet_byte.as_slice(), *client_id, io.deref_mut())?;
             }
+            io.stats
+                .compression
+                .merge(&connection.message_manager.take_compression_stats());
             Ok(())
         })
         .unwrap_or_else(|e: anyhow::Error| {
@@ -1,6 +1,7 @@
 //! Configuration that has to be the same between the server and the client.
 use bevy::utils::Duration;
 
+use crate::packet::compression::CompressionConfig;
 use crate::shared::log::LogConfig;
 use crate::shared::tick_manager::TickConfig;
 
@@ -13,6 +14,9 @@ pub struct SharedConfig {
     pub server_send_interval: Duration,
     pub tick: TickConfig,
     pub log: LogConfig,
+    /// Compression of the data sent over the network.
+    /// The data is only compressed if both the client and the server enable compression with the same settings
+    pub compression: CompressionConfig,
 }
 
 impl Default for SharedConfig {
@@ -24,6 +28,7 @@ impl Default for SharedConfig {
             server_send_interval: Duration::from_millis(0),
             tick: TickConfig::new(Duration::from_millis(16)),
             log: LogConfig::default(),
+            compression: CompressionConfig::default(),
         }
     }
 }
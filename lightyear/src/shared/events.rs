@@ -2,10 +2,12 @@
 use std::marker::PhantomData;
 
 use bevy::prelude::{Component, Entity, Event};
+use bytes::Bytes;
 
 #[cfg(feature = "leafwing")]
 use crate::inputs::leafwing::InputMessage;
-use crate::packet::message::Message;
+use crate::packet::message::{Message, MessageHandle};
+use crate::protocol::channel::ChannelKind;
 
 #[derive(Event)]
 pub struct ConnectEvent<Ctx = ()>(Ctx);
@@ -71,6 +73,108 @@ impl<M: Message, Ctx> MessageEvent<M, Ctx> {
     }
 }
 
+/// Emitted when a blob sent on a [`ChannelMode::Blob`](crate::channel::builder::ChannelMode::Blob)
+/// channel has been fully received
+#[derive(Event)]
+pub struct BlobReceivedEvent<Ctx = ()> {
+    channel: ChannelKind,
+    bytes: Bytes,
+    context: Ctx,
+}
+
+impl<Ctx> BlobReceivedEvent<Ctx> {
+    pub fn new(channel: ChannelKind, bytes: Bytes, context: Ctx) -> Self {
+        Self {
+            channel,
+            bytes,
+            context,
+        }
+    }
+
+    /// The channel on which the blob was received
+    pub fn channel(&self) -> ChannelKind {
+        self.channel
+    }
+
+    /// The assembled bytes of the blob
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
+/// Emitted when a message sent with a [`MessageHandle`] has been received by the remote peer
+#[derive(Event, Debug)]
+pub struct MessageAckedEvent<Ctx = ()> {
+    handle: MessageHandle,
+    context: Ctx,
+}
+
+impl<Ctx> MessageAckedEvent<Ctx> {
+    pub fn new(handle: MessageHandle, context: Ctx) -> Self {
+        Self { handle, context }
+    }
+
+    /// The handle returned when the message was sent
+    pub fn handle(&self) -> MessageHandle {
+        self.handle
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
+/// Emitted when a message sent with a [`MessageHandle`] on a
+/// [`ChannelMode::UnorderedUnreliableWithAcks`](crate::channel::builder::ChannelMode::UnorderedUnreliableWithAcks)
+/// channel was not acked in time and is considered lost
+#[derive(Event, Debug)]
+pub struct MessageLostEvent<Ctx = ()> {
+    handle: MessageHandle,
+    context: Ctx,
+}
+
+impl<Ctx> MessageLostEvent<Ctx> {
+    pub fn new(handle: MessageHandle, context: Ctx) -> Self {
+        Self { handle, context }
+    }
+
+    /// The handle returned when the message was sent
+    pub fn handle(&self) -> MessageHandle {
+        self.handle
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
+/// Emitted when a message sent with a [`MessageHandle`] on a reliable channel was not acked before its
+/// [`message_ttl`](crate::channel::builder::ReliableSettings::message_ttl), so the sender stopped resending it
+#[derive(Event, Debug)]
+pub struct MessageExpiredEvent<Ctx = ()> {
+    handle: MessageHandle,
+    context: Ctx,
+}
+
+impl<Ctx> MessageExpiredEvent<Ctx> {
+    pub fn new(handle: MessageHandle, context: Ctx) -> Self {
+        Self { handle, context }
+    }
+
+    /// The handle returned when the message was sent
+    pub fn handle(&self) -> MessageHandle {
+        self.handle
+    }
+
+    pub fn context(&self) -> &Ctx {
+        &self.context
+    }
+}
+
 #[derive(Event)]
 /// Event emitted on server every time we receive an event
 pub struct InputEvent<I: crate::inputs::native::UserAction, Ctx = ()> {
@@ -43,6 +43,10 @@ impl Default for LogConfig {
 
 impl Plugin for LogPlugin {
     fn build(&self, app: &mut App) {
+        // the unit tests run many apps in the same process: let each test install its own subscriber
+        if cfg!(test) {
+            return;
+        }
         let finished_subscriber;
         let default_filter = { format!("{},{}", self.config.level, self.config.filter) };
         let filter_layer = EnvFilter::try_from_default_env()
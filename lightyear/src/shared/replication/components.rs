@@ -14,6 +14,7 @@ use crate::netcode::ClientId;
 use crate::prelude::{EntityMapper, MapEntities};
 use crate::protocol::Protocol;
 use crate::server::room::ClientVisibility;
+use crate::shared::replication::net_entity::{NetEntity, NetEntityMap};
 
 /// Component inserted to each replicable entities, to detect when they are despawned
 #[derive(Component, Clone, Copy)]
@@ -71,15 +72,6 @@ impl Default for PerComponentReplicationMetadata {
 }
 
 impl<P: Protocol> Replicate<P> {
-    pub(crate) fn group_id(&self, entity: Option<Entity>) -> ReplicationGroupId {
-        match self.replication_group {
-            ReplicationGroup::FromEntity => {
-                ReplicationGroupId(entity.expect("need to provide an entity").to_bits())
-            }
-            ReplicationGroup::Group(id) => ReplicationGroupId(id),
-        }
-    }
-
     /// Returns true if we don't want to replicate the component
     pub fn is_disabled<C>(&self) -> bool
     where
@@ -121,6 +113,22 @@ impl<P: Protocol> Replicate<P> {
         }
     }
 
+    /// Replication target for the component of kind `kind`, or None if the component is not replicated
+    pub(crate) fn kind_target(
+        &self,
+        kind: &P::ComponentKinds,
+        mut entity_target: NetworkTarget,
+    ) -> Option<NetworkTarget> {
+        match self.per_component_metadata.get(kind) {
+            None => Some(entity_target),
+            Some(metadata) if metadata.disabled => None,
+            Some(metadata) => {
+                entity_target.intersection(metadata.target.clone());
+                Some(entity_target)
+            }
+        }
+    }
+
     /// Disable the replication of a component for this entity
     pub fn disable_component<C>(&mut self)
     where
@@ -196,18 +204,87 @@ impl<P: Protocol> Replicate<P> {
 
 #[derive(Debug, Default, Copy, Clone)]
 pub enum ReplicationGroup {
-    // the group id is the entity id
+    // the group id is derived from the entity's NetEntity
     #[default]
     FromEntity,
+    // use the same group as another entity (that uses `FromEntity`), for example the parent of this entity
+    Entity(Entity),
     // choose a different group id
-    // note: it must not be the same as any entity id!
-    // TODO: how can i generate one that doesn't conflict? maybe take u32 as input, and apply generation = u32::MAX - 1?
+    // note: the id must be smaller than 2^63. It doesn't conflict with the groups derived from entities
     Group(u64),
 }
 
+/// Groups chosen by the user have this bit set, so that they never conflict with the groups derived from entities
+const USER_GROUP_BIT: u64 = 1 << 63;
+
+impl ReplicationGroup {
+    /// Get the group id of `entity` for a given remote
+    pub(crate) fn group_id(
+        &self,
+        entity: Entity,
+        net_entities: &mut NetEntityMap,
+    ) -> ReplicationGroupId {
+        match self {
+            ReplicationGroup::FromEntity => {
+                ReplicationGroupId(net_entities.get_or_allocate(entity).index() as u64)
+            }
+            ReplicationGroup::Entity(owner) => {
+                ReplicationGroupId(net_entities.group_net_entity(entity, *owner).index() as u64)
+            }
+            ReplicationGroup::Group(id) => ReplicationGroupId(id | USER_GROUP_BIT),
+        }
+    }
+
+    /// Get the group id of `entity` for a given remote, without allocating a [`NetEntity`].
+    /// Returns None if the group is derived from an entity that was never replicated to the remote
+    pub(crate) fn existing_group_id(
+        &self,
+        entity: Entity,
+        net_entities: &NetEntityMap,
+    ) -> Option<ReplicationGroupId> {
+        match self {
+            ReplicationGroup::FromEntity => net_entities
+                .get(entity)
+                .map(|net| ReplicationGroupId(net.index() as u64)),
+            ReplicationGroup::Entity(owner) => net_entities
+                .existing_group_net_entity(*owner)
+                .map(|net| ReplicationGroupId(net.index() as u64)),
+            ReplicationGroup::Group(id) => Some(ReplicationGroupId(id | USER_GROUP_BIT)),
+        }
+    }
+}
+
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "ReplicationGroupIdRepr", into = "ReplicationGroupIdRepr")]
 pub struct ReplicationGroupId(pub u64);
 
+/// How the [`ReplicationGroupId`] is sent over the network: groups derived from entities only take a few bits
+#[derive(Serialize, Deserialize)]
+enum ReplicationGroupIdRepr {
+    Entity(NetEntity),
+    Group(u64),
+}
+
+impl From<ReplicationGroupId> for ReplicationGroupIdRepr {
+    fn from(value: ReplicationGroupId) -> Self {
+        match u32::try_from(value.0) {
+            Ok(index) => ReplicationGroupIdRepr::Entity(NetEntity::from_index(index)),
+            Err(_) => ReplicationGroupIdRepr::Group(value.0),
+        }
+    }
+}
+
+impl From<ReplicationGroupIdRepr> for ReplicationGroupId {
+    fn from(value: ReplicationGroupIdRepr) -> Self {
+        match value {
+            ReplicationGroupIdRepr::Entity(net_entity) => {
+                ReplicationGroupId(net_entity.index() as u64)
+            }
+            ReplicationGroupIdRepr::Group(id) => ReplicationGroupId(id),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub enum ReplicationMode {
     /// We will replicate this entity only to clients that are in the same room as the entity
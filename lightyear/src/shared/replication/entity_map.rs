@@ -2,7 +2,9 @@
 use anyhow::Context;
 use bevy::prelude::{Entity, EntityWorldMut, World};
 use bevy::utils::hashbrown::hash_map::Entry;
-use bevy::utils::{EntityHashMap, EntityHashSet};
+use bevy::utils::{EntityHashMap, EntityHashSet, HashMap};
+
+use crate::shared::replication::net_entity::NetEntity;
 
 pub trait EntityMapper {
     /// Map an entity
@@ -24,10 +26,11 @@ impl EntityMapper for EntityHashMap<Entity, Entity> {
 }
 
 #[derive(Default, Debug)]
-/// Map between local and remote entities. (used mostly on client because it's when we receive entity updates)
+/// Map between local entities and the [`NetEntity`]s allocated by the remote.
+/// (used mostly on client because it's when we receive entity updates)
 pub struct RemoteEntityMap {
-    remote_to_local: EntityHashMap<Entity, Entity>,
-    local_to_remote: EntityHashMap<Entity, Entity>,
+    remote_to_local: HashMap<NetEntity, Entity>,
+    local_to_remote: EntityHashMap<Entity, NetEntity>,
 }
 
 #[derive(Default, Debug)]
@@ -60,27 +63,23 @@ impl EntityMapper for InterpolatedEntityMap {
 
 impl RemoteEntityMap {
     #[inline]
-    pub fn insert(&mut self, remote_entity: Entity, local_entity: Entity) {
+    pub fn insert(&mut self, remote_entity: NetEntity, local_entity: Entity) {
         self.remote_to_local.insert(remote_entity, local_entity);
         self.local_to_remote.insert(local_entity, remote_entity);
     }
 
-    pub(crate) fn get_to_remote_mapper(&self) -> Box<dyn EntityMapper + '_> {
-        Box::new(&self.local_to_remote)
-    }
-
     // TODO: makke sure all calls to remote entity map use this to get the exact mapper
     pub(crate) fn get_to_local_mapper(&self) -> Box<dyn EntityMapper + '_> {
-        Box::new(&self.remote_to_local)
+        Box::new(self)
     }
 
     #[inline]
-    pub(crate) fn get_local(&self, remote_entity: Entity) -> Option<&Entity> {
+    pub(crate) fn get_local(&self, remote_entity: NetEntity) -> Option<&Entity> {
         self.remote_to_local.get(&remote_entity)
     }
 
     #[inline]
-    pub(crate) fn get_remote(&self, local_entity: Entity) -> Option<&Entity> {
+    pub(crate) fn get_remote(&self, local_entity: Entity) -> Option<&NetEntity> {
         self.local_to_remote.get(&local_entity)
     }
 
@@ -88,7 +87,7 @@ impl RemoteEntityMap {
     pub(super) fn get_by_remote<'a>(
         &mut self,
         world: &'a mut World,
-        remote_entity: Entity,
+        remote_entity: NetEntity,
     ) -> anyhow::Result<EntityWorldMut<'a>> {
         self.get_local(remote_entity)
             .and_then(|e| world.get_entity_mut(*e))
@@ -99,7 +98,7 @@ impl RemoteEntityMap {
     pub(super) fn get_by_remote_or_spawn<'a>(
         &mut self,
         world: &'a mut World,
-        remote_entity: Entity,
+        remote_entity: NetEntity,
     ) -> EntityWorldMut<'a> {
         match self.remote_to_local.entry(remote_entity) {
             Entry::Occupied(entry) => world.entity_mut(*entry.get()),
@@ -113,7 +112,7 @@ impl RemoteEntityMap {
         }
     }
 
-    pub(super) fn remove_by_remote(&mut self, remote_entity: Entity) -> Option<Entity> {
+    pub(super) fn remove_by_remote(&mut self, remote_entity: NetEntity) -> Option<Entity> {
         let local_entity = self.remote_to_local.remove(&remote_entity);
         if let Some(local_entity) = local_entity {
             self.local_to_remote.remove(&local_entity);
@@ -122,12 +121,12 @@ impl RemoteEntityMap {
     }
 
     #[inline]
-    pub fn to_local(&self) -> &EntityHashMap<Entity, Entity> {
+    pub fn to_local(&self) -> &HashMap<NetEntity, Entity> {
         &self.remote_to_local
     }
 
     #[inline]
-    pub fn to_remote(&self) -> &EntityHashMap<Entity, Entity> {
+    pub fn to_remote(&self) -> &EntityHashMap<Entity, NetEntity> {
         &self.local_to_remote
     }
 
@@ -142,9 +141,10 @@ impl RemoteEntityMap {
 }
 
 impl EntityMapper for RemoteEntityMap {
+    /// The entities inside components and messages are sent as placeholders for the remote [`NetEntity`]
     #[inline]
     fn map(&self, entity: Entity) -> Option<Entity> {
-        self.get_local(entity).copied()
+        self.get_local(NetEntity::from_placeholder(entity)).copied()
     }
 }
 
@@ -160,6 +160,10 @@ pub trait MapEntities<'a> {
 impl<'a> MapEntities<'a> for Entity {
     #[inline]
     fn map_entities(&mut self, entity_mapper: Box<dyn EntityMapper + 'a>) {
+        // entities that were not replicated by the sender stay unresolved
+        if *self == Entity::PLACEHOLDER {
+            return;
+        }
         if let Some(local) = entity_mapper.map(*self) {
             *self = local;
         } else {
@@ -226,14 +230,7 @@ mod tests {
         stepper.frame_step();
 
         // Check that the entity is replicated to client
-        let client_entity = *stepper
-            .client_app
-            .world
-            .resource::<ClientConnectionManager>()
-            .replication_receiver
-            .remote_entity_map
-            .get_local(server_entity)
-            .unwrap();
+        let client_entity = stepper.client_entity(server_entity).unwrap();
         assert_eq!(
             stepper
                 .client_app
@@ -254,14 +251,7 @@ mod tests {
         stepper.frame_step();
 
         // Check that this entity was replicated correctly, and that the component got mapped
-        let client_entity_2 = *stepper
-            .client_app
-            .world
-            .resource::<ClientConnectionManager>()
-            .replication_receiver
-            .remote_entity_map
-            .get_local(server_entity_2)
-            .unwrap();
+        let client_entity_2 = stepper.client_entity(server_entity_2).unwrap();
         // the 'server entity' inside the Component4 component got mapped to the corresponding entity on the client
         assert_eq!(
             stepper
@@ -6,6 +6,7 @@ use bevy::ecs::component::Tick as BevyTick;
 use bevy::prelude::{Component, Entity, Resource};
 use bevy::reflect::Map;
 use bevy::utils::{EntityHashMap, HashSet};
+use bitcode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
 use crate::_reexport::{ComponentProtocol, ComponentProtocolKind, ShouldBeInterpolated};
@@ -15,11 +16,14 @@ use crate::packet::message::MessageId;
 use crate::prelude::{EntityMapper, MapEntities, NetworkTarget, ShouldBePredicted, Tick};
 use crate::protocol::Protocol;
 use crate::shared::replication::components::{Replicate, ReplicationGroupId};
+use crate::shared::replication::net_entity::NetEntity;
 
 pub mod components;
 
 pub mod entity_map;
+pub mod net_entity;
 pub(crate) mod receive;
+pub mod recording;
 pub(crate) mod send;
 pub mod systems;
 
@@ -70,7 +74,18 @@ impl<C, K: Hash + Eq> Default for EntityActions<C, K> {
 pub struct EntityActionMessage<C, K: Hash + Eq> {
     sequence_id: MessageId,
     // we use vec but the order of entities should not matter
-    pub(crate) actions: Vec<(Entity, EntityActions<C, K>)>,
+    pub(crate) actions: Vec<(NetEntity, EntityActions<C, K>)>,
+}
+
+impl<C, K: Hash + Eq> EntityActionMessage<C, K> {
+    /// The entities that are despawned by this message
+    pub(crate) fn despawned_entities(&self) -> Vec<NetEntity> {
+        self.actions
+            .iter()
+            .filter(|(_, actions)| actions.despawn)
+            .map(|(net_entity, _)| *net_entity)
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -79,7 +94,7 @@ pub struct EntityUpdatesMessage<C> {
     /// We set this to None after a certain amount of time without any new Actions, to signify on the receiver side
     /// that there is no ordering constraint with respect to Actions for this group (i.e. the Update can be applied immediately)
     last_action_tick: Option<Tick>,
-    pub(crate) updates: Vec<(Entity, Vec<C>)>,
+    pub(crate) updates: Vec<(NetEntity, Vec<C>)>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -96,12 +111,47 @@ pub struct ReplicationMessage<C, K: Hash + Eq> {
     pub(crate) data: ReplicationMessageData<C, K>,
 }
 
+/// A message that is bound to the replication group of an entity.
+/// It is only released on the receiver side once the group's replicated state has reached `tick`,
+/// so that the message is never applied before the state it refers to.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct EntityMessage<M> {
+    pub(crate) group_id: ReplicationGroupId,
+    /// The latest tick for which the sender sent replication messages for the group
+    pub(crate) tick: Tick,
+    pub(crate) message: M,
+}
+
+/// Message sent to a client to let it know how much of the initial world state has been sent
+/// (see [`LateJoinConfig`](crate::server::late_join::LateJoinConfig))
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq)]
+pub struct LateJoinMessage {
+    /// Number of entities that have been sent so far
+    pub sent: u32,
+    /// Total number of entities in the initial world state
+    pub total: u32,
+}
+
+impl LateJoinMessage {
+    pub(crate) fn is_finished(&self) -> bool {
+        self.sent >= self.total
+    }
+
+    /// Fraction of the world that has been loaded, between 0.0 and 1.0
+    pub(crate) fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.sent as f32 / self.total as f32
+    }
+}
+
 pub trait ReplicationSend<P: Protocol>: Resource {
     // type Manager: ReplicationManager;
 
-    /// Return the list of clients that connected to the server since we last sent any replication messages
-    /// (this is used to send the initial state of the world to new clients)
-    fn new_connected_clients(&self) -> Vec<ClientId>;
+    /// Return the list of newly connected clients that should receive the initial state of this entity
+    /// during the current send interval (this is used to send the initial state of the world to new clients)
+    fn new_connected_clients(&self, entity: Entity) -> Vec<ClientId>;
 
     fn prepare_entity_spawn(
         &mut self,
@@ -214,14 +264,7 @@ mod tests {
         stepper.frame_step();
 
         // Check that the entity is replicated to client
-        let client_entity = *stepper
-            .client_app
-            .world
-            .resource::<ClientConnectionManager>()
-            .replication_receiver
-            .remote_entity_map
-            .get_local(server_entity)
-            .unwrap();
+        let client_entity = stepper.client_entity(server_entity).unwrap();
         assert_eq!(
             stepper
                 .client_app
@@ -250,4 +293,85 @@ mod tests {
             .is_none());
         Ok(())
     }
+
+    fn received_messages(stepper: &BevyStepper) -> Vec<Message2> {
+        let events = stepper
+            .client_app
+            .world
+            .resource::<bevy::prelude::Events<MessageEvent<Message2>>>();
+        events
+            .get_reader()
+            .read(events)
+            .map(|event| event.message().clone())
+            .collect()
+    }
+
+    // An entity message sent in the same frame as the entity spawn
+    // is only received by the client once the entity is replicated.
+    // Entity messages for entities that are not replicated to the client are dropped.
+    #[test]
+    fn test_entity_message() {
+        let frame_duration = Duration::from_millis(10);
+        let tick_duration = Duration::from_millis(10);
+        let shared_config = SharedConfig {
+            enable_replication: true,
+            tick: TickConfig::new(tick_duration),
+            ..Default::default()
+        };
+        let link_conditioner = LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(20),
+            incoming_jitter: Duration::from_millis(0),
+            incoming_loss: 0.0,
+        };
+        let mut stepper = BevyStepper::new(
+            shared_config,
+            SyncConfig::default().speedup_factor(1.0),
+            PredictionConfig::default().disable(false),
+            InterpolationConfig::default(),
+            link_conditioner,
+            frame_duration,
+        );
+        stepper.init();
+
+        let server_entity = stepper
+            .server_app
+            .world
+            .spawn((Component1(0.0), Replicate::default()))
+            .id();
+        let hidden_entity = stepper
+            .server_app
+            .world
+            .spawn((
+                Component1(0.0),
+                Replicate {
+                    replication_target: NetworkTarget::None,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        let mut manager = stepper
+            .server_app
+            .world
+            .resource_mut::<ServerConnectionManager>();
+        manager
+            .send_entity_message::<Channel1, _>(server_entity, Message2(1), NetworkTarget::All)
+            .unwrap();
+        manager
+            .send_entity_message::<Channel1, _>(hidden_entity, Message2(2), NetworkTarget::All)
+            .unwrap();
+
+        let mut received = vec![];
+        for _ in 0..10 {
+            stepper.frame_step();
+            let messages = received_messages(&stepper);
+            if !messages.is_empty() {
+                // the entity must already exist on the client when the message is received
+                assert!(stepper.client_entity(server_entity).is_some());
+                received = messages;
+                break;
+            }
+        }
+        assert_eq!(received, vec![Message2(1)]);
+        assert!(stepper.client_entity(hidden_entity).is_none());
+    }
 }
@@ -0,0 +1,316 @@
+//! Compact identifiers for the entities that are replicated over the network
+//!
+//! The sender allocates a [`NetEntity`] for each entity that it replicates to a remote, instead of sending the
+//! raw bits of the bevy [`Entity`] (which would cost 64 bits and leak the internals of the sender's allocator).
+//! The ids are small and densely packed, so they are serialized with gamma encoding.
+//!
+//! A [`NetEntity`] is only recycled once the remote has acked the despawn of the entity that was using it,
+//! and once no other entity uses it as its replication group id.
+//!
+//! Entities that are referenced inside components or messages but are not replicated to the remote are sent
+//! as unresolved ([`Entity::PLACEHOLDER`]).
+use std::collections::VecDeque;
+
+use bevy::prelude::Entity;
+use bevy::utils::{EntityHashMap, EntityHashSet, HashMap, HashSet};
+use serde::de::{SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::prelude::{EntityMapper, MapEntities};
+
+/// Identifier of a replicated entity, allocated by the sender of the replication messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NetEntity(u32);
+
+impl NetEntity {
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+
+    pub(crate) fn from_index(index: u32) -> Self {
+        Self(index)
+    }
+
+    /// Entities that are stored inside components or messages are sent as an [`Entity`] whose index
+    /// is the [`NetEntity`], so that they can be mapped with [`MapEntities`]
+    pub(crate) fn to_placeholder(self) -> Entity {
+        Entity::from_raw(self.0)
+    }
+
+    pub(crate) fn from_placeholder(entity: Entity) -> Self {
+        Self(entity.index())
+    }
+}
+
+impl Serialize for NetEntity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_u32(self.0);
+        }
+        // bitcode writes the length of a sequence with gamma encoding, so we write the index as the length
+        // of an empty sequence: small indices only take a few bits
+        serializer.serialize_seq(Some(self.0 as usize))?.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for NetEntity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return u32::deserialize(deserializer).map(NetEntity);
+        }
+        struct NetEntityVisitor;
+        impl<'de> Visitor<'de> for NetEntityVisitor {
+            type Value = NetEntity;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a net entity")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                seq.size_hint()
+                    .and_then(|index| u32::try_from(index).ok())
+                    .map(NetEntity)
+                    .ok_or_else(|| serde::de::Error::custom("invalid net entity"))
+            }
+        }
+        deserializer.deserialize_seq(NetEntityVisitor)
+    }
+}
+
+/// Allocates the [`NetEntity`]s of the entities replicated to a remote, and maps them to the local entities
+#[derive(Default, Debug)]
+pub(crate) struct NetEntityMap {
+    local_to_net: EntityHashMap<Entity, NetEntity>,
+    net_to_local: HashMap<NetEntity, Entity>,
+    next_index: u32,
+    /// Ids that can be reused. We reuse the oldest ids first
+    free: VecDeque<NetEntity>,
+    /// Entities that use the [`NetEntity`] of another entity as their group id
+    /// (see [`ReplicationGroup::Entity`](crate::prelude::ReplicationGroup::Entity))
+    group_members: HashMap<NetEntity, EntityHashSet<Entity>>,
+    member_to_group: EntityHashMap<Entity, NetEntity>,
+    /// Group owners that were despawned for the remote while their [`NetEntity`] is still used as a group id
+    despawned_group_owners: EntityHashMap<Entity, NetEntity>,
+    /// Ids whose despawn was acked, but that will only be reused once their group is empty
+    freed_groups: HashSet<NetEntity>,
+    /// Ids that became reusable since the last call to [`NetEntityMap::take_recycled`]
+    recycled: Vec<NetEntity>,
+}
+
+impl NetEntityMap {
+    pub(crate) fn get(&self, entity: Entity) -> Option<NetEntity> {
+        self.local_to_net.get(&entity).copied()
+    }
+
+    pub(crate) fn get_local(&self, net_entity: NetEntity) -> Option<Entity> {
+        self.net_to_local.get(&net_entity).copied()
+    }
+
+    /// Get the [`NetEntity`] of a local entity, or allocate a new one
+    pub(crate) fn get_or_allocate(&mut self, entity: Entity) -> NetEntity {
+        if let Some(net_entity) = self.local_to_net.get(&entity) {
+            return *net_entity;
+        }
+        let net_entity = self.free.pop_front().unwrap_or_else(|| {
+            let net_entity = NetEntity(self.next_index);
+            self.next_index += 1;
+            net_entity
+        });
+        self.local_to_net.insert(entity, net_entity);
+        self.net_to_local.insert(net_entity, entity);
+        net_entity
+    }
+
+    /// Get the [`NetEntity`] used as the group id of `entity`, whose replication group is derived from `owner`.
+    ///
+    /// The id stays the same even if the owner is despawned, as long as other entities are in the group.
+    pub(crate) fn group_net_entity(&mut self, entity: Entity, owner: Entity) -> NetEntity {
+        let net_entity = match self.despawned_group_owners.get(&owner) {
+            Some(net_entity) => *net_entity,
+            None => self.get_or_allocate(owner),
+        };
+        if entity != owner {
+            if let Some(previous) = self.member_to_group.insert(entity, net_entity) {
+                if previous != net_entity {
+                    self.leave_group(entity, previous);
+                }
+            }
+            self.group_members
+                .entry(net_entity)
+                .or_default()
+                .insert(entity);
+        }
+        net_entity
+    }
+
+    /// Get the [`NetEntity`] used as the group id of the entities whose replication group is derived from `owner`,
+    /// without allocating it
+    pub(crate) fn existing_group_net_entity(&self, owner: Entity) -> Option<NetEntity> {
+        self.get(owner)
+            .or_else(|| self.despawned_group_owners.get(&owner).copied())
+    }
+
+    /// The entity is despawned for the remote: remove it from the map.
+    /// Its [`NetEntity`] cannot be reused until the remote acks the despawn (see [`NetEntityMap::free`])
+    pub(crate) fn remove(&mut self, entity: Entity) -> Option<NetEntity> {
+        let net_entity = self.local_to_net.remove(&entity)?;
+        self.net_to_local.remove(&net_entity);
+        // other entities still use the id as their group id
+        if self.group_members.contains_key(&net_entity) {
+            self.despawned_group_owners.insert(entity, net_entity);
+        }
+        if let Some(group) = self.member_to_group.remove(&entity) {
+            self.leave_group(entity, group);
+        }
+        Some(net_entity)
+    }
+
+    /// The remote acked the despawn of the entity, the [`NetEntity`] can be reused
+    pub(crate) fn free(&mut self, net_entity: NetEntity) {
+        // a despawn is only sent once per allocation of the id, so it cannot be freed twice
+        if self.net_to_local.contains_key(&net_entity) {
+            return;
+        }
+        if self.group_members.contains_key(&net_entity) {
+            self.freed_groups.insert(net_entity);
+            return;
+        }
+        self.recycle(net_entity);
+    }
+
+    /// Return the ids that became reusable since the last call.
+    /// The state of the replication groups that used these ids must be cleared.
+    pub(crate) fn take_recycled(&mut self) -> Vec<NetEntity> {
+        std::mem::take(&mut self.recycled)
+    }
+
+    fn leave_group(&mut self, entity: Entity, group: NetEntity) {
+        let Some(members) = self.group_members.get_mut(&group) else {
+            return;
+        };
+        members.remove(&entity);
+        if members.is_empty() {
+            self.group_members.remove(&group);
+            self.despawned_group_owners
+                .retain(|_, net_entity| *net_entity != group);
+            if self.freed_groups.remove(&group) {
+                self.recycle(group);
+            }
+        }
+    }
+
+    fn recycle(&mut self, net_entity: NetEntity) {
+        self.despawned_group_owners
+            .retain(|_, owner| *owner != net_entity);
+        self.free.push_back(net_entity);
+        self.recycled.push(net_entity);
+    }
+
+    /// Replace the local entities inside a component or message by their [`NetEntity`].
+    ///
+    /// Entities that are not replicated to the remote are sent as unresolved
+    pub(crate) fn map_to_net<M: for<'a> MapEntities<'a>>(&self, value: &mut M) {
+        value.map_entities(Box::new(self));
+    }
+}
+
+impl EntityMapper for NetEntityMap {
+    #[inline]
+    fn map(&self, entity: Entity) -> Option<Entity> {
+        Some(
+            self.get(entity)
+                .map_or(Entity::PLACEHOLDER, NetEntity::to_placeholder),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::serialize::reader::ReadBuffer;
+    use crate::serialize::wordbuffer::reader::ReadWordBuffer;
+    use crate::serialize::wordbuffer::writer::WriteWordBuffer;
+    use crate::serialize::writer::WriteBuffer;
+
+    use super::*;
+
+    #[test]
+    fn test_serialize_net_entity() -> anyhow::Result<()> {
+        let mut writer = WriteWordBuffer::with_capacity(50);
+        writer.serialize(&NetEntity(0))?;
+        assert_eq!(writer.num_bits_written(), 1);
+        writer.serialize(&NetEntity(6))?;
+        assert_eq!(writer.num_bits_written(), 1 + 5);
+        writer.serialize(&vec![(NetEntity(300), 1u8), (NetEntity(u32::MAX), 2u8)])?;
+        let bytes = writer.finish_write();
+
+        let mut reader = ReadWordBuffer::start_read(bytes);
+        assert_eq!(reader.deserialize::<NetEntity>()?, NetEntity(0));
+        assert_eq!(reader.deserialize::<NetEntity>()?, NetEntity(6));
+        assert_eq!(
+            reader.deserialize::<Vec<(NetEntity, u8)>>()?,
+            vec![(NetEntity(300), 1u8), (NetEntity(u32::MAX), 2u8)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_net_entity_recycling() {
+        let mut map = NetEntityMap::default();
+        let entity_1 = Entity::from_raw(10);
+        let entity_2 = Entity::from_raw(20);
+        let entity_3 = Entity::from_raw(30);
+        let net_1 = map.get_or_allocate(entity_1);
+        let net_2 = map.get_or_allocate(entity_2);
+        assert_eq!(net_1, NetEntity(0));
+        assert_eq!(net_2, NetEntity(1));
+        assert_eq!(map.get_or_allocate(entity_1), net_1);
+
+        // the id is not reused until the despawn is acked
+        assert_eq!(map.remove(entity_1), Some(net_1));
+        assert_eq!(map.get_or_allocate(entity_3), NetEntity(2));
+        map.free(net_1);
+        assert_eq!(map.get_or_allocate(entity_1), net_1);
+        assert_eq!(map.get_local(net_1), Some(entity_1));
+        assert_eq!(map.get_or_allocate(Entity::from_raw(40)), NetEntity(3));
+    }
+
+    #[test]
+    fn test_map_to_net() {
+        let mut map = NetEntityMap::default();
+        let entity = Entity::from_raw(10);
+        map.get_or_allocate(Entity::from_raw(20));
+        let mut value = Entity::from_raw(20);
+        map.map_to_net(&mut value);
+        assert_eq!(value, NetEntity(0).to_placeholder());
+
+        // entities that are not replicated are sent as unresolved, without allocating an id
+        let mut value = entity;
+        map.map_to_net(&mut value);
+        assert_eq!(value, Entity::PLACEHOLDER);
+        assert_eq!(map.get(entity), None);
+    }
+
+    #[test]
+    fn test_group_net_entity() {
+        let mut map = NetEntityMap::default();
+        let owner = Entity::from_raw(10);
+        let member = Entity::from_raw(20);
+        let group = map.group_net_entity(owner, owner);
+        map.get_or_allocate(member);
+        assert_eq!(map.group_net_entity(member, owner), group);
+
+        // the owner is despawned: the group keeps its id
+        map.remove(owner);
+        map.free(group);
+        assert_eq!(map.group_net_entity(member, owner), group);
+        assert_eq!(map.existing_group_net_entity(owner), Some(group));
+        assert!(map.take_recycled().is_empty());
+
+        // the id is recycled once the group is empty
+        map.remove(member);
+        assert_eq!(map.take_recycled(), vec![group]);
+        assert_eq!(map.existing_group_net_entity(owner), None);
+        assert_eq!(map.get_or_allocate(Entity::from_raw(30)), group);
+    }
+}
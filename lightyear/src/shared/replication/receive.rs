@@ -2,10 +2,10 @@
 use std::collections::BTreeMap;
 use std::iter::Extend;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use bevy::prelude::{DespawnRecursiveExt, Entity, World};
 use bevy::utils::petgraph::data::ElementIterator;
-use bevy::utils::{EntityHashMap, HashSet};
+use bevy::utils::{EntityHashMap, HashMap, HashSet};
 use tracing::{debug, error, info, trace, trace_span, warn};
 use tracing_subscriber::filter::FilterExt;
 use tracing_subscriber::fmt::writer::MakeWriterExt;
@@ -14,14 +14,17 @@ use crate::connection::events::ConnectionEvents;
 use crate::packet::message::MessageId;
 use crate::prelude::client::Confirmed;
 use crate::prelude::{MapEntities, Tick};
+use crate::protocol::channel::ChannelKind;
 use crate::protocol::component::ComponentProtocol;
 use crate::protocol::component::{ComponentBehaviour, ComponentKindBehaviour};
 use crate::protocol::Protocol;
 use crate::shared::replication::components::ReplicationGroupId;
+use crate::shared::replication::net_entity::NetEntity;
 
 use super::entity_map::RemoteEntityMap;
 use super::{
-    EntityActionMessage, EntityUpdatesMessage, ReplicationMessage, ReplicationMessageData,
+    EntityActionMessage, EntityMessage, EntityUpdatesMessage, ReplicationMessage,
+    ReplicationMessageData,
 };
 
 pub(crate) struct ReplicationReceiver<P: Protocol> {
@@ -29,7 +32,7 @@ pub(crate) struct ReplicationReceiver<P: Protocol> {
     pub remote_entity_map: RemoteEntityMap,
 
     /// Map from remote entity to the replication group-id
-    pub remote_entity_to_group: EntityHashMap<Entity, ReplicationGroupId>,
+    pub remote_entity_to_group: HashMap<NetEntity, ReplicationGroupId>,
 
     // BOTH
     /// Buffer to so that we have an ordered receiver per group
@@ -47,6 +50,16 @@ impl<P: Protocol> ReplicationReceiver<P> {
         }
     }
 
+    /// Despawn all the entities that were received from the remote, and reset the replication state
+    pub(crate) fn reset(&mut self, world: &mut World) {
+        for local_entity in self.remote_entity_map.to_local().values() {
+            if let Some(entity_mut) = world.get_entity_mut(*local_entity) {
+                entity_mut.despawn_recursive();
+            }
+        }
+        *self = Self::new();
+    }
+
     /// Recv a new replication message and buffer it
     pub(crate) fn recv_message(
         &mut self,
@@ -107,6 +120,53 @@ impl<P: Protocol> ReplicationReceiver<P> {
         trace!(?channel, "group channel after buffering");
     }
 
+    /// Recv a new message bound to a replication group, and buffer it until the group reaches the message's tick
+    pub(crate) fn recv_entity_message(
+        &mut self,
+        message: EntityMessage<P::Message>,
+        channel_kind: ChannelKind,
+    ) {
+        trace!(group_id = ?message.group_id, tick = ?message.tick, "Received entity message");
+        self.group_channels
+            .entry(message.group_id)
+            .or_default()
+            .entity_messages
+            .push((message.tick, channel_kind, message.message));
+    }
+
+    /// Return the entity messages whose replication group has reached the tick of the message,
+    /// with their entities mapped to local entities.
+    ///
+    /// The messages bound to a group whose entities have all been despawned are dropped.
+    pub(crate) fn read_entity_messages(&mut self) -> Vec<(ChannelKind, P::Message)> {
+        let mut res = vec![];
+        for (group_id, channel) in self.group_channels.iter_mut() {
+            if channel.entity_messages.is_empty() {
+                continue;
+            }
+            let Some(latest_tick) = channel.latest_tick else {
+                continue;
+            };
+            if channel.remote_entities.is_empty() {
+                debug!(
+                    ?group_id,
+                    "Dropping entity messages for a group that is not replicated anymore"
+                );
+                channel.entity_messages.clear();
+                continue;
+            }
+            let (ready, pending) = std::mem::take(&mut channel.entity_messages)
+                .into_iter()
+                .partition(|(tick, _, _)| *tick <= latest_tick);
+            channel.entity_messages = pending;
+            for (_, channel_kind, mut message) in ready {
+                message.map_entities(Box::new(&self.remote_entity_map));
+                res.push((channel_kind, message));
+            }
+        }
+        res
+    }
+
     /// Return the list of replication messages that are ready to be applied to the World
     /// Also include the server_tick when that replication message was emitted
     ///
@@ -165,11 +225,43 @@ impl<P: Protocol> ReplicationReceiver<P> {
 
     // USED BY RECEIVE SIDE (SEND SIZE CAN GET THE GROUP_ID EASILY)
     /// Get the group channel associated with a given entity
-    fn channel_by_remote(&self, remote_entity: Entity) -> Option<&GroupChannel<P>> {
+    fn channel_by_remote(&self, remote_entity: NetEntity) -> Option<&GroupChannel<P>> {
         self.remote_entity_to_group
             .get(&remote_entity)
             .and_then(|group_id| self.group_channels.get(group_id))
     }
+
+    /// Returns true if the remote entity is currently spawned as part of this group.
+    ///
+    /// The remote can reuse the [`NetEntity`] of a despawned entity for an entity of another group as soon as
+    /// it receives the ack for the despawn, and the groups are not applied in a deterministic order; this lets us
+    /// ignore the stale messages of the previous entity.
+    fn is_in_group(&self, remote_entity: NetEntity, group_id: ReplicationGroupId) -> bool {
+        self.remote_entity_to_group.get(&remote_entity) == Some(&group_id)
+    }
+
+    /// Despawn the local entity that corresponds to a remote entity
+    fn despawn_remote(
+        &mut self,
+        world: &mut World,
+        remote_entity: NetEntity,
+        events: &mut ConnectionEvents<P>,
+    ) {
+        let Some(local_entity) = self.remote_entity_map.remove_by_remote(remote_entity) else {
+            error!("Received despawn for an entity that does not exist");
+            return;
+        };
+        if let Some(group_id) = self.remote_entity_to_group.remove(&remote_entity) {
+            if let Some(group) = self.group_channels.get_mut(&group_id) {
+                group.remote_entities.remove(&remote_entity);
+            }
+        }
+        // TODO: we despawn all children as well right now, but that might not be what we want?
+        if let Some(entity_mut) = world.get_entity_mut(local_entity) {
+            entity_mut.despawn_recursive();
+        }
+        events.push_despawn(local_entity);
+    }
 }
 
 /// We want:
@@ -192,28 +284,39 @@ impl<P: Protocol> ReplicationReceiver<P> {
         replication: ReplicationMessageData<P::Components, P::ComponentKinds>,
         group_id: ReplicationGroupId,
         events: &mut ConnectionEvents<P>,
-    ) {
+    ) -> anyhow::Result<()> {
         let _span = trace_span!("Apply received replication message to world").entered();
         match replication {
             ReplicationMessageData::Actions(m) => {
                 debug!(?tick, ?m, "Received replication actions");
+                if let Some((entity, _)) = m
+                    .actions
+                    .iter()
+                    .find(|(_, actions)| actions.spawn && actions.despawn)
+                {
+                    bail!(
+                        "received both a spawn and a despawn for entity {:?}",
+                        entity
+                    );
+                }
                 // NOTE: order matters here, because some components can depend on other entities.
                 // These components could even form a cycle, for example A.HasWeapon(B) and B.HasHolder(A)
                 // Our solution is to first handle spawn for all entities separately.
                 for (entity, actions) in m.actions.iter() {
                     debug!(remote_entity = ?entity, "Received entity actions");
-                    assert!(!(actions.spawn && actions.despawn));
                     // spawn
                     if actions.spawn {
-                        self.remote_entity_to_group.insert(*entity, group_id);
-                        if let Some(local_entity) = self.remote_entity_map.get_local(*entity) {
-                            if world.get_entity(*local_entity).is_some() {
-                                warn!("Received spawn for an entity that already exists");
+                        if self.remote_entity_map.get_local(*entity).is_some() {
+                            if self.is_in_group(*entity, group_id) {
+                                warn!("Received spawn for an entity that is already in our entity mapping! Not spawning");
                                 continue;
                             }
-                            warn!("Received spawn for an entity that is already in our entity mapping! Not spawning");
-                            continue;
+                            // the id was reused by the remote, but we haven't applied the despawn of the
+                            // previous entity yet
+                            debug!(remote_entity = ?entity, "Received spawn for a reused id, despawning the previous entity");
+                            self.despawn_remote(world, *entity, events);
                         }
+                        self.remote_entity_to_group.insert(*entity, group_id);
                         // TODO: optimization: spawn the bundle of insert components
                         let local_entity = world.spawn_empty();
                         self.remote_entity_map.insert(*entity, local_entity.id());
@@ -227,23 +330,16 @@ impl<P: Protocol> ReplicationReceiver<P> {
                 for (entity, actions) in m.actions.into_iter() {
                     debug!(remote_entity = ?entity, "Received entity actions");
 
+                    // the entity was despawned already, and its id is now used by an entity of another group
+                    if !self.is_in_group(entity, group_id) {
+                        debug!(remote_entity = ?entity, "Ignoring stale actions for an entity that is not in the group");
+                        continue;
+                    }
+
                     // despawn
                     if actions.despawn {
                         debug!(remote_entity = ?entity, "Received entity despawn");
-                        if let Some(local_entity) = self.remote_entity_map.remove_by_remote(entity)
-                        {
-                            if let Some(group) = self.group_channels.get_mut(&group_id) {
-                                group.remote_entities.remove(&entity);
-                            }
-                            // TODO: we despawn all children as well right now, but that might not be what we want?
-                            if let Some(entity_mut) = world.get_entity_mut(local_entity) {
-                                entity_mut.despawn_recursive();
-                            }
-                            events.push_despawn(local_entity);
-                            self.remote_entity_to_group.remove(&entity);
-                        } else {
-                            error!("Received despawn for an entity that does not exist")
-                        }
+                        self.despawn_remote(world, entity, events);
                         continue;
                     }
 
@@ -315,6 +411,10 @@ impl<P: Protocol> ReplicationReceiver<P> {
                 debug!(?tick, ?m, "Received replication updates");
                 for (entity, components) in m.updates.into_iter() {
                     debug!(?components, remote_entity = ?entity, "Received UpdateComponent");
+                    if !self.is_in_group(entity, group_id) {
+                        debug!(remote_entity = ?entity, "Ignoring stale update for an entity that is not in the group");
+                        continue;
+                    }
                     // update the entity only if it exists
                     if let Ok(mut local_entity) =
                         self.remote_entity_map.get_by_remote(world, entity)
@@ -355,6 +455,7 @@ impl<P: Protocol> ReplicationReceiver<P> {
                     }
                 }
             });
+        Ok(())
     }
 }
 
@@ -363,7 +464,7 @@ impl<P: Protocol> ReplicationReceiver<P> {
 pub struct GroupChannel<P: Protocol> {
     // entities
     // set of remote entities that are part of the same Replication Group
-    remote_entities: HashSet<Entity>,
+    remote_entities: HashSet<NetEntity>,
     // actions
     pub actions_pending_recv_message_id: MessageId,
     pub actions_recv_message_buffer:
@@ -379,6 +480,8 @@ pub struct GroupChannel<P: Protocol> {
         BTreeMap<Tick, EntityUpdatesMessage<P::Components>>,
     /// remote tick of the latest update/action that we applied to the local group
     pub latest_tick: Option<Tick>,
+    /// messages bound to this group, waiting for the group to reach their tick
+    pub entity_messages: Vec<(Tick, ChannelKind, P::Message)>,
 }
 
 impl<P: Protocol> Default for GroupChannel<P> {
@@ -390,6 +493,7 @@ impl<P: Protocol> Default for GroupChannel<P> {
             buffered_updates_with_last_action_tick: Default::default(),
             buffered_updates_without_last_action_tick: Default::default(),
             latest_tick: None,
+            entity_messages: Vec::new(),
         }
     }
 }
@@ -632,4 +736,43 @@ mod tests {
         assert_eq!(replication_data.get(1).unwrap().0, Tick(3));
         assert_eq!(replication_data.get(2).unwrap().0, Tick(4));
     }
+
+    #[test]
+    fn test_entity_messages_released_with_group() {
+        let mut manager = ReplicationReceiver::<MyProtocol>::new();
+        let group_id = ReplicationGroupId(0);
+        let channel_kind = ChannelKind::of::<Channel1>();
+        let message = |tick: Tick, value: u32| EntityMessage {
+            group_id,
+            tick,
+            message: MyMessageProtocol::Message2(Message2(value)),
+        };
+
+        // the group has not been replicated yet: the message is buffered
+        manager.recv_entity_message(message(Tick(2), 0), channel_kind);
+        assert!(manager.read_entity_messages().is_empty());
+
+        // the group reaches tick 2: the message is released, but not the more recent one
+        manager.recv_entity_message(message(Tick(3), 1), channel_kind);
+        let channel = manager.group_channels.get_mut(&group_id).unwrap();
+        channel.remote_entities.insert(NetEntity::from_index(0));
+        channel.latest_tick = Some(Tick(2));
+        assert_eq!(
+            manager.read_entity_messages(),
+            vec![(channel_kind, MyMessageProtocol::Message2(Message2(0)))]
+        );
+        assert!(manager.read_entity_messages().is_empty());
+
+        // the group gets despawned: the pending messages are dropped
+        let channel = manager.group_channels.get_mut(&group_id).unwrap();
+        channel.remote_entities.clear();
+        channel.latest_tick = Some(Tick(4));
+        assert!(manager.read_entity_messages().is_empty());
+        assert!(manager
+            .group_channels
+            .get(&group_id)
+            .unwrap()
+            .entity_messages
+            .is_empty());
+    }
 }
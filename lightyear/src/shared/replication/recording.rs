@@ -0,0 +1,143 @@
+//! File format used to store a recording of the replication stream (for replays/killcams)
+//!
+//! A recording is made of:
+//! - a header: the magic bytes `LYRP` followed by the tick duration (in nanoseconds) of the recorded world
+//! - a list of frames. Each frame is prefixed by its length in bytes, and contains all the replication messages
+//!   that were produced by the recorded world for a given tick
+use std::hash::Hash;
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::{bail, Context, Result};
+use bevy::utils::Duration;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::_reexport::{ReadBuffer, ReadWordBuffer, WriteBuffer, WriteWordBuffer};
+use crate::prelude::Tick;
+use crate::protocol::Protocol;
+
+use super::ReplicationMessage;
+
+const MAGIC: &[u8; 4] = b"LYRP";
+
+/// All the replication messages produced by the recorded world for a single tick
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub(crate) struct RecordedFrame<C, K: Hash + Eq> {
+    pub(crate) tick: Tick,
+    pub(crate) messages: Vec<ReplicationMessage<C, K>>,
+}
+
+/// Writes frames to the underlying writer (usually a file)
+pub(crate) struct RecordingWriter {
+    writer: Box<dyn Write + Send + Sync>,
+    buffer: WriteWordBuffer,
+}
+
+impl RecordingWriter {
+    pub(crate) fn new(
+        mut writer: impl Write + Send + Sync + 'static,
+        tick_duration: Duration,
+    ) -> Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_u64::<LittleEndian>(tick_duration.as_nanos() as u64)?;
+        Ok(Self {
+            writer: Box::new(writer),
+            buffer: WriteWordBuffer::with_capacity(1024),
+        })
+    }
+
+    pub(crate) fn write_frame<C: Serialize, K: Serialize + Hash + Eq>(
+        &mut self,
+        frame: &RecordedFrame<C, K>,
+    ) -> Result<()> {
+        self.buffer.start_write();
+        self.buffer.serialize(frame)?;
+        let bytes = self.buffer.finish_write();
+        self.writer.write_u32::<LittleEndian>(bytes.len() as u32)?;
+        self.writer.write_all(bytes)?;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        self.writer.flush().context("could not flush recording")
+    }
+}
+
+/// A recording of the replication stream, loaded in memory
+pub struct Recording<P: Protocol> {
+    /// Tick duration of the world that was recorded
+    pub(crate) tick_duration: Duration,
+    pub(crate) frames: Vec<RecordedFrame<P::Components, P::ComponentKinds>>,
+}
+
+impl<P: Protocol> Recording<P> {
+    /// Read a full recording from a reader (for example a [`std::fs::File`])
+    pub fn read(mut reader: impl Read) -> Result<Self> {
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .context("could not read recording header")?;
+        if &magic != MAGIC {
+            bail!("not a lightyear recording");
+        }
+        let tick_duration = Duration::from_nanos(reader.read_u64::<LittleEndian>()?);
+        let mut frames = vec![];
+        loop {
+            let len = match reader.read_u32::<LittleEndian>() {
+                Ok(len) => len as usize,
+                // the recording can end at any frame boundary
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            let mut bytes = vec![0; len];
+            reader
+                .read_exact(&mut bytes)
+                .context("recording ended in the middle of a frame")?;
+            let mut buffer = ReadWordBuffer::start_read(&bytes);
+            frames.push(buffer.deserialize()?);
+        }
+        Ok(Self {
+            tick_duration,
+            frames,
+        })
+    }
+
+    /// Tick duration of the world that was recorded
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    /// Number of frames (ticks where at least one replication message was produced) in the recording
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Total duration covered by the recording
+    pub fn duration(&self) -> Duration {
+        self.tick_offsets()
+            .last()
+            .map_or(Duration::default(), |offset| self.tick_duration * *offset)
+    }
+
+    /// Offset (in number of ticks since the first frame) of each frame.
+    /// Ticks wrap around, so we unwrap them by accumulating the difference between consecutive frames
+    pub(crate) fn tick_offsets(&self) -> Vec<u32> {
+        let mut offset = 0;
+        let mut previous = self.frames.first().map(|f| f.tick);
+        self.frames
+            .iter()
+            .map(|frame| {
+                if let Some(previous) = previous {
+                    offset += (frame.tick - previous).max(0) as u32;
+                }
+                previous = Some(frame.tick);
+                offset
+            })
+            .collect()
+    }
+}
@@ -18,7 +18,8 @@ use crate::protocol::channel::ChannelKind;
 use crate::protocol::component::ComponentProtocol;
 use crate::protocol::component::{ComponentBehaviour, ComponentKindBehaviour};
 use crate::protocol::Protocol;
-use crate::shared::replication::components::{Replicate, ReplicationGroupId};
+use crate::shared::replication::components::{Replicate, ReplicationGroup, ReplicationGroupId};
+use crate::shared::replication::net_entity::{NetEntity, NetEntityMap};
 
 use super::{EntityActionMessage, EntityActions, EntityUpdatesMessage, ReplicationMessageData};
 
@@ -28,34 +29,47 @@ pub(crate) struct ReplicationSender<P: Protocol> {
     /// Stores the last `Replicate` component for each replicated entity owned by the current world (the world that sends replication updates)
     /// Needed to know the value of the Replicate component after the entity gets despawned, to know how we replicate the EntityDespawn
     pub replicate_component_cache: EntityHashMap<Entity, Replicate<P>>,
+    /// [`NetEntity`]s allocated for the entities that are replicated to the remote
+    pub net_entities: NetEntityMap,
     /// Get notified whenever a message-id that was sent has been received by the remote
     pub updates_ack_tracker: Receiver<MessageId>,
+    /// Get notified whenever an entity actions message has been received by the remote
+    pub actions_ack_tracker: Receiver<MessageId>,
     /// Map from message-id to the corresponding group-id that sent this update message, as well as the bevy ChangeTick
     /// when we sent the message. (so that when it's acked, we know we only need to include updates that happened after that tick,
     /// for that replication group)
     pub updates_message_id_to_group_id: HashMap<MessageId, (ReplicationGroupId, BevyTick)>,
+    /// Map from the message-id of an actions message to the entities that were despawned in that message.
+    /// Their [`NetEntity`] can be reused once the message is acked
+    pub actions_message_id_to_despawns: HashMap<MessageId, Vec<NetEntity>>,
     /// messages that are being written. We need to hold a buffer of messages because components actions/updates
     /// are being buffered individually but we want to group them inside a message
     pub pending_actions: EntityHashMap<
         ReplicationGroupId,
-        HashMap<Entity, EntityActions<P::Components, P::ComponentKinds>>,
+        HashMap<NetEntity, EntityActions<P::Components, P::ComponentKinds>>,
     >,
-    pub pending_updates: EntityHashMap<ReplicationGroupId, HashMap<Entity, Vec<P::Components>>>,
+    pub pending_updates: EntityHashMap<ReplicationGroupId, HashMap<NetEntity, Vec<P::Components>>>,
     // Set of unique components for each entity, to avoid sending multiple updates/inserts for the same component
     pub pending_unique_components:
-        EntityHashMap<ReplicationGroupId, HashMap<Entity, HashSet<P::ComponentKinds>>>,
+        EntityHashMap<ReplicationGroupId, HashMap<NetEntity, HashSet<P::ComponentKinds>>>,
 
     /// Buffer to so that we have an ordered receiver per group
     pub group_channels: EntityHashMap<ReplicationGroupId, GroupChannel>,
 }
 
 impl<P: Protocol> ReplicationSender<P> {
-    pub(crate) fn new(updates_ack_tracker: Receiver<MessageId>) -> Self {
+    pub(crate) fn new(
+        updates_ack_tracker: Receiver<MessageId>,
+        actions_ack_tracker: Receiver<MessageId>,
+    ) -> Self {
         Self {
             // SEND
             replicate_component_cache: EntityHashMap::default(),
+            net_entities: NetEntityMap::default(),
             updates_ack_tracker,
+            actions_ack_tracker,
             updates_message_id_to_group_id: Default::default(),
+            actions_message_id_to_despawns: Default::default(),
             pending_actions: EntityHashMap::default(),
             pending_updates: EntityHashMap::default(),
             pending_unique_components: EntityHashMap::default(),
@@ -80,6 +94,51 @@ impl<P: Protocol> ReplicationSender<P> {
                 );
             }
         }
+        // the remote received the despawns: the NetEntities can be reused
+        while let Ok(message_id) = self.actions_ack_tracker.try_recv() {
+            if let Some(despawns) = self.actions_message_id_to_despawns.remove(&message_id) {
+                despawns
+                    .into_iter()
+                    .for_each(|net_entity| self.net_entities.free(net_entity));
+            }
+        }
+        self.clear_recycled_groups();
+    }
+
+    /// Forget the state of the replication groups whose id was recycled,
+    /// so that the next entity that gets the same [`NetEntity`] doesn't reuse it
+    pub(crate) fn clear_recycled_groups(&mut self) {
+        for net_entity in self.net_entities.take_recycled() {
+            let group_id = ReplicationGroupId(net_entity.index() as u64);
+            self.group_channels.remove(&group_id);
+            self.updates_message_id_to_group_id
+                .retain(|_, (group, _)| *group != group_id);
+        }
+    }
+
+    /// Get the id of the replication group of an entity
+    pub(crate) fn group_id(
+        &mut self,
+        entity: Entity,
+        group: ReplicationGroup,
+    ) -> ReplicationGroupId {
+        group.group_id(entity, &mut self.net_entities)
+    }
+
+    /// Get the replication group of an entity that is currently replicated to the remote,
+    /// as well as the latest tick for which we sent replication messages for that group.
+    ///
+    /// Returns None if the entity is not visible to the remote
+    pub(crate) fn replicated_group(
+        &self,
+        entity: Entity,
+        group: ReplicationGroup,
+    ) -> Option<(ReplicationGroupId, Tick)> {
+        // the entity is despawned on the remote as soon as it stops being replicated to it
+        self.net_entities.get(entity)?;
+        let group_id = group.existing_group_id(entity, &self.net_entities)?;
+        let tick = self.group_channels.get(&group_id)?.last_send_tick?;
+        Some((group_id, tick))
     }
 }
 
@@ -95,7 +154,9 @@ impl<P: Protocol> ReplicationSender<P> {
 
     /// Host has spawned an entity, and we want to replicate this to remote
     /// Returns true if we should send a message
-    pub(crate) fn prepare_entity_spawn(&mut self, entity: Entity, group: ReplicationGroupId) {
+    pub(crate) fn prepare_entity_spawn(&mut self, entity: Entity, group: ReplicationGroup) {
+        let group = self.group_id(entity, group);
+        let entity = self.net_entities.get_or_allocate(entity);
         let actions = self
             .pending_actions
             .entry(group)
@@ -105,7 +166,17 @@ impl<P: Protocol> ReplicationSender<P> {
         actions.spawn = true;
     }
 
-    pub(crate) fn prepare_entity_despawn(&mut self, entity: Entity, group: ReplicationGroupId) {
+    pub(crate) fn prepare_entity_despawn(&mut self, entity: Entity, group: ReplicationGroup) {
+        // the entity was never replicated to the remote
+        if self.net_entities.get(entity).is_none() {
+            return;
+        }
+        let group = self.group_id(entity, group);
+        let Some(entity) = self.net_entities.remove(entity) else {
+            return;
+        };
+        // the entity might have been the last member of a group whose id was already freed
+        self.clear_recycled_groups();
         self.pending_actions
             .entry(group)
             .or_default()
@@ -120,9 +191,12 @@ impl<P: Protocol> ReplicationSender<P> {
     pub(crate) fn prepare_component_insert(
         &mut self,
         entity: Entity,
-        group: ReplicationGroupId,
-        component: P::Components,
+        group: ReplicationGroup,
+        mut component: P::Components,
     ) {
+        let group = self.group_id(entity, group);
+        let entity = self.net_entities.get_or_allocate(entity);
+        self.net_entities.map_to_net(&mut component);
         let kind: P::ComponentKinds = (&component).into();
 
         // special case for ShouldBePredicted:
@@ -193,9 +267,15 @@ impl<P: Protocol> ReplicationSender<P> {
     pub(crate) fn prepare_component_remove(
         &mut self,
         entity: Entity,
-        group: ReplicationGroupId,
+        group: ReplicationGroup,
         kind: P::ComponentKinds,
     ) {
+        // the entity was never replicated to the remote
+        let Some(net_entity) = self.net_entities.get(entity) else {
+            return;
+        };
+        let group = self.group_id(entity, group);
+        let entity = net_entity;
         if self
             .pending_unique_components
             .entry(group)
@@ -224,9 +304,12 @@ impl<P: Protocol> ReplicationSender<P> {
     pub(crate) fn prepare_entity_update(
         &mut self,
         entity: Entity,
-        group: ReplicationGroupId,
-        component: P::Components,
+        group: ReplicationGroup,
+        mut component: P::Components,
     ) {
+        let group = self.group_id(entity, group);
+        let entity = self.net_entities.get_or_allocate(entity);
+        self.net_entities.map_to_net(&mut component);
         let kind: P::ComponentKinds = (&component).into();
         if self
             .pending_unique_components
@@ -288,6 +371,7 @@ impl<P: Protocol> ReplicationSender<P> {
             let message_id = channel.actions_next_send_message_id;
             channel.actions_next_send_message_id += 1;
             channel.last_action_tick = Some(tick);
+            channel.last_send_tick = Some(tick);
             messages.push((
                 ChannelKind::of::<EntityActionsChannel>(),
                 group_id,
@@ -303,6 +387,7 @@ impl<P: Protocol> ReplicationSender<P> {
         for (group_id, updates) in self.pending_updates.drain() {
             trace!(?group_id, "pending updates: {:?}", updates);
             let channel = self.group_channels.entry(group_id).or_default();
+            channel.last_send_tick = Some(tick);
             messages.push((
                 ChannelKind::of::<EntityUpdatesChannel>(),
                 group_id,
@@ -335,6 +420,8 @@ pub struct GroupChannel {
     pub collect_changes_since_this_tick: Option<BevyTick>,
     // last tick for which we sent an action message
     pub last_action_tick: Option<Tick>,
+    /// last tick for which we sent an action or an update message
+    pub last_send_tick: Option<Tick>,
 }
 
 impl Default for GroupChannel {
@@ -342,6 +429,7 @@ impl Default for GroupChannel {
         Self {
             actions_next_send_message_id: MessageId(0),
             last_action_tick: None,
+            last_send_tick: None,
             collect_changes_since_this_tick: None,
         }
     }
@@ -371,14 +459,17 @@ mod tests {
     // TODO: add tests for replication with entity relations!
     #[test]
     fn test_buffer_replication_messages() {
-        let (sender, receiver) = crossbeam_channel::unbounded();
-        let mut manager = ReplicationSender::<MyProtocol>::new(receiver);
+        let (_, updates_receiver) = crossbeam_channel::unbounded();
+        let (_, actions_receiver) = crossbeam_channel::unbounded();
+        let mut manager = ReplicationSender::<MyProtocol>::new(updates_receiver, actions_receiver);
 
         let entity_1 = Entity::from_raw(0);
         let entity_2 = Entity::from_raw(1);
         let entity_3 = Entity::from_raw(2);
-        let group_1 = ReplicationGroupId(0);
-        let group_2 = ReplicationGroupId(1);
+        let replication_group_1 = ReplicationGroup::Group(0);
+        let replication_group_2 = ReplicationGroup::Group(1);
+        let group_1 = manager.group_id(entity_1, replication_group_1);
+        let group_2 = manager.group_id(entity_3, replication_group_2);
 
         manager.group_channels.insert(
             group_1,
@@ -396,29 +487,33 @@ mod tests {
         );
 
         // updates should be grouped with actions
-        manager.prepare_entity_spawn(entity_1, group_1);
+        manager.prepare_entity_spawn(entity_1, replication_group_1);
         manager.prepare_component_insert(
             entity_1,
-            group_1,
+            replication_group_1,
             MyComponentsProtocol::Component1(Component1(1.0)),
         );
-        manager.prepare_component_remove(entity_1, group_1, MyComponentsProtocolKind::Component2);
+        manager.prepare_component_remove(
+            entity_1,
+            replication_group_1,
+            MyComponentsProtocolKind::Component2,
+        );
         manager.prepare_entity_update(
             entity_1,
-            group_1,
+            replication_group_1,
             MyComponentsProtocol::Component3(Component3(3.0)),
         );
 
         // handle another entity in the same group: will be added to EntityActions as well
         manager.prepare_entity_update(
             entity_2,
-            group_1,
+            replication_group_1,
             MyComponentsProtocol::Component2(Component2(4.0)),
         );
 
         manager.prepare_entity_update(
             entity_3,
-            group_2,
+            replication_group_2,
             MyComponentsProtocol::Component3(Component3(5.0)),
         );
 
@@ -432,10 +527,10 @@ mod tests {
         };
         assert_eq!(a.sequence_id, MessageId(2));
         assert_eq!(
-            EntityHashMap::from_iter(a.actions.clone()),
-            EntityHashMap::from_iter(vec![
+            HashMap::from_iter(a.actions.clone()),
+            HashMap::from_iter(vec![
                 (
-                    entity_1,
+                    NetEntity::from_index(0),
                     EntityActions {
                         spawn: true,
                         despawn: false,
@@ -445,7 +540,7 @@ mod tests {
                     }
                 ),
                 (
-                    entity_2,
+                    NetEntity::from_index(1),
                     EntityActions {
                         spawn: false,
                         despawn: false,
@@ -466,7 +561,7 @@ mod tests {
                 ReplicationMessageData::Updates(EntityUpdatesMessage {
                     last_action_tick: Some(Tick(3)),
                     updates: vec![(
-                        entity_3,
+                        NetEntity::from_index(2),
                         vec![MyComponentsProtocol::Component3(Component3(5.0))]
                     )],
                 })
@@ -489,4 +584,36 @@ mod tests {
             Some(Tick(2))
         );
     }
+
+    #[test]
+    fn test_recycle_net_entity_on_despawn_ack() {
+        let (_, updates_receiver) = crossbeam_channel::unbounded();
+        let (actions_sender, actions_receiver) = crossbeam_channel::unbounded();
+        let mut manager = ReplicationSender::<MyProtocol>::new(updates_receiver, actions_receiver);
+
+        let entity_1 = Entity::from_raw(0);
+        let entity_2 = Entity::from_raw(1);
+        manager.prepare_entity_spawn(entity_1, ReplicationGroup::FromEntity);
+        let net_entity = manager.net_entities.get(entity_1).unwrap();
+
+        manager.prepare_entity_despawn(entity_1, ReplicationGroup::FromEntity);
+        let message = manager.finalize(Tick(0));
+        let ReplicationMessageData::Actions(ref actions) = message.first().unwrap().2 else {
+            panic!()
+        };
+        assert_eq!(actions.despawned_entities(), vec![net_entity]);
+        manager
+            .actions_message_id_to_despawns
+            .insert(MessageId(0), actions.despawned_entities());
+
+        // the id is not reused until the despawn is acked
+        manager.prepare_entity_spawn(entity_2, ReplicationGroup::FromEntity);
+        assert_ne!(manager.net_entities.get(entity_2), Some(net_entity));
+        manager.prepare_entity_despawn(entity_2, ReplicationGroup::FromEntity);
+
+        actions_sender.send(MessageId(0)).unwrap();
+        manager.recv_update_acks();
+        manager.prepare_entity_spawn(entity_1, ReplicationGroup::FromEntity);
+        assert_eq!(manager.net_entities.get(entity_1), Some(net_entity));
+    }
 }
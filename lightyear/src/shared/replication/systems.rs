@@ -10,6 +10,7 @@ use bevy::prelude::{
 use tracing::{debug, error, trace, warn};
 
 use crate::_reexport::{FromType, ShouldBeInterpolated};
+use crate::netcode::ClientId;
 use crate::prelude::{MainSet, NetworkTarget, ShouldBePredicted};
 use crate::protocol::Protocol;
 use crate::server::room::ClientVisibility;
@@ -111,6 +112,26 @@ fn send_entity_despawn<P: Protocol, R: ReplicationSend<P>>(
     }
 }
 
+/// Newly connected clients that should receive the initial state of an entity in Room mode.
+/// Clients that just gained visibility of the entity are excluded, they already receive it through the room.
+fn new_connected_room_clients<P: Protocol, R: ReplicationSend<P>>(
+    sender: &R,
+    entity: Entity,
+    replicate: &Replicate<P>,
+) -> Vec<ClientId> {
+    sender
+        .new_connected_clients(entity)
+        .into_iter()
+        .filter(|client_id| {
+            replicate.replication_target.should_send_to(client_id)
+                && !matches!(
+                    replicate.replication_clients_cache.get(client_id),
+                    Some(ClientVisibility::Gained)
+                )
+        })
+        .collect()
+}
+
 // TODO: maybe there was no point in making this generic in replication send; because
 //  connect-events is only available on the server ? or should we also add it in the client ?
 //  we can also separate the on_connect part to a separate system
@@ -122,9 +143,23 @@ fn send_entity_spawn<P: Protocol, R: ReplicationSend<P>>(
     // Replicate to already connected clients (replicate only new entities)
     query.iter().for_each(|(entity, replicate)| {
         match replicate.replication_mode {
-            // for room mode, no need to handle newly-connected clients specially; they just need
-            // to be added to the correct room
+            // for room mode, newly-connected clients just need to be added to the correct room;
+            // the ones that were already in the entity's rooms when they connected receive it here
             ReplicationMode::Room => {
+                let new_connected_clients =
+                    new_connected_room_clients(&*sender, entity, &replicate);
+                if !new_connected_clients.is_empty() {
+                    let _ = sender
+                        .prepare_entity_spawn(
+                            entity,
+                            &replicate,
+                            NetworkTarget::Only(new_connected_clients),
+                            system_bevy_ticks.this_run(),
+                        )
+                        .map_err(|e| {
+                            error!("error sending entity spawn: {:?}", e);
+                        });
+                }
                 replicate
                     .replication_clients_cache
                     .iter()
@@ -172,7 +207,7 @@ fn send_entity_spawn<P: Protocol, R: ReplicationSend<P>>(
             ReplicationMode::NetworkTarget => {
                 let mut target = replicate.replication_target.clone();
 
-                let new_connected_clients = sender.new_connected_clients().clone();
+                let new_connected_clients = sender.new_connected_clients(entity);
                 if !new_connected_clients.is_empty() {
                     // replicate to the newly connected clients that match our target
                     let mut new_connected_target = target.clone();
@@ -241,6 +276,20 @@ fn send_component_update<C: Component + Clone, P: Protocol, R: ReplicationSend<P
         }
         match replicate.replication_mode {
             ReplicationMode::Room => {
+                let new_connected_clients = new_connected_room_clients(&*sender, entity, replicate);
+                if !new_connected_clients.is_empty() {
+                    let _ = sender
+                        .prepare_component_insert(
+                            entity,
+                            component.clone().into(),
+                            replicate,
+                            replicate.target::<C>(NetworkTarget::Only(new_connected_clients)),
+                            system_bevy_ticks.this_run(),
+                        )
+                        .map_err(|e| {
+                            error!("error sending component insert: {:?}", e);
+                        });
+                }
                 replicate
                     .replication_clients_cache
                     .iter()
@@ -305,7 +354,7 @@ fn send_component_update<C: Component + Clone, P: Protocol, R: ReplicationSend<P
             ReplicationMode::NetworkTarget => {
                 let mut target = replicate.replication_target.clone();
 
-                let new_connected_clients = sender.new_connected_clients().clone();
+                let new_connected_clients = sender.new_connected_clients(entity);
                 // replicate all components to newly connected clients
                 if !new_connected_clients.is_empty() {
                     // replicate to the newly connected clients that match our target
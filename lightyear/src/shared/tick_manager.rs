@@ -3,10 +3,11 @@ use bevy::utils::Duration;
 
 use crate::_reexport::WrappedTime;
 use crate::client::prediction::plugin::is_in_rollback;
-use crate::client::prediction::Rollback;
+use crate::client::prediction::{Rollback, RollbackState};
 use crate::prelude::FixedUpdateSet;
 use bevy::prelude::*;
-use tracing::{info, trace};
+use bitcode::{Decode, Encode};
+use tracing::{debug, info, trace};
 
 use crate::utils::wrapping_id::wrapping_id;
 
@@ -23,9 +24,28 @@ pub enum TickEvent {
     TickSnap { old_tick: Tick, new_tick: Tick },
 }
 
-fn increment_tick(mut tick_manager: ResMut<TickManager>) {
+fn increment_tick(mut tick_manager: ResMut<TickManager>, mut fixed_time: ResMut<Time<Fixed>>) {
     tick_manager.increment_tick();
     trace!("increment_tick! new tick: {:?}", tick_manager.tick());
+    // the tick duration might have changed at this tick
+    let tick_duration = tick_manager.tick_duration();
+    if fixed_time.timestep() != tick_duration {
+        debug!(tick = ?tick_manager.tick(), ?tick_duration, "Updating the fixed timestep");
+        fixed_time.set_timestep(tick_duration);
+    }
+}
+
+/// Returns true if the simulation is paused at the current tick (or at the tick being re-simulated
+/// during a rollback)
+///
+/// While the simulation is paused, the ticks keep being incremented (so that the client stays in sync
+/// with the server), but the [`FixedUpdateSet::Main`] systems do not run
+pub fn is_paused(tick_manager: Res<TickManager>, rollback: Option<Res<Rollback>>) -> bool {
+    let tick = match rollback.as_ref().map(|r| &r.state) {
+        Some(RollbackState::ShouldRollback { current_tick }) => *current_tick,
+        _ => tick_manager.tick(),
+    };
+    tick_manager.is_paused_at(tick)
 }
 
 impl Plugin for TickManagerPlugin {
@@ -46,11 +66,13 @@ impl Plugin for TickManagerPlugin {
                         .run_if(not(resource_exists::<Rollback>()).or_else(not(is_in_rollback))),
                     apply_deferred.in_set(FixedUpdateSet::MainFlush),
                 ),
-            );
+            )
+            // the game logic doesn't run while the simulation is paused
+            .configure_sets(FixedUpdate, FixedUpdateSet::Main.run_if(not(is_paused)));
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct TickConfig {
     pub tick_duration: Duration,
 }
@@ -61,6 +83,130 @@ impl TickConfig {
     }
 }
 
+/// Maximum number of tick duration changes that we keep track of
+/// (we still need the older ones to convert the times that are slightly in the past, for example
+/// the interpolation time)
+const MAX_TICK_DURATION_CHANGES: usize = 8;
+
+/// A change of the timeline (tick duration, pause state or time scale) that starts at a given tick.
+/// Ticks are counted including their wrap-arounds, so that we can map any tick to a time.
+///
+/// The server shares its changes with the clients, so that they agree on the time of each tick
+#[derive(Encode, Decode, Clone, Copy, Debug, PartialEq)]
+pub enum TimelineChange {
+    /// The duration of a tick changes
+    TickDuration {
+        /// First tick (generation included) that uses the new tick duration
+        start_tick: u32,
+        /// Time at which `start_tick` starts, in nanoseconds
+        start_time_nanos: u64,
+        /// New duration of a tick, in nanoseconds
+        tick_duration_nanos: u64,
+    },
+    /// The simulation is paused or resumed
+    Pause { start_tick: u32, paused: bool },
+    /// The relative speed of the game time compared to the real time changes
+    TimeScale { start_tick: u32, time_scale: f32 },
+}
+
+impl TimelineChange {
+    /// Tick (generation included) at which the change takes effect
+    pub(crate) fn start_tick(&self) -> u32 {
+        match self {
+            TimelineChange::TickDuration { start_tick, .. }
+            | TimelineChange::Pause { start_tick, .. }
+            | TimelineChange::TimeScale { start_tick, .. } => *start_tick,
+        }
+    }
+
+    /// Tick at which the change takes effect
+    pub fn tick(&self) -> Tick {
+        Tick(self.start_tick() as u16)
+    }
+}
+
+/// Duration of the ticks, starting at a given tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TickDurationChange {
+    /// First tick (generation included) that uses the new tick duration
+    start_tick: u32,
+    /// Time at which `start_tick` starts, in nanoseconds
+    start_time_nanos: u64,
+    /// Duration of a tick, in nanoseconds
+    tick_duration_nanos: u64,
+}
+
+impl TickDurationChange {
+    fn tick_duration(&self) -> Duration {
+        Duration::from_nanos(self.tick_duration_nanos)
+    }
+
+    fn start_time(&self) -> Duration {
+        Duration::from_nanos(self.start_time_nanos)
+    }
+
+    fn time_at(&self, absolute_tick: u32) -> Duration {
+        let nanos = self.start_time_nanos as i128
+            + (absolute_tick as i128 - self.start_tick as i128) * self.tick_duration_nanos as i128;
+        Duration::from_nanos(nanos.max(0) as u64)
+    }
+
+    fn absolute_tick_at(&self, time: Duration) -> u32 {
+        let ticks = (time.as_nanos() as i128 - self.start_time_nanos as i128)
+            .div_euclid(self.tick_duration_nanos.max(1) as i128);
+        (self.start_tick as i128 + ticks).clamp(0, u32::MAX as i128) as u32
+    }
+}
+
+/// Latest value of a timeline setting, which starts at a given tick.
+/// We also keep the value from before that tick, since changes are scheduled in the future
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ScheduledValue<T> {
+    /// Tick (generation included) at which the previous value started
+    previous_start_tick: u32,
+    previous: T,
+    /// Tick (generation included) at which the latest value starts
+    start_tick: u32,
+    value: T,
+}
+
+impl<T: Copy> ScheduledValue<T> {
+    fn new(value: T) -> Self {
+        Self {
+            previous_start_tick: 0,
+            previous: value,
+            start_tick: 0,
+            value,
+        }
+    }
+
+    fn at(&self, absolute_tick: u32) -> T {
+        if absolute_tick >= self.start_tick {
+            self.value
+        } else {
+            self.previous
+        }
+    }
+
+    /// Set the value starting at `start_tick`.
+    /// A change that arrives out of order and starts before the latest change can only replace the previous value
+    fn set(&mut self, start_tick: u32, value: T) {
+        if start_tick < self.start_tick {
+            if start_tick >= self.previous_start_tick {
+                self.previous_start_tick = start_tick;
+                self.previous = value;
+            }
+            return;
+        }
+        if start_tick > self.start_tick {
+            self.previous_start_tick = self.start_tick;
+            self.previous = self.value;
+        }
+        self.start_tick = start_tick;
+        self.value = value;
+    }
+}
+
 /// Manages the tick for the host system. Ticks are incremented by one every time
 /// the [`bevy::prelude::FixedUpdate`] schedule runs
 #[derive(Resource)]
@@ -69,13 +215,29 @@ pub struct TickManager {
     pub config: TickConfig,
     /// Current tick (sequence number of the FixedUpdate schedule)
     tick: Tick,
+    /// Number of times the tick wrapped around
+    generation: u16,
+    /// Changes of the tick duration, sorted by tick.
+    /// The first element contains the tick duration from the config
+    tick_duration_changes: Vec<TickDurationChange>,
+    paused: ScheduledValue<bool>,
+    time_scale: ScheduledValue<f32>,
 }
 
 impl TickManager {
     pub(crate) fn from_config(config: TickConfig) -> Self {
+        let initial = TickDurationChange {
+            start_tick: 0,
+            start_time_nanos: 0,
+            tick_duration_nanos: config.tick_duration.as_nanos() as u64,
+        };
         Self {
             config,
             tick: Tick(0),
+            generation: 0,
+            tick_duration_changes: vec![initial],
+            paused: ScheduledValue::new(false),
+            time_scale: ScheduledValue::new(1.0),
         }
     }
 
@@ -83,14 +245,31 @@ impl TickManager {
     #[doc(hidden)]
     pub fn increment_tick(&mut self) {
         self.tick += 1;
+        if self.tick.0 == 0 {
+            self.generation = self.generation.wrapping_add(1);
+        }
         trace!(new_tick = ?self.tick, "incremented tick")
     }
     pub(crate) fn set_tick_to(&mut self, tick: Tick) -> TickEvent {
+        // we assume that the new tick is the one closest to the current tick
+        let absolute_tick = self.absolute_tick() as i64 + (tick - self.tick) as i64;
+        // there are no ticks before tick 0
+        let absolute_tick = if absolute_tick < 0 {
+            tick.0 as i64
+        } else {
+            absolute_tick
+        };
+        self.set_absolute_tick(absolute_tick.min(u32::MAX as i64) as u32)
+    }
+
+    /// Set the tick, including its generation
+    pub(crate) fn set_absolute_tick(&mut self, absolute_tick: u32) -> TickEvent {
         let old_tick = self.tick;
-        self.tick = tick;
+        self.tick = Tick(absolute_tick as u16);
+        self.generation = (absolute_tick >> 16) as u16;
         TickEvent::TickSnap {
             old_tick,
-            new_tick: tick,
+            new_tick: self.tick,
         }
     }
 
@@ -98,4 +277,302 @@ impl TickManager {
     pub fn tick(&self) -> Tick {
         self.tick
     }
+
+    /// The current tick, including the number of times it wrapped around
+    pub(crate) fn absolute_tick(&self) -> u32 {
+        ((self.generation as u32) << 16) | self.tick.0 as u32
+    }
+
+    /// Duration of the current tick.
+    ///
+    /// This can differ from the duration in the [`TickConfig`] if the server changed the tick rate
+    pub fn tick_duration(&self) -> Duration {
+        self.change_for_tick(self.absolute_tick()).tick_duration()
+    }
+
+    /// Returns true if the simulation is paused at the given tick
+    pub fn is_paused_at(&self, tick: Tick) -> bool {
+        self.paused.at(self.to_absolute_tick(tick))
+    }
+
+    /// Relative speed of the game time compared to the real time at the current tick
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale.at(self.absolute_tick())
+    }
+
+    /// Changes that describe the current timeline: the tick duration changes that we know of, and the latest
+    /// pause state and time scale. Applying them to a new [`TickManager`] reproduces this timeline
+    pub fn timeline_changes(&self) -> Vec<TimelineChange> {
+        let mut changes: Vec<TimelineChange> = self
+            .tick_duration_changes
+            .iter()
+            .map(|change| TimelineChange::TickDuration {
+                start_tick: change.start_tick,
+                start_time_nanos: change.start_time_nanos,
+                tick_duration_nanos: change.tick_duration_nanos,
+            })
+            .collect();
+        changes.push(TimelineChange::Pause {
+            start_tick: self.paused.previous_start_tick,
+            paused: self.paused.previous,
+        });
+        changes.push(TimelineChange::Pause {
+            start_tick: self.paused.start_tick,
+            paused: self.paused.value,
+        });
+        changes.push(TimelineChange::TimeScale {
+            start_tick: self.time_scale.previous_start_tick,
+            time_scale: self.time_scale.previous,
+        });
+        changes.push(TimelineChange::TimeScale {
+            start_tick: self.time_scale.start_tick,
+            time_scale: self.time_scale.value,
+        });
+        changes
+    }
+
+    /// Change the duration of the ticks, starting at tick `start_tick` (which must not be in the past).
+    /// Returns the change, which must be shared with the remote
+    pub(crate) fn schedule_tick_duration(
+        &mut self,
+        start_tick: Tick,
+        tick_duration: Duration,
+    ) -> TimelineChange {
+        // a change cannot start before the changes that were already shared with the remote
+        let start_tick = std::cmp::max(
+            self.schedule_tick(start_tick),
+            self.tick_duration_changes.last().unwrap().start_tick,
+        );
+        let change = TimelineChange::TickDuration {
+            start_tick,
+            start_time_nanos: self
+                .change_for_tick(start_tick)
+                .time_at(start_tick)
+                .as_nanos() as u64,
+            tick_duration_nanos: tick_duration.as_nanos() as u64,
+        };
+        self.apply_timeline_change(change);
+        change
+    }
+
+    /// Pause or resume the simulation, starting at tick `start_tick` (which must not be in the past).
+    /// Returns the change, which must be shared with the remote
+    pub(crate) fn schedule_pause(&mut self, start_tick: Tick, paused: bool) -> TimelineChange {
+        let change = TimelineChange::Pause {
+            start_tick: std::cmp::max(self.schedule_tick(start_tick), self.paused.start_tick),
+            paused,
+        };
+        self.apply_timeline_change(change);
+        change
+    }
+
+    /// Change the speed of the game time, starting at tick `start_tick` (which must not be in the past).
+    /// Returns the change, which must be shared with the remote
+    pub(crate) fn schedule_time_scale(
+        &mut self,
+        start_tick: Tick,
+        time_scale: f32,
+    ) -> TimelineChange {
+        let change = TimelineChange::TimeScale {
+            start_tick: std::cmp::max(self.schedule_tick(start_tick), self.time_scale.start_tick),
+            time_scale,
+        };
+        self.apply_timeline_change(change);
+        change
+    }
+
+    /// Absolute tick of a change scheduled at `start_tick` (or now if `start_tick` is in the past)
+    fn schedule_tick(&self, start_tick: Tick) -> u32 {
+        self.absolute_tick() + (start_tick - self.tick).max(0) as u32
+    }
+
+    /// Apply a timeline change that was decided by the remote.
+    /// The changes can arrive in any order; a change replaces the change of the same setting
+    /// that starts at the same tick.
+    pub(crate) fn apply_timeline_change(&mut self, change: TimelineChange) {
+        debug!(?change, "Applying timeline change");
+        match change {
+            TimelineChange::TickDuration {
+                start_tick,
+                start_time_nanos,
+                tick_duration_nanos,
+            } => {
+                let change = TickDurationChange {
+                    start_tick,
+                    start_time_nanos,
+                    tick_duration_nanos,
+                };
+                // keep the changes sorted by tick
+                match self
+                    .tick_duration_changes
+                    .binary_search_by_key(&start_tick, |c| c.start_tick)
+                {
+                    Ok(index) => self.tick_duration_changes[index] = change,
+                    Err(index) => self.tick_duration_changes.insert(index, change),
+                }
+                if self.tick_duration_changes.len() > MAX_TICK_DURATION_CHANGES {
+                    self.tick_duration_changes.remove(0);
+                }
+            }
+            TimelineChange::Pause { start_tick, paused } => self.paused.set(start_tick, paused),
+            TimelineChange::TimeScale {
+                start_tick,
+                time_scale,
+            } => self.time_scale.set(start_tick, time_scale),
+        }
+    }
+
+    /// Convert a tick to an absolute tick, assuming that it is close to the current tick
+    fn to_absolute_tick(&self, tick: Tick) -> u32 {
+        (self.absolute_tick() as i64 + (tick - self.tick) as i64).clamp(0, u32::MAX as i64) as u32
+    }
+
+    fn change_for_tick(&self, absolute_tick: u32) -> &TickDurationChange {
+        self.tick_duration_changes
+            .iter()
+            .rev()
+            .find(|c| c.start_tick <= absolute_tick)
+            .unwrap_or(&self.tick_duration_changes[0])
+    }
+
+    fn change_for_time(&self, time: Duration) -> &TickDurationChange {
+        self.tick_duration_changes
+            .iter()
+            .rev()
+            .find(|c| c.start_time() <= time)
+            .unwrap_or(&self.tick_duration_changes[0])
+    }
+
+    /// Time at which the tick starts, taking into account all the changes of tick duration
+    pub(crate) fn tick_to_time(&self, tick: Tick, generation: u16) -> WrappedTime {
+        let absolute_tick = ((generation as u32) << 16) | tick.0 as u32;
+        WrappedTime::from_duration(self.change_for_tick(absolute_tick).time_at(absolute_tick))
+    }
+
+    /// The tick (including its generation) that is running at the given time
+    pub(crate) fn time_to_absolute_tick(&self, time: WrappedTime) -> u32 {
+        let time = time.to_duration();
+        self.change_for_time(time).absolute_tick_at(time)
+    }
+
+    /// The tick that is running at the given time
+    pub(crate) fn time_to_tick(&self, time: WrappedTime) -> Tick {
+        Tick(self.time_to_absolute_tick(time) as u16)
+    }
+
+    /// The wrapping 'generation' of the tick, by looking at the corresponding time
+    /// (the tick doesn't have to match the time exactly)
+    pub(crate) fn tick_generation(&self, time: WrappedTime, tick: Tick) -> u16 {
+        let absolute_tick = self.time_to_absolute_tick(time);
+        let gen = (absolute_tick >> 16) as u16;
+        let tick_from_time = (absolute_tick as u16) as i32;
+        let tick_from_tick = tick.0 as i32;
+        // case 1: tick |G| tick_from_time
+        if tick_from_time - tick_from_tick > i16::MAX as i32 {
+            gen.saturating_add(1)
+        // case 2: tick_from_time |G| tick
+        } else if tick_from_time - tick_from_tick < i16::MIN as i32 {
+            gen.saturating_sub(1)
+        // case 3: |G| tick_from_time tick |G+1|
+        } else {
+            gen
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_duration_change() {
+        let mut tick_manager = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+        tick_manager.set_tick_to(Tick(100));
+        let change = tick_manager.schedule_tick_duration(Tick(110), Duration::from_millis(20));
+        assert_eq!(change.tick(), Tick(110));
+        assert_eq!(tick_manager.tick_duration(), Duration::from_millis(10));
+
+        // ticks before the change are not affected
+        assert_eq!(
+            tick_manager.tick_to_time(Tick(105), 0),
+            WrappedTime::from_duration(Duration::from_millis(1050))
+        );
+        // ticks after the change use the new tick duration
+        assert_eq!(
+            tick_manager.tick_to_time(Tick(115), 0),
+            WrappedTime::from_duration(Duration::from_millis(1200))
+        );
+        assert_eq!(
+            tick_manager.time_to_tick(WrappedTime::from_duration(Duration::from_millis(1095))),
+            Tick(109)
+        );
+        assert_eq!(
+            tick_manager.time_to_tick(WrappedTime::from_duration(Duration::from_millis(1215))),
+            Tick(115)
+        );
+
+        // the new duration is used once we reach the tick of the change
+        for _ in 0..10 {
+            tick_manager.increment_tick();
+        }
+        assert_eq!(tick_manager.tick_duration(), Duration::from_millis(20));
+
+        // the remote applies the same change
+        let mut remote = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+        remote.apply_timeline_change(change);
+        assert_eq!(
+            remote.tick_to_time(Tick(115), 0),
+            tick_manager.tick_to_time(Tick(115), 0)
+        );
+    }
+
+    #[test]
+    fn test_timeline_changes_for_late_joiners() {
+        let mut tick_manager = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+        tick_manager.set_tick_to(Tick(100));
+        tick_manager.schedule_tick_duration(Tick(110), Duration::from_millis(20));
+        // toggling the pause many times does not evict the tick duration changes
+        for i in 0..20 {
+            tick_manager.schedule_pause(Tick(120 + i), i % 2 == 0);
+        }
+        tick_manager.schedule_time_scale(Tick(150), 0.5);
+        assert_eq!(tick_manager.tick_duration_changes.len(), 2);
+        assert!(tick_manager.is_paused_at(Tick(138)));
+        assert!(!tick_manager.is_paused_at(Tick(139)));
+
+        // a late-joining client can reproduce the timeline, even if the changes arrive in any order
+        let mut remote = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+        remote.set_tick_to(Tick(100));
+        for change in tick_manager.timeline_changes().into_iter().rev() {
+            remote.apply_timeline_change(change);
+        }
+        assert_eq!(
+            remote.tick_duration_changes,
+            tick_manager.tick_duration_changes
+        );
+        assert_eq!(
+            remote.tick_to_time(Tick(115), 0),
+            tick_manager.tick_to_time(Tick(115), 0)
+        );
+        assert!(remote.is_paused_at(Tick(138)));
+        assert!(!remote.is_paused_at(Tick(139)));
+        assert_eq!(remote.time_scale(), 1.0);
+        for _ in 0..50 {
+            remote.increment_tick();
+        }
+        assert_eq!(remote.time_scale(), 0.5);
+        assert_eq!(remote.tick_duration(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_tick_generation_after_wrap() {
+        let mut tick_manager = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+        tick_manager.set_tick_to(Tick(u16::MAX));
+        tick_manager.increment_tick();
+        assert_eq!(tick_manager.tick(), Tick(0));
+        assert_eq!(tick_manager.absolute_tick(), u16::MAX as u32 + 1);
+        let time = tick_manager.tick_to_time(Tick(0), 1);
+        assert_eq!(tick_manager.tick_generation(time, Tick(0)), 1);
+        assert_eq!(tick_manager.tick_generation(time, Tick(u16::MAX)), 0);
+    }
 }
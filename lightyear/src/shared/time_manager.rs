@@ -15,8 +15,10 @@ use std::cmp::Ordering;
 use std::fmt::Formatter;
 use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
 
-use crate::prelude::Tick;
-use bevy::prelude::{IntoSystemConfigs, Plugin, Res, ResMut, Resource, Time, Timer, TimerMode};
+use crate::prelude::{MainSet, Tick, TickManager};
+use bevy::prelude::{
+    IntoSystemConfigs, Plugin, PostUpdate, Res, ResMut, Resource, Time, Timer, TimerMode,
+};
 use bevy::time::{Fixed, Virtual};
 use bevy::utils::Instant;
 use chrono::Duration as ChronoDuration;
@@ -45,6 +47,20 @@ impl Plugin for TimePlugin {
             RunFixedUpdateLoop,
             update_overstep.after(bevy::time::run_fixed_update_schedule),
         );
+        app.add_systems(PostUpdate, update_time_scale.before(MainSet::Sync));
+    }
+}
+
+/// Apply the time scale decided by the server to the virtual time
+fn update_time_scale(
+    tick_manager: Res<TickManager>,
+    mut time_manager: ResMut<TimeManager>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    let time_scale = tick_manager.time_scale();
+    if time_manager.base_relative_speed != time_scale {
+        time_manager.base_relative_speed = time_scale;
+        virtual_time.set_relative_speed(time_manager.get_relative_speed());
     }
 }
 
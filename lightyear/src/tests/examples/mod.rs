@@ -1 +0,0 @@
-mod connection_soak;
@@ -0,0 +1,214 @@
+//! Fuzzing harness for the decoding of the data received from a remote peer
+//!
+//! The harness feeds arbitrary packets to a server [`Connection`], as if they had been received from a client:
+//! decoding the packets, re-assembling the fragments and decoding the messages must never panic.
+//! An invalid input can only make the connection return an error (and the client is then disconnected).
+//!
+//! - [`test_fuzz_corpus`] replays the regression corpus in `src/tests/fuzz_corpus`
+//! - [`test_fuzz_mutations`] randomly mutates valid packets
+//!
+//! Each file of the corpus contains a list of packets, each prefixed by its length (u16, little-endian).
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+use bevy::prelude::World;
+use bevy::utils::Duration;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::_reexport::*;
+use crate::connection::message::ClientMessage;
+use crate::packet::message_manager::MessageManager;
+use crate::packet::packet::FRAGMENT_SIZE;
+use crate::prelude::client::*;
+use crate::prelude::*;
+use crate::server::connection::Connection;
+use crate::tests::protocol::*;
+use crate::tests::stepper::{BevyStepper, Step};
+
+/// Receive the packets on a new server connection, then read the messages they contain.
+/// Returns an error if the data was invalid.
+fn recv_packets(packets: &[Vec<u8>]) -> anyhow::Result<()> {
+    let protocol = protocol();
+    let mut connection =
+        Connection::<MyProtocol>::new(protocol.channel_registry(), &PingConfig::default());
+    let time_manager = TimeManager::new(Duration::default());
+    let tick_manager = TickManager::from_config(TickConfig::new(Duration::from_millis(10)));
+    let mut world = World::new();
+    for packet in packets {
+        connection.recv_packet(&mut ReadWordBuffer::start_read(packet), &tick_manager)?;
+    }
+    connection.receive(&mut world, &time_manager, &tick_manager)?;
+    Ok(())
+}
+
+/// Run the harness on the packets, and fail with the input (in the corpus format) if it panicked
+fn check_no_panic(packets: &[Vec<u8>]) {
+    if catch_unwind(AssertUnwindSafe(|| recv_packets(packets))).is_err() {
+        let input: String = encode_corpus_entry(packets)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        panic!(
+            "decoding panicked; add the input to the corpus with: \
+            echo {} | xxd -r -p > src/tests/fuzz_corpus/<name>.bin",
+            input
+        );
+    }
+}
+
+fn encode_corpus_entry(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for packet in packets {
+        bytes.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(packet);
+    }
+    bytes
+}
+
+fn decode_corpus_entry(mut bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut packets = vec![];
+    while bytes.len() >= 2 {
+        let len = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+        let end = std::cmp::min(2 + len, bytes.len());
+        packets.push(bytes[2..end].to_vec());
+        bytes = &bytes[end..];
+    }
+    packets
+}
+
+/// Valid packets sent by a client: small and fragmented messages, on unreliable and reliable channels
+fn valid_packets(rng: &mut StdRng) -> Vec<Vec<u8>> {
+    let protocol = protocol();
+    let mut message_manager = MessageManager::new(protocol.channel_registry());
+    let channels = [
+        Channel1::kind(),
+        Channel2::kind(),
+        OrderedChannel::kind(),
+        ChannelKind::of::<EntityActionsChannel>(),
+    ];
+    for _ in 0..rng.gen_range(1..4) {
+        let message = if rng.gen_bool(0.5) {
+            MyMessageProtocol::Message2(Message2(rng.gen()))
+        } else {
+            let len = rng.gen_range(0..3 * FRAGMENT_SIZE);
+            MyMessageProtocol::Message1(Message1("a".repeat(len)))
+        };
+        let channel = channels[rng.gen_range(0..channels.len())];
+        message_manager
+            .buffer_send(
+                ClientMessage::<MyProtocol>::Message(message, NetworkTarget::None),
+                channel,
+            )
+            .unwrap();
+    }
+    message_manager.send_packets(Tick(0)).unwrap()
+}
+
+fn mutate(rng: &mut StdRng, packet: &mut Vec<u8>) {
+    if packet.is_empty() {
+        packet.push(rng.gen());
+        return;
+    }
+    let index = rng.gen_range(0..packet.len());
+    match rng.gen_range(0..5) {
+        // flip a bit
+        0 => packet[index] ^= 1 << rng.gen_range(0..8),
+        // overwrite a byte
+        1 => packet[index] = rng.gen(),
+        2 => packet.truncate(index),
+        3 => packet.insert(index, rng.gen()),
+        _ => {
+            packet.remove(index);
+        }
+    }
+}
+
+#[test]
+fn test_fuzz_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/fuzz_corpus");
+    let mut num_entries = 0;
+    for entry in std::fs::read_dir(corpus).unwrap() {
+        let path = entry.unwrap().path();
+        let packets = decode_corpus_entry(&std::fs::read(&path).unwrap());
+        let result = catch_unwind(AssertUnwindSafe(|| recv_packets(&packets)));
+        // every input of the corpus is invalid
+        assert!(
+            matches!(result, Ok(Err(_))),
+            "{:?} did not return an error",
+            path
+        );
+        num_entries += 1;
+    }
+    assert!(num_entries > 0);
+}
+
+#[test]
+fn test_fuzz_mutations() {
+    let mut rng = StdRng::seed_from_u64(0);
+    // the valid packets are accepted
+    let packets = valid_packets(&mut rng);
+    assert!(recv_packets(&packets).is_ok());
+
+    for _ in 0..2000 {
+        let mut packets = valid_packets(&mut rng);
+        for _ in 0..rng.gen_range(1..4) {
+            let index = rng.gen_range(0..packets.len());
+            mutate(&mut rng, &mut packets[index]);
+        }
+        // the packets can also arrive several times
+        if rng.gen_bool(0.2) {
+            let index = rng.gen_range(0..packets.len());
+            packets.push(packets[index].clone());
+        }
+        check_no_panic(&packets);
+    }
+}
+
+// A client that sends a packet that cannot be decoded is disconnected by the server
+#[test]
+fn test_invalid_packet_disconnects_client() {
+    let frame_duration = Duration::from_millis(10);
+    let shared_config = SharedConfig {
+        tick: TickConfig::new(Duration::from_millis(10)),
+        ..Default::default()
+    };
+    let link_conditioner = LinkConditionerConfig {
+        incoming_latency: Duration::from_millis(0),
+        incoming_jitter: Duration::from_millis(0),
+        incoming_loss: 0.0,
+    };
+    let mut stepper = BevyStepper::new(
+        shared_config,
+        SyncConfig::default(),
+        PredictionConfig::default(),
+        InterpolationConfig::default(),
+        link_conditioner,
+        frame_duration,
+    );
+    stepper.init();
+    let client_id = stepper.client_id;
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .connection(client_id)
+        .is_ok());
+
+    // the packet is truncated in the middle of the header
+    stepper.client_app.world.resource_scope(
+        |world, mut netcode: bevy::prelude::Mut<crate::netcode::Client>| {
+            netcode
+                .send(&[0, 0xff], world.resource_mut::<Io>().as_mut())
+                .unwrap();
+        },
+    );
+    stepper.frame_step();
+    stepper.frame_step();
+    assert!(stepper
+        .server_app
+        .world
+        .resource::<ServerConnectionManager>()
+        .connection(client_id)
+        .is_err());
+}
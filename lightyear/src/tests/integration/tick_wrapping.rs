@@ -94,14 +94,7 @@ fn test_sync_after_tick_wrap() {
         stepper.frame_step();
     }
 
-    let client_entity = *stepper
-        .client_app
-        .world
-        .resource::<ClientConnectionManager>()
-        .replication_receiver
-        .remote_entity_map
-        .get_local(server_entity)
-        .unwrap();
+    let client_entity = stepper.client_entity(server_entity).unwrap();
     assert_eq!(
         stepper
             .client_app
@@ -188,14 +181,7 @@ fn test_sync_after_tick_half_wrap() {
         stepper.frame_step();
     }
 
-    let client_entity = *stepper
-        .client_app
-        .world
-        .resource::<ClientConnectionManager>()
-        .replication_receiver
-        .remote_entity_map
-        .get_local(server_entity)
-        .unwrap();
+    let client_entity = stepper.client_entity(server_entity).unwrap();
     assert_eq!(
         stepper
             .client_app
@@ -2,7 +2,7 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 pub mod client;
-mod examples;
+mod fuzz;
 mod integration;
 pub mod protocol;
 pub mod server;
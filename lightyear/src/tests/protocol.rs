@@ -1,5 +1,5 @@
 use bevy::prelude::{Component, Entity, Reflect};
-use bevy::utils::EntityHashSet;
+use bevy::utils::{Duration, EntityHashSet};
 use cfg_if::cfg_if;
 use derive_more::{Add, Mul};
 
@@ -35,6 +35,10 @@ pub struct Component3(pub f32);
 #[message(custom_map)]
 pub struct Component4(pub Entity);
 
+/// Interpolated component that is extrapolated when the server updates stop arriving
+#[derive(Component, MessageInternal, Serialize, Deserialize, Clone, Debug, PartialEq, Add, Mul)]
+pub struct Component5(pub f32);
+
 impl<'a> MapEntities<'a> for Component4 {
     fn map_entities(&mut self, entity_mapper: Box<dyn EntityMapper + 'a>) {
         self.0.map_entities(entity_mapper);
@@ -55,6 +59,8 @@ pub enum MyComponentsProtocol {
     Component3(Component3),
     #[sync(simple)]
     Component4(Component4),
+    #[sync(full, extrapolator = "LinearInterpolator")]
+    Component5(Component5),
 }
 
 // Inputs
@@ -107,6 +113,15 @@ pub struct Channel1;
 #[derive(ChannelInternal)]
 pub struct Channel2;
 
+#[derive(ChannelInternal)]
+pub struct BlobChannel;
+
+#[derive(ChannelInternal)]
+pub struct ScheduledChannel;
+
+#[derive(ChannelInternal)]
+pub struct OrderedChannel;
+
 pub fn protocol() -> MyProtocol {
     let mut p = MyProtocol::default();
     p.add_channel::<Channel1>(ChannelSettings {
@@ -117,5 +132,20 @@ pub fn protocol() -> MyProtocol {
         mode: ChannelMode::UnorderedUnreliableWithAcks,
         direction: ChannelDirection::Bidirectional,
     });
+    p.add_channel::<BlobChannel>(ChannelSettings {
+        mode: ChannelMode::Blob(BlobSettings::default()),
+        direction: ChannelDirection::Bidirectional,
+    });
+    p.add_channel::<ScheduledChannel>(ChannelSettings {
+        mode: ChannelMode::Scheduled(ReliableSettings::default()),
+        direction: ChannelDirection::ServerToClient,
+    });
+    p.add_channel::<OrderedChannel>(ChannelSettings {
+        mode: ChannelMode::OrderedReliable(ReliableSettings {
+            message_ttl: Some(Duration::from_millis(200)),
+            ..Default::default()
+        }),
+        direction: ChannelDirection::Bidirectional,
+    });
     p
 }
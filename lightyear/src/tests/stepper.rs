@@ -3,11 +3,11 @@ use std::net::SocketAddr;
 use std::str::FromStr;
 
 use bevy::ecs::system::SystemState;
-use bevy::prelude::{App, Mut, PluginGroup, Real, Time, World};
+use bevy::prelude::{App, Entity, Mut, PluginGroup, Real, Time, World};
 use bevy::time::TimeUpdateStrategy;
 use bevy::{DefaultPlugins, MinimalPlugins};
 
-use crate::netcode::generate_key;
+use crate::netcode::{generate_key, ClientId};
 use crate::prelude::client::{
     Authentication, ClientConfig, InputConfig, InterpolationConfig, PredictionConfig, SyncConfig,
 };
@@ -32,6 +32,9 @@ pub struct BevyStepper {
     /// fixed timestep duration
     pub tick_duration: Duration,
     pub current_time: bevy::utils::Instant,
+    /// authentication used by the client to connect to the server
+    pub auth: Authentication,
+    pub client_id: ClientId,
 }
 
 // Do not forget to use --features mock_time when using the LinkConditioner
@@ -82,6 +85,7 @@ impl BevyStepper {
             shared: shared_config.clone(),
             netcode: netcode_config,
             ping: PingConfig::default(),
+            ..Default::default()
         };
         let plugin_config = server::PluginConfig::new(config, server_io, protocol());
         let plugin = server::ServerPlugin::new(plugin_config);
@@ -105,7 +109,7 @@ impl BevyStepper {
             prediction: prediction_config,
             interpolation: interpolation_config,
         };
-        let plugin_config = client::PluginConfig::new(config, client_io, protocol(), auth);
+        let plugin_config = client::PluginConfig::new(config, client_io, protocol(), auth.clone());
         let plugin = client::ClientPlugin::new(plugin_config);
         client_app.add_plugins(plugin);
 
@@ -128,6 +132,8 @@ impl BevyStepper {
             frame_duration,
             tick_duration: shared_config.tick.tick_duration,
             current_time: now,
+            auth,
+            client_id,
         }
     }
 
@@ -147,6 +153,26 @@ impl BevyStepper {
     pub(crate) fn server_tick(&self) -> Tick {
         self.server_app.world.resource::<TickManager>().tick()
     }
+    /// Get the client entity that corresponds to an entity replicated by the server
+    pub(crate) fn client_entity(&self, server_entity: Entity) -> Option<Entity> {
+        let net_entity = self
+            .server_app
+            .world
+            .resource::<ServerConnectionManager>()
+            .connection(self.client_id)
+            .ok()?
+            .replication_sender
+            .net_entities
+            .get(server_entity)?;
+        self.client_app
+            .world
+            .resource::<ClientConnectionManager>()
+            .replication_receiver
+            .remote_entity_map
+            .get_local(net_entity)
+            .copied()
+    }
+
     pub(crate) fn init(&mut self) {
         self.client_app
             .world
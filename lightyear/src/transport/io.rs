@@ -13,6 +13,7 @@ use metrics;
 use tracing::info;
 
 use super::LOCAL_SOCKET;
+use crate::packet::compression::CompressionStats;
 use crate::transport::channels::Channels;
 use crate::transport::conditioner::{ConditionedPacketReceiver, LinkConditionerConfig};
 use crate::transport::local::LocalChannel;
@@ -184,6 +185,8 @@ pub struct IoStats {
     pub bytes_received: usize,
     pub packets_sent: usize,
     pub packets_received: usize,
+    /// Statistics about the compression of the data sent and received
+    pub compression: CompressionStats,
 }
 
 impl Io {
@@ -215,6 +218,17 @@ impl Io {
         (&mut self.sender, &mut self.receiver)
     }
 
+    /// Same as [`split`](Self::split), but also returns the stats so that they can be updated
+    pub(crate) fn split_with_stats(
+        &mut self,
+    ) -> (
+        &mut Box<dyn PacketSender>,
+        &mut Box<dyn PacketReceiver>,
+        &mut IoStats,
+    ) {
+        (&mut self.sender, &mut self.receiver, &mut self.stats)
+    }
+
     pub fn stats(&self) -> &IoStats {
         &self.stats
     }
@@ -277,6 +291,10 @@ impl IoDiagnosticsPlugin {
     pub const PACKETS_OUT: DiagnosticId =
         DiagnosticId::from_u128(314668465487051049643062180884137694217);
 
+    /// Ratio between the number of bytes sent after and before compression
+    pub const COMPRESSION_RATIO: DiagnosticId =
+        DiagnosticId::from_u128(107285632185739211904871353402853017164);
+
     /// Max diagnostic history length.
     pub const DIAGNOSTIC_HISTORY_LEN: usize = 60;
 
@@ -299,6 +317,12 @@ impl IoDiagnosticsPlugin {
         diagnostics.add_measurement(Self::PACKETS_OUT, || {
             stats.packets_sent as f64 / delta_seconds
         });
+        if stats.compression.bytes_sent_uncompressed > 0 {
+            diagnostics.add_measurement(Self::COMPRESSION_RATIO, || {
+                stats.compression.bytes_sent_compressed as f64
+                    / stats.compression.bytes_sent_uncompressed as f64
+            });
+        }
         *stats = IoStats::default()
     }
 }
@@ -325,6 +349,11 @@ impl Plugin for IoDiagnosticsPlugin {
             "packets sent per second",
             IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN,
         ));
+        app.register_diagnostic(Diagnostic::new(
+            IoDiagnosticsPlugin::COMPRESSION_RATIO,
+            "compression ratio (sent)",
+            IoDiagnosticsPlugin::DIAGNOSTIC_HISTORY_LEN,
+        ));
     }
 }
 
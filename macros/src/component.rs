@@ -42,6 +42,8 @@ struct SyncField {
     lerp: Option<Ident>,
     #[darling(default)]
     corrector: Option<Ident>,
+    #[darling(default)]
+    extrapolator: Option<Ident>,
 }
 
 impl SyncField {
@@ -185,6 +187,7 @@ pub fn component_protocol_impl(
     let insert_method = insert_method(&input, &fields);
     let update_method = update_method(&input, &fields);
     let type_ids_method = type_ids_method(&fields, &enum_kind_name);
+    let components_of_method = components_of_method(&fields);
 
     // EnumKind methods
     let enum_kind = get_enum_kind(&input, &enum_kind_name);
@@ -199,7 +202,7 @@ pub fn component_protocol_impl(
             use #shared_crate_name::_reexport::*;
             use #shared_crate_name::prelude::*;
             use #shared_crate_name::prelude::client::*;
-            use bevy::prelude::{App, Entity, IntoSystemConfigs, EntityWorldMut, World};
+            use bevy::prelude::{App, Entity, EntityRef, IntoSystemConfigs, EntityWorldMut, World};
             use bevy::utils::{EntityHashMap, EntityHashSet, HashMap};
             use std::any::TypeId;
             use #shared_crate_name::shared::events::{ComponentInsertEvent, ComponentRemoveEvent, ComponentUpdateEvent};
@@ -215,6 +218,7 @@ pub fn component_protocol_impl(
                 type Protocol = #protocol;
 
                 #type_ids_method
+                #components_of_method
                 #insert_method
                 #update_method
                 #add_systems_method
@@ -414,13 +418,23 @@ fn sync_metadata_impl(fields: &Vec<SyncField>, enum_name: &Ident) -> TokenStream
             }
         });
         // prediction
-        let mut corrector = field
+        let corrector = field
             .corrector
             .clone()
             .unwrap_or(Ident::new("InstantCorrector", Span::call_site()));
-        if corrector == "InterpolatedCorrector" {
-            corrector = interpolator.clone();
-        }
+        let corrector = if corrector == "InterpolatedCorrector" {
+            quote! { InterpolatedCorrector<#interpolator> }
+        } else {
+            quote! { #corrector }
+        };
+        // extrapolation (disabled by default)
+        let extrapolator = field.extrapolator.as_ref().map(|extrapolator| {
+            quote! {
+                fn extrapolator() -> Option<fn(#component_type, #component_type, f32) -> #component_type> {
+                    Some(<#extrapolator as LerpFn<#component_type>>::lerp)
+                }
+            }
+        });
         body = quote! {
             #body
             impl SyncMetadata<#component_type> for #enum_name {
@@ -429,6 +443,7 @@ fn sync_metadata_impl(fields: &Vec<SyncField>, enum_name: &Ident) -> TokenStream
                 fn mode() -> ComponentSyncMode {
                     #mode
                 }
+                #extrapolator
             }
         }
     }
@@ -719,6 +734,28 @@ fn update_method(input: &ItemEnum, fields: &Vec<Field>) -> TokenStream {
     }
 }
 
+fn components_of_method(fields: &Vec<Field>) -> TokenStream {
+    let mut body = quote! {
+        let mut res = Vec::new();
+    };
+    for field in fields {
+        let ident = &field.ident;
+        let component_type = &field.ty;
+        body = quote! {
+            #body
+            if let Some(x) = entity.get::<#component_type>() {
+                res.push(Self::#ident(x.clone()));
+            }
+        };
+    }
+    quote! {
+        fn components_of(entity: &EntityRef) -> Vec<Self> {
+            #body
+            res
+        }
+    }
+}
+
 fn type_ids_method(fields: &Vec<Field>, enum_kind_name: &Ident) -> TokenStream {
     let mut body = quote! {
         let mut res = HashMap::default();